@@ -0,0 +1,62 @@
+//! Spotting clients who have walked away, so they don't hold up the lobby
+use std::collections::HashMap;
+
+use cimvr_common::glam::Vec3;
+use cimvr_engine_interface::prelude::*;
+
+/// A ship has to move further than this between uploads to count as being flown (meters)
+const MOVE_EPSILON: f32 = 0.01;
+
+/// When each client was last seen doing something
+#[derive(Default)]
+pub struct AfkTracker {
+    clients: HashMap<ClientId, Activity>,
+}
+
+struct Activity {
+    /// Time of the last sign of life
+    last: f32,
+    /// Where their ship was as of the last upload
+    pos: Option<Vec3>,
+}
+
+impl AfkTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The client did something deliberate, e.g. sent a message
+    pub fn activity(&mut self, client_id: ClientId, now: f32) {
+        self.get(client_id, now).last = now;
+    }
+
+    /// The client uploaded their ship at `pos`, which only counts if it has moved
+    pub fn moved(&mut self, client_id: ClientId, pos: Vec3, now: f32) {
+        let activity = self.get(client_id, now);
+        let moved = match activity.pos {
+            Some(last) => last.distance(pos) > MOVE_EPSILON,
+            None => true,
+        };
+        if moved {
+            activity.last = now;
+        }
+        activity.pos = Some(pos);
+    }
+
+    /// Whether the client has done nothing for `timeout` minutes. Clients never heard from
+    /// count as active, until they've been around for that long.
+    pub fn is_afk(&mut self, client_id: ClientId, now: f32, timeout: f32) -> bool {
+        now - self.get(client_id, now).last > timeout * 60.
+    }
+
+    pub fn remove(&mut self, client_id: ClientId) {
+        self.clients.remove(&client_id);
+    }
+
+    fn get(&mut self, client_id: ClientId, now: f32) -> &mut Activity {
+        self.clients.entry(client_id).or_insert(Activity {
+            last: now,
+            pos: None,
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use cimvr_common::{
+    ui::{Schema, State, UiHandle, UiStateHelper},
+    Transform,
+};
+use cimvr_engine_interface::prelude::*;
+
+use crate::{
+    controls::{TuningParams, MAX_LOOKAHEAD_FRACTION},
+    curve::Curve,
+    InputAbstraction,
+};
+
+/// How long real throttle input disables the assist (seconds)
+const OVERRIDE_TIME: f32 = 2.;
+/// Curvature at which the assist applies its lowest throttle (radians per meter)
+const MAX_CURVATURE: f32 = 0.02;
+/// Lowest throttle applied by the assist
+const MIN_THROTTLE: f32 = 0.4;
+/// Spacing of curvature samples over the lookahead window (meters)
+const SAMPLE_STEP: f32 = 5.;
+
+/// What the auto-throttle did this frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AutoThrottleState {
+    Off,
+    /// Applying the given throttle
+    Active(f32),
+    /// The player is throttling themselves
+    Overridden,
+}
+
+/// Accessibility assist which throttles automatically, easing off ahead of sharp turns
+#[derive(Default)]
+pub struct AutoThrottle {
+    /// Time left before the assist takes over again
+    override_timer: f32,
+    /// HUD note showing the assist's state
+    indicator: Option<UiHandle>,
+}
+
+impl AutoThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the throttle in `input`, unless the player is providing their own
+    pub fn apply(
+        &mut self,
+        dt: f32,
+        path: &Curve,
+        tuning: &TuningParams,
+        tf: &Transform,
+        input: &mut InputAbstraction,
+    ) -> AutoThrottleState {
+        if input.throttle.abs() > tuning.throttle_deadzone {
+            self.override_timer = OVERRIDE_TIME;
+        }
+
+        if self.override_timer > 0. {
+            self.override_timer -= dt;
+            return AutoThrottleState::Overridden;
+        }
+
+        // Ease off for the sharpest turn coming up
+        let start = path.nearest_ctrlp(tf.pos) as f32;
+        let lookahead = path.clamp_distance(tuning.lookahead, MAX_LOOKAHEAD_FRACTION);
+        let n_samples = (lookahead / SAMPLE_STEP).ceil() as usize;
+        let curvature = (0..=n_samples)
+            .map(|i| path.curvature_at(path.advance(start, i as f32 * SAMPLE_STEP)))
+            .fold(0., f32::max);
+
+        let ease = (curvature / MAX_CURVATURE).min(1.);
+        let throttle = 1. - ease * (1. - MIN_THROTTLE);
+        input.throttle = throttle;
+        AutoThrottleState::Active(throttle)
+    }
+
+    /// Forget any override, e.g. when a race starts
+    pub fn reset(&mut self) {
+        self.override_timer = 0.;
+    }
+
+    /// Show the assist's state, or remove the note if `Off` or not `visible`
+    pub fn show_state(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        state: AutoThrottleState,
+        visible: bool,
+    ) {
+        let text = match state {
+            AutoThrottleState::Active(throttle) => {
+                format!("Auto-throttle: {:.0}%", throttle * 100.)
+            }
+            AutoThrottleState::Overridden => "Auto-throttle: overridden".to_string(),
+            AutoThrottleState::Off => String::new(),
+        };
+        let shown = visible && state != AutoThrottleState::Off;
+
+        match (shown, self.indicator) {
+            (true, None) => {
+                let state = vec![State::Label { text }];
+                self.indicator = Some(gui.add(io, "Assist", vec![Schema::Label], state));
+            }
+            (true, Some(handle)) => {
+                gui.modify(io, handle, |ui_state| {
+                    ui_state[0] = State::Label { text: text.clone() }
+                });
+            }
+            (false, Some(handle)) => {
+                gui.delete(io, handle);
+                self.indicator = None;
+            }
+            (false, None) => (),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::VecDeque;
+
+use cimvr_common::ui::{Schema, State, UiHandle, UiStateHelper};
+use cimvr_engine_interface::prelude::*;
+
+/// How long each banner is shown (seconds)
+const BANNER_TIME: f32 = 2.5;
+
+/// Short announcements shown one at a time, in the order they arrive
+#[derive(Default)]
+pub struct Banners {
+    queue: VecDeque<String>,
+    /// Banner being shown and the time it has left
+    current: Option<(UiHandle, f32)>,
+}
+
+impl Banners {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show `text` once any earlier banners are done
+    pub fn push(&mut self, text: String) {
+        self.queue.push_back(text);
+    }
+
+    /// Drop all banners, shown or waiting
+    pub fn clear(&mut self, io: &mut EngineIo, gui: &mut UiStateHelper) {
+        self.queue.clear();
+        if let Some((handle, _)) = self.current.take() {
+            gui.delete(io, handle);
+        }
+    }
+
+    /// Advance the current banner, moving on to the next once it has been up long enough.
+    /// Banners are held back while not `visible`.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        delta: f32,
+        visible: bool,
+    ) {
+        if let Some((handle, time_left)) = &mut self.current {
+            *time_left -= delta;
+            if *time_left <= 0. || !visible {
+                gui.delete(io, *handle);
+                self.current = None;
+            }
+        }
+
+        if self.current.is_none() && visible {
+            if let Some(text) = self.queue.pop_front() {
+                let handle = gui.add(
+                    io,
+                    "Announcement",
+                    vec![Schema::Label],
+                    vec![State::Label { text }],
+                );
+                self.current = Some((handle, BANNER_TIME));
+            }
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! Moving barriers on the track. Where they are is a pure function of the race clock, so every
+//! client and the server see the same thing without any messages.
+use std::f32::consts::TAU;
+
+use cimvr_common::{
+    glam::{Quat, Vec3},
+    render::{MeshHandle, Primitive, Render, UploadMesh},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{
+    controls::{TRACK_HEIGHT, TRACK_WIDTH},
+    curve::Curve,
+    kinematics::KinematicPhysics,
+    shapes::barrier_mesh,
+};
+
+/// Extent of a barrier across the track (meters)
+const BARRIER_WIDTH: f32 = 8.;
+/// Extent along the track (meters)
+const BARRIER_THICKNESS: f32 = 1.;
+/// Ships are kept this far clear of a barrier's faces (meters)
+const SHIP_RADIUS: f32 = 1.;
+/// Fraction of the speed into a barrier which is kept, back the other way
+const BOUNCE: f32 = 0.3;
+const BARRIER_COLOR: [f32; 3] = [1., 0.3, 0.];
+
+/// How a barrier moves
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BarrierMotion {
+    /// Slides `amplitude` meters either side of its anchor across the track, and back, every
+    /// `period` seconds
+    Oscillate { amplitude: f32, period: f32 },
+    /// Turns about the track's up axis once every `period` seconds; negative turns the other way
+    Rotate { period: f32 },
+}
+
+/// A barrier, anchored to the track
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BarrierPlacement {
+    /// Curve position; wraps around the loop, so it's valid on tracks of any size
+    pub index: f32,
+    /// Distance of the anchor from the center line, positive towards local +Z (meters)
+    pub side: f32,
+    pub motion: BarrierMotion,
+}
+
+impl BarrierPlacement {
+    /// Where the barrier is `clock` seconds after "GO". Barriers stand still at their
+    /// positions for zero before then.
+    pub fn transform(&self, path: &Curve, clock: f32) -> Transform {
+        let frame = path.lerp_fast(self.index);
+        let clock = clock.max(0.);
+        let (side, turn) = match self.motion {
+            BarrierMotion::Oscillate { amplitude, period } => {
+                let side = self.side + amplitude * (clock / period * TAU).sin();
+                (side, 0.)
+            }
+            BarrierMotion::Rotate { period } => (self.side, clock / period * TAU),
+        };
+
+        // Never poking out of the sides of the track
+        let max_side = TRACK_WIDTH / 2. - BARRIER_WIDTH / 2.;
+        let pos = frame.pos + frame.orient * Vec3::new(0., 0., side.clamp(-max_side, max_side));
+        Transform::new()
+            .with_position(pos)
+            .with_rotation(frame.orient * Quat::from_rotation_y(turn))
+    }
+}
+
+/// Keep a ship which moved to `tf` at velocity `kt` over the last `dt` seconds, up to `clock`,
+/// from passing through any of `barriers`. The ship's path is tested against each barrier's
+/// movement over the same time, so that neither can jump through the other in one step.
+/// Returns whether the ship hit one.
+pub fn collide(
+    barriers: &[BarrierPlacement],
+    path: &Curve,
+    clock: f32,
+    dt: f32,
+    tf: &mut Transform,
+    kt: &mut KinematicPhysics,
+) -> bool {
+    let mut hit = false;
+    let prev_pos = tf.pos - kt.vel * dt;
+    for barrier in barriers {
+        let before = barrier.transform(path, clock - dt);
+        let after = barrier.transform(path, clock);
+
+        // The ship's path as seen from the barrier
+        let from = before.orient.inverse() * (prev_pos - before.pos);
+        let to = after.orient.inverse() * (tf.pos - after.pos);
+        let reach = BARRIER_THICKNESS / 2. + SHIP_RADIUS;
+        let was_outside = from.x.abs() >= reach;
+        let crossed = from.x.signum() != to.x.signum() || to.x.abs() < reach;
+        let beside = to.z.abs() > BARRIER_WIDTH / 2. + SHIP_RADIUS;
+        let above = to.y.abs() > TRACK_HEIGHT / 2.;
+        if !was_outside || !crossed || beside || above {
+            continue;
+        }
+
+        // Back out on the side the ship came from, losing most of its speed into the barrier
+        let side = from.x.signum();
+        let pos = Vec3::new(side * reach, to.y, to.z);
+        tf.pos = after.pos + after.orient * pos;
+        let normal = after.orient * Vec3::X * side;
+        let into = kt.vel.dot(normal);
+        if into < 0. {
+            kt.vel -= normal * into * (1. + BOUNCE);
+        }
+        hit = true;
+    }
+    hit
+}
+
+/// The barriers' entities on the client
+pub struct Barriers {
+    placements: Vec<BarrierPlacement>,
+    entities: Vec<EntityId>,
+    /// Clock the entities were last placed for
+    shown_clock: Option<f32>,
+}
+
+impl Barriers {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("Barrier"));
+
+    pub fn new(io: &mut EngineIo, path: &Curve, placements: &[BarrierPlacement]) -> Self {
+        let size = [BARRIER_THICKNESS, TRACK_HEIGHT, BARRIER_WIDTH];
+        io.send(&UploadMesh {
+            mesh: barrier_mesh(size, BARRIER_COLOR),
+            id: Self::RDR_ID,
+        });
+
+        let entities = placements
+            .iter()
+            .map(|placement| {
+                io.create_entity()
+                    .add_component(placement.transform(path, 0.))
+                    .add_component(Render::new(Self::RDR_ID).primitive(Primitive::Lines))
+                    .build()
+            })
+            .collect();
+
+        Self {
+            placements: placements.to_vec(),
+            entities,
+            shown_clock: Some(0.),
+        }
+    }
+
+    pub fn teardown(self, io: &mut EngineIo) {
+        for entity in self.entities {
+            io.remove_entity(entity);
+        }
+    }
+
+    /// Move the barriers to where they are at `clock`; nothing is written while it stands still
+    pub fn update(&mut self, io: &mut EngineIo, path: &Curve, clock: f32) {
+        let clock = clock.max(0.);
+        if self.shown_clock == Some(clock) {
+            return;
+        }
+        self.shown_clock = Some(clock);
+        for (placement, &entity) in self.placements.iter().zip(&self.entities) {
+            io.add_component(entity, placement.transform(path, clock));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{harness::long_straight, kinematics::FIXED_DT};
+
+    /// Control point on `long_straight` the barriers stand at, facing along X at x = 200
+    const INDEX: f32 = 10.;
+    const ANCHOR: f32 = 200.;
+    const REACH: f32 = BARRIER_THICKNESS / 2. + SHIP_RADIUS;
+
+    fn standing(side: f32) -> BarrierPlacement {
+        BarrierPlacement {
+            index: INDEX,
+            side,
+            motion: BarrierMotion::Oscillate {
+                amplitude: 0.,
+                period: 1.,
+            },
+        }
+    }
+
+    /// Move a ship from `from` to `to` in one fixed step ending at `clock`, returning whether it
+    /// hit `barrier` and where it ended up at what velocity
+    fn step(barrier: BarrierPlacement, from: Vec3, to: Vec3, clock: f32) -> (bool, Vec3, Vec3) {
+        let mut tf = Transform::new().with_position(to);
+        let mut kt = KinematicPhysics {
+            vel: (to - from) / FIXED_DT,
+            ..KinematicPhysics::new(1.)
+        };
+        let hit = collide(
+            &[barrier],
+            &long_straight(),
+            clock,
+            FIXED_DT,
+            &mut tf,
+            &mut kt,
+        );
+        (hit, tf.pos, kt.vel)
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-2
+    }
+
+    #[test]
+    fn running_into_a_barrier_bounces_back_off_its_face() {
+        let (from, to) = (Vec3::new(190., 0., 2.), Vec3::new(ANCHOR - 0.5, 1., 2.));
+        let (hit, pos, vel) = step(standing(0.), from, to, 5.);
+        assert!(hit);
+        assert!(close(pos, Vec3::new(ANCHOR - REACH, 1., 2.)), "{:?}", pos);
+        let speed = (to - from).x / FIXED_DT;
+        assert!((vel.x + speed * BOUNCE).abs() < 0.1, "{:?}", vel);
+        assert!((vel.y - 1. / FIXED_DT).abs() < 0.1, "{:?}", vel);
+    }
+
+    #[test]
+    fn fast_ships_cant_tunnel_through() {
+        // However far past the barrier one step would carry the ship
+        for dist in [5., 20., 100., 400.] {
+            let from = Vec3::new(ANCHOR - REACH - 0.5, 0., -3.);
+            let (hit, pos, vel) = step(standing(0.), from, from + Vec3::X * dist, 5.);
+            assert!(hit, "{} m step", dist);
+            assert!(close(pos, Vec3::new(ANCHOR - REACH, 0., -3.)), "{:?}", pos);
+            assert!(vel.x < 0., "{} m step: {:?}", dist, vel);
+        }
+
+        // Or which way it goes through
+        let from = Vec3::new(ANCHOR + 50., 0., 0.);
+        let (hit, pos, _) = step(standing(0.), from, Vec3::new(ANCHOR - 50., 0., 0.), 5.);
+        assert!(hit);
+        assert!(close(pos, Vec3::new(ANCHOR + REACH, 0., 0.)), "{:?}", pos);
+    }
+
+    #[test]
+    fn near_misses_are_left_alone() {
+        let barrier = standing(0.);
+        let misses = [
+            // Stopping short
+            (
+                Vec3::new(190., 0., 0.),
+                Vec3::new(ANCHOR - REACH - 0.1, 0., 0.),
+            ),
+            // Driving away from it
+            (
+                Vec3::new(ANCHOR + REACH + 0.1, 0., 0.),
+                Vec3::new(210., 0., 0.),
+            ),
+            // Past the end of it
+            (
+                Vec3::new(190., 0., BARRIER_WIDTH),
+                Vec3::new(210., 0., BARRIER_WIDTH),
+            ),
+            // Over the top
+            (
+                Vec3::new(190., TRACK_HEIGHT, 0.),
+                Vec3::new(210., TRACK_HEIGHT, 0.),
+            ),
+        ];
+        for (from, to) in misses {
+            let (hit, pos, vel) = step(barrier, from, to, 5.);
+            assert!(!hit, "{:?} to {:?}", from, to);
+            assert_eq!(pos, to);
+            assert!(close(vel * FIXED_DT, to - from));
+        }
+    }
+
+    #[test]
+    fn ships_are_tested_against_where_moving_barriers_are_now() {
+        // A quarter period in, the barrier has slid out to the full amplitude
+        let amplitude = 6.;
+        let barrier = BarrierPlacement {
+            motion: BarrierMotion::Oscillate {
+                amplitude,
+                period: 4.,
+            },
+            ..standing(0.)
+        };
+        let through = |z: f32, clock: f32| {
+            step(
+                barrier,
+                Vec3::new(190., 0., z),
+                Vec3::new(210., 0., z),
+                clock,
+            )
+            .0
+        };
+        assert!(through(amplitude, 1.));
+        assert!(!through(0., 1.));
+        // Standing still at its anchor before "GO"
+        assert!(through(0., -1.));
+        assert!(!through(amplitude, -1.));
+    }
+
+    #[test]
+    fn barriers_stay_on_the_track() {
+        let barrier = BarrierPlacement {
+            motion: BarrierMotion::Oscillate {
+                amplitude: TRACK_WIDTH,
+                period: 4.,
+            },
+            ..standing(TRACK_WIDTH / 4.)
+        };
+        let path = long_straight();
+        for i in 0..100 {
+            let z = barrier.transform(&path, i as f32 * 0.1).pos.z;
+            assert!(
+                z.abs() + BARRIER_WIDTH / 2. <= TRACK_WIDTH / 2. + 1e-3,
+                "{}",
+                z
+            );
+        }
+        let (early, go) = (barrier.transform(&path, -3.), barrier.transform(&path, 0.));
+        assert_eq!((early.pos, early.orient), (go.pos, go.orient));
+    }
+}
@@ -0,0 +1,235 @@
+use cimvr_common::{
+    glam::{Quat, Vec3},
+    render::{Mesh, MeshHandle, Primitive, Render, UploadMesh, Vertex},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*, FrameTime};
+
+use crate::{
+    barriers::{BarrierMotion, BarrierPlacement},
+    controls::{TRACK_HEIGHT, TRACK_WIDTH},
+    countdown::color_extra,
+    curve::Curve,
+    race_rules::LapTracker,
+    shapes::panel_mesh,
+    wall_ride::ShieldedSection,
+};
+
+/// Size of a billboard (meters)
+const PANEL_WIDTH: f32 = 24.;
+const PANEL_HEIGHT: f32 = 8.;
+/// Side offset which puts a billboard just clear of the track (meters)
+const TRACKSIDE: f32 = TRACK_WIDTH / 2. + PANEL_WIDTH / 2.;
+/// Distance after the finish line and each checkpoint of the sector markers (meters)
+const SECTOR_SIGN_DISTANCE: f32 = 30.;
+/// Candidate spacings of the distance-to-finish boards, largest first (meters)
+const DISTANCE_STEPS: [f32; 4] = [500., 250., 100., 50.];
+/// Fewest distance boards a lap should have, which decides the spacing
+const MIN_DISTANCE_BOARDS: usize = 3;
+/// Boards closer than this to the finish line would crowd the "FINISH" banner (meters)
+const FINISH_CLEARANCE: f32 = 50.;
+/// How far the brightness dips while flickering (fraction)
+const FLICKER_DEPTH: f32 = 0.15;
+
+const FINISH_COLOR: [f32; 3] = [1., 1., 1.];
+const SECTOR_COLOR: [f32; 3] = [1., 1., 0.];
+const DISTANCE_COLOR: [f32; 3] = [0., 1., 1.];
+const DECORATION_COLOR: [f32; 3] = [1., 0., 1.];
+
+/// Which way a billboard faces
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Facing {
+    /// Towards racers coming up the track
+    Oncoming,
+    /// Across the track, towards its center line
+    Inward,
+}
+
+/// Where to put a billboard, and what it says
+#[derive(Clone, Debug)]
+pub struct BillboardPlacement {
+    /// Curve position; wraps around the loop, so it's valid on tracks of any size
+    pub index: f32,
+    /// Distance from the center line, positive towards local +Z (meters). Billboards stand just
+    /// above the top of the track, so zero makes an overhead banner.
+    pub side: f32,
+    pub facing: Facing,
+    pub text: String,
+    pub color: [f32; 3],
+}
+
+/// Decoration added to a track, on top of the signage every track gets
+#[derive(Clone, Debug)]
+pub struct TrackConfig {
+    pub billboards: Vec<BillboardPlacement>,
+    /// Animate a subtle flicker, as though the billboards were holograms
+    pub flicker: bool,
+    /// Moving obstacles on the track itself
+    pub barriers: Vec<BarrierPlacement>,
+    /// Stretches where the walls can be ridden on shield energy
+    pub shielded_walls: Vec<ShieldedSection>,
+}
+
+impl Default for TrackConfig {
+    fn default() -> Self {
+        let decoration = |index: f32, side: f32, text: &str| BillboardPlacement {
+            index,
+            side,
+            facing: Facing::Inward,
+            text: text.into(),
+            color: DECORATION_COLOR,
+        };
+
+        Self {
+            billboards: vec![
+                decoration(20., TRACKSIDE, "FZ"),
+                decoration(45., -TRACKSIDE, "CHATIMPROVR"),
+                decoration(70., TRACKSIDE, "ZOOM"),
+            ],
+            flicker: true,
+            barriers: vec![BarrierPlacement {
+                index: 60.,
+                side: 0.,
+                motion: BarrierMotion::Oscillate {
+                    amplitude: 8.,
+                    period: 6.,
+                },
+            }],
+            shielded_walls: vec![ShieldedSection {
+                start: 30.,
+                end: 40.,
+            }],
+        }
+    }
+}
+
+/// "FINISH", sector markers and distance-to-finish boards, worked out from the curve so that
+/// they're right on any track
+fn signage(path: &Curve, tracker: &LapTracker) -> Vec<BillboardPlacement> {
+    let sign = |index: f32, side: f32, text: String, color: [f32; 3]| BillboardPlacement {
+        index,
+        side,
+        facing: Facing::Oncoming,
+        text,
+        color,
+    };
+
+    let mut signs = vec![sign(path.finish_line(), 0., "FINISH".into(), FINISH_COLOR)];
+
+    // Each sector begins at the finish line or a checkpoint
+    let sector_starts =
+        std::iter::once(path.finish_line()).chain(tracker.checkpoints().iter().map(|c| c.t));
+    let sign_distance = path.clamp_distance(SECTOR_SIGN_DISTANCE, 0.05);
+    for (idx, start) in sector_starts.enumerate() {
+        signs.push(sign(
+            path.advance(start, sign_distance),
+            TRACKSIDE,
+            format!("S{}", idx + 1),
+            SECTOR_COLOR,
+        ));
+    }
+
+    // Space the distance boards so that even short tracks get a few
+    let length = path.length();
+    let step = DISTANCE_STEPS
+        .into_iter()
+        .find(|step| length / step >= (MIN_DISTANCE_BOARDS + 1) as f32)
+        .unwrap_or(DISTANCE_STEPS[DISTANCE_STEPS.len() - 1]);
+    let mut dist = step;
+    while dist < length - FINISH_CLEARANCE {
+        signs.push(sign(
+            path.advance(path.finish_line(), -dist),
+            -TRACKSIDE,
+            format!("{} m", dist),
+            DISTANCE_COLOR,
+        ));
+        dist += step;
+    }
+
+    signs
+}
+
+/// Where a billboard's panel goes in the world
+fn placement_transform(path: &Curve, placement: &BillboardPlacement) -> Transform {
+    let frame = path.lerp(placement.index.rem_euclid(path.ctrlps.len() as f32));
+    let lift = TRACK_HEIGHT / 2. + PANEL_HEIGHT / 2.;
+    let pos = frame.pos + frame.orient * Vec3::new(0., lift, placement.side);
+
+    // Panels face +Z; the track runs along local +X
+    let turn = match placement.facing {
+        Facing::Oncoming => Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2),
+        Facing::Inward if placement.side > 0. => Quat::from_rotation_y(std::f32::consts::PI),
+        Facing::Inward => Quat::IDENTITY,
+    };
+
+    Transform::new()
+        .with_position(pos)
+        .with_rotation(frame.orient * turn)
+}
+
+/// Add `src` to `dest`, moved by `tf`
+fn append_mesh(dest: &mut Mesh, src: &Mesh, tf: Transform) {
+    let base = dest.vertices.len() as u32;
+    for v in &src.vertices {
+        let pos = tf.pos + tf.orient * Vec3::from(v.pos);
+        dest.vertices.push(Vertex::new(pos.into(), v.uvw));
+    }
+    dest.indices.extend(src.indices.iter().map(|i| base + i));
+}
+
+/// Every billboard along the track, drawn as one mesh
+pub struct Billboards {
+    entity: EntityId,
+    flicker: bool,
+}
+
+impl Billboards {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("Billboards"));
+
+    pub fn new(
+        io: &mut EngineIo,
+        path: &Curve,
+        tracker: &LapTracker,
+        config: &TrackConfig,
+    ) -> Self {
+        let mut mesh = Mesh::new();
+        for placement in signage(path, tracker).iter().chain(&config.billboards) {
+            let panel = panel_mesh(PANEL_WIDTH, PANEL_HEIGHT, &placement.text, placement.color);
+            append_mesh(&mut mesh, &panel, placement_transform(path, placement));
+        }
+
+        io.send(&UploadMesh {
+            mesh,
+            id: Self::RDR_ID,
+        });
+
+        let entity = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(Render::new(Self::RDR_ID).primitive(Primitive::Lines))
+            .add_component(color_extra([1.; 3]))
+            .build();
+
+        Self {
+            entity,
+            flicker: config.flicker,
+        }
+    }
+
+    pub fn teardown(self, io: &mut EngineIo) {
+        io.remove_entity(self.entity);
+    }
+
+    /// Flicker, if enabled
+    pub fn update(&mut self, io: &mut EngineIo, time: FrameTime) {
+        if !self.flicker {
+            return;
+        }
+
+        // A few incommensurate waves, so the flicker never visibly repeats
+        let t = time.time;
+        let wave = ((t * 7.).sin() + (t * 13.1).sin() + (t * 29.7).sin()) / 3.;
+        let brightness = 1. - FLICKER_DEPTH * (wave * 0.5 + 0.5);
+        io.add_component(self.entity, color_extra([brightness; 3]));
+    }
+}
@@ -0,0 +1,211 @@
+//! Panel for changing the keys and gamepad controls. Each row is a button naming an action and
+//! a label showing its key, button or stick; pressing the button waits for the next one used,
+//! and binds the action to it.
+use cimvr_common::ui::{Schema, State, UiHandle, UiStateHelper};
+use cimvr_engine_interface::prelude::*;
+
+use crate::{
+    input_state::{BindableInput, KeyBindings},
+    strings::{tr, Key, Language},
+};
+
+/// Names of the actions which can be rebound, in panel order
+const ACTIONS: &[Key] = &[
+    Key::BindThrottle,
+    Key::BindBrake,
+    Key::BindSteerLeft,
+    Key::BindSteerRight,
+    Key::BindRespawn,
+    Key::BindCycleView,
+    Key::BindPadThrottle,
+    Key::BindPadBrake,
+    Key::BindPadUseItem,
+    Key::BindPadCycleView,
+    Key::BindPadRespawn,
+    Key::BindPadSteer,
+    Key::BindPadPitch,
+    Key::BindPadYaw,
+    Key::BindPadThrottleAxis,
+];
+
+/// Actions before this index of `ACTIONS` are keys, then buttons up to `FIRST_AXIS`, then axes
+const FIRST_BUTTON: usize = 6;
+const FIRST_AXIS: usize = 11;
+
+/// Each action is a button followed by a label showing its key
+const ELEMENTS_PER_ACTION: usize = 2;
+
+/// Input bound to the action at `idx` of `ACTIONS`, if any
+fn binding(idx: usize, bindings: &KeyBindings) -> Option<BindableInput> {
+    use BindableInput::{Axis, Button, Key};
+    match idx {
+        0 => bindings.throttle.map(Key),
+        1 => Some(Key(bindings.brake)),
+        2 => Some(Key(bindings.roll_left)),
+        3 => Some(Key(bindings.roll_right)),
+        4 => bindings.respawn.map(Key),
+        5 => bindings.cycle_view.map(Key),
+        6 => bindings.pad_throttle.map(Button),
+        7 => Some(Button(bindings.pad_brake)),
+        8 => Some(Button(bindings.pad_use_item)),
+        9 => bindings.pad_cycle_view.map(Button),
+        10 => bindings.pad_respawn.map(Button),
+        11 => Some(Axis(bindings.pad_steer)),
+        12 => Some(Axis(bindings.pad_pitch)),
+        13 => Some(Axis(bindings.pad_yaw)),
+        14 => Some(Axis(bindings.pad_throttle_axis)),
+        _ => unreachable!("Action index out of range"),
+    }
+}
+
+/// Bind the action at `idx` to `input`, returning false if it's the wrong kind of input for it
+fn bind(idx: usize, bindings: &mut KeyBindings, input: BindableInput) -> bool {
+    use BindableInput::{Axis, Button, Key};
+    match (idx, input) {
+        (0, Key(key)) => bindings.throttle = Some(key),
+        (1, Key(key)) => bindings.brake = key,
+        (2, Key(key)) => bindings.roll_left = key,
+        (3, Key(key)) => bindings.roll_right = key,
+        (4, Key(key)) => bindings.respawn = Some(key),
+        (5, Key(key)) => bindings.cycle_view = Some(key),
+        (6, Button(button)) => bindings.pad_throttle = Some(button),
+        (7, Button(button)) => bindings.pad_brake = button,
+        (8, Button(button)) => bindings.pad_use_item = button,
+        (9, Button(button)) => bindings.pad_cycle_view = Some(button),
+        (10, Button(button)) => bindings.pad_respawn = Some(button),
+        (11, Axis(axis)) => bindings.pad_steer = axis,
+        (12, Axis(axis)) => bindings.pad_pitch = axis,
+        (13, Axis(axis)) => bindings.pad_yaw = axis,
+        (14, Axis(axis)) => bindings.pad_throttle_axis = axis,
+        _ => return false,
+    }
+    true
+}
+
+fn input_text(language: Language, input: Option<BindableInput>) -> String {
+    match input {
+        Some(BindableInput::Key(key)) => format!("{:?}", key),
+        Some(BindableInput::Button(button)) => format!("{:?}", button),
+        Some(BindableInput::Axis(axis)) => format!("{:?}", axis),
+        None => tr(language, Key::Unbound).into(),
+    }
+}
+
+/// What to ask for while waiting on the action at `idx`
+fn prompt(idx: usize) -> Key {
+    if idx < FIRST_BUTTON {
+        Key::PressAKey
+    } else if idx < FIRST_AXIS {
+        Key::PressAButton
+    } else {
+        Key::MoveAStick
+    }
+}
+
+/// Panel for changing the custom `KeyBindings`
+pub struct BindingsPanel {
+    /// Removed while the HUD is hidden
+    element: Option<UiHandle>,
+    /// Action waiting for a key, as an index into `ACTIONS`
+    capturing: Option<usize>,
+}
+
+impl BindingsPanel {
+    pub fn new() -> Self {
+        Self {
+            element: None,
+            capturing: None,
+        }
+    }
+
+    fn label(&self, idx: usize, bindings: &KeyBindings, language: Language) -> State {
+        let text = match self.capturing == Some(idx) {
+            true => tr(language, prompt(idx)).into(),
+            false => input_text(language, binding(idx, bindings)),
+        };
+        State::Label { text }
+    }
+
+    fn add(
+        &self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        bindings: &KeyBindings,
+        language: Language,
+    ) -> UiHandle {
+        let mut schema = vec![];
+        let mut init_state = vec![];
+        for (idx, &name) in ACTIONS.iter().enumerate() {
+            schema.push(Schema::Button {
+                text: tr(language, name).into(),
+            });
+            schema.push(Schema::Label);
+
+            init_state.push(State::Button { clicked: false });
+            init_state.push(self.label(idx, bindings, language));
+        }
+
+        gui.add(io, tr(language, Key::Controls), schema, init_state)
+    }
+
+    /// Remove or rebuild the panel, showing the current keys. Hiding it stops waiting for a
+    /// key.
+    pub fn set_visible(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        visible: bool,
+        bindings: &KeyBindings,
+        language: Language,
+    ) {
+        match (visible, self.element) {
+            (true, None) => self.element = Some(self.add(io, gui, bindings, language)),
+            (false, Some(handle)) => {
+                gui.delete(io, handle);
+                self.element = None;
+                self.capturing = None;
+            }
+            _ => (),
+        }
+    }
+
+    /// Apply button presses, and bind `pressed`, the input used this frame if any, to the
+    /// action waiting for one if it's the right kind. Expects `gui` to already be downloaded.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        bindings: &mut KeyBindings,
+        pressed: Option<BindableInput>,
+        language: Language,
+    ) {
+        let Some(element) = self.element else { return };
+
+        let clicked: Vec<bool> = gui
+            .read(element)
+            .iter()
+            .map(|state| *state == State::Button { clicked: true })
+            .collect();
+
+        let before = self.capturing;
+        if let (Some(idx), Some(input)) = (self.capturing, pressed) {
+            if bind(idx, bindings, input) {
+                self.capturing = None;
+            }
+        }
+        // Pressing the button again leaves the key as it was
+        for idx in 0..ACTIONS.len() {
+            if clicked[idx * ELEMENTS_PER_ACTION] {
+                self.capturing = (before != Some(idx)).then_some(idx);
+            }
+        }
+
+        if self.capturing != before {
+            gui.modify(io, element, |ui_state| {
+                for idx in 0..ACTIONS.len() {
+                    ui_state[idx * ELEMENTS_PER_ACTION + 1] = self.label(idx, bindings, language);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Pads on the track which kick a ship forward as it passes over them. They're laid out in the
+//! path OBJ, as an object of their own holding four corner vertices for each pad.
+use cimvr_common::{
+    glam::Vec3,
+    render::{Mesh, MeshHandle, Primitive, Render, UploadMesh, Vertex},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{kinematics::KinematicPhysics, obj::mesh_quads, ShipCharacteristics};
+
+/// Name of the path OBJ's object holding the pads
+pub const BOOST_OBJECT: &str = "Boost";
+
+/// Impulse a pad gives the ship, so that a heavier ship gains less speed (Newton-seconds)
+const PAD_IMPULSE: f32 = 15_000.;
+/// Time after a pad fires before another can (seconds)
+const PAD_COOLDOWN: f32 = 1.;
+/// Height a pad is drawn above the track, so that it isn't lost in the track's own lines
+/// (meters)
+const PAD_LIFT: f32 = 0.1;
+const PAD_COLOR: [f32; 3] = [1., 0.55, 0.];
+
+/// A pad on the track, which fires for a ship passing within `radius` of its center
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoostPad {
+    pub transform: Transform,
+    pub radius: f32,
+}
+
+/// Pads from each quad of a mesh, as read from `BOOST_OBJECT`
+pub fn pads_from_mesh(mesh: &Mesh) -> Vec<BoostPad> {
+    mesh_quads(mesh)
+        .into_iter()
+        .map(|(transform, radius)| BoostPad { transform, radius })
+        .collect()
+}
+
+/// Kick the ship at `tf` forward if it's over any of `pads` and `cooldown` has run out, which
+/// it then starts again. Returns whether a pad fired.
+pub fn boost(
+    pads: &[BoostPad],
+    ship: &ShipCharacteristics,
+    dt: f32,
+    tf: &Transform,
+    kt: &mut KinematicPhysics,
+    cooldown: &mut f32,
+) -> bool {
+    *cooldown = (*cooldown - dt).max(0.);
+    if *cooldown > 0. {
+        return false;
+    }
+    let over = pads
+        .iter()
+        .any(|pad| pad.transform.pos.distance(tf.pos) < pad.radius);
+    if !over {
+        return false;
+    }
+
+    // The local ship's own mass is nominal, so go by its class
+    kt.vel += tf.orient * Vec3::X * PAD_IMPULSE / ship.mass;
+    *cooldown = PAD_COOLDOWN;
+    true
+}
+
+/// Outline of each pad, with a chevron pointing along its first edge
+fn pads_mesh(pads: &[BoostPad]) -> Mesh {
+    let mut mesh = Mesh::new();
+    for pad in pads {
+        let size = pad.radius / 2_f32.sqrt();
+        let corners = [
+            Vec3::new(-size, PAD_LIFT, -size),
+            Vec3::new(size, PAD_LIFT, -size),
+            Vec3::new(size, PAD_LIFT, size),
+            Vec3::new(-size, PAD_LIFT, size),
+            Vec3::new(-size / 2., PAD_LIFT, -size / 2.),
+            Vec3::new(size / 2., PAD_LIFT, 0.),
+            Vec3::new(-size / 2., PAD_LIFT, size / 2.),
+        ];
+
+        let base = mesh.vertices.len() as u32;
+        for corner in corners {
+            let pos = pad.transform.pos + pad.transform.orient * corner;
+            mesh.vertices.push(Vertex::new(pos.into(), PAD_COLOR));
+        }
+        for (a, b) in [(0, 1), (1, 2), (2, 3), (3, 0), (4, 5), (5, 6)] {
+            mesh.indices.extend([base + a, base + b]);
+        }
+    }
+    mesh
+}
+
+/// The pads' entity on the client
+pub struct BoostPadMarkings {
+    entity: Option<EntityId>,
+}
+
+impl BoostPadMarkings {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("BoostPads"));
+
+    pub fn new(io: &mut EngineIo, pads: &[BoostPad]) -> Self {
+        if pads.is_empty() {
+            return Self { entity: None };
+        }
+        io.send(&UploadMesh {
+            mesh: pads_mesh(pads),
+            id: Self::RDR_ID,
+        });
+
+        let entity = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(Render::new(Self::RDR_ID).primitive(Primitive::Lines))
+            .build();
+        Self {
+            entity: Some(entity),
+        }
+    }
+
+    pub fn teardown(self, io: &mut EngineIo) {
+        if let Some(entity) = self.entity {
+            io.remove_entity(entity);
+        }
+    }
+}
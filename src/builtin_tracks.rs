@@ -0,0 +1,67 @@
+//! The tracks built into the plugin, which the server takes in turns between races
+use crate::track::TrackFiles;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BuiltinTrack {
+    /// The original course, raced on when the server starts
+    #[default]
+    Loop1,
+    Loop2,
+    /// Generated rather than modelled: a long loop with pylons and rails
+    Loop3,
+}
+
+impl BuiltinTrack {
+    pub const ALL: [BuiltinTrack; 3] = [
+        BuiltinTrack::Loop1,
+        BuiltinTrack::Loop2,
+        BuiltinTrack::Loop3,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinTrack::Loop1 => "Loop 1",
+            BuiltinTrack::Loop2 => "Loop 2",
+            BuiltinTrack::Loop3 => "Loop 3",
+        }
+    }
+
+    /// Identifies the track in `TrackSelect` and `StartRace`
+    pub fn id(self) -> u32 {
+        Self::ALL.iter().position(|&track| track == self).unwrap() as u32
+    }
+
+    pub fn from_id(id: u32) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    /// The track raced after this one
+    pub fn next(self) -> Self {
+        Self::ALL[(self.id() as usize + 1) % Self::ALL.len()]
+    }
+
+    /// OBJ text of the control points
+    pub fn path_obj(self) -> &'static str {
+        match self {
+            BuiltinTrack::Loop1 => include_str!("assets/loop1_path.obj"),
+            BuiltinTrack::Loop2 => include_str!("assets/loop2_path.obj"),
+            BuiltinTrack::Loop3 => include_str!("assets/loop3_path.obj"),
+        }
+    }
+
+    /// OBJ text of the scenery
+    pub fn environment_obj(self) -> &'static str {
+        match self {
+            BuiltinTrack::Loop1 => include_str!("assets/loop1_env.obj"),
+            BuiltinTrack::Loop2 => include_str!("assets/loop2_env.obj"),
+            BuiltinTrack::Loop3 => include_str!("assets/loop3_env.obj"),
+        }
+    }
+
+    pub fn files(self) -> TrackFiles {
+        TrackFiles {
+            path: self.path_obj().to_string(),
+            environment: self.environment_obj().to_string(),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use cimvr_common::{
+    desktop::InputEvent,
+    glam::{EulerRot, Quat, Vec3},
+    render::CameraComponent,
+    utils::camera::Perspective,
+    vr::VrUpdate,
+    Transform,
+};
+use cimvr_engine_interface::prelude::*;
+
+use crate::{
+    camera_walls::CameraWalls,
+    fade::TeleportFade,
+    hud::HudVisibility,
+    race_session::RacePhase,
+    sanitize::{transform_is_finite, Sanitizer},
+    trackside::TracksideCameras,
+    ServerShipComponent, TeleportEvent,
+};
+
+/// Gap kept between the chase camera and a wall it's been pulled in front of (meters)
+const WALL_MARGIN: f32 = 1.;
+/// Closest the chase camera is pulled in to the ship (meters)
+const MIN_CHASE_DISTANCE: f32 = 3.;
+/// Rate the chase camera closes in on a wall (per second)
+const PULL_IN_RATE: f32 = 20.;
+/// Rate the chase camera backs out once the wall has passed (per second)
+const EASE_OUT_RATE: f32 = 3.;
+
+/// The camera entity, and where it should be looking
+pub struct CameraRig {
+    proj: Perspective,
+    camera_ent: EntityId,
+    fade: TeleportFade,
+    /// Camera transform as of the last frame
+    camera_tf: Transform,
+    /// Lagging camera transform used in cinematic mode
+    camera_smoothed: Option<Transform>,
+    /// Distance of the chase camera behind the ship, once walls are in the way
+    chase_distance: Option<f32>,
+    /// Whether the last frame was through the finish line camera
+    photo: bool,
+}
+
+impl CameraRig {
+    pub fn new(io: &mut EngineIo) -> Self {
+        let camera_ent = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(CameraComponent::default())
+            .build();
+
+        Self {
+            proj: Perspective::new(),
+            camera_ent,
+            fade: TeleportFade::new(io),
+            camera_tf: Transform::identity(),
+            camera_smoothed: None,
+            chase_distance: None,
+            photo: false,
+        }
+    }
+
+    /// Camera transform as of the last frame
+    pub fn transform(&self) -> Transform {
+        self.camera_tf
+    }
+
+    /// Drop the cinematic camera's lag, e.g. after the ship has been moved
+    pub fn cut(&mut self) {
+        self.camera_smoothed = None;
+        self.chase_distance = None;
+    }
+
+    /// Place the camera for this frame. Spectators watch from the `trackside` cameras if given;
+    /// otherwise the camera chases the ship, keeping inside the track's `walls`. A `photo`
+    /// finish camera overrides both while it's on, as does a `free_fly` camera, which is only
+    /// given while spectating.
+    /// Expects "ClientShip" and "ServerShips" queries.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        query: &mut QueryResult,
+        phase: RacePhase,
+        watching: Option<ClientId>,
+        trackside: Option<&mut TracksideCameras>,
+        free_fly: Option<Transform>,
+        photo: Option<Transform>,
+        walls: &CameraWalls,
+        hud: HudVisibility,
+        clear_color: [f32; 3],
+        delta: f32,
+        sanitizer: &mut Sanitizer,
+    ) -> Transform {
+        // Perspective matrix stuff
+        for event in io.inbox::<InputEvent>() {
+            self.proj.handle_event(&event);
+        }
+
+        let is_vr = io.inbox_first::<VrUpdate>().is_some();
+
+        if let Some(update) = io.inbox_first::<VrUpdate>() {
+            self.proj.handle_vr_update(&update);
+        }
+
+        let projection = self.proj.matrices();
+        self.proj.fov = 79_f32.to_radians();
+
+        io.add_component(
+            self.camera_ent,
+            CameraComponent {
+                clear_color,
+                projection,
+            },
+        );
+
+        // Cut to the finish line, then ease back as usual
+        if photo.is_some() && !self.photo {
+            self.cut();
+        }
+        self.photo = photo.is_some();
+
+        let camera_tf = match (photo.or(free_fly), phase) {
+            (Some(camera_tf), _) => camera_tf,
+            (None, RacePhase::Racing { .. } | RacePhase::CoolDown { .. }) => {
+                let ship = Self::own_ship(query);
+                self.chase(ship, walls, is_vr, delta)
+            }
+            (None, RacePhase::Spectating) => {
+                let watched = Self::watched(query, watching);
+                match trackside {
+                    Some(cameras) => {
+                        let (camera_tf, cut) = cameras.view(watched.pos);
+                        if cut {
+                            self.cut();
+                        }
+                        camera_tf
+                    }
+                    None => self.chase(watched, walls, is_vr, delta),
+                }
+            }
+        };
+
+        let camera_tf = hud.camera(&mut self.camera_smoothed, camera_tf, delta);
+
+        // Hold still rather than pass on a bad view, and start the smoothing over
+        if !transform_is_finite(&camera_tf) {
+            self.cut();
+        }
+        let camera_tf = sanitizer.camera(camera_tf, self.camera_tf);
+
+        io.add_component(self.camera_ent, camera_tf);
+        self.camera_tf = camera_tf;
+        camera_tf
+    }
+
+    /// Handle teleports, hidden behind a fade if enabled.
+    /// Returns where the ship should be snapped to, if anywhere.
+    pub fn teleports(
+        &mut self,
+        io: &mut EngineIo,
+        fade_enabled: bool,
+        delta: f32,
+    ) -> Vec<Transform> {
+        let is_vr = io.inbox_first::<VrUpdate>().is_some();
+
+        let mut snaps = vec![];
+        for TeleportEvent { to } in io.inbox::<TeleportEvent>().collect::<Vec<_>>() {
+            self.camera_smoothed = None;
+            if fade_enabled {
+                self.fade.begin(io, to, is_vr);
+            } else if let Some(tf) = to {
+                snaps.push(tf);
+            }
+        }
+
+        snaps.extend(self.fade.update(io, self.camera_tf, delta, is_vr));
+        snaps
+    }
+
+    /// Transform of the ship we're watching
+    fn watched(query: &mut QueryResult, watching: Option<ClientId>) -> Transform {
+        let mut pos = Transform::default();
+        for entity in query.iter("ServerShips") {
+            let shipc = query.read::<ServerShipComponent>(entity);
+            let tf = query.read::<Transform>(entity);
+            if Some(shipc.client_id) == watching {
+                pos = tf;
+            }
+        }
+        pos
+    }
+
+    fn offset(is_vr: bool) -> Transform {
+        if is_vr {
+            Transform::new()
+                .with_rotation(Quat::from_euler(EulerRot::XYZ, 0., PI, 0.))
+                .with_position(Vec3::new(0., -0.6, 0.))
+        } else {
+            Transform::new()
+                .with_rotation(Quat::from_euler(EulerRot::XYZ, 0., -FRAC_PI_2, 0.))
+                .with_position(Vec3::new(-13., 2., 0.))
+        }
+    }
+
+    /// Transform of the local ship
+    fn own_ship(query: &mut QueryResult) -> Transform {
+        match query.iter("ClientShip").next() {
+            Some(ship_ent) => query.read(ship_ent),
+            None => Transform::new(),
+        }
+    }
+
+    /// Camera behind `ship`, pulled in to stay on this side of any wall between them
+    fn chase(&mut self, ship: Transform, walls: &CameraWalls, is_vr: bool, dt: f32) -> Transform {
+        let camera_tf = ship * Self::offset(is_vr);
+        if is_vr {
+            // The VR camera sits in the cockpit
+            self.chase_distance = None;
+            return camera_tf;
+        }
+
+        let offset = camera_tf.pos - ship.pos;
+        let wanted = offset.length();
+        let clear = walls
+            .obstruction(ship.pos, camera_tf.pos)
+            .map_or(wanted, |t| {
+                (t * wanted - WALL_MARGIN).max(MIN_CHASE_DISTANCE)
+            });
+
+        // Close in quickly so the wall is never seen through, and ease back out
+        let current = self.chase_distance.unwrap_or(clear);
+        let rate = match clear < current {
+            true => PULL_IN_RATE,
+            false => EASE_OUT_RATE,
+        };
+        let distance = current + (clear - current) * (dt * rate).min(1.);
+        self.chase_distance = Some(distance);
+
+        camera_tf.with_position(ship.pos + offset.normalize_or_zero() * distance)
+    }
+}
@@ -0,0 +1,177 @@
+//! The track's walls, for keeping the chase camera on the inside of them
+use std::collections::{HashMap, HashSet};
+
+use cimvr_common::glam::Vec3;
+
+use crate::{
+    controls::{TRACK_HEIGHT, TRACK_WIDTH},
+    curve::Curve,
+};
+
+/// Size of the cells the walls are bucketed into (meters)
+const CELL_SIZE: f32 = 16.;
+
+type Cell = (i32, i32, i32);
+
+/// The walls either side of the track, as triangles bucketed into a coarse grid, so that
+/// the camera only tests the few near it
+pub struct CameraWalls {
+    triangles: Vec<[Vec3; 3]>,
+    cells: HashMap<Cell, Vec<usize>>,
+}
+
+impl CameraWalls {
+    /// Stand a wall on each edge of the track, as tall as the space ships may fly in
+    pub fn new(path: &Curve) -> Self {
+        let n = path.ctrlps.len();
+        let corner = |idx: usize, side: f32, up: f32| {
+            let tf = path.ctrlps[idx % n];
+            tf.pos + tf.orient * Vec3::new(0., up * TRACK_HEIGHT / 2., side * TRACK_WIDTH / 2.)
+        };
+
+        let mut triangles = vec![];
+        for idx in 0..n {
+            for side in [-1., 1.] {
+                let [a, b] = [idx, idx + 1].map(|i| corner(i, side, -1.));
+                let [c, d] = [idx, idx + 1].map(|i| corner(i, side, 1.));
+                triangles.push([a, b, c]);
+                triangles.push([b, d, c]);
+            }
+        }
+
+        Self::from_triangles(triangles)
+    }
+
+    fn from_triangles(triangles: Vec<[Vec3; 3]>) -> Self {
+        let mut cells: HashMap<Cell, Vec<usize>> = HashMap::new();
+        for (idx, tri) in triangles.iter().enumerate() {
+            let min = tri[0].min(tri[1]).min(tri[2]);
+            let max = tri[0].max(tri[1]).max(tri[2]);
+            for cell in cells_between(min, max) {
+                cells.entry(cell).or_default().push(idx);
+            }
+        }
+
+        Self { triangles, cells }
+    }
+
+    /// Fraction of the way from `from` to `to` at which the segment first meets a wall, if it
+    /// does
+    pub fn obstruction(&self, from: Vec3, to: Vec3) -> Option<f32> {
+        let mut tested = HashSet::new();
+        cells_between(from.min(to), from.max(to))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter(|&&idx| tested.insert(idx))
+            .filter_map(|&idx| segment_triangle(from, to, self.triangles[idx]))
+            .min_by(|a, b| a.total_cmp(b))
+    }
+}
+
+/// Every grid cell overlapping the box from `min` to `max`
+fn cells_between(min: Vec3, max: Vec3) -> impl Iterator<Item = Cell> {
+    let min = (min / CELL_SIZE).floor().as_ivec3();
+    let max = (max / CELL_SIZE).floor().as_ivec3();
+    (min.x..=max.x).flat_map(move |x| {
+        (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| (x, y, z)))
+    })
+}
+
+/// Fraction of the way from `from` to `to` at which the segment crosses `tri`, if it does
+fn segment_triangle(from: Vec3, to: Vec3, tri: [Vec3; 3]) -> Option<f32> {
+    let dir = to - from;
+    let edge1 = tri[1] - tri[0];
+    let edge2 = tri[2] - tri[0];
+
+    let p = dir.cross(edge2);
+    let det = edge1.dot(p);
+    if det.abs() < f32::EPSILON {
+        // Parallel to the triangle
+        return None;
+    }
+
+    let s = from - tri[0];
+    let u = s.dot(p) / det;
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = dir.dot(q) / det;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+
+    let t = edge2.dot(q) / det;
+    (0. ..=1.).contains(&t).then_some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::long_straight;
+
+    /// Somewhere along `long_straight`'s straight, where the walls are at z = ±16
+    const X: f32 = 205.;
+    const WALL: f32 = TRACK_WIDTH / 2.;
+
+    fn at(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3::new(x, y, z)
+    }
+
+    fn close(hit: Option<f32>, expected: f32) -> bool {
+        hit.map_or(false, |t| (t - expected).abs() < 1e-4)
+    }
+
+    #[test]
+    fn segments_down_the_corridor_are_clear() {
+        let walls = CameraWalls::new(&long_straight());
+        let inside = WALL - 1.;
+        for (from, to) in [
+            (at(X, 0., 0.), at(X + 100., 0., 0.)),
+            (at(X, -4., -inside), at(X + 60., 4., inside)),
+            (at(X, 0., inside), at(X, 0., -inside)),
+        ] {
+            assert_eq!(walls.obstruction(from, to), None, "{:?} to {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn segments_out_through_a_wall_stop_at_it() {
+        let walls = CameraWalls::new(&long_straight());
+        let center = at(X, 0., 0.);
+        let hit = walls.obstruction(center, at(X, 0., 2. * WALL));
+        assert!(close(hit, 0.5), "{:?}", hit);
+        let hit = walls.obstruction(center, at(X, 2., -1.5 * WALL));
+        assert!(close(hit, 2. / 3.), "{:?}", hit);
+
+        // The nearer wall, when going through both
+        let (from, to) = (at(X, 0., -30.), at(X, 0., 30.));
+        let hit = walls.obstruction(from, to);
+        assert!(close(hit, (30. - WALL) / 60.), "{:?}", hit);
+        let hit = walls.obstruction(to, from);
+        assert!(close(hit, (30. - WALL) / 60.), "{:?}", hit);
+    }
+
+    #[test]
+    fn long_segments_find_walls_cells_away() {
+        // Forty meters across over many cells along, reaching the wall two fifths of the way
+        let walls = CameraWalls::new(&long_straight());
+        let hit = walls.obstruction(at(X - 100., 0., 0.), at(X + 100., 0., 2.5 * WALL));
+        assert!(close(hit, 0.4), "{:?}", hit);
+    }
+
+    #[test]
+    fn segments_over_the_walls_or_outside_them_are_clear() {
+        let walls = CameraWalls::new(&long_straight());
+        let above = TRACK_HEIGHT / 2. + 1.;
+        let misses = [
+            (at(X, above, 0.), at(X, above, 2. * WALL)),
+            (at(X, 0., WALL + 2.), at(X + 100., 0., WALL + 2.)),
+            (at(X, 0., WALL + 2.), at(X, 0., WALL + 10.)),
+        ];
+        for (from, to) in misses {
+            assert_eq!(walls.obstruction(from, to), None, "{:?} to {:?}", from, to);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+
+use chat::{ChatDownload, ChatUpload};
+use cimvr_common::ui::{Schema, State, UiHandle, UiStateHelper};
+use cimvr_engine_interface::prelude::*;
+
+/// Number of chat lines kept and shown
+const SCROLLBACK: usize = 12;
+
+/// Messages which can be sent with a single button press
+const QUICK_CHAT: &[&str] = &["gg", "ready?", "one more"];
+
+/// Username the server uses for its announcements
+const SERVER_NAME: &str = "Server";
+
+/// Recent chat, and buttons for replying to it
+pub struct ChatPanel {
+    lines: VecDeque<String>,
+    /// Removed while hidden
+    element: Option<UiHandle>,
+    /// Whether lines have arrived since the panel was last updated
+    changed: bool,
+}
+
+impl ChatPanel {
+    pub fn new() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            element: None,
+            changed: false,
+        }
+    }
+
+    /// Add a message to the scrollback. The server's own messages are skipped, since they also
+    /// arrive as announcements which we can show in the player's language.
+    pub fn push(&mut self, ChatDownload { username, text }: ChatDownload) {
+        if username == SERVER_NAME {
+            return;
+        }
+        for line in text.lines() {
+            self.push_line(format!("{}: {}", username, line));
+        }
+    }
+
+    /// Add an announcement from the server, already in the player's language
+    pub fn push_announcement(&mut self, text: &str) {
+        for line in text.lines() {
+            self.push_line(format!("[Race] {}", line));
+        }
+    }
+
+    /// Add a line, dropping the oldest lines once the scrollback is full
+    fn push_line(&mut self, line: String) {
+        self.lines.push_back(line);
+        while self.lines.len() > SCROLLBACK {
+            self.lines.pop_front();
+        }
+        self.changed = true;
+    }
+
+    /// Always the same number of rows, so the panel keeps its size as chat fills up
+    fn rows(&self) -> impl Iterator<Item = State> + '_ {
+        let padding = SCROLLBACK - self.lines.len();
+        std::iter::repeat(String::new())
+            .take(padding)
+            .chain(self.lines.iter().cloned())
+            .map(|text| State::Label { text })
+    }
+
+    fn add(&self, io: &mut EngineIo, gui: &mut UiStateHelper) -> UiHandle {
+        let mut schema = vec![Schema::Label; SCROLLBACK];
+        let mut init_state: Vec<State> = self.rows().collect();
+        for text in QUICK_CHAT {
+            schema.push(Schema::Button {
+                text: text.to_string(),
+            });
+            init_state.push(State::Button { clicked: false });
+        }
+
+        gui.add(io, "Chat", schema, init_state)
+    }
+
+    /// Show or remove the panel, and send any quick chat pressed.
+    /// Expects `gui` to already be downloaded.
+    pub fn update(&mut self, io: &mut EngineIo, gui: &mut UiStateHelper, visible: bool) {
+        match (visible, self.element) {
+            (true, None) => {
+                self.element = Some(self.add(io, gui));
+                self.changed = false;
+            }
+            (false, Some(handle)) => {
+                gui.delete(io, handle);
+                self.element = None;
+            }
+            _ => (),
+        }
+
+        let Some(element) = self.element else { return };
+
+        let clicked: Vec<bool> = gui.read(element)[SCROLLBACK..]
+            .iter()
+            .map(|state| *state == State::Button { clicked: true })
+            .collect();
+        for (text, _) in QUICK_CHAT
+            .iter()
+            .zip(clicked)
+            .filter(|(_, clicked)| *clicked)
+        {
+            io.send(&ChatUpload(text.to_string()));
+        }
+
+        if self.changed {
+            let rows: Vec<State> = self.rows().collect();
+            gui.modify(io, element, |ui_state| {
+                for (state, row) in ui_state.iter_mut().zip(&rows) {
+                    *state = row.clone();
+                }
+            });
+            self.changed = false;
+        }
+    }
+}
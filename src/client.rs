@@ -1,155 +1,216 @@
-use std::f32::consts::{FRAC_PI_2, PI};
-
-use chat::{ChatDownload, ChatUpload};
+use chat::ChatDownload;
 use cimvr_common::{
-    desktop::{InputEvent, KeyCode},
-    gamepad::{Axis, Button, GamepadState},
-    glam::{EulerRot, Quat, Vec3},
-    render::{CameraComponent, MeshHandle, Primitive, Render, UploadMesh},
-    ui::{Schema, State, UiHandle, UiStateHelper, UiUpdate},
-    utils::{camera::Perspective, input_helper::InputHelper},
-    vr::VrUpdate,
-    Transform,
+    desktop::InputEvent, gamepad::GamepadState, glam::Vec3, render::Mesh, ui::UiUpdate,
+    vr::VrUpdate, Transform,
 };
-use cimvr_engine_interface::{dbg, pkg_namespace, prelude::*, FrameTime};
+use cimvr_engine_interface::{prelude::*, println, FrameTime};
 use kinematics::KinematicPhysics;
 
 use crate::{
-    controls::ship_controller,
-    countdown::CountdownAnimation,
-    curve::{path_mesh_to_transforms, Curve},
+    assists::{AutoThrottle, AutoThrottleState},
+    billboards::TrackConfig,
+    builtin_tracks::BuiltinTrack,
+    camera_rig::CameraRig,
+    conditions::Conditions,
+    controls::{ControlEvent, ShipState, TuningParams},
+    countdown::{color_extra, CountdownAnimation, CountdownStyle, GO_TIME},
+    curve::{parse_path, path_obj_to_curve, Curve},
+    engine_sound::{EngineBars, EngineSample, EngineSounds},
+    entity_pool::EntityPool,
+    envelope::{self, LegacyDecodes},
+    ghost::MirrorGhost,
+    ghost_delta::{GhostDelta, RaceDistance},
+    hud::HudVisibility,
+    hud_state::{ready_text, HudState},
+    input_state::InputState,
+    items::Items,
     kinematics,
-    obj::obj_lines_to_mesh,
-    shapes::grid_mesh,
-    ClientReady, ClientShipComponent, Finished, InputAbstraction, ServerShipComponent,
-    ShipCharacteristics, ShipUpload, StartRace, SHIP_RDR,
+    markers::{aimed_t, Markers},
+    minimap::Minimap,
+    nametags::Nametags,
+    net_stats::RemoteShips,
+    obj::{obj_to_mesh, try_obj_to_mesh, ObjElements},
+    photo_finish::PhotoFinishCamera,
+    player_colors::PlayerColor,
+    position_graph::PositionGraph,
+    race_rng::RaceRng,
+    race_rules::{format_race_time, FinishPlane, LapEvent, LapTracker},
+    race_session::{RacePhase, RaceSession},
+    relay::RelayPanel,
+    sanitize::Sanitizer,
+    settings::Quality,
+    ship_class::ShipClass,
+    ship_collision::{self, OtherShip},
+    ship_preview::ShipPreview,
+    ship_shadows::ShipShadows,
+    slipstream::Slipstream,
+    smoothing::RemoteSmoothing,
+    spectator::{SpectateView, SpectatorState},
+    strings::{Key, Text},
+    track::TrackAssembler,
+    track_reveal::{RevealRedraw, TrackReveal},
+    track_scene::TrackScene,
+    track_summary::TrackSummary,
+    wall_ride::{ship_tint, WallRide, WallSparks},
+    wrong_way::WrongWaySign,
+    Announcement, ClientShipComponent, HandicapFactor, Hello, LoadSettings, LobbyStatus, LogDump,
+    MarkerPlaced, NextRace, PhotoFinish, PickupRejected, PickupState, PlaceMarker, PushTrack,
+    RaceAbort, RaceEvent, RaceEvents, RaceOver, RacePositions, RaceProgress, RaceResults,
+    RacerFinished, RacerNames, RecordsSync, RelayStatus, RequestTimeTrial, RequestTrack,
+    ResumeRace, RuleVote, SealedStartRace, ServerShipComponent, ShipCharacteristics, ShipStateAck,
+    SoundCue, StartRace, TeleportEvent, TrackData, TrackSelect, YourLeg, PROTOCOL_VERSION,
 };
 
-// TODO: This is a dumb thing to hardcode lol
-const N_LAPS: usize = 3;
-const ENV_OBJ: &str = include_str!("assets/loop1_env.obj");
-const PATH_OBJ: &str = include_str!("assets/loop1_path.obj");
-
-enum GameMode {
-    Spectator {
-        /// Which player to spectate (if any)
-        watching: Option<ClientId>,
-        /// Whether the player is ready to enter the next race when it starts
-        ready: bool,
-    },
-    Racing {
-        /// ID of this client, used to ascertain
-        client_id: ClientId,
-        /// Lap count
-        lap: usize,
-    },
-}
+#[cfg(feature = "tuning")]
+use crate::dummies::{DummyAction, DummyShips};
 
 // All state associated with client-side behaviour
 pub struct ClientState {
-    mode: GameMode,
-    proj: Perspective,
-    camera_ent: EntityId,
+    session: RaceSession,
+    camera: CameraRig,
+    hud: HudState,
+    input: InputState,
+    spectator: SpectatorState,
     ship_ent: EntityId,
-    countdown: CountdownAnimation,
-    input_helper: InputHelper,
-    input: InputAbstraction,
+    /// Whether the local ship is currently rendered
+    local_ship_shown: bool,
+    scene: TrackScene,
+    /// Conditions the scene and ships are dressed for
+    conditions: Conditions,
+    /// Decoration applied to whichever track is loaded
+    track_config: TrackConfig,
+    /// Ship picker shown in the lobby
+    preview: ShipPreview,
+    /// Connection health of everyone else's ships
+    remote_ships: RemoteShips,
+    /// Markers on the track under each ship
+    shadows: ShipShadows,
+    /// Names above everyone else's ships
+    nametags: Nametags,
+    minimap: Minimap,
+    engine_sounds: EngineSounds,
+    engine_bars: EngineBars,
+    /// Throttle and boost the local ship was last driven with
+    engine_controls: (f32, bool),
+    /// Whether the local ship was touching the wall last frame
+    wall_contact: bool,
+    /// Whether the local ship was touching another ship last frame
+    ship_contact: bool,
+    /// The local ship's hold on the shielded walls
+    wall_ride: WallRide,
+    /// Time before a boost pad can fire again for the local ship (seconds)
+    boost_cooldown: f32,
+    sparks: WallSparks,
+    wrong_way: WrongWaySign,
+    /// Tow from drafting the other racers
+    slipstream: Slipstream,
+    /// Color the server gave our ship
+    own_color: PlayerColor,
+    ghost: MirrorGhost,
+    /// Our distance along the race, for comparing with the ghost
+    race_distance: RaceDistance,
+    ghost_delta: GhostDelta,
+    /// Chart of the last race, until the next one starts
+    position_graph: PositionGraph,
+    /// Finish line camera, while it's showing a finish
+    photo: PhotoFinishCamera,
+    /// Where the camera was last frame
+    camera_tf: Transform,
+    /// Spectators' markers on the track
+    markers: Markers,
+    /// Short-lived entities of the markers, lap ticks and pickups
+    entities: EntityPool,
+    /// Catches bad transforms in the camera and the simulation
+    sanitizer: Sanitizer,
+    /// Enveloped messages from the server which weren't in the current layout
+    legacy_decodes: LegacyDecodes,
+    /// Glides the other ships between their updates
+    smoothing: RemoteSmoothing,
+    /// How much of the track we've driven, for drawing only that in practice
+    reveal: TrackReveal,
+    /// Whether the latest race is ours alone
+    solo_race: bool,
+    /// Our team, in a relay race
+    relay: RelayPanel,
+    /// Track files arriving from the server
+    track_download: TrackAssembler,
+    /// Built-in track loaded, or `None` for one the server sent the files of
+    track: Option<BuiltinTrack>,
+    /// Built-in track the server switched to while we were racing, to load once we're not
+    pending_track: Option<BuiltinTrack>,
+    auto_throttle: AutoThrottle,
     motion_cfg: ShipCharacteristics,
+    tuning: TuningParams,
     path: Curve,
-    last_ship_pos: Transform,
-
-    // TODO: This should all go in another struct
-    gui: UiStateHelper,
-    ready_state_element: UiHandle,
+    items: Items,
+    /// Practice grid of dummy ships
+    #[cfg(feature = "tuning")]
+    dummies: DummyShips,
 }
 
-pub const MAP_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("Map"));
-pub const FLOOR_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("Floor"));
-pub const FINISH_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("FinishLine"));
+const CLEAR_COLOR: [f32; 3] = [0.; 3];
 
-const FINISH_LINE_INDEX: f32 = 10.;
+/// Distance of the countdown behind the finish line, over the grid (meters)
+const COUNTDOWN_BEHIND_FINISH: f32 = 40.;
 
-fn finish_line_pos(curve: &Curve) -> Transform {
-    curve.lerp(FINISH_LINE_INDEX)
+/// Where the countdown plays on the given track
+fn countdown_pos(path: &Curve) -> Transform {
+    let behind_finish = path.clamp_distance(COUNTDOWN_BEHIND_FINISH, 1. / 8.);
+    path.smooth_lerp(path.advance(path.finish_line(), -behind_finish))
 }
 
 impl UserState for ClientState {
     // Implement a constructor
     fn new(io: &mut EngineIo, sched: &mut EngineSchedule<Self>) -> Self {
         // Parse path mesh
-        let path = Curve::new(path_mesh_to_transforms(&obj_lines_to_mesh(PATH_OBJ)));
-
-        // Add environment
-        io.create_entity()
-            .add_component(Transform::identity())
-            .add_component(Render::new(MAP_RDR).primitive(Primitive::Lines))
-            .build();
-
-        // Add finish line
-        io.create_entity()
-            .add_component(finish_line_pos(&path))
-            .add_component(Render::new(FINISH_RDR).primitive(Primitive::Lines))
-            .build();
-
-        // Add floor
-        io.create_entity()
-            .add_component(Transform::new().with_position(Vec3::new(0., -50., 0.)))
-            .add_component(Render::new(FLOOR_RDR).primitive(Primitive::Lines))
-            .build();
+        let track = BuiltinTrack::default();
+        let path = path_obj_to_curve(track.path_obj());
+
+        // Add environment, finish line and floor
+        let lap_tracker = LapTracker::new(&path);
+        TrackScene::assets(io, CLEAR_COLOR);
+        let track_config = TrackConfig::default();
+        let (environment, elements) = obj_to_mesh(track.environment_obj());
+        let quality = Quality::default();
+        let scene = TrackScene::new(
+            io,
+            &path,
+            &lap_tracker,
+            environment,
+            elements,
+            &track_config,
+            quality,
+        );
 
         //let mesh = obj_lines_to_mesh(include_str!("assets/ship.obj"));
-        let mut environment_mesh = obj_lines_to_mesh(ENV_OBJ);
-        environment_mesh.recolor([0.2, 1., 0.2]);
-        io.send(&UploadMesh {
-            mesh: environment_mesh,
-            id: MAP_RDR,
-        });
-
-        io.send(&UploadMesh {
-            mesh: grid_mesh(20, 20., [0., 0.2, 0.]),
-            id: FLOOR_RDR,
-        });
-
-        let ship_mesh = obj_lines_to_mesh(include_str!("assets/ship.obj"));
-        // Upload ship
-        io.send(&UploadMesh {
-            mesh: ship_mesh,
-            id: SHIP_RDR,
-        });
-
-        let mut finish_line_mesh = obj_lines_to_mesh(include_str!("assets/finish_line.obj"));
-        finish_line_mesh
-            .vertices
-            .iter_mut()
-            .for_each(|v| v.uvw = [1., 0., 0.]);
 
-        // Upload finishline
-        io.send(&UploadMesh {
-            mesh: finish_line_mesh,
-            id: FINISH_RDR,
-        });
+        // Upload ships
+        ShipClass::assets(io);
 
         // Add camera
-        let camera_ent = io
-            .create_entity()
-            .add_component(Transform::identity())
-            .add_component(CameraComponent::default())
-            .build();
+        let camera = CameraRig::new(io);
 
         sched
             .add_system(Self::controller_input)
             .subscribe::<GamepadState>()
             .subscribe::<InputEvent>()
+            .subscribe::<FrameTime>()
             .build();
 
         sched
             .add_system(Self::game_mode)
-            .subscribe::<StartRace>()
+            .subscribe::<TrackSelect>()
+            .subscribe::<SealedStartRace>()
+            .subscribe::<ResumeRace>()
+            .subscribe::<YourLeg>()
+            .subscribe::<RaceOver>()
+            .subscribe::<RaceAbort>()
+            .subscribe::<FrameTime>()
             .build();
 
         sched
             .add_system(Self::deleter)
-            .subscribe::<StartRace>()
+            .subscribe::<SealedStartRace>()
             .query(
                 "AllServerShips",
                 Query::new().intersect::<ServerShipComponent>(Access::Read),
@@ -161,18 +222,23 @@ impl UserState for ClientState {
             .subscribe::<FrameTime>()
             .build();
 
-        sched.add_system(Self::gui).subscribe::<UiUpdate>().subscribe::<VrUpdate>().build();
+        sched
+            .add_system(Self::gui)
+            .subscribe::<UiUpdate>()
+            .subscribe::<VrUpdate>()
+            .subscribe::<ChatDownload>()
+            .subscribe::<Announcement>()
+            .subscribe::<RuleVote>()
+            .build();
 
-        let animation_pos = path.lerp(6.);
-        let mut countdown = CountdownAnimation::new(io, animation_pos);
+        let countdown_style = CountdownStyle::default();
+        let countdown = CountdownAnimation::new(io, countdown_pos(&path), countdown_style);
         CountdownAnimation::assets(io);
 
-        let input_helper = InputHelper::new();
-
         let ship_ent = io
             .create_entity()
             .add_component(Transform::identity())
-            .add_component(Render::new(SHIP_RDR).primitive(Primitive::Lines))
+            .add_component(ShipClass::default().render())
             .add_component(ClientShipComponent)
             .add_component(KinematicPhysics {
                 vel: Vec3::ZERO,
@@ -189,7 +255,25 @@ impl UserState for ClientState {
                 "Kinematics",
                 Query::new()
                     .intersect::<Transform>(Access::Write)
-                    .intersect::<KinematicPhysics>(Access::Write),
+                    .intersect::<KinematicPhysics>(Access::Write)
+                    .intersect::<ServerShipComponent>(Access::Read),
+            )
+            .subscribe::<FrameTime>()
+            .build();
+
+        sched
+            .add_system(Self::slipstream)
+            .query(
+                "ClientShip",
+                Query::new()
+                    .intersect::<Transform>(Access::Read)
+                    .intersect::<ClientShipComponent>(Access::Read),
+            )
+            .query(
+                "ServerShips",
+                Query::new()
+                    .intersect::<Transform>(Access::Read)
+                    .intersect::<ServerShipComponent>(Access::Read),
             )
             .subscribe::<FrameTime>()
             .build();
@@ -206,14 +290,22 @@ impl UserState for ClientState {
             )
             .query(
                 "ServerShips",
-                Query::new().intersect::<ServerShipComponent>(Access::Read),
+                Query::new()
+                    .intersect::<Transform>(Access::Read)
+                    .intersect::<KinematicPhysics>(Access::Read)
+                    .intersect::<ServerShipComponent>(Access::Read),
             )
             .subscribe::<FrameTime>()
+            .subscribe::<ShipStateAck>()
+            .subscribe::<HandicapFactor>()
+            .subscribe::<PickupState>()
+            .subscribe::<PickupRejected>()
             .build();
 
         sched
             .add_system(Self::camera)
             .subscribe::<InputEvent>()
+            .subscribe::<TeleportEvent>()
             .subscribe::<VrUpdate>()
             .subscribe::<FrameTime>()
             .query(
@@ -230,52 +322,179 @@ impl UserState for ClientState {
             )
             .build();
 
+        sched
+            .add_system(Self::lap_indicators)
+            .subscribe::<RaceProgress>()
+            .query(
+                "ServerShips",
+                Query::new()
+                    .intersect::<Transform>(Access::Read)
+                    .intersect::<ServerShipComponent>(Access::Read),
+            )
+            .build();
+
+        sched
+            .add_system(Self::track_sync)
+            .subscribe::<RequestTrack>()
+            .subscribe::<TrackData>()
+            .build();
+
+        sched
+            .add_system(Self::handshake)
+            .subscribe::<Hello>()
+            .build();
+
+        sched
+            .add_system(Self::log_dump)
+            .subscribe::<LogDump>()
+            .build();
+
+        sched
+            .add_system(Self::markers)
+            .subscribe::<MarkerPlaced>()
+            .subscribe::<RaceEvents>()
+            .subscribe::<FrameTime>()
+            .build();
+
+        sched
+            .add_system(Self::settings_sync)
+            .subscribe::<LoadSettings>()
+            .subscribe::<FrameTime>()
+            .build();
+
+        sched
+            .add_system(Self::track_reveal)
+            .subscribe::<FrameTime>()
+            .subscribe::<RacerNames>()
+            .query(
+                "ClientShip",
+                Query::new()
+                    .intersect::<Transform>(Access::Read)
+                    .intersect::<ClientShipComponent>(Access::Read),
+            )
+            .build();
+
+        sched
+            .add_system(Self::position_graph)
+            .subscribe::<RaceResults>()
+            .subscribe::<RacerNames>()
+            .build();
+
+        sched
+            .add_system(Self::photo_finish)
+            .subscribe::<RacerFinished>()
+            .subscribe::<PhotoFinish>()
+            .build();
+
+        sched
+            .add_system(Self::relay)
+            .subscribe::<RelayStatus>()
+            .subscribe::<RacerNames>()
+            .subscribe::<RaceOver>()
+            .build();
+
+        sched
+            .add_system(Self::net_stats)
+            .query(
+                "RemoteShips",
+                Query::new()
+                    .intersect::<Transform>(Access::Read)
+                    .intersect::<ServerShipComponent>(Access::Read)
+                    .intersect::<KinematicPhysics>(Access::Write),
+            )
+            .query(
+                "ClientShip",
+                Query::new()
+                    .intersect::<Transform>(Access::Read)
+                    .intersect::<KinematicPhysics>(Access::Read)
+                    .intersect::<ClientShipComponent>(Access::Read),
+            )
+            .subscribe::<LobbyStatus>()
+            .subscribe::<FrameTime>()
+            .build();
+
+        sched
+            .add_system(Self::engine_bars)
+            .subscribe::<SoundCue>()
+            .subscribe::<FrameTime>()
+            .build();
+
+        sched
+            .add_system(Self::race_feed)
+            .subscribe::<RaceProgress>()
+            .subscribe::<RaceResults>()
+            .subscribe::<RacePositions>()
+            .subscribe::<RacerFinished>()
+            .subscribe::<RacerNames>()
+            .subscribe::<RaceOver>()
+            .subscribe::<LobbyStatus>()
+            .subscribe::<NextRace>()
+            .subscribe::<RecordsSync>()
+            .subscribe::<FrameTime>()
+            .build();
+
         // For editing ui: sends schema implicitly
         io.create_entity()
             .add_component(ServerShipComponent::default())
             .build();
 
         // Define ship capabilities
-        let motion_cfg = ShipCharacteristics {
-            mass: 1000.,
-            moment: 1000. * 3_f32.powi(2),
-            max_twirl: 5.,
-            max_impulse: 30.,
-        };
+        let motion_cfg = ShipClass::default().characteristics();
 
-        let mut gui = UiStateHelper::new();
-        let schema = vec![
-            Schema::Button {
-                text: "Toggle Ready".into(),
-            },
-            Schema::Label,
-        ];
-        let init_state = vec![
-            State::Button { clicked: false },
-            State::Label {
-                text: "(Not ready)".into(),
-            },
-        ];
-        let ready_state_element = gui.add(io, "FZ", schema, init_state);
-
-        let mode = GameMode::Spectator {
-            watching: None,
-            ready: false,
-        };
+        let hud = HudState::new(io);
+        let tuning = TuningParams::default();
 
         Self {
-            mode,
+            session: RaceSession::new(countdown, lap_tracker),
+            camera,
+            hud,
+            input: InputState::new(),
+            spectator: SpectatorState::new(),
+            preview: ShipPreview::new(io, ShipClass::default()),
+            remote_ships: RemoteShips::new(),
+            shadows: ShipShadows::new(io),
+            nametags: Nametags::new(),
+            minimap: Minimap::new(io, &path),
+            engine_sounds: EngineSounds::new(),
+            engine_bars: EngineBars::new(io),
+            engine_controls: (0., false),
+            wall_contact: false,
+            ship_contact: false,
+            wall_ride: WallRide::default(),
+            boost_cooldown: 0.,
+            sparks: WallSparks::new(io),
+            wrong_way: WrongWaySign::new(io),
+            slipstream: Slipstream::new(),
+            own_color: PlayerColor::default(),
+            ghost: MirrorGhost::new(io),
+            race_distance: RaceDistance::new(),
+            ghost_delta: GhostDelta::new(),
+            position_graph: PositionGraph::new(),
+            photo: PhotoFinishCamera::new(io),
+            camera_tf: Transform::identity(),
+            markers: Markers::new(io),
+            entities: EntityPool::new(),
+            sanitizer: Sanitizer::new(),
+            legacy_decodes: LegacyDecodes::new(),
+            smoothing: RemoteSmoothing::new(),
+            reveal: TrackReveal::new(path.ctrlps.len()),
+            solo_race: false,
+            relay: RelayPanel::new(),
+            ship_ent,
+            local_ship_shown: true,
+            scene,
+            conditions: Conditions::Clear,
+            track_config,
+            track_download: TrackAssembler::new(),
+            track: Some(track),
+            pending_track: None,
+            auto_throttle: AutoThrottle::new(),
             motion_cfg,
-            input: InputAbstraction::default(),
+            tuning,
             path,
-            proj: Perspective::new(),
-            input_helper,
-            countdown,
-            camera_ent,
-            ship_ent,
-            gui,
-            last_ship_pos: Transform::default(),
-            ready_state_element,
+            items: Items::new(io),
+            #[cfg(feature = "tuning")]
+            dummies: DummyShips::new(),
         }
     }
 }
@@ -284,261 +503,895 @@ impl ClientState {
     fn gui(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
         let is_vr = io.inbox_first::<VrUpdate>().is_some();
 
-        // Toggle ready state based on UI interaction
-        if let GameMode::Spectator { ready, .. } = &mut self.mode {
-            self.gui.download(io);
-            let mut clicked =
-                self.gui.read(self.ready_state_element)[0] != (State::Button { clicked: false });
-
-            clicked |= !*ready && is_vr;
+        self.hud.download(io);
 
-            if clicked {
-                *ready = !*ready;
+        #[cfg(feature = "tuning")]
+        match self
+            .hud
+            .update_dev_panels(io, &mut self.motion_cfg, &mut self.tuning)
+        {
+            // Laid out ahead of wherever we're looking from, which is just behind the ship
+            Some(DummyAction::Spawn(placements)) => {
+                let from = self.camera_tf.pos;
+                self.dummies.spawn(io, &self.path, from, &placements);
             }
+            Some(DummyAction::Clear) => self.dummies.clear(io),
+            None => (),
+        }
 
-            let ready_text = match ready {
-                true => "Ready!".to_string(),
-                false => "(Not ready)".to_string(),
-            };
+        let ready = self.session.ready();
+        let pressed = self.input.pressed();
+        if let Some(ready) = self.hud.update_panels(io, ready, is_vr, pressed) {
+            self.session
+                .set_ready(io, ready, ready_text(self.hud.language(), ready));
+        }
+        if self.hud.time_trial_pressed() {
+            io.send(&RequestTimeTrial);
+        }
 
-            self.gui.modify(io, self.ready_state_element, |ui_state| {
-                let text = ready_text.clone();
-                ui_state[1] = State::Label { text };
-            });
+        let quality = self.hud.settings().quality;
+        if quality != self.scene.quality() {
+            self.scene.set_quality(io, &self.path, quality, CLEAR_COLOR);
+        }
 
-            if clicked {
-                io.send(&ClientReady(*ready));
-                io.send(&ChatUpload(ready_text));
-            }
+        let in_lobby = self.session.phase() == RacePhase::Spectating && self.hud.show_hud();
+        let step = self.input.cycle_ship();
+        if let Some(class) = self
+            .preview
+            .update_panel(io, &mut self.hud.gui, in_lobby, step)
+        {
+            self.set_class(io, class);
         }
+
+        self.hud.update_chat(io, self.session.phase());
+        self.hud.update_votes(io, self.session.phase());
     }
 
     fn deleter(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
-        if let GameMode::Racing { client_id, .. } = self.mode {
-            for ship_entity in query.iter("AllServerShips") {
-                let ServerShipComponent {
-                    client_id: ships_id,
-                    is_racing,
-                    ..
-                } = query.read(ship_entity);
-
-                if ships_id == client_id || !is_racing {
-                    io.remove_entity(ship_entity);
+        let Some(client_id) = self.session.phase().client_id() else {
+            return;
+        };
+        for ship_entity in query.iter("AllServerShips") {
+            let ServerShipComponent {
+                client_id: ships_id,
+                is_racing,
+                ..
+            } = query.read(ship_entity);
+
+            if ships_id == client_id || !is_racing {
+                io.remove_entity(ship_entity);
+            }
+        }
+    }
+
+    fn lap_indicators(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
+        let phase = self.session.phase();
+
+        if let Some(progress) = io.inbox_first::<RaceProgress>() {
+            // Spectators see the gates as the racer they're watching does
+            if phase == RacePhase::Spectating {
+                match self.spectator.watched_checkpoints(&progress) {
+                    Some(passed) => self.scene.gates.mirror(passed),
+                    None => self.scene.gates.reset(),
                 }
             }
+            self.hud.set_progress(progress);
         }
+
+        let lap = self.session.progress();
+        let camera_pos = self.camera.transform().pos;
+        self.hud
+            .update_lap_indicators(io, &mut self.entities, query, phase, lap, camera_pos);
+    }
+
+    fn slipstream(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
+        let Some(time) = io.inbox_first::<FrameTime>() else {
+            return;
+        };
+        let Some(entity) = query.iter("ClientShip").next() else {
+            return;
+        };
+        let tf: Transform = query.read(entity);
+
+        let own_id = self.session.phase().client_id();
+        let others: Vec<Vec3> = query
+            .iter("ServerShips")
+            .filter_map(|entity| {
+                let shipc = query.read::<ServerShipComponent>(entity);
+                let racing = shipc.is_racing && !shipc.is_lagging;
+                (racing && Some(shipc.client_id) != own_id)
+                    .then(|| query.read::<Transform>(entity).pos)
+            })
+            .collect();
+
+        // Not until "GO", nor where the server simulates us, since it knows nothing of the tow
+        let racing = self.session.phase().is_racing();
+        let started = self.session.should_be_moving(time) && !self.session.pacing(time);
+        let active = racing && started && !self.session.predicting();
+        self.slipstream.update(tf, others, time.delta, active);
+    }
+
+    fn net_stats(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
+        let Some(FrameTime { time, .. }) = io.inbox_first() else {
+            return;
+        };
+        let own_id = self.session.phase().client_id();
+        self.remote_ships
+            .update(io, query, "RemoteShips", own_id, time);
+
+        // The local ship is drawn in the color the server gave us
+        let own_color = query
+            .iter("RemoteShips")
+            .map(|entity| query.read::<ServerShipComponent>(entity))
+            .find(|shipc| Some(shipc.client_id) == own_id)
+            .map(|shipc| shipc.color);
+        if let Some(color) = own_color.filter(|&color| color != self.own_color) {
+            self.own_color = color;
+            let brightness = self.conditions.ship_brightness();
+            let tint = ship_tint(self.wall_ride, color.rgb(), brightness);
+            io.add_component(self.ship_ent, color_extra(self.slipstream.tint(tint)));
+        }
+
+        // Our own server copy lags behind, so mark under the local ship instead
+        let mut ships: Vec<(ClientId, Transform)> = query
+            .iter("RemoteShips")
+            .map(|entity| (query.read::<ServerShipComponent>(entity), entity))
+            .filter(|(shipc, _)| Some(shipc.client_id) != own_id)
+            .map(|(shipc, entity)| (shipc.client_id, query.read(entity)))
+            .collect();
+
+        if let Some(LobbyStatus(entries)) = io.inbox_first() {
+            self.nametags.set_names(&entries);
+        }
+        let camera_tf = self.camera.transform();
+        let visible = self.hud.show_hud();
+        // Without our own, which would only be in the way while we race
+        self.nametags
+            .update(io, ships.iter().copied(), camera_tf, visible);
+
+        let local_ship = query.iter("ClientShip").next();
+        let mut dots: Vec<(ClientId, Vec3)> = ships.iter().map(|&(id, tf)| (id, tf.pos)).collect();
+        if let (Some(own_id), Some(entity)) = (own_id, local_ship) {
+            dots.push((own_id, query.read::<Transform>(entity).pos));
+        }
+        let racing = self.session.phase().is_racing();
+        self.minimap
+            .update(io, dots, own_id, camera_tf, visible && racing);
+
+        if let (Some(own_id), Some(entity)) = (own_id, local_ship) {
+            if self.local_ship_shown {
+                ships.push((own_id, query.read(entity)));
+            }
+        }
+        self.shadows
+            .update(io, &self.path, ships, camera_tf.pos, visible);
+
+        let mut engines: Vec<EngineSample> = query
+            .iter("RemoteShips")
+            .map(|entity| (query.read::<ServerShipComponent>(entity), entity))
+            .filter(|(shipc, _)| Some(shipc.client_id) != own_id)
+            .map(|(shipc, entity)| EngineSample {
+                client: Some(shipc.client_id),
+                pos: query.read::<Transform>(entity).pos,
+                vel: query.read::<KinematicPhysics>(entity).vel,
+                controls: None,
+            })
+            .collect();
+        if let Some(entity) = local_ship.filter(|_| self.local_ship_shown) {
+            engines.push(EngineSample {
+                client: None,
+                pos: query.read::<Transform>(entity).pos,
+                vel: query.read::<KinematicPhysics>(entity).vel,
+                controls: Some(self.engine_controls),
+            });
+        }
+        self.engine_sounds.update(io, engines, camera_tf.pos, time);
+    }
+
+    /// Show what the engine sounds are being told, with the telemetry
+    fn engine_bars(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let Some(FrameTime { time, .. }) = io.inbox_first() else {
+            return;
+        };
+        let cues: Vec<SoundCue> = io.inbox().collect();
+        let visible = self.hud.settings().telemetry && self.hud.show_hud();
+        self.engine_bars.update(io, &cues, visible, time);
+    }
+
+    fn race_feed(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let (time, delta) = io
+            .inbox_first::<FrameTime>()
+            .map_or((0., 0.), |t| (t.time, t.delta));
+        let watching = self.spectator.watching();
+        self.hud
+            .race_feed(io, self.session.phase(), watching, time, delta);
     }
 
     fn animation(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
-        let Some(time) = io.inbox_first::<FrameTime>() else { return };
-        self.countdown.update(io, time);
+        let Some(time) = io.inbox_first::<FrameTime>() else {
+            return;
+        };
+        let show_hud = self.hud.show_hud();
+        let camera_tf = self.camera.transform();
+        self.session.animate(io, time, show_hud, camera_tf);
+
+        let palette = self.hud.palette();
+        self.scene
+            .gates
+            .update(io, &mut self.hud.gui, time.delta, show_hud, palette);
+        self.scene.billboards.update(io, time);
+
+        // Spectators go by the server's clock, as there's no countdown of our own
+        let clock = match self.session.phase().is_driving() {
+            true => self.session.barrier_clock(time),
+            false => self.hud.race_time(time.time).map_or(0., |t| t - GO_TIME),
+        };
+        self.scene.barriers.update(io, &self.path, clock);
     }
 
     fn camera(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
-        // Perspective matrix stuff
-        for event in io.inbox::<InputEvent>() {
-            self.proj.handle_event(&event);
+        let phase = self.session.phase();
+        self.spectator
+            .update(query, phase, self.input.cycle_watched());
+
+        let (time, delta) = io
+            .inbox_first::<FrameTime>()
+            .map_or((0., 0.), |t| (t.time, t.delta));
+        let visibility = self.hud.visibility();
+        let watching = self.spectator.watching();
+        let trackside =
+            (self.spectator.view() == SpectateView::Trackside).then_some(&mut self.scene.trackside);
+        let free_fly = match phase {
+            RacePhase::Spectating => self
+                .spectator
+                .fly(&self.input.input(), self.camera_tf, delta),
+            _ => None,
+        };
+        let photo = self.photo.update(io, query, phase.client_id(), delta);
+        let camera_tf = self.camera.update(
+            io,
+            query,
+            phase,
+            watching,
+            trackside,
+            free_fly,
+            photo,
+            &self.scene.walls,
+            visibility,
+            CLEAR_COLOR,
+            delta,
+            &mut self.sanitizer,
+        );
+        self.scene.follow_floor(io, camera_tf);
+        self.camera_tf = camera_tf;
+
+        let in_lobby = phase == RacePhase::Spectating && self.hud.show_hud();
+        self.preview.update(io, camera_tf, time, in_lobby);
+
+        let fade = self.hud.settings().teleport_fade;
+        for tf in self.camera.teleports(io, fade, delta) {
+            self.snap_ship(io, tf);
         }
 
-        let is_vr = io.inbox_first::<VrUpdate>().is_some();
+        // Cinematic mode hides the local ship
+        let show_ship = visibility.show_local_ship();
+        if show_ship != self.local_ship_shown {
+            let render = self.session.class().render();
+            io.add_component(self.ship_ent, HudVisibility::render(show_ship, render));
+            self.local_ship_shown = show_ship;
+        }
+    }
+
+    /// Race with a different ship from now on
+    fn set_class(&mut self, io: &mut EngineIo, class: ShipClass) {
+        self.session.set_class(io, class);
+        self.motion_cfg = class.characteristics();
+        let render = HudVisibility::render(self.local_ship_shown, class.render());
+        io.add_component(self.ship_ent, render);
+    }
 
-        if let Some(update) = io.inbox_first::<VrUpdate>() {
-            self.proj.handle_vr_update(&update);
+    /// Dress the track and ships for a race's conditions
+    fn set_conditions(&mut self, io: &mut EngineIo, conditions: Conditions) {
+        if conditions == self.conditions {
+            return;
         }
+        self.conditions = conditions;
+        self.scene
+            .set_conditions(io, &self.path, conditions, CLEAR_COLOR);
+
+        let brightness = conditions.ship_brightness();
+        let tint = ship_tint(self.wall_ride, self.own_color.rgb(), brightness);
+        io.add_component(self.ship_ent, color_extra(self.slipstream.tint(tint)));
+        self.remote_ships.set_brightness(brightness);
+    }
 
-        let projection = self.proj.matrices();
-        self.proj.fov = 79_f32.to_radians();
-        let clear_color = [0.; 3];
+    /// Move the local ship instantly, without counting it as driving there
+    fn snap_ship(&mut self, io: &mut EngineIo, tf: Transform) {
+        io.add_component(self.ship_ent, tf);
+        self.session.snap(tf.pos);
+        self.wrong_way.reset();
+        self.scene.gates.reset();
+        self.camera.cut();
+    }
 
-        io.add_component(
-            self.camera_ent,
-            CameraComponent {
-                clear_color,
-                projection,
-            },
-        );
+    /// Upload our track files when the server asks, and switch to the server's track once
+    /// it has all arrived
+    fn track_sync(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        if io.inbox_first::<RequestTrack>().is_some() {
+            let files = self.track.unwrap_or_default().files();
+            for chunk in files.chunks() {
+                io.send(&PushTrack(chunk));
+            }
+        }
+
+        let mut complete = None;
+        for TrackData(chunk) in io.inbox::<TrackData>().collect::<Vec<_>>() {
+            complete = self.track_download.push(chunk).or(complete);
+        }
+        let Some(files) = complete else { return };
 
-        let camera_tf = match &mut self.mode {
-            GameMode::Racing { .. } => Self::camera_trail_behind(query, is_vr),
-            GameMode::Spectator { watching, .. } => Self::camera_spectate(query, watching, is_vr),
+        // The server has already checked these
+        let path = match parse_path(&files.path) {
+            Ok(path) => path,
+            Err(e) => return println!("Bad track from server: {}", e),
+        };
+        let (environment, elements) = match try_obj_to_mesh(&files.environment) {
+            Ok(environment) => environment,
+            Err(e) => return println!("Bad environment from server: {}", e),
         };
+        self.track = None;
+        self.pending_track = None;
+        self.load_track(io, path, environment, elements);
+    }
 
-        io.add_component(self.camera_ent, camera_tf);
+    /// Switch to a built-in track
+    fn load_builtin_track(&mut self, io: &mut EngineIo, track: BuiltinTrack) {
+        if self.track == Some(track) {
+            return;
+        }
+        println!("Loading {}", track.name());
+        let path = path_obj_to_curve(track.path_obj());
+        let (environment, elements) = obj_to_mesh(track.environment_obj());
+        self.track = Some(track);
+        self.load_track(io, path, environment, elements);
     }
 
-    fn camera_spectate(query: &mut QueryResult, watching: &mut Option<ClientId>, is_vr: bool) -> Transform {
-        // Find someone to watch
-        if watching.is_none() {
-            for entity in query.iter("ServerShips") {
-                let shipc = query.read::<ServerShipComponent>(entity);
-                //if shipc.is_racing {
-                *watching = Some(shipc.client_id);
-                //}
-            }
+    /// Replace everything derived from the old track, and go back to the lobby
+    fn load_track(
+        &mut self,
+        io: &mut EngineIo,
+        path: Curve,
+        environment: Mesh,
+        elements: ObjElements,
+    ) {
+        self.session.set_track(&path, countdown_pos(&path));
+        self.minimap.set_track(io, &path);
+        let tracker = self.session.lap_tracker();
+        let config = &self.track_config;
+        let quality = self.hud.settings().quality;
+        let new_scene = TrackScene::new(io, &path, tracker, environment, elements, config, quality);
+        let old_scene = std::mem::replace(&mut self.scene, new_scene);
+        old_scene.teardown(io, &mut self.hud.gui);
+        self.markers.clear(io, &mut self.entities);
+        self.items.clear(io, &mut self.entities);
+        self.ghost.clear();
+        self.hud.clear_lap_indicators(io, &mut self.entities);
+        self.shadows.clear(io);
+        self.position_graph.hide(io);
+        self.reveal.reset(path.ctrlps.len());
+        self.path = path;
+        self.set_conditions(io, Conditions::Clear);
+        self.hud.show_track_summary(TrackSummary::new(&self.path));
+
+        self.spectator.reset();
+        io.send(&TeleportEvent {
+            to: Some(self.path.lerp(self.path.finish_line())),
+        });
+    }
+
+    /// In races of our own, draw only as much of the track as we've driven in full
+    fn track_reveal(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
+        let Some(FrameTime { time, .. }) = io.inbox_first() else {
+            return;
+        };
+        if let Some(RacerNames(names)) = io.inbox_first() {
+            self.solo_race = names.len() == 1;
         }
 
-        // Watch from their ship's perspective
-        let mut pos = Transform::default();
-        for entity in query.iter("ServerShips") {
-            let shipc = query.read::<ServerShipComponent>(entity);
-            let tf = query.read::<Transform>(entity);
-            if Some(shipc.client_id) == *watching {
-                pos = tf;
+        let phase = self.session.phase();
+        let active = self.hud.settings().track_reveal && phase.is_driving() && self.solo_race;
+        if active {
+            if let Some(ship_ent) = query.iter("ClientShip").next() {
+                let tf: Transform = query.read(ship_ent);
+                self.reveal.explore(self.path.nearest_ctrlp(tf.pos));
             }
         }
 
-        pos * Self::cam_offset(is_vr)
+        let visible = active && self.hud.show_hud();
+        let language = self.hud.language();
+        if self
+            .reveal
+            .update_panel(io, &mut self.hud.gui, language, visible)
+        {
+            self.reveal.reset(self.path.ctrlps.len());
+        }
+
+        let explored = match self.reveal.redraw(active, time) {
+            Some(RevealRedraw::Explored(explored)) => Some(explored),
+            Some(RevealRedraw::Everything) => None,
+            None => return,
+        };
+        self.scene
+            .set_explored(io, &self.path, explored, CLEAR_COLOR);
     }
 
-    fn cam_offset(is_vr: bool) -> Transform {
-        if is_vr {
-            Transform::new()
-                .with_rotation(Quat::from_euler(EulerRot::XYZ, 0., PI, 0.))
-                .with_position(Vec3::new(0., -0.6, 0.))
-        } else {
-            Transform::new()
-                .with_rotation(Quat::from_euler(EulerRot::XYZ, 0., -FRAC_PI_2, 0.))
-                .with_position(Vec3::new(-13., 2., 0.))
+    /// Chart each race once it's over, and take the chart down when the next begins
+    fn position_graph(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        if io.inbox_first::<RacerNames>().is_some() {
+            self.position_graph.hide(io);
+        }
+        if let Some(results) = io.inbox_first::<RaceResults>() {
+            self.position_graph.show(io, &self.path, &results);
         }
     }
 
-    fn camera_trail_behind(query: &mut QueryResult, is_vr: bool) -> Transform {
-        // Set camera pos
-        if let Some(ship_ent) = query.iter("ClientShip").next() {
-            let ship_transf: Transform = query.read(ship_ent);
+    /// Look at finishes from the finish line: every one while spectating, but only our own
+    /// while racing, so that it never gets in the way of someone still going
+    fn photo_finish(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        for photo in io.inbox::<PhotoFinish>().collect::<Vec<_>>() {
+            self.photo.trigger(io, &photo);
+        }
 
-            ship_transf * Self::cam_offset(is_vr)
-        } else {
-            Transform::new()
+        if !self.hud.settings().photo_finish {
+            return;
+        }
+        let phase = self.session.phase();
+        for finish in io.inbox::<RacerFinished>().collect::<Vec<_>>() {
+            let own = Some(finish.client_id) == phase.client_id();
+            if !own && phase != RacePhase::Spectating {
+                continue;
+            }
+            io.send(&PhotoFinish {
+                client_id: finish.client_id,
+                username: finish.username.clone(),
+                transform: FinishPlane::new(&self.path, self.path.finish_line()).transform,
+                time: finish.time,
+            });
+            let text = Text::new(Key::RacerFinished)
+                .with(&finish.username)
+                .with(finish.position)
+                .with(format_race_time(finish.time));
+            self.hud.announce(&text);
         }
     }
 
-    fn controller_input(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
-        self.input = InputAbstraction::default();
-
-        if let Some(GamepadState(gamepads)) = io.inbox_first() {
-            if let Some(gamepad) = gamepads.into_iter().next() {
-                self.input.yaw = gamepad.axes[&Axis::RightStickX];
-                self.input.pitch = gamepad.axes[&Axis::LeftStickY];
-                self.input.roll = gamepad.axes[&Axis::LeftStickX];
-                self.input.throttle = gamepad.axes[&Axis::RightStickY];
-                if gamepad.buttons[&Button::RightTrigger2] {
-                    self.input.throttle = 1.;
-                }
-                if gamepad.buttons[&Button::LeftTrigger2] {
-                    self.input.throttle = -1.;
-                }
+    /// Keep up with our relay team, watching whoever is out on their leg while we aren't
+    fn relay(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let new_race = io.inbox_first::<RacerNames>().is_some();
+        let race_over = io.inbox_first::<RaceOver>().is_some();
+        // Runners who handed off went straight to spectating, without a cool down to end
+        let spectating = self.session.phase() == RacePhase::Spectating;
+        if race_over && spectating && self.relay.in_relay() {
+            self.spectator.reset();
+            self.set_conditions(io, Conditions::Clear);
+        }
+        if new_race || race_over {
+            self.relay.clear();
+        }
+        if let Some(status) = io.inbox_first::<RelayStatus>() {
+            self.relay.set_status(status);
+        }
+
+        if self.session.phase() == RacePhase::Spectating {
+            if let Some(teammate) = self.relay.teammate_running() {
+                self.spectator.watch(teammate);
             }
         }
 
-        self.input_helper.handle_input_events(io);
+        let visible = self.hud.show_hud();
+        let language = self.hud.language();
+        self.relay.update(io, &mut self.hud.gui, visible, language);
+    }
+
+    /// Answer the server's `Hello` with the protocol we speak
+    fn handshake(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let Some(Hello { protocol }) = io.inbox_first() else {
+            return;
+        };
+        io.send(&Hello {
+            protocol: PROTOCOL_VERSION,
+        });
+        if protocol != PROTOCOL_VERSION {
+            println!(
+                "Server speaks protocol {}, we speak {}",
+                protocol, PROTOCOL_VERSION
+            );
+        }
+    }
+
+    /// Print the server's race log, as asked for with `/log`
+    fn log_dump(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        for dump in io.inbox::<LogDump>() {
+            println!("Race log part {}/{}", dump.part + 1, dump.parts);
+            for line in dump.lines {
+                println!("{}", line);
+            }
+        }
+    }
 
-        if self.input_helper.key_held(KeyCode::W) {
-            self.input.throttle = 1.0;
+    /// Take on the settings the server kept for us, and keep it up to date with our changes
+    fn settings_sync(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let loaded = io.inbox::<LoadSettings>().last();
+        // Saved by another version of the plugin, which may have meant something else by them
+        if let Some(settings) = loaded.and_then(|LoadSettings(saved)| saved.restore()) {
+            self.hud.load_settings(io, settings);
         }
 
-        if self.input_helper.key_held(KeyCode::S) {
-            self.input.throttle = -1.0;
+        let Some(FrameTime { time, .. }) = io.inbox_first() else {
+            return;
+        };
+        self.hud.sync_settings(io, time);
+    }
+
+    fn controller_input(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let Some(FrameTime { time, .. }) = io.inbox_first() else {
+            return;
+        };
+
+        let show_hud = self.hud.show_hud();
+        let bindings = *self.hud.settings().active_bindings();
+        let scheme = self.hud.settings().scheme();
+        self.input
+            .set_mouse_steer(self.hud.settings().mouse_steer());
+        self.input
+            .update(io, &mut self.hud.gui, time, show_hud, &bindings, scheme);
+
+        if self.input.toggle_hud() {
+            self.hud.toggle();
+        }
+        if self.input.cycle_view() && self.session.phase() == RacePhase::Spectating {
+            self.spectator.cycle_view();
+            self.scene.trackside.reset();
         }
 
-        if self.input_helper.key_held(KeyCode::A) {
-            self.input.roll = -1.0;
+        // Point out a part of the track to whoever's watching, from a trackside camera
+        let trackside = self.spectator.view() == SpectateView::Trackside;
+        if self.input.drop_marker() && self.session.phase() == RacePhase::Spectating && trackside {
+            if let Some(curve_t) = aimed_t(&self.path, self.camera_tf) {
+                io.send(&PlaceMarker { curve_t });
+            }
         }
+    }
 
-        if self.input_helper.key_held(KeyCode::D) {
-            self.input.roll = 1.0;
+    /// Show everyone's markers until they expire, clearing them all when a race starts
+    fn markers(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let Some(FrameTime { time, .. }) = io.inbox_first() else {
+            return;
+        };
+        let started = io
+            .inbox::<RaceEvents>()
+            .any(|events| events.event == RaceEvent::Started);
+        if started {
+            self.markers.clear(io, &mut self.entities);
         }
+        for marker in io.inbox::<MarkerPlaced>().collect::<Vec<_>>() {
+            self.markers
+                .place(io, &mut self.entities, &self.path, &marker, time);
+        }
+        self.markers.update(io, &mut self.entities, time);
     }
 
     fn game_mode(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
-        if let Some(StartRace {
-            client_id,
-            position,
-        }) = io.inbox_first()
-        {
-            self.mode = GameMode::Racing { client_id, lap: 0 };
+        let time = io.inbox_first::<FrameTime>();
+        let now = time.map_or(0., |t| t.time);
+
+        // Finished racers stop driving once the race is over
+        let race_over = io.inbox_first::<RaceOver>().is_some();
+        if let Some(time) = time {
+            if self
+                .session
+                .update_cool_down(io, &self.path, time, race_over)
+            {
+                self.spectator.reset();
+                self.set_conditions(io, Conditions::Clear);
+            }
+        }
+
+        // The server gave up on the race
+        if let Some(abort) = io.inbox_first::<RaceAbort>() {
+            if self.session.abort(io, &self.path, abort) {
+                self.spectator.reset();
+                self.set_conditions(io, Conditions::Clear);
+            }
+        }
 
-            self.countdown.restart();
+        // Never switch tracks under a race; only once we're spectating, or starting the next
+        if let Some(TrackSelect { track_id }) = io.inbox_first() {
+            self.pending_track = BuiltinTrack::from_id(track_id);
+        }
+        let start = envelope::inbox::<StartRace>(io, &mut self.legacy_decodes)
+            .into_iter()
+            .next();
+        if let Some(track) = start
+            .and_then(|s| s.track_id)
+            .and_then(BuiltinTrack::from_id)
+        {
+            self.pending_track = Some(track);
+        }
+        let spectating = self.session.phase() == RacePhase::Spectating;
+        if spectating || start.is_some() {
+            if let Some(track) = self.pending_track.take() {
+                self.load_builtin_track(io, track);
+            }
+        }
 
-            // Reset ship position
-            io.add_component(self.ship_ent, position);
-            self.last_ship_pos = position;
+        let mut started = None;
+        if let Some(start) = start {
+            #[cfg(feature = "tuning")]
+            self.dummies.clear(io);
+            started = self.session.start(io, &self.path, start);
+        }
+        if let Some(resume) = io.inbox_first::<ResumeRace>() {
+            started = Some(self.session.resume(io, resume, now));
         }
+        if let Some(leg) = io.inbox_first::<YourLeg>() {
+            started = Some(self.session.take_leg(io, leg, now));
+            self.hud.announce(&Text::new(Key::RelayYourLeg));
+        }
+        let Some(settings) = started else { return };
+
+        self.preview.hide(io);
+        self.ghost.start(self.session.class());
+        self.race_distance.reset();
+        self.ghost_delta.reset();
+        self.auto_throttle.reset();
+        self.wall_ride = WallRide::default();
+        self.boost_cooldown = 0.;
+        self.slipstream.reset();
+        let brightness = self.conditions.ship_brightness();
+        let tint = ship_tint(self.wall_ride, self.own_color.rgb(), brightness);
+        io.add_component(self.ship_ent, color_extra(tint));
+        self.hud.clear_lap_indicators(io, &mut self.entities);
+        self.set_conditions(io, settings.conditions);
+
+        // Lay out this race's pickups
+        let rng = RaceRng::new(settings.seed);
+        self.items.start(io, &mut self.entities, &self.path, &rng);
     }
 
     fn motion_update(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
         let Some(time) = io.inbox_first() else { return };
         let FrameTime { delta, .. } = time;
 
-        let is_racing = matches!(self.mode, GameMode::Racing { .. });
-        let match_started = self.countdown.match_started(time);
-        let should_be_moving = is_racing && match_started;
+        let is_racing = self.session.phase().is_racing();
+        let should_be_moving = self.session.should_be_moving(time);
+        // Behind the pace line, the throttle and items wait for the green flag
+        let pacing = self.session.pacing(time);
 
-        let Some(ship_ent) = query.iter("ClientShip").next() else { return };
+        let Some(ship_ent) = query.iter("ClientShip").next() else {
+            return;
+        };
 
         // Get current physical properties
-        let mut kt: KinematicPhysics = query.read(ship_ent);
-        let mut tf: Transform = query.read(ship_ent);
+        let mut state = ShipState {
+            tf: query.read(ship_ent),
+            kt: query.read(ship_ent),
+            wall_ride: self.wall_ride,
+            boost_cooldown: self.boost_cooldown,
+        };
         //let ShipComponent(client_id) = query.read(ship_ent);
 
-        // Step ship forward in time
+        let mut input = self.input.input();
+        let mut auto_throttle = AutoThrottleState::Off;
+        if should_be_moving && !pacing && self.hud.settings().auto_throttle() {
+            auto_throttle =
+                self.auto_throttle
+                    .apply(delta, &self.path, &self.tuning, &state.tf, &mut input);
+            self.session.used_auto_throttle();
+        }
+        if should_be_moving && self.hud.settings().preset().is_some() {
+            self.session.used_one_handed();
+        }
         if should_be_moving {
-            ship_controller(
-                delta,
-                self.motion_cfg,
-                self.input,
-                &self.path,
-                &mut tf,
-                &mut kt,
-            );
-        } else {
-            kt.vel = Vec3::ZERO;
-            kt.ang_vel = Vec3::ZERO;
+            self.session.used_scheme(input.scheme);
+        }
+        let show_hud = self.hud.show_hud();
+        self.auto_throttle
+            .show_state(io, &mut self.hud.gui, auto_throttle, show_hud);
+
+        // Items
+        for pickup in io.inbox::<PickupState>() {
+            self.items.apply_state(pickup);
+        }
+        for PickupRejected { pickup_id } in io.inbox::<PickupRejected>() {
+            self.items.reject(pickup_id);
+        }
+        let race_time = self.session.race_time(time);
+        if should_be_moving && !pacing {
+            if self.input.use_item() {
+                match is_racing {
+                    true => self.items.use_item(),
+                    // Boost is free once the race is done
+                    false => self.items.free_boost(),
+                }
+            }
+            input.boost = self.items.boosting(delta);
         }
 
-        io.send(&ShipUpload(tf, kt));
+        #[cfg(feature = "tuning")]
+        self.dummies.update(io, delta);
+
+        // Slipstream the other racers, the ghost, and any dummies. Only where we're trusted
+        // with our own position, since the server knows nothing of them.
+        let mut ship = self.motion_cfg;
+        if !self.session.predicting() {
+            ship.max_impulse *= self.slipstream.factor();
+            ship.max_impulse *= self.ghost.draft_factor(state.tf.pos);
+            #[cfg(feature = "tuning")]
+            {
+                ship.max_impulse *= self.dummies.draft_factor(state.tf.pos);
+            }
+        }
 
-        query.write(ship_ent, &kt);
-        query.write(ship_ent, &tf);
+        // Back to where we'd be put after leaving the track, on request
+        let respawn_pressed = self.input.respawn() && should_be_moving;
+        if respawn_pressed && self.session.respawn(&self.path, &mut state) {
+            io.send(&TeleportEvent { to: None });
+        }
 
-        // Check if we've crossed the finish line
-        let area_sanity_check =
-            (self.path.nearest_ctrlp(tf.pos) as i32 - FINISH_LINE_INDEX as i32).abs() < 3;
-        let finish_line = finish_line_pos(&self.path);
-        let cross_over = (finish_line.inverse() * self.last_ship_pos).pos.x < 0.
-            && (finish_line.inverse() * tf).pos.x > 0.;
-        if area_sanity_check && cross_over {
-            if let GameMode::Racing { lap, .. } = &mut self.mode {
-                if *lap != 9 {
-                    let time = self.countdown.elapsed(time);
-                    let minutes = (time / 60.).floor();
-                    let seconds = (time % 60.).floor();
-                    let milliseconds = ((time % 60.).fract() * 1000.).floor();
-                    io.send(&ChatUpload(format!(
-                        "Lap {lap}, time: {minutes}:{seconds}:{milliseconds}"
-                    )))
-                }
+        // Step ship forward in time
+        self.engine_controls = match should_be_moving {
+            true => (input.throttle, input.boost),
+            false => (0., false),
+        };
+        let control_events = self.session.drive(
+            io,
+            ship,
+            self.tuning,
+            &self.path,
+            &self.track_config,
+            input,
+            time,
+            &mut state,
+        );
+        let ShipState {
+            mut tf,
+            mut kt,
+            wall_ride,
+            boost_cooldown,
+        } = state;
+        self.boost_cooldown = boost_cooldown;
+
+        // Bump into the other racers, after the controller has held us to the track. Not
+        // where the server simulates us, since it knows nothing of collisions.
+        let mut ship_contact = false;
+        if should_be_moving && !self.session.predicting() {
+            let own_id = self.session.phase().client_id();
+            let others: Vec<OtherShip> = query
+                .iter("ServerShips")
+                .filter_map(|entity| {
+                    let shipc = query.read::<ServerShipComponent>(entity);
+                    let racing = shipc.is_racing && !shipc.is_lagging;
+                    (racing && Some(shipc.client_id) != own_id).then(|| OtherShip {
+                        pos: query.read::<Transform>(entity).pos,
+                        vel: query.read::<KinematicPhysics>(entity).vel,
+                        mass: shipc.class.characteristics().mass,
+                    })
+                })
+                .collect();
+            let radius = self.tuning.ship_radius;
+            ship_contact = ship_collision::collide(&self.path, radius, &mut tf, &mut kt, &others);
+        }
+        if ship_contact && !self.ship_contact {
+            io.send(&SoundCue::Collision { pos: tf.pos });
+        }
+        self.ship_contact = ship_contact;
+
+        // Lit up in the shield's color while riding a wall, and tinted by any tow
+        let riding_changed = wall_ride.side.is_some() != self.wall_ride.side.is_some();
+        if riding_changed || self.slipstream.tint_changed() {
+            let brightness = self.conditions.ship_brightness();
+            let tint = ship_tint(wall_ride, self.own_color.rgb(), brightness);
+            io.add_component(self.ship_ent, color_extra(self.slipstream.tint(tint)));
+        }
+        self.wall_ride = wall_ride;
+        self.sparks.update(io, tf, wall_ride);
+        for text in self.session.take_announcements() {
+            self.hud.announce(&text);
+        }
 
-                *lap += 1;
+        query.write(ship_ent, &kt);
+        query.write(ship_ent, &self.session.drawn(tf));
 
-                // We've finisehd the whole race!
-                if *lap > N_LAPS {
-                    io.send(&Finished(self.countdown.elapsed(time)));
+        if should_be_moving && is_racing {
+            if let Some(claim) = self.items.collect(&self.path, tf.pos, race_time) {
+                io.send(&claim);
+            }
+        }
+        self.items
+            .update(io, &mut self.hud.gui, race_time, show_hud && is_racing);
+
+        let mut lines = vec![
+            format!("Speed: {:.1} m/s", kt.vel.length()),
+            format!("Catch-up: x{:.3}", self.session.catch_up()),
+            format!("Pooled entities: {}", self.entities.live()),
+            format!(
+                "Non-finite recoveries: {}",
+                self.sanitizer.total() + self.session.sanitizer().total()
+            ),
+            format!(
+                "Old-layout messages: {} read, {} dropped",
+                self.legacy_decodes.legacy, self.legacy_decodes.unreadable
+            ),
+        ];
+        lines.extend(self.remote_ships.telemetry_lines(time.time));
+        self.hud.update_telemetry(io, lines);
+        let driving = self.session.phase().is_driving();
+        let lap_lines = self.session.lap_lines(self.hud.language());
+        self.hud.update_laps(io, driving, lap_lines);
+
+        // The controller put us back on the track
+        if control_events.contains(&ControlEvent::Reset) {
+            io.send(&TeleportEvent { to: None });
+        }
+        // One bang per hit, not one per frame spent scraping along the wall
+        let wall_contact = control_events.contains(&ControlEvent::WallContact);
+        if wall_contact && !self.wall_contact {
+            io.send(&SoundCue::Collision { pos: tf.pos });
+        }
+        self.wall_contact = wall_contact;
 
-                    self.mode = GameMode::Spectator {
-                        watching: None,
-                        ready: false,
-                    };
-                }
+        // Check if we've crossed the finish line
+        let language = self.hud.language();
+        let (events, finished) = self
+            .session
+            .track_laps(io, &self.path, tf.pos, time, language);
+        self.scene
+            .gates
+            .handle_events(io, &mut self.hud.gui, &events);
+        for event in &events {
+            if let LapEvent::Lap(lap) = event {
+                io.send(&SoundCue::Lap(*lap));
             }
         }
+        self.wrong_way
+            .update(&self.path, tf.pos, delta, should_be_moving && is_racing);
+        let camera_tf = self.camera.transform();
+        self.wrong_way.draw(io, tf, camera_tf, show_hud);
+        // Record the whole run, so it can be raced next time
+        let lap = self.session.lap_tracker().lap();
+        let distance = self.race_distance.update(&self.path, tf.pos, lap);
+        if finished {
+            self.scene.gates.reset();
+            // Only clean runs are worth chasing
+            if self.session.laps_valid() {
+                self.ghost.finish(race_time, tf, distance);
+            }
+        } else if should_be_moving && is_racing {
+            self.ghost.record(race_time, tf, distance);
+        }
+        let ghost_visible = self.hud.settings().mirror_ghost && self.session.phase().is_driving();
+        self.ghost.update(io, race_time, ghost_visible);
 
-        self.last_ship_pos = tf;
+        // How far ahead of or behind the ghost we are, at the same point of the race
+        let ghost_delta = match should_be_moving && is_racing {
+            true => self.ghost.time_at_distance(distance).map(|t| race_time - t),
+            false => None,
+        };
+        let steady = self.race_distance.steady(kt.vel.length());
+        let visible = ghost_visible && show_hud;
+        self.ghost_delta
+            .update(io, &mut self.hud.gui, ghost_delta, steady, delta, visible);
     }
 
-    /// Simulate kinematics
+    /// Move the other ships along between their updates; the session integrates our own as it
+    /// drives
     fn kinematics_update(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
-        let Some(FrameTime { delta, .. }) = io.inbox_first() else { return };
-        kinematics::simulate(query, delta);
+        let Some(FrameTime { time, .. }) = io.inbox_first() else {
+            return;
+        };
+        let own_id = self.session.phase().client_id();
+        self.smoothing.update(query, "Kinematics", own_id, time);
+        self.sanitizer.simulated(query, &self.path);
     }
 }
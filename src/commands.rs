@@ -0,0 +1,89 @@
+#[cfg(feature = "dev-tracks")]
+use crate::dev_tracks::DevTrack;
+use crate::{conditions::Conditions, rule_votes::Proposal};
+
+/// Chat commands understood by the server
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// `/authoritative on|off`
+    Authoritative(bool),
+    /// `/catchup on|off`, or `/rubberband on|off`
+    CatchUp(bool),
+    /// `/catchup <percent>`
+    CatchUpStrength(f32),
+    /// `/reloadtrack`
+    ReloadTrack,
+    /// `/resettime <seconds>`
+    ResetTime(f32),
+    /// `/maxspeed <m/s>`
+    MaxSpeed(f32),
+    /// `/laps <n>`
+    Laps(usize),
+    /// `/log`
+    Log,
+    /// `/diag`
+    Diagnostics,
+    /// `/bindings` to show our saved bindings as text, or `/bindings <text>` to restore them
+    Bindings(Option<String>),
+    /// `/devtrack circle|eight|hairpin|climb|minimal`
+    #[cfg(feature = "dev-tracks")]
+    DevTrack(DevTrack),
+    /// `/propose laps <n>`,
+    /// `/propose catchup|authoritative|invalidate|seeded|rolling|relay on|off`,
+    /// or `/propose conditions clear|storm|night`
+    Propose(Proposal),
+}
+
+/// Parse a line of chat into a command, if it is one
+pub fn parse_command(text: &str) -> Option<Command> {
+    let mut words = text.split_whitespace();
+    let command = words.next()?;
+    let arg = words.next();
+
+    if command == "/propose" {
+        let value = words.next()?;
+        return parse_proposal(arg?, value).map(Command::Propose);
+    }
+
+    match (command, arg) {
+        ("/reloadtrack", None) => Some(Command::ReloadTrack),
+        ("/log", None) => Some(Command::Log),
+        ("/diag", None) => Some(Command::Diagnostics),
+        ("/bindings", arg) => Some(Command::Bindings(arg.map(str::to_string))),
+        #[cfg(feature = "dev-tracks")]
+        ("/devtrack", Some(arg)) => DevTrack::from_name(arg).map(Command::DevTrack),
+        ("/resettime", Some(arg)) => arg.parse().ok().map(Command::ResetTime),
+        ("/maxspeed", Some(arg)) => arg.parse().ok().map(Command::MaxSpeed),
+        ("/laps", Some(arg)) => arg.parse().ok().map(Command::Laps),
+        ("/authoritative", Some(arg)) => parse_on_off(arg).map(Command::Authoritative),
+        ("/catchup" | "/rubberband", Some(arg)) => {
+            parse_on_off(arg).map(Command::CatchUp).or_else(|| {
+                let percent: f32 = arg.trim_end_matches('%').parse().ok()?;
+                Some(Command::CatchUpStrength(percent / 100.))
+            })
+        }
+        _ => None,
+    }
+}
+
+fn parse_proposal(rule: &str, value: &str) -> Option<Proposal> {
+    match rule {
+        "laps" => value.parse().ok().map(Proposal::Laps),
+        "catchup" => parse_on_off(value).map(Proposal::CatchUp),
+        "authoritative" => parse_on_off(value).map(Proposal::Authoritative),
+        "invalidate" => parse_on_off(value).map(Proposal::InvalidateLaps),
+        "conditions" => Conditions::from_name(value).map(Proposal::Conditions),
+        "seeded" => parse_on_off(value).map(Proposal::SeedByRating),
+        "rolling" => parse_on_off(value).map(Proposal::RollingStart),
+        "relay" => parse_on_off(value).map(Proposal::Relay),
+        _ => None,
+    }
+}
+
+fn parse_on_off(word: &str) -> Option<bool> {
+    match word {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,118 @@
+//! Weather for a race: mostly how the track looks, and a little of how it drives
+use serde::{Deserialize, Serialize};
+
+use crate::race_rng::RaceRng;
+
+/// Sideways acceleration of the strongest storm gusts (meters per second squared)
+const GUST_STRENGTH: f32 = 1.5;
+/// Control points between each independent gust; the wind changes smoothly in between
+const GUST_SPACING: usize = 8;
+/// Sideways thrust in a storm, as a fraction of that on a clear day
+const STORM_GRIP: f32 = 0.9;
+/// Environment this far from the track has faded away completely in a storm (meters)
+const STORM_VISIBILITY: f32 = 250.;
+/// Ship brightness at night, to stand out against the darkened track
+const NIGHT_SHIP_BRIGHTNESS: f32 = 1.6;
+
+/// Conditions a race is held in, voted on in the lobby
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Conditions {
+    #[default]
+    Clear,
+    /// Poor visibility, gusts and a little less grip
+    Storm,
+    /// Darker track, brighter ships
+    Night,
+}
+
+impl Conditions {
+    pub const ALL: [Conditions; 3] = [Conditions::Clear, Conditions::Storm, Conditions::Night];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Conditions::Clear => "clear",
+            Conditions::Storm => "storm",
+            Conditions::Night => "night",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    /// Color of the environment's lines, near the track
+    pub fn environment_color(self) -> [f32; 3] {
+        match self {
+            Conditions::Clear | Conditions::Storm => [0.2, 1., 0.2],
+            Conditions::Night => [0.08, 0.4, 0.12],
+        }
+    }
+
+    /// Color of the floor grid's lines, near the middle
+    pub fn floor_color(self) -> [f32; 3] {
+        match self {
+            Conditions::Clear | Conditions::Storm => [0., 0.2, 0.],
+            Conditions::Night => [0., 0.08, 0.],
+        }
+    }
+
+    /// Distance from the track at which the environment has faded into the clear color, if it
+    /// does at all (meters)
+    pub fn visibility(self) -> Option<f32> {
+        match self {
+            Conditions::Storm => Some(STORM_VISIBILITY),
+            Conditions::Clear | Conditions::Night => None,
+        }
+    }
+
+    /// Brightness multiplier for ships
+    pub fn ship_brightness(self) -> f32 {
+        match self {
+            Conditions::Night => NIGHT_SHIP_BRIGHTNESS,
+            Conditions::Clear | Conditions::Storm => 1.,
+        }
+    }
+}
+
+/// How the conditions affect driving. Depends only on the race's settings, so every client and
+/// the server feel exactly the same wind in the same places.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weather {
+    conditions: Conditions,
+    gusts: RaceRng,
+}
+
+impl Weather {
+    pub fn new(conditions: Conditions, rng: &RaceRng) -> Self {
+        Self {
+            conditions,
+            gusts: rng.stream("gusts"),
+        }
+    }
+
+    /// No weather to speak of
+    pub fn calm() -> Self {
+        Self::new(Conditions::Clear, &RaceRng::new(0))
+    }
+
+    /// Sideways acceleration around control point `idx`, along the track's local Z
+    /// (meters per second squared)
+    pub fn gust(&self, idx: usize) -> f32 {
+        if self.conditions != Conditions::Storm {
+            return 0.;
+        }
+
+        let block = idx / GUST_SPACING;
+        let u = (idx % GUST_SPACING) as f32 / GUST_SPACING as f32;
+        let strength = |block: usize| 2. * self.gusts.value_at(block as u64) - 1.;
+        GUST_STRENGTH * (strength(block) * (1. - u) + strength(block + 1) * u)
+    }
+
+    /// Multiplier on the sideways thrusters
+    pub fn grip(&self) -> f32 {
+        match self.conditions {
+            Conditions::Storm => STORM_GRIP,
+            Conditions::Clear | Conditions::Night => 1.,
+        }
+    }
+}
@@ -4,85 +4,650 @@ use cimvr_common::{
     glam::{EulerRot, Quat, Vec3},
     Transform,
 };
+use serde::{Deserialize, Serialize};
 
-use crate::{curve::Curve, kinematics::KinematicPhysics, InputAbstraction, ShipCharacteristics};
-
-pub fn ship_controller(
-    dt: f32,
-    ship: ShipCharacteristics,
-    input: InputAbstraction,
-    path: &Curve,
-    tf: &mut Transform,
-    kt: &mut KinematicPhysics,
-) {
-    // Calculate position within the course
-    let nearest_ctrlp_idx = path.nearest_ctrlp(tf.pos);
-    let nearest_ctrlp = path.ctrlps[nearest_ctrlp_idx];
-    let nearest_iso: Transform = nearest_ctrlp.into();
-    let tf_iso: Transform = tf.clone().into();
-    let path_local_space = nearest_iso.inverse() * tf_iso;
-
-    // Collision detection
-    const TRACK_WIDTH: f32 = 32.;
-    const TRACK_HEIGHT: f32 = 10.;
-    const TRACK_LENGTH: f32 = 10.;
-    let z_bound = path_local_space.pos.z.abs() > TRACK_WIDTH / 2.;
-    let y_bound = path_local_space.pos.y.abs() > TRACK_HEIGHT / 2.;
-    if z_bound || y_bound {
-        *tf = nearest_ctrlp;
-        kt.ang_vel = Vec3::ZERO;
-        kt.vel = Vec3::ZERO;
-    }
-
-    // Force controls
-    let throttle_deadzone = 0.1;
-    let force_live = input.throttle.abs() > throttle_deadzone;
-    let wanted_impulse = if force_live {
-        tf.orient * Vec3::X * input.throttle * ship.max_impulse
-    } else {
-        Vec3::ZERO
-    };
-
-    // Apply directional impulse
-    if wanted_impulse != Vec3::ZERO {
-        let total_impulse = wanted_impulse.length().min(ship.max_impulse);
+use crate::{
+    barriers::{self, BarrierPlacement},
+    boost_pads,
+    conditions::Weather,
+    curve::{CrossSection, Curve},
+    kinematics::{self, KinematicPhysics, FIXED_DT},
+    wall_ride::{ShieldedSection, WallRide},
+    InputAbstraction, ShipCharacteristics,
+};
+
+/// Controller constants, adjustable live with the `tuning` feature
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TuningParams {
+    /// Multiplier on the speed-dependent orientation slerp
+    pub lerp_speed: f32,
+    /// Distance ahead of the ship to aim for (meters)
+    pub lookahead: f32,
+    /// Exponent applied to forward speed when powering the horizontal thrusters
+    pub horiz_power_exp: f32,
+    /// Bank angle at full roll input (radians)
+    pub max_bank: f32,
+    /// Throttle inputs smaller than this are ignored
+    pub throttle_deadzone: f32,
+    /// Roll inputs smaller than this are ignored
+    pub roll_deadzone: f32,
+    /// Radius of the sphere ships bump into each other with (meters)
+    pub ship_radius: f32,
+}
+
+impl Default for TuningParams {
+    fn default() -> Self {
+        Self {
+            lerp_speed: 2.,
+            lookahead: 35.,
+            horiz_power_exp: 1.1,
+            max_bank: PI / 16.,
+            throttle_deadzone: 0.1,
+            roll_deadzone: 0.05,
+            ship_radius: 1.5,
+        }
+    }
+}
+
+/// How the sticks fly the ship
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControlScheme {
+    /// Roll banks the ship, and the bank pushes it across the track
+    #[default]
+    Banked,
+    /// Like a drone: the lateral and vertical thrusters are driven directly, yaw and pitch aim
+    /// the nose, and the ship keeps to the bank of the track on its own
+    Strafe,
+}
+
+/// Physical state of a ship, as seen by the controller
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShipState {
+    pub tf: Transform,
+    pub kt: KinematicPhysics,
+    pub wall_ride: WallRide,
+    /// Time before a boost pad can fire again (seconds)
+    pub boost_cooldown: f32,
+}
+
+/// Notable things which happened during a controller step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlEvent {
+    /// The ship crossed the side of the track
+    WallContact,
+    /// The ship left the track bounds and was placed back onto the track
+    Reset,
+    /// The ship is riding a shielded wall
+    WallRide,
+}
+
+/// Result of the bounds check stage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ControlOutput {
+    /// The ship must be placed back onto the track: it's above or below it, or so far past
+    /// the side that bouncing it back would look wrong
+    pub reset: bool,
+    /// The ship crossed the side of the track, and bounces back off it unless it's reset
+    pub wall_contact: bool,
+}
+
+/// The ship's position relative to the nearest part of the track
+#[derive(Clone, Copy, Debug)]
+pub struct TrackFrame {
+    /// Index of the nearest control point
+    pub nearest_idx: usize,
+    /// The nearest control point
+    pub nearest: Transform,
+    /// Ship transform in the nearest control point's local space
+    pub local: Transform,
+    /// Size of the track where the ship is
+    pub cross_section: CrossSection,
+}
+
+/// Standard size of the track, for control points with unit axes
+pub const TRACK_WIDTH: f32 = 32.;
+pub const TRACK_HEIGHT: f32 = 10.;
+const TRACK_LENGTH: f32 = 10.;
+
+/// Thrust multiplier while boosting, for the standard ship
+pub const BOOST_FACTOR: f32 = 1.5;
+
+/// Greatest fraction of the track the ship may look ahead, for tiny tracks
+pub const MAX_LOOKAHEAD_FRACTION: f32 = 1. / 16.;
+
+/// Furthest the nose turns from the track direction at full yaw and pitch, strafing (radians)
+const STRAFE_MAX_YAW: f32 = PI / 8.;
+const STRAFE_MAX_PITCH: f32 = PI / 16.;
+/// Closest a ship hovers to the ceiling, at full vertical input or pitch (meters)
+const HOVER_CLEARANCE: f32 = 1.;
+/// Shield energy the thrusters use per second at full lateral and vertical input
+const STRAFE_DRAIN: f32 = 0.2;
+/// Furthest the nose turns from the track direction at full yaw, banked, for a ship as agile
+/// as the standard one (radians)
+const BANKED_MAX_YAW: f32 = PI / 12.;
+/// Pitch and yaw inside this are ignored when banked, leaving the ship to follow the track by
+/// itself; wider than the roll deadzone, since the sticks they're on also roll and throttle
+const BANKED_DEADZONE: f32 = 0.2;
+/// Share of the speed along the track lost per second with the nose turned fully aside
+const AIR_BRAKE: f32 = 0.3;
+/// Share of the speed into a side wall the ship bounces back off it with
+const WALL_RESTITUTION: f32 = 0.4;
+/// Share of its speed a ship loses hitting a side wall head on; less for a glancing blow
+const WALL_SPEED_PENALTY: f32 = 0.5;
+/// Distance inside a side wall a ship which hit it is put back to (meters)
+const WALL_INSET: f32 = 0.1;
+/// Distance from the middle of the track, in half widths, past which a ship is put back on the
+/// track rather than bounced off the wall
+const WALL_RESET_DISTANCE: f32 = 2.;
+
+/// Ship control, split into stages which are each a pure function of their inputs
+pub struct ShipController<'a> {
+    pub ship: ShipCharacteristics,
+    pub tuning: TuningParams,
+    pub path: &'a Curve,
+    pub weather: Weather,
+    /// Moving barriers to keep out of, and seconds since "GO" to place them at
+    pub barriers: &'a [BarrierPlacement],
+    pub clock: f32,
+    /// Stretches of track whose walls can be ridden
+    pub shielded: &'a [ShieldedSection],
+    /// Where to put the ship back after leaving the track, in place of the nearest control
+    /// point
+    pub respawn: Option<Transform>,
+    /// Leave the ship be outside the track bounds, just after being put back
+    pub bounds_off: bool,
+}
+
+impl<'a> ShipController<'a> {
+    pub fn new(ship: ShipCharacteristics, tuning: TuningParams, path: &'a Curve) -> Self {
+        Self {
+            ship,
+            tuning,
+            path,
+            weather: Weather::calm(),
+            barriers: &[],
+            clock: 0.,
+            shielded: &[],
+            respawn: None,
+            bounds_off: false,
+        }
+    }
+
+    /// Drive in the race's conditions, rather than calm
+    pub fn with_weather(mut self, weather: Weather) -> Self {
+        self.weather = weather;
+        self
+    }
+
+    /// Collide with `barriers` as they are `clock` seconds after "GO"
+    pub fn with_barriers(mut self, barriers: &'a [BarrierPlacement], clock: f32) -> Self {
+        self.barriers = barriers;
+        self.clock = clock;
+        self
+    }
+
+    /// Let the ship ride the walls of the `shielded` sections
+    pub fn with_shielded_walls(mut self, shielded: &'a [ShieldedSection]) -> Self {
+        self.shielded = shielded;
+        self
+    }
+
+    /// Put the ship back at `respawn` after leaving the track, if it's known, with the bounds
+    /// off while `bounds_off`
+    pub fn with_respawn(mut self, respawn: Option<Transform>, bounds_off: bool) -> Self {
+        self.respawn = respawn;
+        self.bounds_off = bounds_off;
+        self
+    }
+
+    /// Calculate position within the course
+    pub fn track_frame(&self, tf: &Transform) -> TrackFrame {
+        let nearest_idx = self.path.nearest_ctrlp(tf.pos);
+        let nearest = self.path.ctrlps[nearest_idx];
+        let local = nearest.inverse() * *tf;
+        TrackFrame {
+            nearest_idx,
+            nearest,
+            local,
+            cross_section: self.path.cross_section_near(nearest_idx, local.pos.x),
+        }
+    }
+
+    /// Collision detection against the track bounds
+    pub fn bounds_check(&self, frame: &TrackFrame) -> ControlOutput {
+        if self.bounds_off {
+            return ControlOutput::default();
+        }
+        let half_width = frame.cross_section.half_width();
+        let z_bound = frame.local.pos.z.abs() > half_width;
+        let z_far = frame.local.pos.z.abs() > half_width * WALL_RESET_DISTANCE;
+        let y_bound = frame.local.pos.y.abs() > frame.cross_section.half_height();
+        ControlOutput {
+            reset: z_far || y_bound,
+            wall_contact: z_bound,
+        }
+    }
+
+    /// Forward impulse (per second) requested by the throttle for a ship with the given orientation
+    pub fn thrust(&self, input: &InputAbstraction, orient: Quat) -> Vec3 {
+        let max_impulse = match input.boost {
+            true => self.ship.max_impulse * self.ship.boost_factor,
+            false => self.ship.max_impulse,
+        };
+
+        let force_live = input.throttle.abs() > self.tuning.throttle_deadzone;
+        let wanted_impulse = if force_live {
+            orient * Vec3::X * input.throttle * max_impulse
+        } else {
+            Vec3::ZERO
+        };
+
+        let total_impulse = wanted_impulse.length().min(max_impulse);
         let norm = wanted_impulse.normalize_or_zero();
-        let impulse = total_impulse * norm;
-        kt.force(impulse * dt);
+        total_impulse * norm
+    }
+
+    /// Roll input with the deadzone applied
+    pub fn desired_roll(&self, input: &InputAbstraction) -> f32 {
+        if input.roll.abs() > self.tuning.roll_deadzone {
+            input.roll
+        } else {
+            0.
+        }
+    }
+
+    /// Pitch and yaw of a banked ship, with the deadzone applied
+    fn banked_commands(&self, input: &InputAbstraction) -> (f32, f32) {
+        let deadzone = |v: f32| match v.abs() > BANKED_DEADZONE {
+            true => v.clamp(-1., 1.),
+            false => 0.,
+        };
+        (deadzone(input.pitch), deadzone(input.yaw))
+    }
+
+    /// Furthest a banked ship's nose yaws from the track direction, more for a ship with more
+    /// twirl for its moment of inertia
+    fn banked_max_yaw(&self) -> f32 {
+        let standard = ShipCharacteristics::standard();
+        let agility =
+            (self.ship.max_twirl / self.ship.moment) / (standard.max_twirl / standard.moment);
+        BANKED_MAX_YAW * agility
+    }
+
+    /// Orientation the ship steers towards; follows the path direction, banked by roll
+    pub fn steering(&self, frame: &TrackFrame, desired_roll: f32) -> Quat {
+        let lookahead = self
+            .path
+            .clamp_distance(self.tuning.lookahead, MAX_LOOKAHEAD_FRACTION);
+        let ahead = self.path.advance(frame.nearest_idx as f32, lookahead);
+        let future_pt = self.path.smooth_lerp(ahead);
+        future_pt.orient
+            * Quat::from_euler(EulerRot::XYZ, desired_roll * self.tuning.max_bank, 0., 0.)
     }
 
-    // Roll input
-    let roll_deadzone = 0.05;
-    let desired_roll = if input.roll.abs() > roll_deadzone {
-        input.roll
-    } else {
-        0.
-    };
+    /// Velocity change (per second) from the horizontal thrusters at `command`, from -1 to 1
+    pub fn lateral_thrust(&self, frame: &TrackFrame, track_rel_vel: Vec3, command: f32) -> Vec3 {
+        let horiz_force = frame.nearest.orient * Vec3::Z;
+        let available_power =
+            track_rel_vel.x.abs().powf(self.tuning.horiz_power_exp) + track_rel_vel.z.abs() + 1.;
+        let grip = self.weather.grip();
+        horiz_force * available_power * command * grip
+    }
+
+    /// Orientation a strafing ship steers towards; follows the path direction and bank, turned
+    /// by yaw and pitch
+    pub fn strafe_steering(&self, frame: &TrackFrame, input: &InputAbstraction) -> Quat {
+        let yaw = -input.yaw.clamp(-1., 1.) * STRAFE_MAX_YAW;
+        let pitch = input.pitch.clamp(-1., 1.) * STRAFE_MAX_PITCH;
+        self.steering(frame, 0.) * Quat::from_euler(EulerRot::YZX, yaw, pitch, 0.)
+    }
+
+    /// Lateral and vertical commands of a strafing ship, with the deadzone applied and scaled
+    /// down by however much of the energy they need is left
+    fn strafe_commands(
+        &self,
+        input: &InputAbstraction,
+        wall_ride: &mut WallRide,
+        dt: f32,
+    ) -> (f32, f32) {
+        let deadzone = |v: f32| match v.abs() > self.tuning.roll_deadzone {
+            true => v.clamp(-1., 1.),
+            false => 0.,
+        };
+        let (lateral, vertical) = (deadzone(input.lateral), deadzone(input.vertical));
+        let demand = (lateral.abs() + vertical.abs()).min(1.);
+        let power = wall_ride.spend(demand * STRAFE_DRAIN * dt);
+        (lateral * power, vertical * power)
+    }
+
+    /// Step the ship forward in time by `dt`
+    pub fn step(
+        &self,
+        dt: f32,
+        input: InputAbstraction,
+        state: ShipState,
+    ) -> (ShipState, Vec<ControlEvent>) {
+        let ShipState {
+            mut tf,
+            mut kt,
+            mut wall_ride,
+            mut boost_cooldown,
+        } = state;
+        let mut events = vec![];
+
+        let mut frame = self.track_frame(&tf);
+
+        // Held against a shielded wall, rather than hitting it
+        let n = self.path.ctrlps.len();
+        let shielded = self
+            .shielded
+            .iter()
+            .any(|section| section.contains(frame.nearest_idx as f32, n));
+        let lateral = frame.local.pos.z;
+        let half_width = frame.cross_section.half_width();
+        if let Some(hold) = wall_ride.update(shielded, lateral, half_width, input.steer(), dt) {
+            let mut local = frame.local;
+            local.pos.z = hold;
+            tf.pos = (frame.nearest * local).pos;
+            let normal = frame.nearest.orient * Vec3::Z * hold.signum();
+            let into = kt.vel.dot(normal);
+            if into > 0. {
+                kt.vel -= normal * into;
+            }
+            frame = self.track_frame(&tf);
+            events.push(ControlEvent::WallRide);
+        }
+
+        let output = self.bounds_check(&frame);
+        if output.wall_contact {
+            events.push(ControlEvent::WallContact);
+        }
+        if output.reset {
+            tf = self.respawn.unwrap_or(frame.nearest);
+            kt.ang_vel = Vec3::ZERO;
+            kt.vel = Vec3::ZERO;
+            events.push(ControlEvent::Reset);
+        } else if output.wall_contact {
+            let mut local = frame.local;
+            let local_vel = frame.nearest.orient.inverse() * kt.vel;
+            let half_width = frame.cross_section.half_width();
+            let (pos, vel) = wall_bounce(local.pos, local_vel, half_width);
+            local.pos = pos;
+            tf.pos = (frame.nearest * local).pos;
+            kt.vel = frame.nearest.orient * vel;
+            frame = self.track_frame(&tf);
+        }
+
+        if barriers::collide(self.barriers, self.path, self.clock, dt, &mut tf, &mut kt) {
+            events.push(ControlEvent::WallContact);
+        }
+
+        // Apply directional impulse
+        let impulse = self.thrust(&input, tf.orient);
+        if impulse != Vec3::ZERO {
+            kt.force(impulse * dt);
+        }
+        let pads = &self.path.boost_pads;
+        boost_pads::boost(pads, &self.ship, dt, &tf, &mut kt, &mut boost_cooldown);
+
+        // Follow path direction smoothly
+        let ceiling = frame.cross_section.half_height() - HOVER_CLEARANCE;
+        let (wanted_orient, lateral, hover, air_brake) = match input.scheme {
+            ControlScheme::Banked => {
+                let desired_roll = self.desired_roll(&input);
+                let lateral = (desired_roll * PI / 2.).sin();
+                // Pitch climbs and yaw turns the nose aside, braking
+                let (pitch, yaw) = self.banked_commands(&input);
+                let turn = Quat::from_rotation_y(-yaw * self.banked_max_yaw());
+                let wanted_orient = self.steering(&frame, desired_roll) * turn;
+                (
+                    wanted_orient,
+                    lateral,
+                    pitch.max(0.) * ceiling,
+                    yaw.abs() * AIR_BRAKE,
+                )
+            }
+            ControlScheme::Strafe => {
+                let (lateral, vertical) = self.strafe_commands(&input, &mut wall_ride, dt);
+                let hover = vertical.max(0.) * ceiling;
+                (self.strafe_steering(&frame, &input), lateral, hover, 0.)
+            }
+        };
 
-    // Follow pathdirection smoothly
-    let future_pt = path.lerp(nearest_ctrlp_idx as f32 + 3.5);
-    let wanted_orient =
-        future_pt.orient * Quat::from_euler(EulerRot::XYZ, desired_roll * PI / 16., 0., 0.);
+        let track_rel_vel = frame.nearest.orient.inverse() * kt.vel;
+        let lerp_speed = dt * track_rel_vel.x / TRACK_LENGTH;
+        tf.orient = tf
+            .orient
+            .slerp(wanted_orient, lerp_speed * self.tuning.lerp_speed);
 
-    let track_rel_vel = nearest_ctrlp.orient.inverse() * kt.vel;
-    let lerp_speed = dt * track_rel_vel.x / TRACK_LENGTH;
-    tf.orient = tf.orient.slerp(wanted_orient, lerp_speed * 2.);
+        kt.vel += self.lateral_thrust(&frame, track_rel_vel, lateral) * dt;
+        kt.vel -= frame.nearest.orient * Vec3::X * track_rel_vel.x * air_brake * dt;
+        kt.vel += frame.nearest.orient * Vec3::Z * self.weather.gust(frame.nearest_idx) * dt;
 
-    // Horizontal thrusters
-    let horiz_force = nearest_ctrlp.orient * Vec3::Z;
+        // Zero velocity component in the y direction relative to the track
+        kt.vel -= frame.nearest.orient * Vec3::Y * track_rel_vel.y;
 
-    let available_power = track_rel_vel.x.abs().powf(1.1) + track_rel_vel.z.abs() + 1.;
-    kt.vel += horiz_force * dt * available_power * (desired_roll * PI / 2.).sin();
+        // Lock Y pos to track
+        let mut path_local_tf = frame.nearest.inverse() * tf;
+        path_local_tf.pos.y = lerp(path_local_tf.pos.y, hover, lerp_speed);
+        tf.pos = (frame.nearest * path_local_tf).pos;
 
-    // Zero velocity component in the y direction relative to the track
-    kt.vel -= nearest_ctrlp.orient * Vec3::Y * track_rel_vel.y;
+        let state = ShipState {
+            tf,
+            kt,
+            wall_ride,
+            boost_cooldown,
+        };
+        (state, events)
+    }
 
-    // Lock Y pos to track
-    let mut path_local_tf = nearest_ctrlp.inverse() * *tf;
-    path_local_tf.pos.y = lerp(path_local_tf.pos.y, 0., lerp_speed);
-    tf.pos = (nearest_ctrlp * path_local_tf).pos;
+    /// Control, then integrate the ship over one fixed step.
+    /// The client and the authoritative server both use this, so they agree exactly.
+    pub fn fixed_step(
+        &self,
+        input: InputAbstraction,
+        state: ShipState,
+    ) -> (ShipState, Vec<ControlEvent>) {
+        let (mut state, events) = self.step(FIXED_DT, input, state);
+        kinematics::integrate(&mut state.tf, &state.kt, FIXED_DT);
+        (state, events)
+    }
 }
 
 fn lerp(a: f32, b: f32, t: f32) -> f32 {
     (1. - t) * a + t * b
 }
+
+/// Bounce a ship at `pos` moving at `vel`, both in track-local space, back off the side wall
+/// it's past, for a track `half_width` across. It's put just back inside, with the speed it
+/// had into the wall turned around and cut down, and loses more of its speed the more head
+/// on it hit. Ships inside the walls are left as they are.
+fn wall_bounce(mut pos: Vec3, mut vel: Vec3, half_width: f32) -> (Vec3, Vec3) {
+    if pos.z.abs() <= half_width {
+        return (pos, vel);
+    }
+    let side = pos.z.signum();
+    pos.z = side * (half_width - WALL_INSET).max(0.);
+
+    // Already heading back in, or running along the wall
+    let into = vel.z * side;
+    if into <= 0. {
+        return (pos, vel);
+    }
+    let impact = into / vel.length();
+    vel.z = -vel.z * WALL_RESTITUTION;
+    (pos, vel * (1. - WALL_SPEED_PENALTY * impact))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::long_straight;
+
+    const HALF_WIDTH: f32 = 16.;
+    /// Control point on `long_straight` to start from, a fifth of the way along the straight
+    const START: usize = 10;
+    /// Speed along the track to start at, fast enough for the steering to take hold (m/s)
+    const START_SPEED: f32 = 50.;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn ships_inside_the_walls_are_left_alone() {
+        let (pos, vel) = (Vec3::new(3., 0., HALF_WIDTH - 1.), Vec3::new(20., 0., 5.));
+        assert_eq!(wall_bounce(pos, vel, HALF_WIDTH), (pos, vel));
+    }
+
+    #[test]
+    fn head_on_hits_bounce_back_slowest() {
+        let speed = 10.;
+        let (pos, vel) = wall_bounce(Vec3::Z * (HALF_WIDTH + 1.), Vec3::Z * speed, HALF_WIDTH);
+        assert!(close(pos, Vec3::Z * (HALF_WIDTH - WALL_INSET)));
+        let kept = WALL_RESTITUTION * (1. - WALL_SPEED_PENALTY);
+        assert!(close(vel, -Vec3::Z * speed * kept), "{:?}", vel);
+    }
+
+    #[test]
+    fn glancing_hits_keep_most_of_the_speed() {
+        let into = Vec3::new(30., 0., 1.);
+        let (pos, vel) = wall_bounce(Vec3::Z * (HALF_WIDTH + 1.), into, HALF_WIDTH);
+        assert!(close(pos, Vec3::Z * (HALF_WIDTH - WALL_INSET)));
+        assert!(vel.z < 0.);
+        let penalty = WALL_SPEED_PENALTY / into.length();
+        assert!((vel.x - into.x * (1. - penalty)).abs() < 1e-4);
+        assert!((vel.z + WALL_RESTITUTION * (1. - penalty)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn either_wall_bounces_the_same() {
+        let (right_pos, right_vel) = wall_bounce(
+            Vec3::new(0., 0., HALF_WIDTH + 2.),
+            Vec3::new(5., 0., 8.),
+            HALF_WIDTH,
+        );
+        let (left_pos, left_vel) = wall_bounce(
+            Vec3::new(0., 0., -HALF_WIDTH - 2.),
+            Vec3::new(5., 0., -8.),
+            HALF_WIDTH,
+        );
+        let mirror = Vec3::new(1., 1., -1.);
+        assert!(close(left_pos, right_pos * mirror));
+        assert!(close(left_vel, right_vel * mirror));
+    }
+
+    #[test]
+    fn ships_already_heading_back_in_keep_their_speed() {
+        let vel = Vec3::new(20., 0., -3.);
+        let (pos, bounced) = wall_bounce(Vec3::Z * (HALF_WIDTH + 1.), vel, HALF_WIDTH);
+        assert!(close(pos, Vec3::Z * (HALF_WIDTH - WALL_INSET)));
+        assert_eq!(bounced, vel);
+    }
+
+    /// A ship on the straight, `side` meters across it, at velocity `vel`
+    fn on_straight(curve: &Curve, side: f32, vel: Vec3) -> ShipState {
+        let mut tf = curve.ctrlps[START];
+        tf.pos.z += side;
+        ShipState {
+            tf,
+            kt: KinematicPhysics {
+                vel,
+                mass: 1.,
+                moment: 1.,
+                ..Default::default()
+            },
+            wall_ride: WallRide::default(),
+            boost_cooldown: 0.,
+        }
+    }
+
+    fn full_throttle() -> InputAbstraction {
+        InputAbstraction {
+            throttle: 1.,
+            ..Default::default()
+        }
+    }
+
+    /// Run `seconds` of fixed steps, passing each state and its events to `check`
+    fn drive(
+        controller: &ShipController,
+        input: InputAbstraction,
+        mut state: ShipState,
+        seconds: f32,
+        mut check: impl FnMut(&ShipState, &[ControlEvent]),
+    ) -> ShipState {
+        for _ in 0..(seconds / FIXED_DT) as usize {
+            let (next, events) = controller.fixed_step(input, state);
+            check(&next, &events);
+            state = next;
+        }
+        state
+    }
+
+    #[test]
+    fn driving_straight_holds_the_center_line() {
+        let curve = long_straight();
+        let controller = ShipController::new(
+            ShipCharacteristics::standard(),
+            TuningParams::default(),
+            &curve,
+        );
+        let start = on_straight(&curve, 0., Vec3::X * START_SPEED);
+        let end = drive(&controller, full_throttle(), start, 2., |state, events| {
+            assert!(events.is_empty(), "{:?}", events);
+            assert!(state.tf.pos.z.abs() < 1e-3, "{:?}", state.tf.pos);
+            assert!(state.tf.pos.y.abs() < 1e-3, "{:?}", state.tf.pos);
+        });
+        assert!(end.kt.vel.x > START_SPEED);
+    }
+
+    #[test]
+    fn full_roll_converges_to_the_max_bank() {
+        let curve = long_straight();
+        let tuning = TuningParams::default();
+        let controller = ShipController::new(ShipCharacteristics::standard(), tuning, &curve);
+        let input = InputAbstraction {
+            roll: 1.,
+            ..full_throttle()
+        };
+        let banked = Quat::from_rotation_x(tuning.max_bank);
+
+        let start = on_straight(&curve, 0., Vec3::X * START_SPEED);
+        let end = drive(&controller, input, start, 2., |state, _| {
+            // Turning towards the bank, never past it
+            let roll = state.tf.orient.angle_between(Quat::IDENTITY);
+            assert!(roll <= tuning.max_bank + 1e-4, "{}", roll);
+        });
+        assert!(end.tf.orient.angle_between(banked) < 1e-3);
+    }
+
+    #[test]
+    fn crossing_the_side_is_one_wall_event() {
+        let curve = long_straight();
+        let controller = ShipController::new(
+            ShipCharacteristics::standard(),
+            TuningParams::default(),
+            &curve,
+        );
+        let start = on_straight(&curve, HALF_WIDTH - 1., Vec3::new(30., 0., 20.));
+        let mut contacts = 0;
+        let end = drive(
+            &controller,
+            InputAbstraction::default(),
+            start,
+            1.,
+            |_, events| {
+                assert!(!events.contains(&ControlEvent::Reset), "{:?}", events);
+                contacts += events
+                    .iter()
+                    .filter(|&&e| e == ControlEvent::WallContact)
+                    .count();
+            },
+        );
+        assert_eq!(contacts, 1);
+        // Bounced back in, and slowed down
+        assert!(end.tf.pos.z < HALF_WIDTH && end.kt.vel.z < 0.);
+        assert!(end.kt.vel.length() < start.kt.vel.length());
+    }
+}
@@ -1,18 +1,92 @@
 use cimvr_common::{
-    glam::Vec3,
+    glam::{Quat, Vec3},
     render::{MeshHandle, Primitive, Render, RenderExtra, UploadMesh},
     Transform,
 };
 use cimvr_engine_interface::{pkg_namespace, prelude::*, FrameTime};
 
-use crate::obj::obj_lines_to_mesh;
+use crate::{curve::Curve, hud::HudVisibility, obj::obj_lines_to_mesh, SoundCue};
+
+/// How long the countdown stays up after it starts, including "GO" (seconds)
+const SHOW_TIME: f32 = 8.;
+/// Time from the countdown starting to "GO" (seconds)
+pub const GO_TIME: f32 = 3.;
+/// Time each numeral is up for (seconds)
+const NUMERAL_TIME: f32 = GO_TIME / 3.;
+/// Distance of the countdown ahead of a player's place on the grid (meters)
+const AHEAD_OF_START: f32 = 30.;
+
+/// Colors of the orbiting copies, round again after the last
+const COLORS: &[[f32; 3]] = &[[1., 0., 1.], [0., 1., 1.], [1., 1., 0.]];
+
+/// How the numerals are laid out
+#[derive(Clone, Copy, Debug)]
+pub struct CountdownStyle {
+    /// Number of orbiting copies of each numeral
+    pub copies: usize,
+    /// Radius of the orbit (meters)
+    pub radius: f32,
+    /// Angular speed of the orbit (radians per second); zero for a static display
+    pub speed: f32,
+}
+
+impl Default for CountdownStyle {
+    fn default() -> Self {
+        Self {
+            copies: 3,
+            radius: 1.,
+            speed: 3.,
+        }
+    }
+}
+
+/// Where the countdown plays for a player starting at `start`: a way ahead of them along the
+/// track, just as far off its middle, so that it's in view wherever they are on the grid
+pub fn ahead_of_start(path: &Curve, start: Transform) -> Transform {
+    let t = path.nearest_t(start.pos);
+    let here = path.smooth_lerp(t);
+    let offset = here.orient.inverse() * (start.pos - here.pos);
+    let ahead = path.smooth_lerp(path.advance(t, path.clamp_distance(AHEAD_OF_START, 1. / 8.)));
+    ahead.with_position(ahead.pos + ahead.orient * offset)
+}
+
+/// What the countdown is showing. Each is passed through once per race, in order, however
+/// long the frames are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CountdownState {
+    /// Nothing, before a race or once "GO" has been up for long enough
+    Idle,
+    Three,
+    Two,
+    One,
+    /// "GO", which came at the given time (seconds)
+    Go {
+        at: f32,
+    },
+}
+
+/// The countdown moving from one state to the next
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CountdownEvent {
+    /// A numeral came up: 3, 2 or 1
+    Numeral(u32),
+    /// "GO", at the given time, which may have been part way through the frame (seconds)
+    RaceStarted { at: f32 },
+    /// "GO" has been taken down
+    Finished,
+}
 
 pub struct CountdownAnimation {
     entities: Vec<EntityId>,
+    style: CountdownStyle,
     start_time: f32,
     needs_restart: bool,
     position: Transform,
     is_running: bool,
+    state: CountdownState,
+    visible: bool,
+    /// State and visibility last written to the entities, if they're shown at all
+    shown: Option<(CountdownState, bool)>,
 }
 
 impl CountdownAnimation {
@@ -21,14 +95,6 @@ impl CountdownAnimation {
     const RDR_ID_3: MeshHandle = MeshHandle::new(pkg_namespace!("Countdown3"));
     const RDR_ID_GO: MeshHandle = MeshHandle::new(pkg_namespace!("CountdownGo"));
 
-    fn colors() -> Vec<[f32; 3]> {
-        vec![[1., 0., 1.], [0., 1., 1.], [1., 1., 0.]]
-            .into_iter()
-            //.cycle()
-            //.take(3 * 4)
-            .collect()
-    }
-
     pub fn assets(io: &mut EngineIo) {
         io.send(&UploadMesh {
             mesh: obj_lines_to_mesh(include_str!("assets/1.obj")),
@@ -48,25 +114,43 @@ impl CountdownAnimation {
         });
     }
 
-    pub fn new(io: &mut EngineIo, position: Transform) -> Self {
-        let entities = (0..Self::colors().len())
-            .map(|_| {
+    pub fn new(io: &mut EngineIo, position: Transform, style: CountdownStyle) -> Self {
+        let entities = COLORS
+            .iter()
+            .copied()
+            .cycle()
+            .take(style.copies)
+            .map(|color| {
                 io.create_entity()
                     .add_component(Transform::default())
                     .add_component(Render::new(Self::RDR_ID_1).limit(Some(0)))
+                    .add_component(color_extra(color))
                     .build()
             })
             .collect();
+        Self::with_entities(entities, position, style)
+    }
 
+    /// Animate the numerals on the given `entities`, one for each copy
+    fn with_entities(entities: Vec<EntityId>, position: Transform, style: CountdownStyle) -> Self {
         Self {
             position,
+            style,
             entities,
             start_time: 0.,
             needs_restart: false,
             is_running: false,
+            state: CountdownState::Idle,
+            visible: true,
+            shown: None,
         }
     }
 
+    /// Move the animation, e.g. when the track changes or a race puts us on the grid
+    pub fn set_position(&mut self, position: Transform) {
+        self.position = position;
+    }
+
     pub fn elapsed(&self, time: FrameTime) -> f32 {
         time.time - self.start_time
     }
@@ -74,55 +158,354 @@ impl CountdownAnimation {
     pub fn restart(&mut self) {
         self.needs_restart = true;
         self.is_running = true;
+        self.state = CountdownState::Idle;
+    }
+
+    /// Carry on at `now` with a race which started at `start_time`, without replaying the
+    /// countdown. Its state is set to the one before where it's up to, so that the next
+    /// update still announces that, e.g. the race having started.
+    pub fn resume(&mut self, start_time: f32, now: f32) {
+        self.start_time = start_time;
+        self.needs_restart = false;
+        self.is_running = true;
+        let elapsed = now - start_time;
+        self.state = if elapsed >= GO_TIME {
+            CountdownState::One
+        } else if elapsed >= 2. * NUMERAL_TIME {
+            CountdownState::Two
+        } else {
+            CountdownState::Three
+        };
     }
 
-    pub fn match_started(&self, time: FrameTime) -> bool {
-        self.is_running && self.elapsed(time) > 3.
+    /// Hide or show the numerals. The animation keeps time while hidden.
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
     }
 
-    pub fn update(&mut self, io: &mut EngineIo, time: FrameTime) {
+    /// Time since "GO", negative before it. `None` while there's no countdown to go by.
+    pub fn since_go(&self, time: FrameTime) -> Option<f32> {
+        let counting = self.is_running && !self.needs_restart;
+        counting.then(|| self.elapsed(time) - GO_TIME)
+    }
+
+    /// Move on to the next state if it's due by `time`. Call until it returns `None` to catch
+    /// up over a long frame, passing through every state on the way.
+    pub fn poll_transition(&mut self, time: f32) -> Option<CountdownEvent> {
         if !self.is_running {
-            return;
+            return None;
         }
 
         if self.needs_restart {
-            self.start_time = time.time;
+            self.start_time = time;
             self.needs_restart = false;
+            self.state = CountdownState::Three;
+            return Some(CountdownEvent::Numeral(3));
         }
 
-        let elapsed = self.elapsed(time);
-
-        let rdr_component = match elapsed as i32 + 1 {
-            1 => Render::new(Self::RDR_ID_3),
-            2 => Render::new(Self::RDR_ID_2),
-            3 => Render::new(Self::RDR_ID_1),
-            _ => Render::new(Self::RDR_ID_GO),
+        let elapsed = time - self.start_time;
+        let (due, next) = match self.state {
+            CountdownState::Idle => return None,
+            CountdownState::Three => (NUMERAL_TIME, CountdownState::Two),
+            CountdownState::Two => (2. * NUMERAL_TIME, CountdownState::One),
+            CountdownState::One => (
+                GO_TIME,
+                CountdownState::Go {
+                    at: self.start_time + GO_TIME,
+                },
+            ),
+            CountdownState::Go { .. } => (SHOW_TIME, CountdownState::Idle),
         };
+        if elapsed < due {
+            return None;
+        }
+        self.state = next;
+        Some(match next {
+            CountdownState::Two => CountdownEvent::Numeral(2),
+            CountdownState::One => CountdownEvent::Numeral(1),
+            CountdownState::Go { at } => CountdownEvent::RaceStarted { at },
+            CountdownState::Idle | CountdownState::Three => CountdownEvent::Finished,
+        })
+    }
 
-        /*
-        let limit = match elapsed < 8. {
-        true => None,
-        false => Some(0),
-        };
-        */
+    /// Catch the countdown up to `time`, and animate the numerals, turned to face `camera`.
+    /// Returns the countdown's events since the last update, in order. Writes nothing while
+    /// idle, and only re-renders when the numeral changes.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        time: FrameTime,
+        camera: Transform,
+    ) -> Vec<CountdownEvent> {
+        let events: Vec<CountdownEvent> =
+            std::iter::from_fn(|| self.poll_transition(time.time)).collect();
 
-        let rdr_component = rdr_component /*.limit(limit)*/
-            .primitive(Primitive::Lines);
+        for event in &events {
+            match *event {
+                CountdownEvent::Numeral(numeral) => io.send(&SoundCue::Countdown(numeral)),
+                // Not if it's already over, e.g. after rejoining a race
+                CountdownEvent::RaceStarted { .. } if self.state != CountdownState::Idle => {
+                    io.send(&SoundCue::Countdown(0))
+                }
+                _ => (),
+            }
+        }
 
-        for (idx, (&entity, color)) in self.entities.iter().zip(Self::colors()).enumerate() {
-            let animation = Transform::identity().with_position(Vec3::new(
-                idx as f32 / 3.,
-                (time.time * 3. + idx as f32 / 3.).cos(),
-                (time.time * 3. + idx as f32 / 3.).sin(),
-            ));
-            let transf = self.position * animation;
-            io.add_component(entity, transf);
-            io.add_component(entity, rdr_component);
-            io.add_component(entity, color_extra(color));
+        let NumeralWrites { render, transforms } = self.writes(time.time, camera);
+        if let Some(render) = render {
+            for &entity in &self.entities {
+                io.add_component(entity, render);
+            }
         }
+        for (&entity, &transform) in self.entities.iter().zip(&transforms) {
+            io.add_component(entity, transform);
+        }
+
+        events
     }
+
+    /// What the numerals' entities need to look like at `time`, turned to face `camera`
+    fn writes(&mut self, time: f32, camera: Transform) -> NumeralWrites {
+        // Done counting down; hide once and then stay idle
+        if self.state == CountdownState::Idle {
+            return NumeralWrites {
+                render: self
+                    .shown
+                    .take()
+                    .map(|_| Render::new(Self::RDR_ID_GO).limit(Some(0))),
+                transforms: vec![],
+            };
+        }
+
+        let shown = Some((self.state, self.visible));
+        let changed = self.shown != shown;
+        self.shown = shown;
+
+        let render = changed.then(|| {
+            let rdr_component = match self.state {
+                CountdownState::Three => Render::new(Self::RDR_ID_3),
+                CountdownState::Two => Render::new(Self::RDR_ID_2),
+                CountdownState::One => Render::new(Self::RDR_ID_1),
+                _ => Render::new(Self::RDR_ID_GO),
+            }
+            .primitive(Primitive::Lines);
+            HudVisibility::render(self.visible, rdr_component)
+        });
+
+        // The numerals are drawn across their YZ plane, read from -X
+        let facing = camera.orient * Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let billboard = Transform::new()
+            .with_position(self.position.pos)
+            .with_rotation(facing);
+
+        let CountdownStyle {
+            copies,
+            radius,
+            speed,
+        } = self.style;
+        let transforms = (0..copies)
+            .map(|idx| {
+                let offset = idx as f32 / copies as f32;
+                let angle = time * speed + offset;
+                let animation = Transform::identity().with_position(Vec3::new(
+                    offset,
+                    radius * angle.cos(),
+                    radius * angle.sin(),
+                ));
+                billboard * animation
+            })
+            .collect();
+
+        NumeralWrites { render, transforms }
+    }
+}
+
+/// Components for the numerals' entities after an update
+struct NumeralWrites {
+    /// Render component for every copy, if what's shown has changed
+    render: Option<Render>,
+    /// Where each copy goes, unless they're hidden
+    transforms: Vec<Transform>,
 }
 
-fn color_extra([r, g, b]: [f32; 3]) -> RenderExtra {
+pub fn color_extra([r, g, b]: [f32; 3]) -> RenderExtra {
     RenderExtra([r, g, b, 1., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0.])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race_rng::RaceRng;
+
+    /// Time the countdown is restarted (seconds)
+    const START: f32 = 10.;
+
+    /// A countdown with nothing to show it on
+    fn countdown() -> CountdownAnimation {
+        CountdownAnimation::with_entities(vec![], Transform::identity(), CountdownStyle::default())
+    }
+
+    /// Each event from counting down with frames `deltas` apart, with the time since the
+    /// start of the frame it came in, and the time of every frame. Ends with a frame well after
+    /// "GO" has been taken down.
+    fn count_down(deltas: impl IntoIterator<Item = f32>) -> (Vec<(f32, CountdownEvent)>, Vec<f32>) {
+        let mut countdown = countdown();
+        countdown.restart();
+
+        let mut time = START;
+        let (mut events, mut frames) = (vec![], vec![]);
+        let mut deltas = deltas.into_iter();
+        loop {
+            let elapsed = time - START;
+            frames.push(elapsed);
+            events.extend(
+                std::iter::from_fn(|| countdown.poll_transition(time)).map(|e| (elapsed, e)),
+            );
+            match deltas.next() {
+                Some(delta) if elapsed < 2. * SHOW_TIME => time += delta,
+                _ => break,
+            }
+        }
+        (events, frames)
+    }
+
+    /// Counting down with frames `deltas` apart shows every state once, in order, on the first
+    /// frame it's due
+    fn check(name: &str, deltas: &[f32]) {
+        let (events, frames) = count_down(deltas.iter().copied().cycle());
+        let just_events: Vec<CountdownEvent> = events.iter().map(|&(_, e)| e).collect();
+        assert_eq!(
+            just_events,
+            [
+                CountdownEvent::Numeral(3),
+                CountdownEvent::Numeral(2),
+                CountdownEvent::Numeral(1),
+                CountdownEvent::RaceStarted {
+                    at: START + GO_TIME
+                },
+                CountdownEvent::Finished,
+            ],
+            "{}",
+            name
+        );
+
+        let dues = [0., NUMERAL_TIME, 2. * NUMERAL_TIME, GO_TIME, SHOW_TIME];
+        for (&(elapsed, event), due) in events.iter().zip(dues) {
+            let first = frames.iter().copied().find(|&t| t >= due).unwrap();
+            assert_eq!(elapsed, first, "{}: {:?} due at {}", name, event, due);
+        }
+    }
+
+    #[test]
+    fn every_state_comes_once_in_order_at_any_frame_rate() {
+        check("60 fps", &[1. / 60.]);
+        check("7 fps", &[1. / 7.]);
+        check("just under a numeral", &[NUMERAL_TIME * 0.99]);
+        check("uneven", &[0.25, 1.5, 1. / 60., 0.75, 2.25]);
+        check("one long frame", &[100.]);
+        check("a long frame before GO", &[2.5, 10.]);
+
+        let mut rng = RaceRng::new(3).stream("frames");
+        let random: Vec<f32> = (0..1000).map(|_| rng.range(0., 2.)).collect();
+        check("random", &random);
+    }
+
+    #[test]
+    fn nothing_more_once_idle() {
+        let mut countdown = countdown();
+        countdown.restart();
+        let mut poll = |time| std::iter::from_fn(|| countdown.poll_transition(time)).count();
+        assert_eq!(poll(START), 1);
+        assert_eq!(poll(START + 100.), 4);
+        assert_eq!(poll(START + 101.), 0);
+        assert_eq!(poll(START + 1000.), 0);
+    }
+
+    #[test]
+    fn nothing_before_a_restart() {
+        let mut countdown = countdown();
+        assert_eq!(countdown.poll_transition(START), None);
+        assert_eq!(countdown.poll_transition(START + 100.), None);
+    }
+
+    #[test]
+    fn resuming_announces_where_the_race_is_up_to() {
+        let mut countdown = countdown();
+        countdown.resume(START, START + 5.);
+        let events: Vec<_> = std::iter::from_fn(|| countdown.poll_transition(START + 5.)).collect();
+        assert_eq!(
+            events,
+            [CountdownEvent::RaceStarted {
+                at: START + GO_TIME
+            }]
+        );
+    }
+
+    /// Catch `countdown` up to `time` as an update would, returning its numerals' component
+    /// writes and whether it re-rendered them
+    fn writes(countdown: &mut CountdownAnimation, time: f32) -> (usize, bool) {
+        while countdown.poll_transition(time).is_some() {}
+        let NumeralWrites { render, transforms } = countdown.writes(time, Transform::identity());
+        let copies = countdown.style.copies;
+        (
+            render.map_or(0, |_| copies) + transforms.len(),
+            render.is_some(),
+        )
+    }
+
+    #[test]
+    fn nothing_is_written_in_the_lobby() {
+        let mut countdown = countdown();
+        let written: usize = (0..1000)
+            .map(|k| writes(&mut countdown, k as f32 * 0.1).0)
+            .sum();
+        assert_eq!(written, 0);
+    }
+
+    #[test]
+    fn nothing_is_written_once_go_is_hidden_again() {
+        let mut countdown = countdown();
+        countdown.restart();
+        let frames = |from: f32, to: f32| {
+            (0..)
+                .map(move |k| from + k as f32 / 60.)
+                .take_while(move |&t| t < to)
+        };
+
+        let renders = frames(START, START + SHOW_TIME)
+            .filter(|&time| writes(&mut countdown, time).1)
+            .count();
+        // Three, two, one and go
+        assert_eq!(renders, 4);
+
+        // Hidden once, when go comes down
+        let (written, hidden) = writes(&mut countdown, START + SHOW_TIME);
+        assert!(hidden);
+        assert_eq!(written, countdown.style.copies);
+
+        let written: usize = frames(START + SHOW_TIME, START + 100.)
+            .map(|time| writes(&mut countdown, time).0)
+            .sum();
+        assert_eq!(written, 0);
+    }
+
+    #[test]
+    fn every_copy_moves_each_frame_while_counting_down() {
+        let mut countdown = CountdownAnimation::with_entities(
+            vec![],
+            Transform::identity(),
+            CountdownStyle {
+                copies: 1,
+                radius: 0.,
+                speed: 0.,
+            },
+        );
+        countdown.restart();
+        assert_eq!(writes(&mut countdown, START), (2, true));
+        assert_eq!(writes(&mut countdown, START + 0.1), (1, false));
+
+        // Hiding the numerals re-renders them
+        countdown.set_visible(false);
+        assert_eq!(writes(&mut countdown, START + 0.2), (2, true));
+    }
+}
@@ -1,9 +1,215 @@
+use std::fmt;
+
 use cimvr_common::{
     glam::{Mat3, Quat, Vec3},
-    render::Mesh,
+    render::{Mesh, Vertex},
     Transform,
 };
 
+use crate::{
+    boost_pads::{pads_from_mesh, BoostPad, BOOST_OBJECT},
+    controls::{TRACK_HEIGHT, TRACK_WIDTH},
+    obj::{try_obj_objects, ObjError},
+    sanitize::transform_is_finite,
+};
+
+/// Fewest control points a track may have
+const MIN_CTRLPS: usize = 4;
+/// Lines per segment in `to_line_mesh`
+const LINE_MESH_STEPS: usize = 4;
+/// Control points either side of the last known position which `nearest_t_from` keeps to
+const CONTINUITY_WINDOW: usize = 4;
+/// How much nearer another part of the track must be before `nearest_t_from` jumps to it
+/// (meters)
+const CONTINUITY_SLACK: f32 = 16.;
+/// Spacing of the sample table read by `lerp_fast`, along the curve. A 2000 point track of
+/// 10 m segments holds 80,000 samples, about 2.2 MB (meters).
+const SAMPLE_SPACING: f32 = 0.25;
+/// Narrowest and lowest the track may be anywhere, with room for a ship (meters)
+const MIN_CROSS_SECTION: f32 = 4.;
+/// Name of the path OBJ's object marking the start/finish line: a single vertex, at or near
+/// the control point the line goes across. Without one the line is at the first.
+pub const FINISH_OBJECT: &str = "Finish";
+
+/// Why a path OBJ can't be used as a track
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathError {
+    Obj(ObjError),
+    /// Control points are made of four vertices each
+    IncompleteControlPoint {
+        vertices: usize,
+    },
+    TooFewControlPoints {
+        found: usize,
+    },
+    /// Two consecutive control points are in the same place
+    CoincidentControlPoints {
+        index: usize,
+    },
+    /// A control point's axes make the track too narrow or low to drive
+    CrampedControlPoint {
+        index: usize,
+    },
+    /// Boost pads are made of four vertices each
+    IncompleteBoostPad {
+        vertices: usize,
+    },
+    /// A boost pad's corners don't make a quad, e.g. being in a line
+    DegenerateBoostPad {
+        index: usize,
+    },
+    /// The finish line is marked with one vertex, not several
+    AmbiguousFinishLine {
+        vertices: usize,
+    },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Obj(e) => write!(f, "{}", e),
+            Self::IncompleteControlPoint { vertices } => {
+                write!(
+                    f,
+                    "{} vertices is not a whole number of control points",
+                    vertices
+                )
+            }
+            Self::TooFewControlPoints { found } => {
+                write!(f, "{} control points, need at least {}", found, MIN_CTRLPS)
+            }
+            Self::CoincidentControlPoints { index } => {
+                write!(f, "control points {} and {} coincide", index, index + 1)
+            }
+            Self::CrampedControlPoint { index } => write!(
+                f,
+                "control point {} is narrower or lower than {} m",
+                index, MIN_CROSS_SECTION
+            ),
+            Self::IncompleteBoostPad { vertices } => {
+                write!(
+                    f,
+                    "{} vertices is not a whole number of boost pads",
+                    vertices
+                )
+            }
+            Self::DegenerateBoostPad { index } => write!(f, "boost pad {} is not a quad", index),
+            Self::AmbiguousFinishLine { vertices } => {
+                write!(f, "{} vertices mark the finish line, need one", vertices)
+            }
+        }
+    }
+}
+
+/// The parts of a path OBJ
+#[derive(Default)]
+struct PathObj {
+    /// Everything not in another object
+    ctrlps: Mesh,
+    /// `BOOST_OBJECT`
+    pads: Mesh,
+    /// `FINISH_OBJECT`
+    finish: Mesh,
+}
+
+fn split_path_obj(obj: &str) -> Result<PathObj, ObjError> {
+    let mut parts = PathObj::default();
+    for object in try_obj_objects(obj)? {
+        let dest = match object.name.as_str() {
+            BOOST_OBJECT => &mut parts.pads,
+            FINISH_OBJECT => &mut parts.finish,
+            _ => &mut parts.ctrlps,
+        };
+        let base = dest.vertices.len() as u32;
+        dest.vertices.extend(object.mesh.vertices);
+        dest.indices
+            .extend(object.mesh.indices.iter().map(|i| i + base));
+    }
+    Ok(parts)
+}
+
+/// The start/finish line of `curve`, at the control point nearest the marker in `finish`
+fn finish_line(curve: &Curve, finish: &Mesh) -> f32 {
+    match finish.vertices.first() {
+        Some(vertex) => curve.nearest_ctrlp(Vec3::from(vertex.pos)) as f32,
+        None => 0.,
+    }
+}
+
+/// Read and check a path OBJ
+pub fn parse_path(obj: &str) -> Result<Curve, PathError> {
+    let PathObj {
+        ctrlps: mesh,
+        pads,
+        finish,
+    } = split_path_obj(obj).map_err(PathError::Obj)?;
+
+    let vertices = mesh.vertices.len();
+    if vertices % 4 != 0 {
+        return Err(PathError::IncompleteControlPoint { vertices });
+    }
+
+    let ctrlps = path_mesh_to_transforms(&mesh);
+    if ctrlps.len() < MIN_CTRLPS {
+        return Err(PathError::TooFewControlPoints {
+            found: ctrlps.len(),
+        });
+    }
+
+    let n = ctrlps.len();
+    for index in 0..n {
+        if ctrlps[index].pos == ctrlps[(index + 1) % n].pos {
+            return Err(PathError::CoincidentControlPoints { index });
+        }
+    }
+
+    let cross_sections = path_mesh_to_cross_sections(&mesh);
+    let cramped = cross_sections
+        .iter()
+        .position(|s| s.width.min(s.height) < MIN_CROSS_SECTION);
+    if let Some(index) = cramped {
+        return Err(PathError::CrampedControlPoint { index });
+    }
+
+    let vertices = pads.vertices.len();
+    if vertices % 4 != 0 {
+        return Err(PathError::IncompleteBoostPad { vertices });
+    }
+    let boost_pads = pads_from_mesh(&pads);
+    let degenerate = boost_pads
+        .iter()
+        .position(|pad| !transform_is_finite(&pad.transform));
+    if let Some(index) = degenerate {
+        return Err(PathError::DegenerateBoostPad { index });
+    }
+
+    if finish.vertices.len() > 1 {
+        return Err(PathError::AmbiguousFinishLine {
+            vertices: finish.vertices.len(),
+        });
+    }
+
+    let curve = Curve::with_cross_sections(ctrlps, cross_sections).with_boost_pads(boost_pads);
+    let finish_line = finish_line(&curve, &finish);
+    Ok(curve.with_finish_line(finish_line))
+}
+
+/// A path mesh known to be good, such as one built into the plugin
+pub fn path_mesh_to_curve(mesh: &Mesh) -> Curve {
+    Curve::with_cross_sections(
+        path_mesh_to_transforms(mesh),
+        path_mesh_to_cross_sections(mesh),
+    )
+}
+
+/// A path OBJ known to be good, such as one built into the plugin
+pub fn path_obj_to_curve(obj: &str) -> Curve {
+    let parts = split_path_obj(obj).expect("Invalid path OBJ");
+    let curve = path_mesh_to_curve(&parts.ctrlps).with_boost_pads(pads_from_mesh(&parts.pads));
+    let finish_line = finish_line(&curve, &parts.finish);
+    curve.with_finish_line(finish_line)
+}
+
 /// Extract position and orientation data from the specially designed path mesh
 pub fn path_mesh_to_transforms(mesh: &Mesh) -> Vec<Transform> {
     let mut transforms = vec![];
@@ -17,7 +223,8 @@ pub fn path_mesh_to_transforms(mesh: &Mesh) -> Vec<Transform> {
         let y = to_vect(2);
         let z = -to_vect(3);
 
-        let mat = Mat3::from_cols(-x, y, z);
+        // Scaled to the size of the track there; see `path_mesh_to_cross_sections`
+        let mat = Mat3::from_cols(-x.normalize(), y.normalize(), z.normalize());
         let orient = Quat::from_mat3(&mat);
 
         transforms.push(Transform {
@@ -29,13 +236,173 @@ pub fn path_mesh_to_transforms(mesh: &Mesh) -> Vec<Transform> {
     transforms
 }
 
+/// Size of the track at each control point of a path mesh. The lengths of a control point's
+/// Z and Y axes scale `TRACK_WIDTH` and `TRACK_HEIGHT`, so unit axes give the standard size.
+pub fn path_mesh_to_cross_sections(mesh: &Mesh) -> Vec<CrossSection> {
+    mesh.vertices
+        .chunks_exact(4)
+        .map(|axes| {
+            let origin = Vec3::from(axes[1].pos);
+            let length = |i: usize| (Vec3::from(axes[i].pos) - origin).length();
+            CrossSection {
+                width: TRACK_WIDTH * length(3),
+                height: TRACK_HEIGHT * length(2),
+            }
+        })
+        .collect()
+}
+
+/// Width and height of the track, centered on the curve (meters)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrossSection {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for CrossSection {
+    fn default() -> Self {
+        Self {
+            width: TRACK_WIDTH,
+            height: TRACK_HEIGHT,
+        }
+    }
+}
+
+impl CrossSection {
+    pub fn half_width(self) -> f32 {
+        self.width / 2.
+    }
+
+    pub fn half_height(self) -> f32 {
+        self.height / 2.
+    }
+
+    fn lerp(self, other: Self, u: f32) -> Self {
+        Self {
+            width: self.width + (other.width - self.width) * u,
+            height: self.height + (other.height - self.height) * u,
+        }
+    }
+}
+
 pub struct Curve {
     pub ctrlps: Vec<Transform>,
+    /// Size of the track at each control point
+    cross_sections: Vec<CrossSection>,
+    /// Distance along the curve from the first control point to each control point (meters)
+    arc: Vec<f32>,
+    /// Unit direction of the segment from each control point to the next
+    tangents: Vec<Vec3>,
+    /// `lerp` every `SAMPLE_SPACING` meters along the curve, from the first control point
+    samples: Vec<Transform>,
+    /// Length of the whole loop (meters)
+    length: f32,
+    pub boost_pads: Vec<BoostPad>,
+    /// Curve position of the start/finish line
+    finish_line: f32,
 }
 
 impl Curve {
+    /// A track of the standard size all the way round
     pub fn new(ctrlps: Vec<Transform>) -> Self {
-        Self { ctrlps }
+        let cross_sections = vec![CrossSection::default(); ctrlps.len()];
+        Self::with_cross_sections(ctrlps, cross_sections)
+    }
+
+    /// With one cross section for each control point
+    pub fn with_cross_sections(ctrlps: Vec<Transform>, cross_sections: Vec<CrossSection>) -> Self {
+        let n = ctrlps.len();
+        let mut arc = Vec::with_capacity(n);
+        let mut tangents = Vec::with_capacity(n);
+        let mut length = 0.;
+        for i in 0..n {
+            arc.push(length);
+            let segment = ctrlps[(i + 1) % n].pos - ctrlps[i].pos;
+            tangents.push(segment.normalize_or_zero());
+            length += segment.length();
+        }
+
+        let mut curve = Self {
+            ctrlps,
+            cross_sections,
+            arc,
+            tangents,
+            samples: vec![],
+            length,
+            boost_pads: vec![],
+            finish_line: 0.,
+        };
+        curve.samples = curve.sample_table();
+        curve
+    }
+
+    /// With boost pads on the track
+    pub fn with_boost_pads(mut self, boost_pads: Vec<BoostPad>) -> Self {
+        self.boost_pads = boost_pads;
+        self
+    }
+
+    /// With the start/finish line at curve position `t`, rather than the first control point
+    pub fn with_finish_line(mut self, t: f32) -> Self {
+        self.finish_line = t;
+        self
+    }
+
+    /// Curve position of the start/finish line, which laps, the grid and the signs along the
+    /// track are all measured from
+    pub fn finish_line(&self) -> f32 {
+        self.finish_line
+    }
+
+    /// The curve sampled every `SAMPLE_SPACING` meters, walking the segments once
+    fn sample_table(&self) -> Vec<Transform> {
+        let n = self.ctrlps.len();
+        let count = (self.length / SAMPLE_SPACING).ceil() as usize;
+        let mut samples = Vec::with_capacity(count);
+        let mut i = 0;
+        for k in 0..count {
+            let dist = k as f32 * SAMPLE_SPACING;
+            while i + 1 < n && self.arc[i + 1] <= dist {
+                i += 1;
+            }
+            let u = (dist - self.arc[i]) / self.segment_length(i);
+            samples.push(self.lerp(i as f32 + u.min(1.)));
+        }
+        samples
+    }
+
+    /// Length of the segment from control point `i` to the next (meters)
+    fn segment_length(&self, i: usize) -> f32 {
+        let n = self.ctrlps.len();
+        let end = match i + 1 < n {
+            true => self.arc[i + 1],
+            false => self.length,
+        };
+        (end - self.arc[i]).max(f32::EPSILON)
+    }
+
+    /// Length of the whole loop (meters)
+    pub fn length(&self) -> f32 {
+        self.length
+    }
+
+    /// Limit `dist` to the given fraction of the loop's length, so that distances tuned for
+    /// a full size track stay sensible on tiny ones
+    pub fn clamp_distance(&self, dist: f32, fraction: f32) -> f32 {
+        dist.min(self.length * fraction)
+    }
+
+    /// Distance from the first control point to `t`, along the curve (meters)
+    pub fn arc_length(&self, t: f32) -> f32 {
+        let t = t.rem_euclid(self.ctrlps.len() as f32);
+        let (behind, _) = self.index(t);
+        self.arc[behind] + t.fract() * self.segment_length(behind)
+    }
+
+    /// Distance along the loop between `a` and `b`, going whichever way is shorter (meters)
+    pub fn loop_distance(&self, a: f32, b: f32) -> f32 {
+        let d = (self.arc_length(a) - self.arc_length(b)).abs();
+        d.min(self.length - d)
     }
 
     /// Get the indices of the transforms behind and in front of the given t value respectively
@@ -56,6 +423,170 @@ impl Curve {
         self.ctrlps[behind].lerp_slerp(&self.ctrlps[in_front], t.fract())
     }
 
+    /// As `lerp`, but without a kink at each control point: position follows a Catmull-Rom
+    /// spline through the control points, and orientation a squad chain through theirs. Both
+    /// pass through the control points exactly, with matching tangents either side.
+    pub fn smooth_lerp(&self, t: f32) -> Transform {
+        let n = self.ctrlps.len();
+        let t = t.rem_euclid(n as f32);
+        let i = t.floor() as usize % n;
+        let u = t.fract();
+        let at = |offset: usize| self.ctrlps[(i + n + offset - 1) % n];
+        let (a, b, c, d) = (at(0), at(1), at(2), at(3));
+
+        let pos = catmull_rom(a.pos, b.pos, c.pos, d.pos, u);
+
+        // Each in the same hemisphere as the one before, so the chain never goes the long way
+        let qa = a.orient;
+        let qb = same_hemisphere(qa, b.orient);
+        let qc = same_hemisphere(qb, c.orient);
+        let qd = same_hemisphere(qc, d.orient);
+        let (sb, sc) = (squad_control(qa, qb, qc), squad_control(qb, qc, qd));
+        let orient = qb.slerp(qc, u).slerp(sb.slerp(sc, u), 2. * u * (1. - u));
+
+        Transform::new()
+            .with_position(pos)
+            .with_rotation(orient.normalize())
+    }
+
+    /// As `lerp`, but read from the sample table with one slerp between the samples either
+    /// side. Exact where both samples are on the same segment. Across a control point the
+    /// position cuts the corner by under `SAMPLE_SPACING / 2`, and the orientation is
+    /// between those of the two samples.
+    pub fn lerp_fast(&self, t: f32) -> Transform {
+        self.sample(self.arc_length(t))
+    }
+
+    /// The sample table read `dist` meters along the loop from the first control point
+    fn sample(&self, dist: f32) -> Transform {
+        let count = self.samples.len();
+        if count == 0 {
+            return self.lerp(0.);
+        }
+        let dist = dist.rem_euclid(self.length);
+        let k = ((dist / SAMPLE_SPACING) as usize).min(count - 1);
+        let start = k as f32 * SAMPLE_SPACING;
+        // The last sample is followed by a shorter gap back to the first
+        let end = match k + 1 < count {
+            true => start + SAMPLE_SPACING,
+            false => self.length,
+        };
+        let u = ((dist - start) / (end - start).max(f32::EPSILON)).clamp(0., 1.);
+        self.samples[k].lerp_slerp(&self.samples[(k + 1) % count], u)
+    }
+
+    /// Move `t` along the curve by `dist` meters (negative for backwards), wrapping around the loop
+    pub fn advance(&self, t: f32, dist: f32) -> f32 {
+        let n = self.ctrlps.len();
+        let t = t.rem_euclid(n as f32);
+        let mut i = t.floor() as usize % n;
+        let mut u = t.fract();
+        let mut remaining = dist.abs();
+
+        if dist >= 0. {
+            loop {
+                let len = self.segment_length(i);
+                let available = (1. - u) * len;
+                if remaining <= available {
+                    u += remaining / len;
+                    break;
+                }
+                remaining -= available;
+                i = (i + 1) % n;
+                u = 0.;
+            }
+        } else {
+            loop {
+                let len = self.segment_length(i);
+                let available = u * len;
+                if remaining <= available {
+                    u -= remaining / len;
+                    break;
+                }
+                remaining -= available;
+                i = (i + n - 1) % n;
+                u = 1.;
+            }
+        }
+
+        i as f32 + u
+    }
+
+    /// Size of the track at `t`, between those of the control points either side
+    pub fn cross_section_at(&self, t: f32) -> CrossSection {
+        let t = t.rem_euclid(self.ctrlps.len() as f32);
+        let (behind, in_front) = self.index(t);
+        self.cross_sections[behind].lerp(self.cross_sections[in_front], t.fract())
+    }
+
+    /// Size of the track `along` meters ahead of control point `idx` (negative for behind),
+    /// towards that of the neighboring control point in that direction
+    pub fn cross_section_near(&self, idx: usize, along: f32) -> CrossSection {
+        let n = self.ctrlps.len();
+        let (neighbor, segment) = match along >= 0. {
+            true => ((idx + 1) % n, idx),
+            false => ((idx + n - 1) % n, (idx + n - 1) % n),
+        };
+        let u = (along.abs() / self.segment_length(segment)).min(1.);
+        self.cross_sections[idx].lerp(self.cross_sections[neighbor], u)
+    }
+
+    /// How sharply the curve turns between the control points around `t` (radians per meter)
+    pub fn curvature_at(&self, t: f32) -> f32 {
+        let (behind, in_front) = self.index(t.rem_euclid(self.ctrlps.len() as f32));
+        let (a, b) = (self.ctrlps[behind], self.ctrlps[in_front]);
+        let dist = (b.pos - a.pos).length().max(f32::EPSILON);
+        (a.orient * Vec3::X).angle_between(b.orient * Vec3::X) / dist
+    }
+
+    /// Number of distinct stretches which turn more sharply than `threshold` (radians per
+    /// meter). A loop which turns that sharply all the way round is one long corner.
+    pub fn corner_count(&self, threshold: f32) -> usize {
+        let n = self.ctrlps.len();
+        let sharp: Vec<bool> = (0..n)
+            .map(|i| self.curvature_at(i as f32) > threshold)
+            .collect();
+        let entries = (0..n)
+            .filter(|&i| sharp[i] && !sharp[(i + n - 1) % n])
+            .count();
+        match entries == 0 && sharp.iter().any(|&s| s) {
+            true => 1,
+            false => entries,
+        }
+    }
+
+    /// Lowest and highest control points, as (min, max) heights (meters)
+    pub fn elevation_range(&self) -> (f32, f32) {
+        let (min, max) = self.bounds();
+        (min.y, max.y)
+    }
+
+    /// The track's center line as a closed loop of lines, following `smooth_lerp`, with each
+    /// position multiplied by `scale`; e.g. a zero Y component flattens it onto the ground
+    pub fn to_line_mesh(&self, scale: Vec3, color: [f32; 3]) -> Mesh {
+        let mut m = Mesh::new();
+        let count = self.ctrlps.len() * LINE_MESH_STEPS;
+        let points: Vec<u32> = (0..count)
+            .map(|k| {
+                let pos = self.smooth_lerp(k as f32 / LINE_MESH_STEPS as f32).pos * scale;
+                m.push_vertex(Vertex::new(pos.into(), color))
+            })
+            .collect();
+        for k in 0..points.len() {
+            m.indices
+                .extend([points[k], points[(k + 1) % points.len()]]);
+        }
+        m
+    }
+
+    /// Axis-aligned bounding box of the control points, as (min, max)
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        self.ctrlps.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), tf| (min.min(tf.pos), max.max(tf.pos)),
+        )
+    }
+
     /// Estimates the nearest curve index `t` to the given 3D position.
     /// Increasing iterations increases accuracy at the cost of performance
     pub fn nearest_ctrlp(&self, pt: Vec3) -> usize {
@@ -72,4 +603,320 @@ impl Curve {
 
         smallest_idx
     }
+
+    /// Curve index `t` of the point on the curve nearest to `pt`, between control points
+    pub fn nearest_t(&self, pt: Vec3) -> f32 {
+        self.nearest_point(pt).0
+    }
+
+    /// As `nearest_t`, but keeping to the part of the track around `last`, the previous
+    /// answer, unless somewhere else is much nearer. Where the track passes close to itself,
+    /// e.g. at a crossing, this follows the ship rather than whichever part it's nearer.
+    pub fn nearest_t_from(&self, pt: Vec3, last: f32) -> f32 {
+        let n = self.ctrlps.len();
+        let last = last.rem_euclid(n as f32) as usize % n;
+        let window = CONTINUITY_WINDOW.min(n / 2);
+        let (near_t, near_dist) = (0..2 * window)
+            .map(|k| self.segment_point((last + n - window + k) % n, pt))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0., f32::MAX));
+
+        let (t, dist) = self.nearest_point(pt);
+        match near_dist <= dist + CONTINUITY_SLACK {
+            true => near_t,
+            false => t,
+        }
+    }
+
+    /// Curve index of the nearest point on the curve to `pt`, and its distance from it
+    fn nearest_point(&self, pt: Vec3) -> (f32, f32) {
+        let n = self.ctrlps.len();
+        let i = self.nearest_ctrlp(pt);
+
+        // The nearest point is on one of the segments either side of the nearest control point
+        [(i + n - 1) % n, i]
+            .into_iter()
+            .map(|start| self.segment_point(start, pt))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0., f32::MAX))
+    }
+
+    /// Curve index of the nearest point to `pt` on the segment from control point `start`,
+    /// and its distance from it
+    fn segment_point(&self, start: usize, pt: Vec3) -> (f32, f32) {
+        let a = self.ctrlps[start].pos;
+        let len = self.segment_length(start);
+        let along = self.tangents[start].dot(pt - a).clamp(0., len);
+        let u = along / len;
+        (
+            start as f32 + u,
+            pt.distance(a + self.tangents[start] * along),
+        )
+    }
+
+    /// Project `pt` onto the plane of the nearest control point, whose local Y is up from the
+    /// track. Returns the point on the plane, oriented with the track, and the height of `pt`
+    /// above it (negative below).
+    pub fn project(&self, pt: Vec3) -> (Transform, f32) {
+        let frame = self.ctrlps[self.nearest_ctrlp(pt)];
+        let mut local = frame.orient.inverse() * (pt - frame.pos);
+        let height = local.y;
+        local.y = 0.;
+        let tf = Transform::new()
+            .with_position(frame.pos + frame.orient * local)
+            .with_rotation(frame.orient);
+        (tf, height)
+    }
+}
+
+/// Uniform Catmull-Rom spline between `b` and `c`, at `u` from 0 to 1
+fn catmull_rom(a: Vec3, b: Vec3, c: Vec3, d: Vec3, u: f32) -> Vec3 {
+    let (u2, u3) = (u * u, u * u * u);
+    ((2. * b) + (c - a) * u + (2. * a - 5. * b + 4. * c - d) * u2 + (3. * b - a - 3. * c + d) * u3)
+        * 0.5
+}
+
+fn same_hemisphere(prev: Quat, q: Quat) -> Quat {
+    match prev.dot(q) < 0. {
+        true => -q,
+        false => q,
+    }
+}
+
+/// Inner control point of a squad chain at `q`, between `prev` and `next`
+fn squad_control(prev: Quat, q: Quat, next: Quat) -> Quat {
+    let inv = q.inverse();
+    let tangent = (quat_log(inv * next) + quat_log(inv * prev)) * -0.25;
+    q * quat_exp(tangent)
+}
+
+/// Logarithm of a unit quaternion, as its vector part
+fn quat_log(q: Quat) -> Vec3 {
+    let (axis, angle) = q.to_axis_angle();
+    axis * angle / 2.
+}
+
+/// Inverse of `quat_log`
+fn quat_exp(v: Vec3) -> Quat {
+    let half_angle = v.length();
+    match half_angle > f32::EPSILON {
+        true => Quat::from_axis_angle(v / half_angle, half_angle * 2.),
+        false => Quat::IDENTITY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_4, PI, TAU};
+
+    use super::*;
+    use crate::harness;
+
+    /// Step either side of a control point to take slopes over
+    const H: f32 = 1e-3;
+
+    /// A circle of control points which rises and falls, and banks differently at each
+    fn wobbly_loop() -> Curve {
+        let n = 12;
+        let ctrlps = (0..n)
+            .map(|i| {
+                let angle = i as f32 / n as f32 * std::f32::consts::TAU;
+                let pos = Vec3::new(
+                    angle.cos() * 50.,
+                    (angle * 3.).sin() * 5.,
+                    angle.sin() * 50.,
+                );
+                let bank = Quat::from_rotation_x((i as f32 * 1.7).sin() * 0.6);
+                let heading = Quat::from_rotation_y(-angle - std::f32::consts::FRAC_PI_2);
+                Transform::new()
+                    .with_position(pos)
+                    .with_rotation(heading * bank)
+            })
+            .collect();
+        Curve::new(ctrlps)
+    }
+
+    /// Small rotation from `a` to `b`, as its axis scaled by its angle (radians). Read off the
+    /// vector part, since the angle from `w` alone is lost to rounding this close to zero.
+    fn turn(a: Quat, b: Quat) -> Vec3 {
+        same_hemisphere(Quat::IDENTITY, b * a.inverse()).xyz() * 2.
+    }
+
+    #[test]
+    fn smooth_lerp_passes_through_the_control_points() {
+        let curve = wobbly_loop();
+        for (i, ctrlp) in curve.ctrlps.iter().enumerate() {
+            let at = curve.smooth_lerp(i as f32);
+            assert!((at.pos - ctrlp.pos).length() < 1e-3, "control point {}", i);
+            assert!(
+                at.orient.dot(ctrlp.orient).abs() > 1. - 1e-5,
+                "control point {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn smooth_lerp_is_c1_at_the_control_points() {
+        let curve = wobbly_loop();
+        for i in 0..curve.ctrlps.len() {
+            let t = i as f32;
+            let (before, at, after) = (
+                curve.smooth_lerp(t - H),
+                curve.smooth_lerp(t),
+                curve.smooth_lerp(t + H),
+            );
+
+            let slope_in = (at.pos - before.pos) / H;
+            let slope_out = (after.pos - at.pos) / H;
+            let kink = (slope_out - slope_in).length();
+            assert!(
+                kink < slope_in.length() * 0.01,
+                "control point {}: {}",
+                i,
+                kink
+            );
+
+            let spin_in = turn(before.orient, at.orient) / H;
+            let spin_out = turn(at.orient, after.orient) / H;
+            let kink = (spin_out - spin_in).length();
+            assert!(
+                kink < spin_in.length() * 0.02 + 1e-2,
+                "control point {}: {}",
+                i,
+                kink
+            );
+        }
+    }
+
+    #[test]
+    fn plain_lerp_kinks_where_smooth_lerp_does_not() {
+        let curve = wobbly_loop();
+        let slopes = |at: &dyn Fn(f32) -> Transform| {
+            let out = (at(1. + H).pos - at(1.).pos) / H;
+            let into = (at(1.).pos - at(1. - H).pos) / H;
+            (out - into).length() / into.length()
+        };
+        assert!(slopes(&|t| curve.lerp(t)) > 0.1);
+        assert!(slopes(&|t| curve.smooth_lerp(t)) < 0.01);
+    }
+
+    const SIDE: f32 = 100.;
+
+    /// A flat square of side `SIDE`, with control points at its corners and a third of the way
+    /// along each side, so that it's straight between the corners
+    fn square() -> Curve {
+        let corners = [Vec3::ZERO, Vec3::X, Vec3::X + Vec3::Z, Vec3::Z].map(|c| c * SIDE);
+        let points: Vec<Vec3> = (0..4)
+            .flat_map(|i| {
+                let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                [a, a.lerp(b, 1. / 3.), a.lerp(b, 2. / 3.)]
+            })
+            .collect();
+        harness::track(&points)
+    }
+
+    #[test]
+    fn a_square_is_four_sides_long_with_four_corners() {
+        let curve = square();
+        assert!(
+            (curve.length() - 4. * SIDE).abs() < 1e-3,
+            "{}",
+            curve.length()
+        );
+        assert!((curve.arc_length(3.) - SIDE).abs() < 1e-3);
+        assert!((curve.arc_length(1.5) - SIDE / 2.).abs() < 1e-3);
+
+        // Turning by 45 degrees over a third of a side into and out of each corner
+        let turn = FRAC_PI_4 / (SIDE / 3.);
+        assert!((curve.curvature_at(2.5) - turn).abs() < turn * 1e-3);
+        assert!((curve.curvature_at(3.5) - turn).abs() < turn * 1e-3);
+        assert!(curve.curvature_at(1.5) < turn * 1e-2);
+        assert_eq!(curve.corner_count(turn / 2.), 4);
+        assert_eq!(curve.corner_count(turn * 2.), 0);
+
+        assert_eq!(curve.elevation_range(), (0., 0.));
+        assert_eq!(curve.bounds(), (Vec3::ZERO, Vec3::new(SIDE, 0., SIDE)));
+    }
+
+    #[test]
+    fn a_circle_is_one_long_corner() {
+        let (n, radius) = (32, 100.);
+        let curve = harness::ring(n, radius);
+        let chord = 2. * radius * (PI / n as f32).sin();
+        assert!(
+            (curve.length() - n as f32 * chord).abs() < 1e-2,
+            "{}",
+            curve.length()
+        );
+
+        let turn = TAU / n as f32 / chord;
+        for i in 0..n {
+            assert!(
+                (curve.curvature_at(i as f32) - turn).abs() < turn * 1e-3,
+                "{}",
+                i
+            );
+        }
+        assert_eq!(curve.corner_count(turn / 2.), 1);
+        assert_eq!(curve.corner_count(turn * 2.), 0);
+    }
+
+    #[test]
+    fn elevation_range_is_the_lowest_and_highest_points() {
+        // A circle rising and falling by `height` once round, at its quarter points exactly
+        let (n, height) = (16, 10.);
+        let points: Vec<Vec3> = (0..n)
+            .map(|i| {
+                let angle = i as f32 / n as f32 * TAU;
+                Vec3::new(angle.cos() * 100., angle.sin() * height, angle.sin() * 100.)
+            })
+            .collect();
+        let (low, high) = harness::track(&points).elevation_range();
+        assert!((low + height).abs() < 1e-4 && (high - height).abs() < 1e-4);
+    }
+
+    #[test]
+    fn lerp_fast_agrees_with_lerp_on_loop1() {
+        let curve = harness::loop1();
+        let n = curve.ctrlps.len();
+        for k in 0..n * 100 {
+            let t = k as f32 / 100.;
+            let (exact, fast) = (curve.lerp(t), curve.lerp_fast(t));
+            let off = exact.pos.distance(fast.pos);
+            let turned = exact.orient.angle_between(fast.orient);
+
+            // Exact away from the control points, and only cutting the corners at them
+            let (behind, _) = curve.index(t);
+            let along = t.fract() * curve.segment_length(behind);
+            let clear = along.min(curve.segment_length(behind) - along) > SAMPLE_SPACING;
+            let limit = if clear { 2e-3 } else { SAMPLE_SPACING / 2. };
+            assert!(off < limit, "{} m off at {}", off, t);
+            assert!(turned < 0.01, "{} radians off at {}", turned, t);
+        }
+    }
+
+    #[test]
+    fn sample_tables_for_big_tracks_are_quick_and_small() {
+        // Two thousand 10 m segments
+        let (n, segment) = (2000, 10.);
+        let radius = segment / (2. * (PI / n as f32).sin());
+        let start = std::time::Instant::now();
+        let curve = harness::ring(n, radius);
+        let took = start.elapsed();
+
+        let bytes = curve.samples.len() * std::mem::size_of::<Transform>();
+        println!(
+            "{} point track: {} samples, {:.1} MB, built in {:?}",
+            n,
+            curve.samples.len(),
+            bytes as f32 / 1e6,
+            took
+        );
+        let expected = (n as f32 * segment / SAMPLE_SPACING) as usize;
+        assert!(curve.samples.len().abs_diff(expected) <= 1);
+        assert!(bytes < 4_000_000, "{} bytes", bytes);
+        // Generous, as tests are built without optimizations
+        assert!(took.as_secs_f32() < 2., "{:?}", took);
+    }
 }
@@ -0,0 +1,243 @@
+//! Small, awkward tracks generated from formulas, for developing features which need to cope
+//! with any shape of track. Enabled with the `dev-tracks` feature, and loaded with `/devtrack`.
+// Also built for the tests of the curves' shapes, without the commands which load them
+#![cfg_attr(not(feature = "dev-tracks"), allow(dead_code))]
+use std::f32::consts::{PI, TAU};
+
+use cimvr_common::{
+    glam::{Mat3, Quat, Vec3},
+    Transform,
+};
+
+use crate::{controls::TRACK_HEIGHT, curve::Curve, track::TrackFiles};
+
+/// Radius of the circle, and of the loops in most of the others (meters)
+const RADIUS: f32 = 300.;
+/// Control points round the circle; its length is `2 * RADIUS * sin(PI / n)` per point
+const CIRCLE_POINTS: usize = 64;
+/// Length of the straights either side of the hairpins (meters)
+const HAIRPIN_STRAIGHT: f32 = 400.;
+/// Radius of the hairpins, a little over the track's half width (meters)
+const HAIRPIN_RADIUS: f32 = 20.;
+/// Height of the hills on the climbing track, either side of level (meters)
+const CLIMB_HEIGHT: f32 = 150.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DevTrack {
+    /// Constant curvature of `1 / RADIUS` all the way round
+    Circle,
+    /// Crosses itself at the middle, one track height above the other, so two parts of the
+    /// loop are close together but half a lap apart
+    FigureEight,
+    /// Two long straights joined by 180 degree turns of `HAIRPIN_RADIUS`
+    Hairpin,
+    /// A circle, rising and falling twice by `CLIMB_HEIGHT` each lap
+    Climb,
+    /// The fewest control points which make a usable loop: a pentagon
+    Minimal,
+}
+
+impl DevTrack {
+    pub const ALL: [DevTrack; 5] = [
+        DevTrack::Circle,
+        DevTrack::FigureEight,
+        DevTrack::Hairpin,
+        DevTrack::Climb,
+        DevTrack::Minimal,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DevTrack::Circle => "circle",
+            DevTrack::FigureEight => "eight",
+            DevTrack::Hairpin => "hairpin",
+            DevTrack::Climb => "climb",
+            DevTrack::Minimal => "minimal",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|track| track.name() == name)
+    }
+
+    /// Position of each control point, in order round the loop
+    fn points(self) -> Vec<Vec3> {
+        let around = |n: usize| (0..n).map(move |i| i as f32 / n as f32 * TAU);
+        match self {
+            DevTrack::Circle => around(CIRCLE_POINTS)
+                .map(|a| Vec3::new(a.cos(), 0., a.sin()) * RADIUS)
+                .collect(),
+            DevTrack::FigureEight => around(CIRCLE_POINTS)
+                .map(|a| {
+                    let y = a.cos() * TRACK_HEIGHT;
+                    Vec3::new(a.sin() * RADIUS, y, a.sin() * a.cos() * RADIUS)
+                })
+                .collect(),
+            DevTrack::Hairpin => hairpin_points(),
+            DevTrack::Climb => around(CIRCLE_POINTS)
+                .map(|a| {
+                    let y = (2. * a).sin() * CLIMB_HEIGHT;
+                    Vec3::new(a.cos() * RADIUS, y, a.sin() * RADIUS)
+                })
+                .collect(),
+            DevTrack::Minimal => around(5)
+                .map(|a| Vec3::new(a.cos(), 0., a.sin()) * RADIUS)
+                .collect(),
+        }
+    }
+
+    pub fn curve(self) -> Curve {
+        Curve::new(frames(&self.points()))
+    }
+
+    /// The track as files, the same as an upload of it would be. There's no environment.
+    pub fn files(self) -> TrackFiles {
+        TrackFiles {
+            path: path_obj(&self.curve().ctrlps),
+            environment: String::new(),
+        }
+    }
+}
+
+/// Straight, hairpin, straight back, hairpin
+fn hairpin_points() -> Vec<Vec3> {
+    const STRAIGHT_POINTS: usize = 16;
+    const TURN_POINTS: usize = 8;
+    let mut points = vec![];
+    for (side, dir) in [(-1., 1.), (1., -1.)] {
+        for i in 0..STRAIGHT_POINTS {
+            let x = (i as f32 / STRAIGHT_POINTS as f32 - 0.5) * HAIRPIN_STRAIGHT * dir;
+            points.push(Vec3::new(x, 0., side * HAIRPIN_RADIUS));
+        }
+        let end = HAIRPIN_STRAIGHT / 2. * dir;
+        for i in 0..TURN_POINTS {
+            let a = i as f32 / TURN_POINTS as f32 * PI;
+            let offset = Vec3::new(a.sin() * dir, 0., a.cos() * side);
+            points.push(Vec3::new(end, 0., 0.) + offset * HAIRPIN_RADIUS);
+        }
+    }
+    points
+}
+
+/// Control points at `points`, each facing the next with its Y axis as near to up as it goes
+fn frames(points: &[Vec3]) -> Vec<Transform> {
+    let n = points.len();
+    (0..n)
+        .map(|i| {
+            let x = (points[(i + 1) % n] - points[(i + n - 1) % n]).normalize();
+            let y = (Vec3::Y - x * x.y).normalize();
+            let z = x.cross(y);
+            Transform {
+                pos: points[i],
+                orient: Quat::from_mat3(&Mat3::from_cols(x, y, z)),
+            }
+        })
+        .collect()
+}
+
+/// The path OBJ which `curve::parse_path` reads back into `ctrlps`: four vertices for each,
+/// at the tip of its X axis, its origin, the tip of its Y axis and the tip of its -Z axis
+fn path_obj(ctrlps: &[Transform]) -> String {
+    let mut obj = String::new();
+    for tf in ctrlps {
+        let axes = [Vec3::X, Vec3::ZERO, Vec3::Y, -Vec3::Z];
+        for axis in axes {
+            let v = tf.pos + tf.orient * axis;
+            obj.push_str(&format!("v {} {} {}\n", v.x, v.y, v.z));
+        }
+    }
+    obj
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `a` is within a `tolerance` fraction of `b`
+    fn close(a: f32, b: f32, tolerance: f32) -> bool {
+        (a - b).abs() <= tolerance * b.abs()
+    }
+
+    /// Length of `n` equal chords round a circle of `radius`
+    fn polygon_length(n: usize, radius: f32) -> f32 {
+        n as f32 * 2. * radius * (PI / n as f32).sin()
+    }
+
+    #[test]
+    fn the_circle_is_a_circle() {
+        let curve = DevTrack::Circle.curve();
+        let length = polygon_length(CIRCLE_POINTS, RADIUS);
+        assert!(close(curve.length(), length, 1e-4), "{}", curve.length());
+        assert!(close(
+            curve.arc_length(CIRCLE_POINTS as f32 / 4.),
+            length / 4.,
+            1e-4
+        ));
+
+        // Each control point turns by the same angle over the same chord
+        let chord = length / CIRCLE_POINTS as f32;
+        let curvature = TAU / CIRCLE_POINTS as f32 / chord;
+        for i in 0..CIRCLE_POINTS {
+            let at = curve.curvature_at(i as f32);
+            assert!(close(at, curvature, 1e-3), "{}: {}", i, at);
+        }
+        assert!(close(curvature, 1. / RADIUS, 1e-3));
+        assert_eq!(curve.corner_count(0.5 / RADIUS), 1);
+        assert_eq!(curve.corner_count(2. / RADIUS), 0);
+        assert_eq!(curve.elevation_range(), (0., 0.));
+    }
+
+    #[test]
+    fn the_hairpin_has_two_tight_turns() {
+        let curve = DevTrack::Hairpin.curve();
+        // Each half is a straight and eight chords round half a circle
+        let length = 2. * (HAIRPIN_STRAIGHT + polygon_length(16, HAIRPIN_RADIUS) / 2.);
+        assert!(close(curve.length(), length, 1e-4), "{}", curve.length());
+
+        let n = curve.ctrlps.len();
+        let tightest = (0..n)
+            .map(|i| curve.curvature_at(i as f32))
+            .fold(0., f32::max);
+        assert!(close(tightest, 1. / HAIRPIN_RADIUS, 0.1), "{}", tightest);
+        assert_eq!(curve.corner_count(0.5 / HAIRPIN_RADIUS), 2);
+    }
+
+    #[test]
+    fn the_climb_and_the_eight_reach_their_heights() {
+        let (low, high) = DevTrack::Climb.curve().elevation_range();
+        assert!(close(low, -CLIMB_HEIGHT, 1e-4) && close(high, CLIMB_HEIGHT, 1e-4));
+        let (low, high) = DevTrack::FigureEight.curve().elevation_range();
+        assert!(close(low, -TRACK_HEIGHT, 1e-4) && close(high, TRACK_HEIGHT, 1e-4));
+    }
+
+    #[test]
+    fn the_minimal_track_is_a_pentagon() {
+        let curve = DevTrack::Minimal.curve();
+        assert_eq!(curve.ctrlps.len(), 5);
+        assert!(close(curve.length(), polygon_length(5, RADIUS), 1e-4));
+        assert_eq!(curve.corner_count(0.), 1);
+    }
+
+    #[test]
+    fn following_the_eight_stays_on_its_branch_through_the_crossing() {
+        // Either crossing, from a ship nearer the other branch than its own
+        let curve = DevTrack::FigureEight.curve();
+        let n = CIRCLE_POINTS as f32;
+        let drop = 1.2 * TRACK_HEIGHT;
+        for (crossing, below) in [(0., -Vec3::Y), (n / 2., Vec3::Y)] {
+            let (mut t, mut last) = (crossing + n - 2., crossing + n - 2.);
+            let mut jumped = false;
+            while t < crossing + n + 2. {
+                let pos = curve.lerp(t).pos + below * drop;
+                last = curve.nearest_t_from(pos, last);
+                let off = curve.loop_distance(last, t);
+                assert!(off < 2., "{} m off at {}, crossing at {}", off, t, crossing);
+
+                // Whereas the nearest point overall is on the other branch
+                jumped |= curve.loop_distance(curve.nearest_t(pos), t) > curve.length() / 4.;
+                t += 0.05;
+            }
+            assert!(jumped, "crossing at {}", crossing);
+        }
+    }
+}
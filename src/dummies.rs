@@ -0,0 +1,267 @@
+//! Practice grid: dummy ships around the track, for trying out collisions, drafting and items
+//! without a second player. They only ever exist on this client; nothing here is uploaded,
+//! and with neither ship component they're left out of the standings and the spectator.
+use cimvr_common::{
+    glam::Vec3,
+    ui::{Schema, State, UiHandle, UiStateHelper},
+    Transform,
+};
+use cimvr_engine_interface::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    curve::Curve,
+    ghost::{self, Run},
+    hud::HudVisibility,
+    rolling_start::RACE_SPEED,
+    ship_class::ShipClass,
+};
+
+/// Dummies in each preset
+const DUMMY_COUNT: usize = 8;
+/// Distance across the track between the two lines of the slalom (meters)
+const SLALOM_OFFSET: f32 = 8.;
+/// Fraction of race speed the pace line moves at
+const PACE_LINE_FRACTION: f32 = 0.8;
+/// Starting distance along the track between dummies, and the step the panel changes it by
+/// (meters)
+const DEFAULT_SPACING: f32 = 40.;
+const SPACING_STEP: f32 = 10.;
+
+/// Marks a dummy ship of the practice grid
+#[derive(Component, Serialize, Deserialize, Default, Copy, Clone, PartialEq, Eq)]
+pub struct DummyShipComponent;
+
+/// Where a dummy goes, relative to the local ship when the grid is spawned
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DummyPlacement {
+    /// Distance along the track ahead of the local ship (meters)
+    pub ahead: f32,
+    /// Distance across the track from the center line (meters)
+    pub lateral: f32,
+    /// Speed to follow the track at (meters per second); `None` stands still
+    pub speed: Option<f32>,
+}
+
+/// Stationary dummies alternating either side of the center line, to weave between
+fn slalom_line(spacing: f32) -> Vec<DummyPlacement> {
+    (0..DUMMY_COUNT)
+        .map(|i| DummyPlacement {
+            ahead: (i + 1) as f32 * spacing,
+            lateral: if i % 2 == 0 {
+                SLALOM_OFFSET
+            } else {
+                -SLALOM_OFFSET
+            },
+            speed: None,
+        })
+        .collect()
+}
+
+/// Dummies in single file down the center line, lapping below race speed, to draft and pass
+fn pace_line(spacing: f32) -> Vec<DummyPlacement> {
+    (0..DUMMY_COUNT)
+        .map(|i| DummyPlacement {
+            ahead: (i + 1) as f32 * spacing,
+            lateral: 0.,
+            speed: Some(RACE_SPEED * PACE_LINE_FRACTION),
+        })
+        .collect()
+}
+
+/// Names and layouts of the grids, in panel order
+const PRESETS: &[(&str, fn(f32) -> Vec<DummyPlacement>)] = &[
+    ("Slalom line", slalom_line),
+    ("Pace line at 80%", pace_line),
+];
+
+enum Motion {
+    Parked(Transform),
+    /// Lapping along a run, from the time it was spawned
+    Paced(Run),
+}
+
+struct Dummy {
+    entity: EntityId,
+    motion: Motion,
+    /// Where the dummy is now
+    tf: Transform,
+}
+
+/// The dummy ships out on the track
+#[derive(Default)]
+pub struct DummyShips {
+    dummies: Vec<Dummy>,
+    /// Seconds since the grid was spawned, which paced dummies count their laps from
+    clock: f32,
+}
+
+impl DummyShips {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the grid with dummies at `placements`, counted from the track nearest `from`
+    pub fn spawn(
+        &mut self,
+        io: &mut EngineIo,
+        path: &Curve,
+        from: Vec3,
+        placements: &[DummyPlacement],
+    ) {
+        self.clear(io);
+        self.clock = 0.;
+        let start = path.nearest_t(from);
+        let class = ShipClass::default();
+        for placement in placements {
+            let t = path.advance(start, placement.ahead);
+            let motion = match placement.speed {
+                Some(speed) => Motion::Paced(Run::paced(path, t, placement.lateral, speed, class)),
+                None => {
+                    let offset = Transform::new().with_position(Vec3::Z * placement.lateral);
+                    Motion::Parked(path.lerp_fast(t) * offset)
+                }
+            };
+            let tf = match &motion {
+                Motion::Parked(tf) => *tf,
+                Motion::Paced(run) => run.at(0.).unwrap_or_else(Transform::identity),
+            };
+            let entity = io
+                .create_entity()
+                .add_component(tf)
+                .add_component(HudVisibility::render(true, class.render()))
+                .add_component(DummyShipComponent)
+                .build();
+            self.dummies.push(Dummy { entity, motion, tf });
+        }
+    }
+
+    /// Move the paced dummies on by `dt`
+    pub fn update(&mut self, io: &mut EngineIo, dt: f32) {
+        self.clock += dt;
+        for dummy in &mut self.dummies {
+            let Motion::Paced(run) = &dummy.motion else {
+                continue;
+            };
+            let lap_time = self.clock.rem_euclid(run.duration().max(f32::EPSILON));
+            if let Some(tf) = run.at(lap_time) {
+                dummy.tf = tf;
+                io.add_component(dummy.entity, tf);
+            }
+        }
+    }
+
+    pub fn clear(&mut self, io: &mut EngineIo) {
+        for dummy in self.dummies.drain(..) {
+            io.remove_entity(dummy.entity);
+        }
+    }
+
+    /// Thrust multiplier for a ship at `pos`, from slipstreaming the best placed dummy
+    pub fn draft_factor(&self, pos: Vec3) -> f32 {
+        self.dummies
+            .iter()
+            .map(|dummy| ghost::draft_factor(dummy.tf, pos))
+            .fold(1., f32::max)
+    }
+}
+
+/// What a press on the practice grid panel asked for
+pub enum DummyAction {
+    Spawn(Vec<DummyPlacement>),
+    Clear,
+}
+
+/// The spacing label and its "-" and "+" buttons, then a button per preset, then "Clear"
+const PRESET_ELEMENT: usize = 3;
+const CLEAR_ELEMENT: usize = PRESET_ELEMENT + PRESETS.len();
+
+/// Developer panel for spawning and clearing the practice grid
+pub struct DummyPanel {
+    /// Removed while the HUD is hidden
+    element: Option<UiHandle>,
+    spacing: f32,
+}
+
+impl DummyPanel {
+    pub fn new(io: &mut EngineIo, gui: &mut UiStateHelper) -> Self {
+        Self {
+            element: Some(Self::add(io, gui, DEFAULT_SPACING)),
+            spacing: DEFAULT_SPACING,
+        }
+    }
+
+    fn add(io: &mut EngineIo, gui: &mut UiStateHelper, spacing: f32) -> UiHandle {
+        let mut schema = vec![
+            Schema::Label,
+            Schema::Button { text: "-".into() },
+            Schema::Button { text: "+".into() },
+        ];
+        let mut init_state = vec![
+            State::Label {
+                text: spacing_text(spacing),
+            },
+            State::Button { clicked: false },
+            State::Button { clicked: false },
+        ];
+        for &(name, _) in PRESETS {
+            schema.push(Schema::Button { text: name.into() });
+            init_state.push(State::Button { clicked: false });
+        }
+        schema.push(Schema::Button {
+            text: "Clear dummies".into(),
+        });
+        init_state.push(State::Button { clicked: false });
+
+        gui.add(io, "Practice grid", schema, init_state)
+    }
+
+    pub fn set_visible(&mut self, io: &mut EngineIo, gui: &mut UiStateHelper, visible: bool) {
+        match (visible, self.element) {
+            (true, None) => self.element = Some(Self::add(io, gui, self.spacing)),
+            (false, Some(handle)) => {
+                gui.delete(io, handle);
+                self.element = None;
+            }
+            _ => (),
+        }
+    }
+
+    /// Apply button presses. Expects `gui` to already be downloaded.
+    pub fn update(&mut self, io: &mut EngineIo, gui: &mut UiStateHelper) -> Option<DummyAction> {
+        let element = self.element?;
+
+        let clicked: Vec<bool> = gui
+            .read(element)
+            .iter()
+            .map(|state| *state == State::Button { clicked: true })
+            .collect();
+
+        let spacing = match (clicked[1], clicked[2]) {
+            (true, false) => (self.spacing - SPACING_STEP).max(SPACING_STEP),
+            (false, true) => self.spacing + SPACING_STEP,
+            _ => self.spacing,
+        };
+        if spacing != self.spacing {
+            self.spacing = spacing;
+            gui.modify(io, element, |ui_state| {
+                ui_state[0] = State::Label {
+                    text: spacing_text(spacing),
+                }
+            });
+        }
+
+        if clicked[CLEAR_ELEMENT] {
+            return Some(DummyAction::Clear);
+        }
+        PRESETS
+            .iter()
+            .enumerate()
+            .find(|&(idx, _)| clicked[PRESET_ELEMENT + idx])
+            .map(|(_, (_, layout))| DummyAction::Spawn(layout(self.spacing)))
+    }
+}
+
+fn spacing_text(spacing: f32) -> String {
+    format!("Spacing: {:.0} m", spacing)
+}
@@ -0,0 +1,184 @@
+//! Engine sound, as data for an audio plugin to turn into pitch and volume
+use std::collections::{HashMap, HashSet};
+
+use cimvr_common::{
+    glam::Vec3,
+    render::{Mesh, MeshHandle, Primitive, Render, UploadMesh, Vertex},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{controls::BOOST_FACTOR, countdown::color_extra, EngineState, SoundCue};
+
+/// Time between engine updates for each ship (seconds)
+const INTERVAL: f32 = 0.25;
+/// Ships further than this from the camera can't be heard (meters)
+const MAX_DISTANCE: f32 = 150.;
+/// Speed which counts as flat out (meters per second)
+const REFERENCE_SPEED: f32 = 80.;
+/// Acceleration of a ship at full throttle, for guessing other ships' throttles
+/// (meters per second squared)
+const REFERENCE_ACCEL: f32 = 30.;
+
+/// What we know about a ship's engine this frame
+pub struct EngineSample {
+    /// `None` for the local ship
+    pub client: Option<ClientId>,
+    pub pos: Vec3,
+    pub vel: Vec3,
+    /// Throttle and boost, when we know them; other ships' are guessed from how they speed up
+    pub controls: Option<(f32, bool)>,
+}
+
+/// When each ship's engine was last sent
+#[derive(Clone, Copy)]
+struct Sent {
+    time: f32,
+    speed: f32,
+}
+
+/// Sends `SoundCue::Engine` for ships near the camera, a few times a second each
+#[derive(Default)]
+pub struct EngineSounds {
+    sent: HashMap<Option<ClientId>, Sent>,
+}
+
+impl EngineSounds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        samples: impl IntoIterator<Item = EngineSample>,
+        camera_pos: Vec3,
+        now: f32,
+    ) {
+        let mut heard = HashSet::new();
+        for sample in samples {
+            if sample.pos.distance(camera_pos) > MAX_DISTANCE {
+                continue;
+            }
+            heard.insert(sample.client);
+
+            let last = self.sent.get(&sample.client).copied();
+            if last.map_or(false, |last| now - last.time < INTERVAL) {
+                continue;
+            }
+
+            let speed = sample.vel.length();
+            let (throttle, boosting) = sample.controls.unwrap_or_else(|| {
+                let accel = last.map_or(0., |last| (speed - last.speed) / (now - last.time));
+                let throttle = (accel / REFERENCE_ACCEL).clamp(0., 1.);
+                (throttle, accel > REFERENCE_ACCEL * (1. + BOOST_FACTOR) / 2.)
+            });
+
+            io.send(&SoundCue::Engine(EngineState {
+                client: sample.client,
+                pos: sample.pos,
+                speed_frac: (speed / REFERENCE_SPEED).min(1.),
+                throttle,
+                boosting,
+            }));
+            self.sent.insert(sample.client, Sent { time: now, speed });
+        }
+
+        // Start afresh with ships which come back into range
+        self.sent.retain(|client, _| heard.contains(client));
+    }
+}
+
+/// Blocks in a bar at full speed
+const BAR_BLOCKS: usize = 10;
+/// Height of the bar above the ship (meters)
+const BAR_HEIGHT: f32 = 3.5;
+/// Spacing between blocks (meters)
+const BLOCK_SPACING: f32 = 0.25;
+/// Bars of ships we haven't heard from in this long are removed (seconds)
+const BAR_TIMEOUT: f32 = 4. * INTERVAL;
+
+struct Bar {
+    blocks: Vec<EntityId>,
+    /// Time of the last engine update
+    heard: f32,
+}
+
+/// Debugging view of the engine updates: a bar over each ship, as long as its speed and as
+/// bright as its throttle, orange while boosting
+pub struct EngineBars {
+    bars: HashMap<Option<ClientId>, Bar>,
+}
+
+impl EngineBars {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("EngineBlock"));
+
+    pub fn new(io: &mut EngineIo) -> Self {
+        io.send(&UploadMesh {
+            mesh: block_mesh(),
+            id: Self::RDR_ID,
+        });
+
+        Self {
+            bars: HashMap::new(),
+        }
+    }
+
+    /// Redraw bars from the latest `SoundCue::Engine`s, or remove them all unless `visible`
+    pub fn update(&mut self, io: &mut EngineIo, cues: &[SoundCue], visible: bool, now: f32) {
+        for cue in cues {
+            let SoundCue::Engine(state) = cue else {
+                continue;
+            };
+            if !visible {
+                continue;
+            }
+
+            let bar = self.bars.entry(state.client).or_insert(Bar {
+                blocks: vec![],
+                heard: now,
+            });
+            bar.heard = now;
+
+            let n_blocks = (state.speed_frac * BAR_BLOCKS as f32).round() as usize;
+            while bar.blocks.len() > n_blocks {
+                io.remove_entity(bar.blocks.pop().unwrap());
+            }
+            while bar.blocks.len() < n_blocks {
+                let entity = io
+                    .create_entity()
+                    .add_component(Transform::identity())
+                    .add_component(Render::new(Self::RDR_ID).primitive(Primitive::Lines))
+                    .build();
+                bar.blocks.push(entity);
+            }
+
+            let color = match state.boosting {
+                true => [1., 0.5, 0.],
+                false => [0.3 + 0.7 * state.throttle; 3],
+            };
+            for (idx, &block) in bar.blocks.iter().enumerate() {
+                let offset = Vec3::new(idx as f32 * BLOCK_SPACING, BAR_HEIGHT, 0.);
+                io.add_component(block, Transform::new().with_position(state.pos + offset));
+                io.add_component(block, color_extra(color));
+            }
+        }
+
+        self.bars.retain(|_, bar| {
+            let keep = visible && now - bar.heard < BAR_TIMEOUT;
+            if !keep {
+                bar.blocks.drain(..).for_each(|e| io.remove_entity(e));
+            }
+            keep
+        });
+    }
+}
+
+/// A short vertical block
+fn block_mesh() -> Mesh {
+    let mut m = Mesh::new();
+    let bottom = m.push_vertex(Vertex::new([0., 0., 0.], [1.; 3]));
+    let top = m.push_vertex(Vertex::new([0., 0.5, 0.], [1.; 3]));
+    m.indices.extend([bottom, top]);
+    m
+}
@@ -0,0 +1,339 @@
+//! One place for the client's short-lived presentation entities, so that a long session
+//! doesn't pile them up: entities are reused rather than created where they can be, and
+//! past a cap the oldest decorations give way to new ones
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    hash::Hash,
+};
+
+use cimvr_common::{
+    render::{MeshHandle, Render},
+    Transform,
+};
+use cimvr_engine_interface::{prelude::*, println};
+
+use crate::countdown::color_extra;
+
+/// Most pooled entities alive at once, shown or waiting to be reused
+const MAX_LIVE: usize = 1024;
+/// Most hidden entities kept for reuse, for each mesh
+const MAX_FREE_PER_MESH: usize = 64;
+
+/// Which subsystem an entity belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Owner {
+    Markers,
+    LapIndicators,
+    Pickups,
+}
+
+impl Owner {
+    /// Whether the entity is only there to look at, so it may be taken down to make room
+    fn decorative(self) -> bool {
+        match self {
+            Owner::Markers | Owner::LapIndicators => true,
+            Owner::Pickups => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Entry<E> {
+    entity: E,
+    owner: Owner,
+    mesh: MeshHandle,
+}
+
+/// What became of a released entity
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Release {
+    /// Kept for reuse; hide it by drawing its mesh nowhere
+    Hide(MeshHandle),
+    /// More are kept for its mesh than are worth it
+    Remove,
+}
+
+/// What had to go to make room for another entity
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Room<E> {
+    /// Nothing, there's room
+    Spare,
+    /// A hidden entity
+    Free(E),
+    /// The oldest decoration, still in use by its owner
+    Evicted(E, Owner),
+    /// Nothing could go
+    Full,
+}
+
+/// The pool's bookkeeping, apart from the engine's entities
+#[derive(Debug)]
+struct Slots<E> {
+    /// Entities in use, oldest first
+    in_use: VecDeque<Entry<E>>,
+    /// Hidden entities waiting to be reused, by the mesh they were drawn with
+    free: HashMap<MeshHandle, Vec<E>>,
+    /// Entities taken down to make room whose owners haven't released them yet. Writes to
+    /// them are dropped.
+    evicted: HashSet<E>,
+}
+
+impl<E> Default for Slots<E> {
+    fn default() -> Self {
+        Self {
+            in_use: VecDeque::new(),
+            free: HashMap::new(),
+            evicted: HashSet::new(),
+        }
+    }
+}
+
+impl<E: Copy + Eq + Hash> Slots<E> {
+    /// A hidden entity drawn with `mesh`, if there is one
+    fn reuse(&mut self, mesh: MeshHandle) -> Option<E> {
+        self.free.get_mut(&mesh).and_then(|free| free.pop())
+    }
+
+    fn push(&mut self, entity: E, owner: Owner, mesh: MeshHandle) {
+        self.in_use.push_back(Entry {
+            entity,
+            owner,
+            mesh,
+        });
+    }
+
+    /// Take `entity` back, unless it was evicted or isn't ours
+    fn release(&mut self, entity: E) -> Option<Release> {
+        if self.evicted.remove(&entity) {
+            return None;
+        }
+        let idx = self.in_use.iter().position(|e| e.entity == entity)?;
+        let entry = self.in_use.remove(idx)?;
+
+        let free = self.free.entry(entry.mesh).or_default();
+        match free.len() < MAX_FREE_PER_MESH {
+            true => {
+                free.push(entity);
+                Some(Release::Hide(entry.mesh))
+            }
+            false => Some(Release::Remove),
+        }
+    }
+
+    fn is_evicted(&self, entity: E) -> bool {
+        self.evicted.contains(&entity)
+    }
+
+    fn live(&self) -> usize {
+        self.in_use.len() + self.free.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// Keep under `MAX_LIVE` before another entity is created: first by dropping hidden
+    /// ones, then by taking down the oldest decoration
+    fn make_room(&mut self) -> Room<E> {
+        if self.live() < MAX_LIVE {
+            return Room::Spare;
+        }
+
+        if let Some(entity) = self.free.values_mut().find_map(|free| free.pop()) {
+            return Room::Free(entity);
+        }
+
+        let Some(idx) = self.in_use.iter().position(|e| e.owner.decorative()) else {
+            return Room::Full;
+        };
+        let Some(entry) = self.in_use.remove(idx) else {
+            return Room::Full;
+        };
+        self.evicted.insert(entry.entity);
+        Room::Evicted(entry.entity, entry.owner)
+    }
+}
+
+/// Pooled entities, by who owns them
+#[derive(Default)]
+pub struct EntityPool {
+    slots: Slots<EntityId>,
+}
+
+impl EntityPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An entity drawn with `render` at `tf`, reusing a hidden one with the same mesh if
+    /// there is one
+    pub fn spawn(
+        &mut self,
+        io: &mut EngineIo,
+        owner: Owner,
+        render: Render,
+        tf: Transform,
+    ) -> EntityId {
+        let mesh = render.id;
+        let entity = match self.slots.reuse(mesh) {
+            Some(entity) => {
+                io.add_component(entity, tf);
+                io.add_component(entity, render);
+                // Untinted, whatever it was drawn as last time
+                io.add_component(entity, color_extra([1.; 3]));
+                entity
+            }
+            None => {
+                self.make_room(io);
+                io.create_entity()
+                    .add_component(tf)
+                    .add_component(render)
+                    .build()
+            }
+        };
+        self.slots.push(entity, owner, mesh);
+        entity
+    }
+
+    /// Give an entity back, hiding it until it's reused
+    pub fn release(&mut self, io: &mut EngineIo, entity: EntityId) {
+        match self.slots.release(entity) {
+            Some(Release::Hide(mesh)) => io.add_component(entity, Render::new(mesh).limit(Some(0))),
+            Some(Release::Remove) => io.remove_entity(entity),
+            None => (),
+        }
+    }
+
+    /// Add a component to a pooled entity, unless it was taken down to make room
+    pub fn add_component<C: Component>(&self, io: &mut EngineIo, entity: EntityId, c: C) {
+        if !self.slots.is_evicted(entity) {
+            io.add_component(entity, c);
+        }
+    }
+
+    /// Entities alive, in use or waiting to be reused
+    pub fn live(&self) -> usize {
+        self.slots.live()
+    }
+
+    fn make_room(&mut self, io: &mut EngineIo) {
+        match self.slots.make_room() {
+            Room::Spare => (),
+            Room::Free(entity) => io.remove_entity(entity),
+            Room::Evicted(entity, owner) => {
+                println!(
+                    "Entity pool is full; removing the oldest {:?} entity to make room",
+                    owner
+                );
+                io.remove_entity(entity);
+            }
+            Room::Full => println!(
+                "Entity pool is over {} with nothing decorative to remove",
+                MAX_LIVE
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cimvr_engine_interface::pkg_namespace;
+
+    use super::*;
+
+    const MARKER: MeshHandle = MeshHandle::new(pkg_namespace!("TestMarker"));
+    const TICK: MeshHandle = MeshHandle::new(pkg_namespace!("TestTick"));
+    const PICKUP: MeshHandle = MeshHandle::new(pkg_namespace!("TestPickup"));
+    const RACES: usize = 10;
+
+    /// The pool, round an engine which numbers the entities it creates
+    #[derive(Default)]
+    struct Engine {
+        slots: Slots<u32>,
+        created: u32,
+        removed: usize,
+    }
+
+    impl Engine {
+        fn spawn(&mut self, owner: Owner, mesh: MeshHandle) -> u32 {
+            let entity = match self.slots.reuse(mesh) {
+                Some(entity) => entity,
+                None => {
+                    if let Room::Free(_) | Room::Evicted(..) = self.slots.make_room() {
+                        self.removed += 1;
+                    }
+                    self.created += 1;
+                    self.created
+                }
+            };
+            self.slots.push(entity, owner, mesh);
+            assert!(self.slots.live() <= MAX_LIVE, "{} live", self.slots.live());
+            entity
+        }
+
+        fn release(&mut self, entity: u32) {
+            if self.slots.release(entity) == Some(Release::Remove) {
+                self.removed += 1;
+            }
+        }
+    }
+
+    /// A race's worth of pickups, lap ticks and markers dropped along the way, all released
+    /// when it's reset
+    fn race(engine: &mut Engine) {
+        let mut owned = vec![];
+        owned.extend((0..20).map(|_| engine.spawn(Owner::Pickups, PICKUP)));
+        for _lap in 0..3 {
+            owned.push(engine.spawn(Owner::LapIndicators, TICK));
+            // Markers come and go during the lap
+            let markers: Vec<u32> = (0..40)
+                .map(|_| engine.spawn(Owner::Markers, MARKER))
+                .collect();
+            markers.iter().take(30).for_each(|&m| engine.release(m));
+            owned.extend(&markers[30..]);
+        }
+        owned.into_iter().for_each(|entity| engine.release(entity));
+    }
+
+    #[test]
+    fn races_reuse_the_same_entities() {
+        let mut engine = Engine::default();
+        race(&mut engine);
+        let (live, created) = (engine.slots.live(), engine.created);
+        assert!(live < MAX_LIVE);
+        assert!(engine.slots.in_use.is_empty());
+
+        for k in 1..RACES {
+            race(&mut engine);
+            assert_eq!(engine.slots.live(), live, "after race {}", k);
+            assert_eq!(engine.created, created, "after race {}", k);
+        }
+        assert_eq!(engine.removed, 0);
+    }
+
+    #[test]
+    fn leaked_decorations_make_way_for_new_ones() {
+        // Markers which are never given back, race after race
+        let mut engine = Engine::default();
+        let mut leaked = vec![];
+        for _ in 0..RACES {
+            race(&mut engine);
+            leaked.extend((0..200).map(|_| engine.spawn(Owner::Markers, MARKER)));
+        }
+        assert!(engine.slots.live() <= MAX_LIVE);
+        assert!(engine.removed > 0);
+
+        // The oldest went first, and giving them back late is harmless
+        let oldest = leaked[0];
+        assert!(engine.slots.is_evicted(oldest));
+        engine.release(oldest);
+        assert!(!engine.slots.is_evicted(oldest));
+        assert!(!engine.slots.is_evicted(*leaked.last().unwrap()));
+    }
+
+    #[test]
+    fn pickups_are_never_taken_down() {
+        let mut engine = Engine::default();
+        let pickups: Vec<u32> = (0..MAX_LIVE)
+            .map(|_| engine.spawn(Owner::Pickups, PICKUP))
+            .collect();
+        assert_eq!(engine.slots.make_room(), Room::Full);
+        assert!(pickups.iter().all(|&p| !engine.slots.is_evicted(p)));
+    }
+}
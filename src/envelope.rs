@@ -0,0 +1,236 @@
+//! Versioned envelopes for the remote messages which keep growing. Each is sent as the version
+//! of its layout and its encoded body, rather than as the bare struct, so that a receiver can
+//! tell which layout it was written in and still read one a version behind its own.
+//!
+//! The policy for changing an enveloped message:
+//! - Adding a field is fine. Bump its `VERSION`, make the old definition its `Previous`, and
+//!   fill in the new field in `upgrade`.
+//! - Anything else (removing, reordering or retyping a field) is a breaking change, which
+//!   bumps `PROTOCOL_VERSION` as well, keeping mismatched clients out of races.
+use cimvr_engine_interface::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// A message's body, with the version of the layout it was encoded in
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Envelope {
+    pub version: u8,
+    pub body: Vec<u8>,
+}
+
+/// A message which travels in an `Envelope`
+pub trait Versioned: Serialize + DeserializeOwned {
+    /// Version of the current layout
+    const VERSION: u8;
+    /// The layout one version before, which can still be read
+    type Previous: DeserializeOwned;
+    /// Message carrying the envelope over the wire
+    type Wire: Message + From<Envelope> + Into<Envelope>;
+    /// Bring a message in the previous layout up to date
+    fn upgrade(previous: Self::Previous) -> Self;
+}
+
+impl Envelope {
+    fn from_wire<T: Versioned>(wire: T::Wire) -> Self {
+        wire.into()
+    }
+
+    pub fn seal<T: Versioned>(message: &T) -> Self {
+        Self {
+            version: T::VERSION,
+            // Only fails for types serde can't describe, which no message is
+            body: bincode::serialize(message).unwrap_or_default(),
+        }
+    }
+
+    /// The message, in whichever layout it was sealed in if it's one we can read. Decodes of
+    /// the previous layout are counted in `decodes`.
+    pub fn open<T: Versioned>(&self, decodes: &mut LegacyDecodes) -> Option<T> {
+        let opened = if self.version == T::VERSION {
+            bincode::deserialize(&self.body).ok()
+        } else if self.version.checked_add(1) == Some(T::VERSION) {
+            let previous = bincode::deserialize(&self.body).ok();
+            decodes.legacy += previous.is_some() as u32;
+            previous.map(T::upgrade)
+        } else {
+            None
+        };
+        if opened.is_none() {
+            decodes.unreadable += 1;
+        }
+        opened
+    }
+}
+
+/// `Previous` for a message still on its first layout, which nothing decodes into
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum NoPrevious {}
+
+/// Count of enveloped messages which weren't in the current layout
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LegacyDecodes {
+    /// Read from the previous layout
+    pub legacy: u32,
+    /// Dropped, being in a layout too old or too new to read, or garbled
+    pub unreadable: u32,
+}
+
+impl LegacyDecodes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+pub fn send<T: Versioned>(io: &mut EngineIo, message: &T) {
+    io.send(&T::Wire::from(Envelope::seal(message)));
+}
+
+pub fn send_to_client<T: Versioned>(io: &mut EngineIo, message: &T, client_id: ClientId) {
+    io.send_to_client(&T::Wire::from(Envelope::seal(message)), client_id);
+}
+
+/// Every `T` received this frame which could be read
+pub fn inbox<T: Versioned>(io: &mut EngineIo, decodes: &mut LegacyDecodes) -> Vec<T> {
+    io.inbox::<T::Wire>()
+        .filter_map(|wire| Envelope::from_wire::<T>(wire).open(decodes))
+        .collect()
+}
+
+/// As `inbox`, along with the client each came from
+pub fn inbox_clients<T: Versioned>(
+    io: &mut EngineIo,
+    decodes: &mut LegacyDecodes,
+) -> Vec<(ClientId, T)> {
+    io.inbox_clients::<T::Wire>()
+        .filter_map(|(client_id, wire)| {
+            let message = Envelope::from_wire::<T>(wire).open(decodes)?;
+            Some((client_id, message))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Finished, LapTime, RaceSettings, ShipUpload, StartRace};
+    use cimvr_common::{glam::Vec3, Transform};
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Old {
+        a: u32,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct New {
+        a: u32,
+        b: f32,
+    }
+
+    #[derive(Message, Serialize, Deserialize, Clone, Default)]
+    #[locality("Local")]
+    struct SealedNew(Envelope);
+
+    impl From<Envelope> for SealedNew {
+        fn from(envelope: Envelope) -> Self {
+            Self(envelope)
+        }
+    }
+
+    impl From<SealedNew> for Envelope {
+        fn from(SealedNew(envelope): SealedNew) -> Self {
+            envelope
+        }
+    }
+
+    impl Versioned for New {
+        const VERSION: u8 = 2;
+        type Previous = Old;
+        type Wire = SealedNew;
+        fn upgrade(Old { a }: Old) -> Self {
+            Self { a, b: 1. }
+        }
+    }
+
+    #[test]
+    fn current_layout_round_trips() {
+        let mut decodes = LegacyDecodes::new();
+        let message = New { a: 7, b: 0.5 };
+        let opened = Envelope::seal(&message).open::<New>(&mut decodes);
+        assert_eq!(opened, Some(message));
+        assert_eq!(decodes, LegacyDecodes::default());
+    }
+
+    #[test]
+    fn previous_layout_is_upgraded_and_counted() {
+        let mut decodes = LegacyDecodes::new();
+        let envelope = Envelope {
+            version: 1,
+            body: bincode::serialize(&Old { a: 7 }).unwrap(),
+        };
+        let opened = envelope.open::<New>(&mut decodes);
+        assert_eq!(opened, Some(New { a: 7, b: 1. }));
+        assert_eq!(decodes.legacy, 1);
+    }
+
+    #[test]
+    fn other_layouts_are_dropped_and_counted() {
+        let mut decodes = LegacyDecodes::new();
+        for version in [0, 3] {
+            let envelope = Envelope {
+                version,
+                body: bincode::serialize(&New { a: 7, b: 0.5 }).unwrap(),
+            };
+            assert_eq!(envelope.open::<New>(&mut decodes), None);
+        }
+        assert_eq!(decodes.unreadable, 2);
+        assert_eq!(decodes.legacy, 0);
+    }
+
+    /// Seals `message`, opens it again, and checks it reseals to the same bytes
+    fn assert_round_trips<T: Versioned>(message: &T) {
+        let mut decodes = LegacyDecodes::new();
+        let sealed = Envelope::seal(message);
+        let opened = sealed
+            .open::<T>(&mut decodes)
+            .expect("current layout opens");
+        assert_eq!(Envelope::seal(&opened), sealed);
+        assert_eq!(decodes, LegacyDecodes::default());
+    }
+
+    #[test]
+    fn race_messages_round_trip() {
+        let mut tf = Transform::identity();
+        tf.pos = Vec3::new(1., 2., 3.);
+        assert_round_trips(&ShipUpload(tf, Default::default(), 41));
+
+        let laps = vec![
+            LapTime {
+                time: 31.5,
+                valid: true,
+            },
+            LapTime {
+                time: 29.25,
+                valid: false,
+            },
+        ];
+        assert_round_trips(&Finished(60.75, Default::default(), laps));
+
+        assert_round_trips(&StartRace {
+            race_id: 7,
+            client_id: ClientId(3),
+            position: tf,
+            settings: RaceSettings::default(),
+            track_id: Some(2),
+        });
+    }
+
+    #[test]
+    fn first_layouts_have_no_previous() {
+        let mut decodes = LegacyDecodes::new();
+        let envelope = Envelope {
+            version: 0,
+            body: bincode::serialize(&Finished::default()).unwrap(),
+        };
+        assert!(envelope.open::<Finished>(&mut decodes).is_none());
+        assert_eq!(decodes.unreadable, 1);
+    }
+}
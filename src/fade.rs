@@ -0,0 +1,134 @@
+use cimvr_common::{
+    glam::Vec3,
+    render::{Mesh, MeshHandle, Primitive, Render, RenderExtra, UploadMesh, Vertex},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::hud::HudVisibility;
+
+/// Duration of each half (out, then in) of the fade (seconds)
+const FADE_TIME: f32 = 0.3;
+/// Shorter fades are more comfortable in VR
+const FADE_TIME_VR: f32 = 0.1;
+
+/// Number of lines in the fade overlay; dense enough to read as a solid sheet
+const FADE_LINES: u32 = 400;
+
+/// Fades the view out and back in around teleports
+pub struct TeleportFade {
+    entity: EntityId,
+    /// Time into the current fade, if any
+    elapsed: Option<f32>,
+    /// Where to snap to once the view is fully faded out
+    pending: Option<Transform>,
+}
+
+impl TeleportFade {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("TeleportFade"));
+
+    pub fn new(io: &mut EngineIo) -> Self {
+        io.send(&UploadMesh {
+            mesh: fade_mesh(),
+            id: Self::RDR_ID,
+        });
+
+        let entity = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(Self::render(false))
+            .build();
+
+        Self {
+            entity,
+            elapsed: None,
+            pending: None,
+        }
+    }
+
+    fn render(visible: bool) -> Render {
+        HudVisibility::render(
+            visible,
+            Render::new(Self::RDR_ID).primitive(Primitive::Lines),
+        )
+    }
+
+    /// Start a fade. If `to` is given, it's returned from `update` once the view is dark;
+    /// otherwise the teleport already happened and the fade starts dark.
+    pub fn begin(&mut self, io: &mut EngineIo, to: Option<Transform>, is_vr: bool) {
+        if self.elapsed.is_none() {
+            io.add_component(self.entity, Self::render(true));
+        }
+
+        let half = fade_time(is_vr);
+        self.elapsed = Some(match to {
+            Some(_) => 0.,
+            None => half,
+        });
+        self.pending = to;
+    }
+
+    /// Advance the fade, keeping the overlay in front of the camera.
+    /// Returns a pending teleport destination when it's time to snap.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        camera_tf: Transform,
+        dt: f32,
+        is_vr: bool,
+    ) -> Option<Transform> {
+        let elapsed = self.elapsed.as_mut()?;
+        *elapsed += dt;
+        let elapsed = *elapsed;
+
+        let half = fade_time(is_vr);
+
+        // Snap once fully dark
+        let snap = if elapsed >= half {
+            self.pending.take()
+        } else {
+            None
+        };
+
+        if elapsed >= 2. * half {
+            self.elapsed = None;
+            io.add_component(self.entity, Self::render(false));
+            return snap;
+        }
+
+        let alpha = 1. - (elapsed - half).abs() / half;
+        let overlay = Transform::new().with_position(Vec3::new(0., 0., -0.2));
+        io.add_component(self.entity, camera_tf * overlay);
+        io.add_component(
+            self.entity,
+            RenderExtra([
+                0., 0., 0., alpha, 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0.,
+            ]),
+        );
+
+        snap
+    }
+}
+
+fn fade_time(is_vr: bool) -> f32 {
+    if is_vr {
+        FADE_TIME_VR
+    } else {
+        FADE_TIME
+    }
+}
+
+/// Sheet of closely spaced horizontal lines, covering the view from just in front of the camera
+fn fade_mesh() -> Mesh {
+    let mut m = Mesh::new();
+    let half_size = 0.5;
+
+    for i in 0..=FADE_LINES {
+        let y = -half_size + 2. * half_size * i as f32 / FADE_LINES as f32;
+        let a = m.push_vertex(Vertex::new([-half_size, y, 0.], [0.; 3]));
+        let b = m.push_vertex(Vertex::new([half_size, y, 0.], [0.; 3]));
+        m.indices.extend([a, b]);
+    }
+
+    m
+}
@@ -0,0 +1,350 @@
+//! Evidence for reviewing a race's fairness: what the server saw of each racer's uploads.
+//! Nothing here acts on its own; an admin reads the report and decides.
+use std::collections::HashMap;
+
+use cimvr_common::glam::Vec3;
+use cimvr_engine_interface::prelude::*;
+
+/// Distance a ship may move between uploads beyond what its velocity explains, before the
+/// upload counts as implausible. Generous, since the controller puts ships back onto the
+/// track from the walls (meters).
+const JUMP_SLACK: f32 = 40.;
+
+/// Limits above which a racer is flagged for review. Deliberately loose: ships boosting
+/// downhill, clients on bad connections and wall resets should all stay under them.
+#[derive(Clone, Copy, Debug)]
+pub struct Thresholds {
+    /// Reported speed (meters per second)
+    pub top_speed: f32,
+    /// Movement between two consecutive uploads (meters)
+    pub largest_jump: f32,
+    /// Fraction of uploads failing the plausibility check
+    pub implausible: f32,
+    /// Variance of the time between uploads (seconds squared)
+    pub interval_variance: f32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            top_speed: 400.,
+            largest_jump: 150.,
+            implausible: 0.05,
+            interval_variance: 0.05,
+        }
+    }
+}
+
+/// Running statistics of one racer's uploads, in constant memory
+#[derive(Default, Debug, Clone)]
+struct UploadStats {
+    /// Time and position of the last upload
+    last: Option<(f32, Vec3)>,
+    updates: u32,
+    implausible: u32,
+    top_speed: f32,
+    largest_jump: f32,
+    /// Count, mean and sum of squared deviations of the upload intervals (Welford's method)
+    intervals: u32,
+    interval_mean: f32,
+    interval_m2: f32,
+}
+
+impl UploadStats {
+    fn record(&mut self, time: f32, pos: Vec3, vel: Vec3) {
+        self.updates += 1;
+        self.top_speed = self.top_speed.max(vel.length());
+
+        let Some((last_time, last_pos)) = self.last.replace((time, pos)) else {
+            return;
+        };
+        let dt = (time - last_time).max(0.);
+        let jump = pos.distance(last_pos);
+        self.largest_jump = self.largest_jump.max(jump);
+        if jump > vel.length() * dt + JUMP_SLACK {
+            self.implausible += 1;
+        }
+
+        self.intervals += 1;
+        let delta = dt - self.interval_mean;
+        self.interval_mean += delta / self.intervals as f32;
+        self.interval_m2 += delta * (dt - self.interval_mean);
+    }
+
+    fn interval_variance(&self) -> f32 {
+        match self.intervals > 1 {
+            true => self.interval_m2 / (self.intervals - 1) as f32,
+            false => 0.,
+        }
+    }
+
+    fn implausible_fraction(&self) -> f32 {
+        match self.updates {
+            0 => 0.,
+            n => self.implausible as f32 / n as f32,
+        }
+    }
+}
+
+/// Upload statistics of everyone in the current race
+#[derive(Default)]
+pub struct FairnessMonitor {
+    racers: HashMap<ClientId, UploadStats>,
+}
+
+impl FairnessMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget the last race
+    pub fn clear(&mut self) {
+        self.racers.clear();
+    }
+
+    /// A racer uploaded their ship at `pos`, moving at `vel`, at server time `time`
+    pub fn record(&mut self, client_id: ClientId, time: f32, pos: Vec3, vel: Vec3) {
+        self.racers
+            .entry(client_id)
+            .or_default()
+            .record(time, pos, vel);
+    }
+
+    pub fn remove(&mut self, client_id: ClientId) {
+        self.racers.remove(&client_id);
+    }
+
+    /// Everything seen so far, judged against `thresholds`
+    pub fn diagnostics(
+        &self,
+        race: u64,
+        usernames: &HashMap<ClientId, String>,
+        thresholds: Thresholds,
+    ) -> RaceDiagnostics {
+        let mut racers: Vec<RacerDiagnostics> = self
+            .racers
+            .iter()
+            .map(|(&client_id, stats)| {
+                let mut racer = RacerDiagnostics {
+                    client_id,
+                    username: usernames.get(&client_id).cloned().unwrap_or_default(),
+                    updates: stats.updates,
+                    top_speed: stats.top_speed,
+                    largest_jump: stats.largest_jump,
+                    implausible: stats.implausible_fraction(),
+                    interval_variance: stats.interval_variance(),
+                    flags: vec![],
+                };
+                racer.flags = racer.exceeded(thresholds);
+                racer
+            })
+            .collect();
+        racers.sort_by(|a, b| a.username.cmp(&b.username));
+        RaceDiagnostics { race, racers }
+    }
+}
+
+/// What the server saw of one racer
+#[derive(Clone, Debug)]
+pub struct RacerDiagnostics {
+    pub client_id: ClientId,
+    pub username: String,
+    pub updates: u32,
+    pub top_speed: f32,
+    pub largest_jump: f32,
+    /// Fraction of uploads failing the plausibility check
+    pub implausible: f32,
+    pub interval_variance: f32,
+    /// Names of the thresholds exceeded
+    pub flags: Vec<&'static str>,
+}
+
+impl RacerDiagnostics {
+    fn exceeded(&self, thresholds: Thresholds) -> Vec<&'static str> {
+        [
+            ("speed", self.top_speed > thresholds.top_speed),
+            ("jump", self.largest_jump > thresholds.largest_jump),
+            ("implausible", self.implausible > thresholds.implausible),
+            (
+                "interval",
+                self.interval_variance > thresholds.interval_variance,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, exceeded)| exceeded.then_some(name))
+        .collect()
+    }
+
+    /// Fields for the race log
+    pub fn log_fields(&self, race: u64) -> Vec<(&'static str, String)> {
+        vec![
+            ("race", race.to_string()),
+            ("client", format!("{:?}", self.client_id)),
+            ("name", self.username.clone()),
+            ("updates", self.updates.to_string()),
+            ("top_speed", format!("{:.1}", self.top_speed)),
+            ("largest_jump", format!("{:.1}", self.largest_jump)),
+            ("implausible", format!("{:.3}", self.implausible)),
+            (
+                "interval_variance",
+                format!("{:.4}", self.interval_variance),
+            ),
+            ("flags", self.flags.join(",")),
+        ]
+    }
+}
+
+/// Fairness diagnostics of one race
+#[derive(Clone, Debug)]
+pub struct RaceDiagnostics {
+    pub race: u64,
+    pub racers: Vec<RacerDiagnostics>,
+}
+
+impl RaceDiagnostics {
+    /// The report as chat lines, headed by a reminder of what it is and isn't
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Fairness diagnostics for race {}. Flags mark numbers worth a closer look, \
+             not proof of cheating.",
+            self.race
+        )];
+        if self.racers.is_empty() {
+            lines.push("No uploads were recorded".to_string());
+        }
+        for racer in &self.racers {
+            let flags = match racer.flags.is_empty() {
+                true => "ok".to_string(),
+                false => format!("FLAGGED: {}", racer.flags.join(", ")),
+            };
+            lines.push(format!(
+                "{}: top speed {:.1} m/s, largest jump {:.1} m, implausible {:.1}%, \
+                 interval variance {:.4} s², {} updates. {}",
+                racer.username,
+                racer.top_speed,
+                racer.largest_jump,
+                racer.implausible * 100.,
+                racer.interval_variance,
+                racer.updates,
+                flags
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HONEST: ClientId = ClientId(1);
+    const JITTERY: ClientId = ClientId(2);
+    const TELEPORTER: ClientId = ClientId(3);
+    /// Uploads in each racer's stream, and the upload the teleporter jumps at
+    const UPLOADS: usize = 600;
+    const JUMP_AT: usize = 300;
+    const SPEED: f32 = 80.;
+    const TELEPORT: f32 = 500.;
+
+    /// Times of each upload, at 60 Hz or alternately early and late
+    fn times(jitter: bool) -> Vec<f32> {
+        let intervals = (0..UPLOADS).map(|k| match (jitter, k % 2) {
+            (false, _) => 1. / 60.,
+            (true, 0) => 0.01,
+            (true, _) => 0.03,
+        });
+        intervals
+            .scan(0., |time, dt| {
+                *time += dt;
+                Some(*time)
+            })
+            .collect()
+    }
+
+    /// Three racers flat out down a long straight, one of whom skips ahead half way
+    fn monitor() -> FairnessMonitor {
+        let mut monitor = FairnessMonitor::new();
+        let vel = Vec3::X * SPEED;
+        for (client_id, jitter) in [(HONEST, false), (JITTERY, true), (TELEPORTER, false)] {
+            for (k, time) in times(jitter).into_iter().enumerate() {
+                let mut pos = vel * time;
+                if client_id == TELEPORTER && k >= JUMP_AT {
+                    pos.x += TELEPORT;
+                }
+                monitor.record(client_id, time, pos, vel);
+            }
+        }
+        monitor
+    }
+
+    fn usernames() -> HashMap<ClientId, String> {
+        [(HONEST, "ann"), (JITTERY, "bo"), (TELEPORTER, "cy")]
+            .into_iter()
+            .map(|(id, name)| (id, name.to_string()))
+            .collect()
+    }
+
+    fn racer(diagnostics: &RaceDiagnostics, client_id: ClientId) -> &RacerDiagnostics {
+        let racer = diagnostics.racers.iter().find(|r| r.client_id == client_id);
+        racer.unwrap()
+    }
+
+    #[test]
+    fn only_the_teleporter_is_flagged() {
+        let diagnostics = monitor().diagnostics(4, &usernames(), Thresholds::default());
+        let names: Vec<&str> = diagnostics.racers.iter().map(|r| &r.username[..]).collect();
+        assert_eq!(names, ["ann", "bo", "cy"]);
+
+        for client_id in [HONEST, JITTERY] {
+            let racer = racer(&diagnostics, client_id);
+            assert!(racer.flags.is_empty(), "{:?}", racer);
+            assert_eq!(racer.implausible, 0.);
+            assert!(racer.largest_jump < SPEED * 0.05, "{:?}", racer);
+        }
+
+        let teleporter = racer(&diagnostics, TELEPORTER);
+        assert_eq!(teleporter.flags, ["jump"]);
+        assert_eq!(teleporter.updates, UPLOADS as u32);
+        assert!(teleporter.largest_jump > TELEPORT, "{:?}", teleporter);
+        // One bad upload among hundreds, which alone isn't worth flagging
+        assert!((teleporter.implausible - 1. / UPLOADS as f32).abs() < 1e-6);
+        assert!((teleporter.top_speed - SPEED).abs() < 1e-3);
+    }
+
+    #[test]
+    fn running_interval_variance_matches_the_whole_stream() {
+        let diagnostics = monitor().diagnostics(4, &usernames(), Thresholds::default());
+        for (client_id, jitter) in [(HONEST, false), (JITTERY, true)] {
+            let times = times(jitter);
+            let intervals: Vec<f32> = times.windows(2).map(|w| w[1] - w[0]).collect();
+            let mean = intervals.iter().sum::<f32>() / intervals.len() as f32;
+            let squares: f32 = intervals.iter().map(|dt| (dt - mean).powi(2)).sum();
+            let variance = squares / (intervals.len() - 1) as f32;
+
+            let running = racer(&diagnostics, client_id).interval_variance;
+            assert!(
+                (running - variance).abs() < 1e-6,
+                "{} vs {}",
+                running,
+                variance
+            );
+        }
+        // Uneven, but nowhere near a lagging connection
+        let jittery = racer(&diagnostics, JITTERY).interval_variance;
+        assert!(jittery > 5e-5 && jittery < Thresholds::default().interval_variance);
+    }
+
+    #[test]
+    fn the_report_says_what_the_flags_mean() {
+        let report = monitor()
+            .diagnostics(4, &usernames(), Thresholds::default())
+            .report();
+        assert_eq!(report.len(), 4);
+        assert!(report[0].contains("race 4") && report[0].contains("not proof"));
+        assert!(report[1].starts_with("ann:") && report[1].ends_with(" ok"));
+        assert!(report[3].starts_with("cy:") && report[3].ends_with("FLAGGED: jump"));
+
+        let empty = FairnessMonitor::new().diagnostics(5, &usernames(), Thresholds::default());
+        assert_eq!(empty.report().len(), 2);
+    }
+}
@@ -0,0 +1,181 @@
+use cimvr_common::{
+    render::{MeshHandle, Primitive, Render, UploadMesh},
+    ui::{Schema, State, UiHandle, UiStateHelper},
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{
+    controls::{TRACK_HEIGHT, TRACK_WIDTH},
+    countdown::color_extra,
+    palette::Palette,
+    race_rules::{LapEvent, LapTracker},
+    shapes::gate_mesh,
+};
+
+/// How long a gate flashes after being passed or missed (seconds)
+const FLASH_TIME: f32 = 1.;
+/// Blinks per second while flashing
+const FLASH_RATE: f32 = 6.;
+/// How long the missed checkpoint note stays up (seconds)
+const NOTE_TIME: f32 = 3.;
+
+/// Arches over the track at each checkpoint, colored by the progress of the racer being shown
+pub struct CheckpointGates {
+    gates: Vec<Gate>,
+    /// "Missed checkpoint" note and the time it has left
+    note: Option<(UiHandle, f32)>,
+}
+
+struct Gate {
+    entity: EntityId,
+    state: GateState,
+    /// Time left flashing
+    flash: f32,
+    /// Color last sent to the engine
+    shown: Option<[f32; 3]>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GateState {
+    Neutral,
+    Passed,
+    Missed,
+}
+
+impl CheckpointGates {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("CheckpointGate"));
+
+    pub fn new(io: &mut EngineIo, tracker: &LapTracker) -> Self {
+        io.send(&UploadMesh {
+            mesh: gate_mesh(TRACK_WIDTH, TRACK_HEIGHT, [1.; 3]),
+            id: Self::RDR_ID,
+        });
+
+        let gates = tracker
+            .checkpoints()
+            .iter()
+            .map(|checkpoint| Gate {
+                entity: io
+                    .create_entity()
+                    .add_component(checkpoint.transform)
+                    .add_component(Render::new(Self::RDR_ID).primitive(Primitive::Lines))
+                    .build(),
+                state: GateState::Neutral,
+                flash: 0.,
+                shown: None,
+            })
+            .collect();
+
+        Self { gates, note: None }
+    }
+
+    /// Remove the gates and any note
+    pub fn teardown(self, io: &mut EngineIo, gui: &mut UiStateHelper) {
+        for gate in self.gates {
+            io.remove_entity(gate.entity);
+        }
+        if let Some((handle, _)) = self.note {
+            gui.delete(io, handle);
+        }
+    }
+
+    /// React to the local racer's lap events
+    pub fn handle_events(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        events: &[LapEvent],
+    ) {
+        for event in events {
+            match *event {
+                LapEvent::Checkpoint(idx) => self.set(idx, GateState::Passed),
+                LapEvent::MissedCheckpoint { expected, reached } => {
+                    self.set(reached, GateState::Missed);
+                    self.show_note(io, gui, format!("Missed checkpoint {}", expected + 1));
+                }
+                LapEvent::Lap(_) | LapEvent::IncompleteLap => self.reset(),
+                LapEvent::Backwards => (),
+            }
+        }
+    }
+
+    /// Show someone else's progress, having passed the first `passed` gates this lap
+    pub fn mirror(&mut self, passed: usize) {
+        for (idx, gate) in self.gates.iter_mut().enumerate() {
+            gate.state = match idx < passed {
+                true => GateState::Passed,
+                false => GateState::Neutral,
+            };
+        }
+    }
+
+    /// Return every gate to neutral
+    pub fn reset(&mut self) {
+        for gate in &mut self.gates {
+            gate.state = GateState::Neutral;
+            gate.flash = 0.;
+        }
+    }
+
+    fn set(&mut self, idx: usize, state: GateState) {
+        if let Some(gate) = self.gates.get_mut(idx) {
+            gate.state = state;
+            gate.flash = FLASH_TIME;
+        }
+    }
+
+    fn show_note(&mut self, io: &mut EngineIo, gui: &mut UiStateHelper, text: String) {
+        match self.note {
+            Some((handle, _)) => gui.modify(io, handle, |ui_state| {
+                ui_state[0] = State::Label { text: text.clone() }
+            }),
+            None => {
+                let handle = gui.add(
+                    io,
+                    "Checkpoint",
+                    vec![Schema::Label],
+                    vec![State::Label { text }],
+                );
+                self.note = Some((handle, 0.));
+            }
+        }
+        if let Some((_, time_left)) = &mut self.note {
+            *time_left = NOTE_TIME;
+        }
+    }
+
+    /// Advance flashing and the note, and recolor gates which changed
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        delta: f32,
+        show_hud: bool,
+        palette: Palette,
+    ) {
+        if let Some((handle, time_left)) = &mut self.note {
+            *time_left -= delta;
+            if *time_left <= 0. || !show_hud {
+                gui.delete(io, *handle);
+                self.note = None;
+            }
+        }
+
+        for gate in &mut self.gates {
+            gate.flash = (gate.flash - delta).max(0.);
+            let blink_off = gate.flash > 0. && (gate.flash * FLASH_RATE).fract() < 0.5;
+
+            let color = match gate.state {
+                _ if blink_off => palette.neutral(),
+                GateState::Neutral => palette.neutral(),
+                GateState::Passed => palette.passed(),
+                GateState::Missed => palette.missed(),
+            };
+
+            if gate.shown != Some(color) {
+                io.add_component(gate.entity, color_extra(color));
+                gate.shown = Some(color);
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Replaying our last completed run on a ship racing alongside us
+use cimvr_common::{
+    glam::{Quat, Vec3},
+    render::{MeshHandle, Primitive, Render, UploadMesh},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{curve::Curve, hud::HudVisibility, shapes::panel_mesh, ship_class::ShipClass};
+
+/// Time between recorded samples (seconds)
+const SAMPLE_INTERVAL: f32 = 0.05;
+/// Height of the nameplate above the ghost (meters)
+const NAMEPLATE_HEIGHT: f32 = 3.;
+/// Ships within this distance directly behind the ghost get a tow (meters)
+const DRAFT_DISTANCE: f32 = 25.;
+/// Width of the wake, either side of the ghost's line (meters)
+const DRAFT_WIDTH: f32 = 3.;
+/// Thrust multiplier right behind the ghost, falling off to none at `DRAFT_DISTANCE`
+const DRAFT_BOOST: f32 = 1.1;
+
+/// Marks the ghost ship and its nameplate, which are never racers
+#[derive(Component, Serialize, Deserialize, Default, Copy, Clone, PartialEq, Eq)]
+pub struct GhostComponent;
+
+#[derive(Clone, Copy)]
+struct Sample {
+    time: f32,
+    tf: Transform,
+    /// Furthest the run had got along the race (meters); see `RaceDistance`
+    distance: f32,
+}
+
+/// Every position of one run, against the race clock
+#[derive(Default, Clone)]
+pub struct Run {
+    samples: Vec<Sample>,
+    class: ShipClass,
+}
+
+impl Run {
+    #[cfg(feature = "tuning")]
+    /// One lap at a steady `speed` from curve index `start`, `lateral` meters off the center
+    /// line, as if driven
+    pub fn paced(path: &Curve, start: f32, lateral: f32, speed: f32, class: ShipClass) -> Self {
+        let mut run = Run {
+            samples: vec![],
+            class,
+        };
+        let n_samples = (path.length() / (speed * SAMPLE_INTERVAL)).ceil().max(1.) as usize;
+        let offset = Transform::new().with_position(Vec3::Z * lateral);
+        for k in 0..=n_samples {
+            let time = k as f32 * SAMPLE_INTERVAL;
+            let t = path.advance(start, speed * time);
+            run.push(time, path.lerp_fast(t) * offset, speed * time);
+        }
+        run
+    }
+
+    #[cfg(feature = "tuning")]
+    /// Race time of the last sample
+    pub fn duration(&self) -> f32 {
+        self.samples.last().map_or(0., |s| s.time)
+    }
+
+    /// Where the ship was at `race_time`, if the run covers it
+    pub fn at(&self, race_time: f32) -> Option<Transform> {
+        let (first, last) = (self.samples.first()?, self.samples.last()?);
+        if race_time < first.time || race_time > last.time {
+            return None;
+        }
+
+        let next = self.samples.partition_point(|s| s.time <= race_time);
+        let a = self.samples[next.saturating_sub(1)];
+        let Some(b) = self.samples.get(next) else {
+            return Some(a.tf);
+        };
+        Some(a.tf.lerp_slerp(&b.tf, (race_time - a.time) / (b.time - a.time)))
+    }
+
+    /// Race time at which the run first got `distance` along the race, if it did
+    fn time_at_distance(&self, distance: f32) -> Option<f32> {
+        let (first, last) = (self.samples.first()?, self.samples.last()?);
+        if distance < first.distance || distance > last.distance {
+            return None;
+        }
+
+        let next = self.samples.partition_point(|s| s.distance < distance);
+        let b = self.samples[next];
+        let Some(a) = next.checked_sub(1).map(|i| self.samples[i]) else {
+            return Some(b.time);
+        };
+        let gap = (b.distance - a.distance).max(f32::EPSILON);
+        Some(a.time + (b.time - a.time) * (distance - a.distance) / gap)
+    }
+
+    fn push(&mut self, time: f32, tf: Transform, distance: f32) {
+        // Only ever forwards, so that it can be searched; reversing doesn't get you anywhere
+        let furthest = self
+            .samples
+            .last()
+            .map_or(distance, |s| s.distance.max(distance));
+        self.samples.push(Sample {
+            time,
+            tf,
+            distance: furthest,
+        });
+    }
+}
+
+/// Records each run, and replays the last completed one on a solid ship with a nameplate
+pub struct MirrorGhost {
+    entity: EntityId,
+    nameplate: EntityId,
+    /// Run being recorded
+    recording: Run,
+    /// Last completed run, waiting for the next attempt to start
+    finished: Option<Run>,
+    /// Run the ghost is replaying
+    ghost: Option<Run>,
+    /// Where the ghost is now, if shown
+    position: Option<Transform>,
+    /// Class of the mesh currently rendered, if shown
+    shown: Option<ShipClass>,
+}
+
+impl MirrorGhost {
+    const NAMEPLATE_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("GhostNameplate"));
+
+    pub fn new(io: &mut EngineIo) -> Self {
+        io.send(&UploadMesh {
+            mesh: panel_mesh(8., 1.5, "YOU (previous)", [0.6, 0.6, 1.]),
+            id: Self::NAMEPLATE_RDR,
+        });
+
+        let entity = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(HudVisibility::render(false, ShipClass::default().render()))
+            .add_component(GhostComponent)
+            .build();
+        let nameplate = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(Self::nameplate_render(false))
+            .add_component(GhostComponent)
+            .build();
+
+        Self {
+            entity,
+            nameplate,
+            recording: Run::default(),
+            finished: None,
+            ghost: None,
+            position: None,
+            shown: None,
+        }
+    }
+
+    fn nameplate_render(shown: bool) -> Render {
+        let render = Render::new(Self::NAMEPLATE_RDR).primitive(Primitive::Lines);
+        HudVisibility::render(shown, render)
+    }
+
+    /// A new attempt begins. A run finished since the last attempt replaces the ghost;
+    /// otherwise (e.g. the last attempt was aborted) the ghost stays as it was.
+    pub fn start(&mut self, class: ShipClass) {
+        if let Some(run) = self.finished.take() {
+            self.ghost = Some(run);
+        }
+        self.recording = Run {
+            samples: vec![],
+            class,
+        };
+    }
+
+    /// Note where we are, `race_time` into the race and `distance` along it
+    pub fn record(&mut self, race_time: f32, tf: Transform, distance: f32) {
+        let due = self
+            .recording
+            .samples
+            .last()
+            .map_or(true, |s| race_time - s.time >= SAMPLE_INTERVAL);
+        if due {
+            self.recording.push(race_time, tf, distance);
+        }
+    }
+
+    /// We crossed the line; this run becomes the ghost for the next attempt
+    pub fn finish(&mut self, race_time: f32, tf: Transform, distance: f32) {
+        self.recording.push(race_time, tf, distance);
+        self.finished = Some(std::mem::take(&mut self.recording));
+    }
+
+    /// Race time at which the ghost got `distance` along the race, if there is a ghost
+    pub fn time_at_distance(&self, distance: f32) -> Option<f32> {
+        self.ghost.as_ref()?.time_at_distance(distance)
+    }
+
+    /// Forget every run, e.g. when the track changes
+    pub fn clear(&mut self) {
+        self.recording = Run::default();
+        self.finished = None;
+        self.ghost = None;
+    }
+
+    /// Move the ghost to where it was `race_time` into its run, or hide it
+    pub fn update(&mut self, io: &mut EngineIo, race_time: f32, visible: bool) {
+        let ghost = self.ghost.as_ref().filter(|_| visible);
+        self.position = ghost.and_then(|run| run.at(race_time));
+        let shown = self.position.and(ghost.map(|run| run.class));
+
+        if shown != self.shown {
+            let class = shown.unwrap_or_default();
+            let render = HudVisibility::render(shown.is_some(), class.render());
+            io.add_component(self.entity, render);
+            io.add_component(self.nameplate, Self::nameplate_render(shown.is_some()));
+            self.shown = shown;
+        }
+
+        let Some(tf) = self.position else { return };
+        io.add_component(self.entity, tf);
+
+        // Face whoever is chasing the ghost
+        let above = Transform::new()
+            .with_position(Vec3::Y * NAMEPLATE_HEIGHT)
+            .with_rotation(Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2));
+        io.add_component(self.nameplate, tf * above);
+    }
+
+    /// Thrust multiplier for a ship at `pos`, from slipstreaming the ghost
+    pub fn draft_factor(&self, pos: Vec3) -> f32 {
+        self.position.map_or(1., |tf| draft_factor(tf, pos))
+    }
+}
+
+/// Thrust multiplier for a ship at `pos`, from slipstreaming a ship at `tf`
+pub fn draft_factor(tf: Transform, pos: Vec3) -> f32 {
+    // Ships face along their local +X
+    let local = tf.orient.inverse() * (pos - tf.pos);
+    let behind = -local.x;
+    let off_line = Vec3::new(0., local.y, local.z).length();
+    if behind <= 0. || behind > DRAFT_DISTANCE || off_line > DRAFT_WIDTH {
+        return 1.;
+    }
+
+    1. + (DRAFT_BOOST - 1.) * (1. - behind / DRAFT_DISTANCE)
+}
@@ -0,0 +1,142 @@
+//! Live time difference to the ghost, compared at the same distance along the race
+use cimvr_common::{glam::Vec3, ui::UiStateHelper};
+use cimvr_engine_interface::prelude::*;
+
+use crate::{curve::Curve, label_panel::LabelPanel, off_track::off_surface};
+
+/// The shown difference catches up with the measured one over about this long (seconds)
+const SMOOTHING_TIME: f32 = 0.3;
+/// The difference isn't measured below this speed, where a moment's pause would swing it
+/// (meters per second)
+const MIN_SPEED: f32 = 2.;
+/// Cells either side of the middle of the bar
+const BAR_CELLS: usize = 8;
+/// Difference which fills one side of the bar (seconds)
+const BAR_FULL: f32 = 2.;
+
+/// Distance along the race from the finish line, carried on across laps rather than starting
+/// over at each crossing, so that runs can be compared anywhere
+#[derive(Default)]
+pub struct RaceDistance {
+    /// Curve index, distance round the loop past the finish line, and distance along the
+    /// race, as of the last update
+    last: Option<(f32, f32, f32)>,
+    /// Whether the last update was off the racing surface
+    off: bool,
+}
+
+impl RaceDistance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start measuring afresh, e.g. at the start of a race
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    /// Distance along the race at `pos` (meters), negative on the grid. Measuring starts from
+    /// `lap`, the lap tracker's count of crossings; after that, it follows the ship.
+    pub fn update(&mut self, path: &Curve, pos: Vec3, lap: usize) -> f32 {
+        let length = path.length();
+        let t = match self.last {
+            Some((last_t, _, _)) => path.nearest_t_from(pos, last_t),
+            None => path.nearest_t(pos),
+        };
+        let finish = path.arc_length(path.finish_line());
+        let around = (path.arc_length(t) - finish).rem_euclid(length);
+
+        let distance = match self.last {
+            // Passing the line takes `around` from the end of the loop back to the start
+            Some((_, last_around, last_distance)) => {
+                let mut step = around - last_around;
+                if step > length / 2. {
+                    step -= length;
+                } else if step < -length / 2. {
+                    step += length;
+                }
+                last_distance + step
+            }
+            None if lap == 0 => match around > length / 2. {
+                true => around - length,
+                false => around,
+            },
+            None => (lap - 1) as f32 * length + around,
+        };
+        self.last = Some((t, around, distance));
+
+        let frame = path.lerp_fast(t);
+        let lateral = (frame.orient.inverse() * (pos - frame.pos)).z;
+        self.off = off_surface(lateral, path.cross_section_at(t).half_width());
+
+        distance
+    }
+
+    /// Whether a ship going at `speed` is making steady progress on the surface, so that
+    /// comparing it with the ghost means something
+    pub fn steady(&self, speed: f32) -> bool {
+        !self.off && speed > MIN_SPEED
+    }
+}
+
+/// "+0.42" behind the ghost or "-0.15" ahead, with a bar filling left when ahead and right
+/// when behind
+pub struct GhostDelta {
+    /// Smoothed difference, while there's one to show (seconds)
+    shown: Option<f32>,
+    panel: LabelPanel,
+}
+
+impl GhostDelta {
+    pub fn new() -> Self {
+        Self {
+            shown: None,
+            panel: LabelPanel::new("Ghost"),
+        }
+    }
+
+    /// Forget the difference, e.g. when a race starts
+    pub fn reset(&mut self) {
+        self.shown = None;
+    }
+
+    /// Ease towards `delta`, our race time less the ghost's at the same distance, and show it.
+    /// The difference holds still unless `steady`; see `RaceDistance::steady`.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        delta: Option<f32>,
+        steady: bool,
+        dt: f32,
+        visible: bool,
+    ) {
+        match (delta, self.shown) {
+            (None, _) => self.shown = None,
+            (Some(delta), None) => self.shown = Some(delta),
+            (Some(delta), Some(shown)) if steady => {
+                let blend = 1. - (-dt / SMOOTHING_TIME).exp();
+                self.shown = Some(shown + (delta - shown) * blend);
+            }
+            _ => (),
+        }
+
+        let visible = visible && self.shown.is_some();
+        let lines = self
+            .shown
+            .map_or(vec![], |shown| vec![format!("{:+.2}", shown), bar(shown)]);
+        self.panel.update(io, gui, visible, lines);
+    }
+}
+
+/// Filled cells left of the middle for ahead, right for behind
+fn bar(delta: f32) -> String {
+    let filled = ((delta.abs() / BAR_FULL).min(1.) * BAR_CELLS as f32).round() as usize;
+    let (ahead, behind) = match delta < 0. {
+        true => (filled, 0),
+        false => (0, filled),
+    };
+    let cells = |n: usize| "█".repeat(n) + &"░".repeat(BAR_CELLS - n);
+    let left: String = cells(ahead).chars().rev().collect();
+    format!("{}|{}", left, cells(behind))
+}
@@ -0,0 +1,443 @@
+//! Headless driving for tests: the controller, lap rules and integration run on a built-in
+//! track with no engine, fed seeded random input, so that properties can be checked over many
+//! runs rather than one hand-picked one.
+use std::f32::consts::{PI, TAU};
+
+use cimvr_common::{
+    glam::{Mat3, Quat, Vec3},
+    Transform,
+};
+
+use crate::{
+    builtin_tracks::BuiltinTrack,
+    controls::{ControlScheme, ShipController, ShipState, TuningParams, TRACK_WIDTH},
+    curve::{path_obj_to_curve, Curve},
+    kinematics::{self, KinematicPhysics, FIXED_DT},
+    race_rng::RaceRng,
+    race_rules::{GridLayout, LapTracker},
+    wall_ride::WallRide,
+    InputAbstraction, ShipCharacteristics,
+};
+
+/// Number of seeds each property is tried with
+const SEEDS: u64 = 8;
+/// Length of each random drive (seconds)
+const DRIVE_TIME: f32 = 20.;
+/// How far past the point the controller puts ships back a single step may carry one (meters)
+const OFF_TRACK_MARGIN: f32 = 10.;
+/// Longest a random input is held for (fixed steps)
+const MAX_HOLD: u32 = 60;
+/// Length of `long_straight`'s straights, and the radius and control points of its bends
+/// (meters)
+const STRAIGHT: f32 = 1000.;
+const STRAIGHT_SPACING: f32 = 20.;
+const BEND_RADIUS: f32 = 100.;
+const BEND_POINTS: usize = 8;
+/// Radius of `tiny_track`, and so the length of each of its segments (meters)
+const TINY_RADIUS: f32 = 60.;
+
+pub fn loop1() -> Curve {
+    path_obj_to_curve(BuiltinTrack::Loop1.path_obj())
+}
+
+/// Track through `points` in order, each control point facing the next with its Y axis up.
+/// The points must stay level enough for up to be off the direction of travel.
+pub fn track(points: &[Vec3]) -> Curve {
+    let n = points.len();
+    let ctrlps = (0..n)
+        .map(|i| {
+            let x = (points[(i + 1) % n] - points[(i + n - 1) % n]).normalize();
+            let y = (Vec3::Y - x * x.y).normalize();
+            Transform {
+                pos: points[i],
+                orient: Quat::from_mat3(&Mat3::from_cols(x, y, x.cross(y))),
+            }
+        })
+        .collect();
+    Curve::new(ctrlps)
+}
+
+/// A flat circle of `n` control points, starting on the X axis and going round towards Z
+pub fn ring(n: usize, radius: f32) -> Curve {
+    let points: Vec<Vec3> = (0..n)
+        .map(|i| {
+            let angle = i as f32 / n as f32 * TAU;
+            Vec3::new(angle.cos(), 0., angle.sin()) * radius
+        })
+        .collect();
+    track(&points)
+}
+
+/// A stadium-shaped loop, whose first half is a kilometer long straight from the origin along
+/// X, with control points every `STRAIGHT_SPACING`
+pub fn long_straight() -> Curve {
+    let mut points = vec![];
+    for (start, dir) in [
+        (Vec3::ZERO, 1.),
+        (Vec3::new(STRAIGHT, 0., 2. * BEND_RADIUS), -1.),
+    ] {
+        let n = (STRAIGHT / STRAIGHT_SPACING) as usize;
+        points.extend((0..n).map(|i| start + Vec3::X * dir * (i as f32 * STRAIGHT_SPACING)));
+
+        // Half a turn round to the start of the other straight
+        let center = start + Vec3::new(STRAIGHT * dir, 0., BEND_RADIUS * dir);
+        points.extend((0..BEND_POINTS).map(|i| {
+            let angle = i as f32 / BEND_POINTS as f32 * PI;
+            center + Vec3::new(angle.sin() * dir, 0., -angle.cos() * dir) * BEND_RADIUS
+        }));
+    }
+    track(&points)
+}
+
+/// The smallest track worth racing on: six control points, each a long way from the next
+pub fn tiny_track() -> Curve {
+    ring(6, TINY_RADIUS)
+}
+
+/// A ship at rest in the first grid slot, weighing what the local ship does
+pub fn grid_start(curve: &Curve) -> ShipState {
+    ShipState {
+        tf: GridLayout::compute(curve, 1)[0],
+        kt: KinematicPhysics {
+            mass: 1.,
+            moment: 1.,
+            ..Default::default()
+        },
+        wall_ride: WallRide::default(),
+        boost_cooldown: 0.,
+    }
+}
+
+/// Random controls, each held for a random number of steps as a player's would be
+pub struct RandomInput {
+    rng: RaceRng,
+    scheme: ControlScheme,
+    current: InputAbstraction,
+    steps_left: u32,
+}
+
+impl RandomInput {
+    pub fn new(seed: u64, scheme: ControlScheme) -> Self {
+        Self {
+            rng: RaceRng::new(seed).stream("input"),
+            scheme,
+            current: InputAbstraction::default(),
+            steps_left: 0,
+        }
+    }
+
+    pub fn next(&mut self) -> InputAbstraction {
+        if self.steps_left == 0 {
+            let rng = &mut self.rng;
+            self.current = InputAbstraction {
+                pitch: rng.range(-1., 1.),
+                yaw: rng.range(-1., 1.),
+                roll: rng.range(-1., 1.),
+                // Mostly forwards, as otherwise the ship barely goes anywhere
+                throttle: rng.range(-0.5, 1.),
+                boost: rng.next_f32() < 0.1,
+                lateral: rng.range(-1., 1.),
+                vertical: rng.range(-1., 1.),
+                scheme: self.scheme,
+            };
+            self.steps_left = 1 + rng.next_u32() % MAX_HOLD;
+        }
+        self.steps_left -= 1;
+        self.current
+    }
+}
+
+/// Move through `tracker` along the curve from `from` to `to` (in control points, either way
+/// round), in uneven strides which sometimes step back, and weaving across the track.
+/// Returns the most laps it counted at any point.
+pub fn drive_along(
+    curve: &Curve,
+    tracker: &mut LapTracker,
+    rng: &mut RaceRng,
+    from: f32,
+    to: f32,
+) -> usize {
+    let direction = (to - from).signum();
+    let (mut t, mut offset) = (from, 0.);
+    let mut most_laps = tracker.lap();
+    while (to - t) * direction > 0. {
+        t += rng.range(-0.05, 0.25) * direction;
+        if (to - t) * direction < 0. {
+            t = to;
+        }
+        offset = (offset + rng.range(-1., 1.)).clamp(-TRACK_WIDTH / 4., TRACK_WIDTH / 4.);
+
+        let weave = Transform::new().with_position(Vec3::Z * offset);
+        tracker.update(curve, (curve.smooth_lerp(t) * weave).pos);
+        most_laps = most_laps.max(tracker.lap());
+    }
+    most_laps
+}
+
+/// Allocations made on each thread, counted by a global allocator wrapping the system's
+#[cfg(feature = "alloc-count")]
+mod alloc_count {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            // Not counted while the thread is being torn down
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    /// Allocations, including reallocations, made on this thread while running `f`. Other
+    /// tests running at the same time don't count.
+    pub fn allocations(f: impl FnOnce()) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+}
+
+fn is_finite(state: &ShipState) -> bool {
+    state.tf.pos.is_finite()
+        && state.tf.orient.is_finite()
+        && state.kt.vel.is_finite()
+        && state.kt.ang_vel.is_finite()
+}
+
+#[test]
+fn random_driving_stays_finite_and_near_the_track() {
+    let curve = loop1();
+    let controller = ShipController::new(
+        ShipCharacteristics::standard(),
+        TuningParams::default(),
+        &curve,
+    );
+    for seed in 0..SEEDS {
+        let scheme = match seed % 2 {
+            0 => ControlScheme::Banked,
+            _ => ControlScheme::Strafe,
+        };
+        let mut input = RandomInput::new(seed, scheme);
+        let mut state = grid_start(&curve);
+        for step in 0..(DRIVE_TIME / FIXED_DT) as usize {
+            (state, _) = controller.fixed_step(input.next(), state);
+            assert!(
+                is_finite(&state),
+                "seed {} step {}: {:?}",
+                seed,
+                step,
+                state
+            );
+
+            // The controller puts ships back once they're twice the half width out
+            let frame = controller.track_frame(&state.tf);
+            let bound = frame.cross_section.half_width() * 2. + OFF_TRACK_MARGIN;
+            let lateral = frame.local.pos.z.abs();
+            assert!(
+                lateral <= bound,
+                "seed {} step {}: {} m from the middle of the track",
+                seed,
+                step,
+                lateral
+            );
+        }
+    }
+}
+
+#[test]
+fn driving_k_loops_forwards_counts_k_laps() {
+    for curve in [loop1(), tiny_track()] {
+        let n = curve.ctrlps.len() as f32;
+        for seed in 0..SEEDS {
+            let mut rng = RaceRng::new(seed).stream("laps");
+            let k = 1 + (rng.next_u32() % 3) as usize;
+
+            // From just behind the finish line, round to the same spot k times
+            let start = curve.finish_line() - 0.5;
+            let mut tracker = LapTracker::new(&curve);
+            tracker.reset(curve.smooth_lerp(start).pos);
+            let most = drive_along(&curve, &mut tracker, &mut rng, start, start + k as f32 * n);
+
+            assert_eq!(tracker.lap(), k, "{} points, seed {}", n, seed);
+            assert_eq!(most, k, "{} points, seed {}", n, seed);
+        }
+    }
+}
+
+#[test]
+fn driving_backwards_never_counts_a_lap() {
+    for curve in [loop1(), tiny_track()] {
+        let n = curve.ctrlps.len() as f32;
+        for seed in 0..SEEDS {
+            let mut rng = RaceRng::new(seed).stream("backwards");
+            let start = curve.finish_line() - 0.5;
+
+            // Straight off the grid the wrong way
+            let mut tracker = LapTracker::new(&curve);
+            tracker.reset(curve.smooth_lerp(start).pos);
+            let most = drive_along(&curve, &mut tracker, &mut rng, start, start - 2. * n);
+            assert_eq!(most, 0, "{} points, seed {}", n, seed);
+
+            // Or after leaving the grid properly, and turning round
+            let mut tracker = LapTracker::new(&curve);
+            tracker.reset(curve.smooth_lerp(start).pos);
+            let over = start + 1.;
+            drive_along(&curve, &mut tracker, &mut rng, start, over);
+            assert_eq!(tracker.lap(), 1, "{} points, seed {}", n, seed);
+            let most = drive_along(&curve, &mut tracker, &mut rng, over, over - 2. * n);
+            assert_eq!(most, 1, "{} points, seed {}", n, seed);
+        }
+    }
+}
+
+#[test]
+fn tiny_track_steering_looks_ahead_not_round_the_loop() {
+    let curve = tiny_track();
+    let controller = ShipController::new(
+        ShipCharacteristics::standard(),
+        TuningParams::default(),
+        &curve,
+    );
+    for ctrlp in &curve.ctrlps {
+        let frame = controller.track_frame(ctrlp);
+        let aim = controller.steering(&frame, 0.) * Vec3::X;
+        let along = ctrlp.orient * Vec3::X;
+        // Six points apart, the next control point is already a sixth of a turn round
+        assert!(aim.dot(along) > 0.5, "aiming {:?} from {:?}", aim, along);
+    }
+}
+
+#[test]
+fn random_driving_on_the_tiny_track_stays_finite() {
+    let curve = tiny_track();
+    let controller = ShipController::new(
+        ShipCharacteristics::standard(),
+        TuningParams::default(),
+        &curve,
+    );
+    for seed in 0..SEEDS {
+        let mut input = RandomInput::new(seed, ControlScheme::Banked);
+        let mut state = grid_start(&curve);
+        for step in 0..(DRIVE_TIME / FIXED_DT) as usize {
+            (state, _) = controller.fixed_step(input.next(), state);
+            assert!(is_finite(&state), "seed {} step {}", seed, step);
+        }
+    }
+}
+
+#[test]
+fn tiny_track_grid_starts_behind_the_line() {
+    let curve = tiny_track();
+    let n = curve.ctrlps.len() as f32;
+    let finish = LapTracker::new(&curve).finish().transform;
+    let mut rng = RaceRng::new(0).stream("grid");
+    for (i, slot) in GridLayout::compute(&curve, 8).iter().enumerate() {
+        assert!(
+            slot.pos.is_finite() && slot.orient.is_finite(),
+            "slot {}",
+            i
+        );
+        let ahead = (finish.inverse() * *slot).pos.x;
+        assert!(ahead < 0., "slot {} is {} m past the line", i, ahead);
+
+        // Nothing counts until the ship gets to the line, then leaving the grid does
+        let from = curve.nearest_t(slot.pos);
+        let mut to = curve.finish_line() + 0.5;
+        if to < from {
+            to += n;
+        }
+        let mut tracker = LapTracker::new(&curve);
+        tracker.reset(slot.pos);
+        assert!(tracker.update(&curve, slot.pos).is_empty(), "slot {}", i);
+        drive_along(&curve, &mut tracker, &mut rng, from, to);
+        assert_eq!(tracker.lap(), 1, "slot {}", i);
+    }
+}
+
+#[test]
+fn integration_moves_by_velocity_and_keeps_rotations_unit() {
+    let mut rng = RaceRng::new(0).stream("integrate");
+    let mut random_vec =
+        |scale: f32| Vec3::new(rng.range(-1., 1.), rng.range(-1., 1.), rng.range(-1., 1.)) * scale;
+    let mut tf = Transform::identity();
+    for _ in 0..1000 {
+        let kt = KinematicPhysics {
+            vel: random_vec(200.),
+            ang_vel: random_vec(10.),
+            ..KinematicPhysics::new(1.)
+        };
+        let before = tf;
+        kinematics::integrate(&mut tf, &kt, FIXED_DT);
+
+        let moved = tf.pos - before.pos;
+        assert!((moved - kt.vel * FIXED_DT).length() < 1e-3);
+        assert!((tf.orient.length() - 1.).abs() < 1e-3);
+        let turned = tf.orient * before.orient.inverse();
+        let expected = Quat::from_scaled_axis(kt.ang_vel * FIXED_DT);
+        assert!(turned.dot(expected).abs() > 1. - 1e-4);
+
+        // Keep the position from drifting too far for f32 to track small moves
+        tf.pos = Vec3::ZERO;
+    }
+}
+
+/// The engine-free part of the client's frame for the local ship, once it's up to speed: no
+/// collections grow, and there are no events to report. The rest of the frame needs the
+/// engine, so can't run here.
+#[cfg(feature = "alloc-count")]
+#[test]
+fn steady_driving_allocates_nothing_per_frame() {
+    use crate::{kinematics::FixedStep, net_stats::StreamStats, watchdog::MonotonicClock};
+
+    /// Frames to let the upload statistics fill their window, and frames measured after
+    const WARM_UP: usize = 180;
+    const MEASURED: usize = 240;
+    let delta = 1. / 60.;
+
+    // Coasting down the straight, a fifth of the way along
+    let curve = long_straight();
+    let mut state = grid_start(&curve);
+    state.tf = curve.ctrlps[10];
+    state.kt.vel = state.tf.orient * Vec3::X * 50.;
+    let mut tracker = LapTracker::new(&curve);
+    tracker.reset(state.tf.pos);
+    let (mut fixed, mut stats, mut clock) = (
+        FixedStep::default(),
+        StreamStats::new(),
+        MonotonicClock::new(),
+    );
+
+    let mut frame = |k: usize| {
+        let controller = ShipController::new(
+            ShipCharacteristics::standard(),
+            TuningParams::default(),
+            &curve,
+        );
+        for _ in 0..fixed.steps(delta) {
+            let (next, events) = controller.fixed_step(InputAbstraction::default(), state);
+            state = next;
+            assert!(events.is_empty(), "frame {}: {:?}", k, events);
+        }
+        assert!(tracker.update(&curve, state.tf.pos).is_empty());
+        stats.record(clock.now(k as f32 * delta), k as u32);
+    };
+
+    (0..WARM_UP).for_each(&mut frame);
+    let made = alloc_count::allocations(|| (WARM_UP..WARM_UP + MEASURED).for_each(&mut frame));
+    assert_eq!(made, 0);
+}
@@ -0,0 +1,58 @@
+use cimvr_common::{render::Render, Transform};
+
+/// How much of the client-side presentation is visible. Cycled with F1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HudVisibility {
+    /// Everything is shown
+    #[default]
+    Visible,
+    /// All overlays are hidden, for screenshots and streaming
+    Hidden,
+    /// Overlays and the local ship are hidden, and the camera lags behind
+    Cinematic,
+}
+
+/// How quickly the cinematic camera catches up with the ship (per second)
+const CINEMATIC_CAMERA_RATE: f32 = 2.;
+
+impl HudVisibility {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Visible => Self::Hidden,
+            Self::Hidden => Self::Cinematic,
+            Self::Cinematic => Self::Visible,
+        }
+    }
+
+    /// Whether overlays (panels, banners, markers) should be shown
+    pub fn show_hud(self) -> bool {
+        self == Self::Visible
+    }
+
+    /// Whether the local ship mesh should be shown
+    pub fn show_local_ship(self) -> bool {
+        self != Self::Cinematic
+    }
+
+    /// Render component for an element which is only visible if `shown`
+    pub fn render(shown: bool, render: Render) -> Render {
+        if shown {
+            render
+        } else {
+            render.limit(Some(0))
+        }
+    }
+
+    /// Smooth the camera transform in cinematic mode; passes it through otherwise
+    pub fn camera(self, smoothed: &mut Option<Transform>, target: Transform, dt: f32) -> Transform {
+        if self != Self::Cinematic {
+            *smoothed = None;
+            return target;
+        }
+
+        let current = smoothed.unwrap_or(target);
+        let next = current.lerp_slerp(&target, (dt * CINEMATIC_CAMERA_RATE).min(1.));
+        *smoothed = Some(next);
+        next
+    }
+}
@@ -0,0 +1,619 @@
+use chat::ChatDownload;
+use cimvr_common::{
+    glam::Vec3,
+    ui::{Schema, State, UiHandle, UiStateHelper},
+};
+use cimvr_engine_interface::prelude::*;
+
+use crate::{
+    banners::Banners,
+    bindings_panel::BindingsPanel,
+    chat_panel::ChatPanel,
+    entity_pool::EntityPool,
+    hud::HudVisibility,
+    input_state::BindableInput,
+    label_panel::LabelPanel,
+    lap_indicators::{LapIndicators, LocalView},
+    palette::Palette,
+    race_rules::format_race_time,
+    race_session::RacePhase,
+    records::TrackRecords,
+    scoreboard::Scoreboard,
+    settings::{ClientSettings, SettingsChange, SettingsPanel, SettingsSync},
+    strings::{tr, Key, Language, Text},
+    track_summary::TrackSummary,
+    vote_panel::VotePanel,
+    watched_timing::WatchedTiming,
+    Announcement, LobbyStatus, NextRace, RaceOver, RacePositions, RaceProgress, RaceResults,
+    RacerFinished, RacerNames, RecordsSync, RuleVote,
+};
+
+#[cfg(feature = "tuning")]
+use crate::{
+    controls::TuningParams,
+    dummies::{DummyAction, DummyPanel},
+    tuning::TuningPanel,
+    ShipCharacteristics,
+};
+
+/// How long everyone's results stay up once the race is over (seconds)
+const RESULTS_TIME: f32 = 10.;
+
+pub fn ready_text(language: Language, ready: bool) -> String {
+    match ready {
+        true => tr(language, Key::Ready).to_string(),
+        false => tr(language, Key::NotReady).to_string(),
+    }
+}
+
+/// A line for each client in the lobby, with their rating and whether they're ready or away
+fn lobby_lines(language: Language, LobbyStatus(entries): &LobbyStatus) -> Vec<String> {
+    entries
+        .iter()
+        .map(|entry| {
+            let color = tr(language, entry.color.name());
+            let name = match entry.duplicate {
+                true => format!("{} {}", entry.name, tr(language, Key::DuplicateName)),
+                false => format!("{} ({})", entry.name, entry.rating),
+            };
+            let name = format!("{} [{}]", name, color);
+            match (entry.afk, entry.ready) {
+                (true, _) => format!("{} {}", name, tr(language, Key::Afk)),
+                (false, true) => format!("{} {}", name, tr(language, Key::Ready)),
+                (false, false) => name,
+            }
+        })
+        .collect()
+}
+
+/// A line for each racer in a race's results: where they finished and in what time, or that
+/// they didn't. Racers still in the `lobby` have their color beside their name.
+fn results_lines(
+    language: Language,
+    RaceResults(racers): &RaceResults,
+    LobbyStatus(lobby): &LobbyStatus,
+) -> Vec<String> {
+    let mut position = 0;
+    racers
+        .iter()
+        .map(|racer| {
+            let name = match racer.name.is_empty() {
+                true => format!("{:?}", racer.client_id),
+                false => racer.name.clone(),
+            };
+            let entry = lobby
+                .iter()
+                .find(|entry| entry.client_id == racer.client_id);
+            let name = match entry {
+                Some(entry) => format!("{} [{}]", name, tr(language, entry.color.name())),
+                None => name,
+            };
+            match racer.finish_time {
+                Some(time) => {
+                    position += 1;
+                    Text::new(Key::ResultLine)
+                        .with(position)
+                        .with(name)
+                        .with(format_race_time(time))
+                        .render(language)
+                }
+                None => Text::new(Key::ResultDnf).with(name).render(language),
+            }
+        })
+        .collect()
+}
+
+/// Our place in the race and the number of racers, e.g. "P2 of 5"
+fn position_text(language: Language, (position, racers): (u8, usize)) -> String {
+    Text::new(Key::RacePosition)
+        .with(position)
+        .with(racers)
+        .render(language)
+}
+
+fn ready_panel(
+    io: &mut EngineIo,
+    gui: &mut UiStateHelper,
+    language: Language,
+    text: String,
+) -> UiHandle {
+    let schema = vec![
+        Schema::Button {
+            text: tr(language, Key::ToggleReady).into(),
+        },
+        Schema::Label,
+        Schema::Button {
+            text: tr(language, Key::TimeTrial).into(),
+        },
+    ];
+    let init_state = vec![
+        State::Button { clicked: false },
+        State::Label { text },
+        State::Button { clicked: false },
+    ];
+    gui.add(io, "FZ", schema, init_state)
+}
+
+/// Panels and overlays, and the player's presentation settings
+pub struct HudState {
+    pub gui: UiStateHelper,
+    visibility: HudVisibility,
+    /// Ready panel; removed while the HUD is hidden
+    ready_state_element: Option<UiHandle>,
+    /// What the ready panel's label shows, so it's only rewritten on a change
+    label_shown: String,
+    /// Our place and the number of racers, from the last `RacePositions`, while racing.
+    /// Shown in the ready panel's label in place of the ready state.
+    race_position: Option<(u8, usize)>,
+    /// The time trial button was pressed this frame, while spectating
+    time_trial_pressed: bool,
+    client_settings: ClientSettings,
+    settings_panel: SettingsPanel,
+    bindings_panel: BindingsPanel,
+    settings_sync: SettingsSync,
+    /// The player asked for the saved settings to be wiped
+    settings_reset: bool,
+    lap_indicators: LapIndicators,
+    telemetry: LabelPanel,
+    /// Our lap times, while driving
+    laps: LabelPanel,
+    banners: Banners,
+    scoreboard: Scoreboard,
+    /// Timing of whoever we're spectating
+    watched: WatchedTiming,
+    /// Everyone connected, from the last `LobbyStatus`
+    lobby: LobbyStatus,
+    lobby_panel: LabelPanel,
+    next_race: Option<NextRace>,
+    /// Records on the current track, from the last `RecordsSync`
+    records: TrackRecords,
+    /// Newly loaded track, described in the lobby panel until we ready up
+    track_summary: Option<TrackSummary>,
+    chat: ChatPanel,
+    votes: VotePanel,
+    /// Our own result, shown while cooling down after the race
+    result: Option<String>,
+    result_panel: LabelPanel,
+    /// Everyone's results from the race just over, and how much longer to show them
+    results: Option<(RaceResults, f32)>,
+    results_panel: LabelPanel,
+    /// Ship tuning and practice grid panels, once they've been shown
+    #[cfg(feature = "tuning")]
+    dev_panels: Option<(TuningPanel, DummyPanel)>,
+}
+
+impl HudState {
+    pub fn new(io: &mut EngineIo) -> Self {
+        let mut gui = UiStateHelper::new();
+        let client_settings = ClientSettings::default();
+        let language = client_settings.language;
+        let label_shown = ready_text(language, false);
+        let ready_state_element = Some(ready_panel(io, &mut gui, language, label_shown.clone()));
+
+        let settings_panel = SettingsPanel::new(io, &mut gui, client_settings);
+
+        Self {
+            gui,
+            visibility: HudVisibility::default(),
+            ready_state_element,
+            label_shown,
+            race_position: None,
+            time_trial_pressed: false,
+            client_settings,
+            settings_panel,
+            bindings_panel: BindingsPanel::new(),
+            settings_sync: SettingsSync::new(client_settings),
+            settings_reset: false,
+            lap_indicators: LapIndicators::new(io),
+            telemetry: LabelPanel::new("Telemetry"),
+            laps: LabelPanel::new("Laps"),
+            banners: Banners::new(),
+            scoreboard: Scoreboard::new(),
+            watched: WatchedTiming::new(),
+            lobby: LobbyStatus::default(),
+            lobby_panel: LabelPanel::new("Lobby"),
+            next_race: None,
+            records: TrackRecords::default(),
+            track_summary: None,
+            chat: ChatPanel::new(),
+            votes: VotePanel::new(),
+            result: None,
+            result_panel: LabelPanel::new("Race complete"),
+            results: None,
+            results_panel: LabelPanel::new("Results"),
+            #[cfg(feature = "tuning")]
+            dev_panels: None,
+        }
+    }
+
+    pub fn visibility(&self) -> HudVisibility {
+        self.visibility
+    }
+
+    pub fn show_hud(&self) -> bool {
+        self.visibility.show_hud()
+    }
+
+    /// Cycle through the HUD visibility levels
+    pub fn toggle(&mut self) {
+        self.visibility = self.visibility.next();
+    }
+
+    pub fn settings(&self) -> &ClientSettings {
+        &self.client_settings
+    }
+
+    /// Take on settings saved in an earlier session
+    pub fn load_settings(&mut self, io: &mut EngineIo, settings: ClientSettings) {
+        self.client_settings = settings;
+        self.settings_sync.loaded(settings);
+
+        // The panels are rebuilt next frame, showing the new values in the new language
+        self.settings_panel
+            .set_visible(io, &mut self.gui, false, settings);
+        let (bindings, language) = (&settings.bindings, settings.language);
+        self.bindings_panel
+            .set_visible(io, &mut self.gui, false, bindings, language);
+        if let Some(handle) = self.ready_state_element.take() {
+            self.gui.delete(io, handle);
+        }
+    }
+
+    /// Save changes to the settings, once they've settled, or wipe them after a reset
+    pub fn sync_settings(&mut self, io: &mut EngineIo, time: f32) {
+        if std::mem::take(&mut self.settings_reset) {
+            self.settings_sync.wipe(io);
+        }
+        self.settings_sync.update(io, time, self.client_settings);
+    }
+
+    pub fn palette(&self) -> Palette {
+        Palette::from_settings(&self.client_settings)
+    }
+
+    pub fn clear_lap_indicators(&mut self, io: &mut EngineIo, pool: &mut EntityPool) {
+        self.lap_indicators.clear(io, pool);
+    }
+
+    pub fn language(&self) -> Language {
+        self.client_settings.language
+    }
+
+    /// Show `text` in a banner, after any already waiting
+    pub fn announce(&mut self, text: &Text) {
+        self.banners.push(text.render(self.language()));
+    }
+
+    /// Introduce a newly loaded track, with a banner and rows in the lobby panel
+    pub fn show_track_summary(&mut self, summary: TrackSummary) {
+        self.announce(&summary.headline());
+        self.track_summary = Some(summary);
+    }
+
+    /// Take in what the player did in the panels. Call each frame before updating any of them.
+    pub fn download(&mut self, io: &mut EngineIo) {
+        self.gui.download(io);
+    }
+
+    /// Update the developer panels, applying the tuning panel's presses to `ship` and `tuning`.
+    /// Returns what the practice grid panel asked for.
+    #[cfg(feature = "tuning")]
+    pub fn update_dev_panels(
+        &mut self,
+        io: &mut EngineIo,
+        ship: &mut ShipCharacteristics,
+        tuning: &mut TuningParams,
+    ) -> Option<DummyAction> {
+        let show_hud = self.show_hud();
+        let (tuning_panel, dummy_panel) = self.dev_panels.get_or_insert_with(|| {
+            let tuning_panel = TuningPanel::new(io, &mut self.gui, *ship, *tuning);
+            (tuning_panel, DummyPanel::new(io, &mut self.gui))
+        });
+
+        tuning_panel.set_visible(io, &mut self.gui, show_hud, *ship, *tuning);
+        tuning_panel.update(io, &mut self.gui, ship, tuning);
+
+        dummy_panel.set_visible(io, &mut self.gui, show_hud);
+        dummy_panel.update(io, &mut self.gui)
+    }
+
+    /// Update the settings, controls and ready panels. `ready` is whether we're ready for the
+    /// next race, if spectating; while racing, the ready panel shows our place instead.
+    /// `pressed` is the input used this frame, for rebinding. Returns the new ready state,
+    /// if the player changed it.
+    pub fn update_panels(
+        &mut self,
+        io: &mut EngineIo,
+        ready: Option<bool>,
+        is_vr: bool,
+        pressed: Option<BindableInput>,
+    ) -> Option<bool> {
+        let show_hud = self.show_hud();
+
+        self.settings_panel
+            .set_visible(io, &mut self.gui, show_hud, self.client_settings);
+        let language = self.language();
+        let change = self
+            .settings_panel
+            .update(io, &mut self.gui, &mut self.client_settings);
+        self.settings_reset |= change == Some(SettingsChange::Reset);
+
+        // The ready panel's button needs relabelling too, and the controls panel throughout
+        let relabel = language != self.language();
+        if relabel || change == Some(SettingsChange::Reset) {
+            let bindings = &self.client_settings.bindings;
+            self.bindings_panel
+                .set_visible(io, &mut self.gui, false, bindings, language);
+        }
+        if let (true, Some(handle)) = (relabel, self.ready_state_element) {
+            self.gui.delete(io, handle);
+            self.ready_state_element = None;
+        }
+        let language = self.language();
+
+        let bindings = &mut self.client_settings.bindings;
+        self.bindings_panel
+            .set_visible(io, &mut self.gui, show_hud, bindings, language);
+        self.bindings_panel
+            .update(io, &mut self.gui, bindings, pressed, language);
+
+        let label = |ready: Option<bool>, race_position| match (ready, race_position) {
+            (None, Some(race_position)) => position_text(language, race_position),
+            _ => ready_text(language, ready.unwrap_or(false)),
+        };
+
+        // Clear or restore the ready panel along with the rest of the HUD
+        match (show_hud, self.ready_state_element) {
+            (true, None) => {
+                let text = label(ready, self.race_position);
+                self.label_shown = text.clone();
+                self.ready_state_element = Some(ready_panel(io, &mut self.gui, language, text));
+            }
+            (false, Some(handle)) => {
+                self.gui.delete(io, handle);
+                self.ready_state_element = None;
+            }
+            _ => (),
+        }
+
+        // Our place changes while racing, when the ready state can't
+        if let (None, Some(handle)) = (ready, self.ready_state_element) {
+            let text = label(ready, self.race_position);
+            if text != self.label_shown {
+                self.set_label(io, handle, text);
+            }
+        }
+
+        // Toggle ready state based on UI interaction
+        self.time_trial_pressed = false;
+        let mut ready = ready?;
+        let mut clicked = match self.ready_state_element {
+            Some(handle) => self.gui.read(handle)[0] != (State::Button { clicked: false }),
+            None => false,
+        };
+        if let Some(handle) = self.ready_state_element {
+            self.time_trial_pressed = self.gui.read(handle)[2] == State::Button { clicked: true };
+        }
+
+        clicked |= !ready && is_vr;
+
+        if clicked {
+            ready = !ready;
+        }
+
+        // They've seen enough of the track to decide
+        if clicked && ready && self.track_summary.take().is_some() {
+            self.banners.clear(io, &mut self.gui);
+        }
+
+        let text = label(Some(ready), None);
+        if let (true, Some(handle)) = (text != self.label_shown, self.ready_state_element) {
+            self.set_label(io, handle, text);
+        }
+
+        clicked.then_some(ready)
+    }
+
+    /// Whether the player asked for a time trial this frame
+    pub fn time_trial_pressed(&self) -> bool {
+        self.time_trial_pressed
+    }
+
+    fn set_label(&mut self, io: &mut EngineIo, handle: UiHandle, text: String) {
+        self.label_shown = text.clone();
+        self.gui.modify(io, handle, |ui_state| {
+            let text = text.clone();
+            ui_state[1] = State::Label { text };
+        });
+    }
+
+    /// Collect incoming chat, and show it unless we're busy racing.
+    /// Expects `gui` to already be downloaded.
+    pub fn update_chat(&mut self, io: &mut EngineIo, phase: RacePhase) {
+        for message in io.inbox::<ChatDownload>().collect::<Vec<_>>() {
+            self.chat.push(message);
+        }
+        for Announcement(text) in io.inbox::<Announcement>().collect::<Vec<_>>() {
+            self.chat.push_announcement(&text.render(self.language()));
+        }
+
+        let racing = phase.is_racing() && !self.client_settings.chat_while_racing;
+        let visible = self.show_hud() && !racing;
+        self.chat.update(io, &mut self.gui, visible);
+    }
+
+    /// Show the open rule votes between races. Expects `gui` to already be downloaded.
+    pub fn update_votes(&mut self, io: &mut EngineIo, phase: RacePhase) {
+        if let Some(votes) = io.inbox::<RuleVote>().last() {
+            self.votes.set(votes);
+        }
+
+        let visible = self.show_hud() && !phase.is_racing();
+        self.votes.update(io, &mut self.gui, visible);
+    }
+
+    pub fn set_progress(&mut self, progress: RaceProgress) {
+        self.lap_indicators.set_progress(progress);
+    }
+
+    /// Expects a "ServerShips" query
+    pub fn update_lap_indicators(
+        &mut self,
+        io: &mut EngineIo,
+        pool: &mut EntityPool,
+        query: &mut QueryResult,
+        phase: RacePhase,
+        lap: usize,
+        camera_pos: Vec3,
+    ) {
+        let view = LocalView {
+            client_id: phase.client_id(),
+            lap,
+            camera_pos,
+            visible: self.show_hud(),
+            palette: self.palette(),
+        };
+        self.lap_indicators.update(io, pool, query, &view);
+    }
+
+    /// Announce other racers' finishes, and keep the spectator scoreboard and the timing of
+    /// `watching` up to date, as of local `time`
+    pub fn race_feed(
+        &mut self,
+        io: &mut EngineIo,
+        phase: RacePhase,
+        watching: Option<ClientId>,
+        time: f32,
+        delta: f32,
+    ) {
+        if let Some(names) = io.inbox_first::<RacerNames>() {
+            self.results = None;
+            self.watched.start(&names);
+            self.scoreboard.start(names);
+        }
+
+        if let Some(progress) = io.inbox_first::<RaceProgress>() {
+            self.scoreboard.set_progress(&progress);
+            self.watched.set_progress(&progress, time);
+        }
+
+        if io.inbox_first::<RaceOver>().is_some() {
+            self.watched.stop();
+        }
+
+        if let Some(results) = io.inbox_first::<RaceResults>() {
+            self.results = Some((results, RESULTS_TIME));
+        }
+        if let Some((_, time_left)) = &mut self.results {
+            *time_left -= delta;
+            if *time_left <= 0. {
+                self.results = None;
+            }
+        }
+
+        if let Some(lobby) = io.inbox_first::<LobbyStatus>() {
+            self.scoreboard.set_ratings(&lobby);
+            self.lobby = lobby;
+        }
+
+        if let Some(next_race) = io.inbox_first::<NextRace>() {
+            self.next_race = Some(next_race);
+        }
+        if let Some(RecordsSync(records)) = io.inbox_first() {
+            self.records = records;
+        }
+
+        if phase.is_racing() {
+            self.result = None;
+        }
+
+        if let Some(RacePositions(positions)) = io.inbox_first::<RacePositions>() {
+            let racers = positions.len();
+            self.race_position = positions
+                .into_iter()
+                .find(|&(client_id, _)| Some(client_id) == phase.client_id())
+                .map(|(_, position)| (position, racers));
+        }
+        if !phase.is_racing() {
+            self.race_position = None;
+        }
+
+        let language = self.language();
+        for finish in io.inbox::<RacerFinished>().collect::<Vec<_>>() {
+            let time = format_race_time(finish.time);
+            if Some(finish.client_id) == phase.client_id() {
+                let text = Text::new(Key::RaceComplete)
+                    .with(finish.position)
+                    .with(&time);
+                self.result = Some(text.render(language));
+            }
+
+            // Let those still racing know they've been beaten
+            if let RacePhase::Racing { client_id } = phase {
+                if finish.client_id != client_id {
+                    let text = Text::new(Key::RacerFinished)
+                        .with(&finish.username)
+                        .with(finish.position)
+                        .with(&time);
+                    self.banners.push(text.render(language));
+                }
+            }
+            self.watched.finish(&finish);
+            self.scoreboard.finish(finish);
+        }
+
+        let show_hud = self.show_hud();
+        self.banners.update(io, &mut self.gui, delta, show_hud);
+
+        let cooling_down = matches!(phase, RacePhase::CoolDown { .. });
+        let lines = self.result.iter().cloned().collect();
+        let show_result = show_hud && cooling_down && self.result.is_some();
+        self.result_panel
+            .update(io, &mut self.gui, show_result, lines);
+
+        let spectating = !phase.is_racing();
+        self.scoreboard
+            .update(io, &mut self.gui, show_hud && spectating);
+        let lines = match &self.results {
+            Some((results, _)) => results_lines(language, results, &self.lobby),
+            None => vec![],
+        };
+        let show_results = show_hud && phase == RacePhase::Spectating && !lines.is_empty();
+        self.results_panel
+            .update(io, &mut self.gui, show_results, lines);
+        let watching = watching.filter(|_| phase == RacePhase::Spectating);
+        self.watched
+            .update(io, &mut self.gui, watching, time, show_hud, language);
+
+        let mut lines = lobby_lines(language, &self.lobby);
+        if let Some(summary) = &self.track_summary {
+            lines.extend(summary.lines(language, self.next_race.as_ref()));
+        }
+        let laps = self.next_race.map(|next_race| next_race.laps);
+        lines.extend(
+            self.records
+                .lines(laps)
+                .iter()
+                .map(|text| text.render(language)),
+        );
+        let show_lobby = show_hud && spectating && !lines.is_empty();
+        self.lobby_panel
+            .update(io, &mut self.gui, show_lobby, lines);
+    }
+
+    /// Race time of the race being spectated at local `time`, as far as we know
+    pub fn race_time(&self, time: f32) -> Option<f32> {
+        self.watched.race_time(time)
+    }
+
+    pub fn update_telemetry(&mut self, io: &mut EngineIo, lines: Vec<String>) {
+        let show_telemetry = self.client_settings.telemetry && self.show_hud();
+        self.telemetry
+            .update(io, &mut self.gui, show_telemetry, lines);
+    }
+
+    pub fn update_laps(&mut self, io: &mut EngineIo, driving: bool, lines: Vec<String>) {
+        let show_laps = driving && self.show_hud() && !lines.is_empty();
+        self.laps.update(io, &mut self.gui, show_laps, lines);
+    }
+}
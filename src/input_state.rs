@@ -0,0 +1,653 @@
+use std::collections::HashSet;
+
+use cimvr_common::{
+    desktop::{InputEvent, KeyCode, MouseEvent, WindowEvent},
+    gamepad::{Axis, Button, GamepadState},
+    ui::{Schema, State, UiHandle, UiStateHelper},
+    utils::input_helper::InputHelper,
+};
+use cimvr_engine_interface::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{controls::ControlScheme, strings::Key, InputAbstraction};
+
+/// How long the last gamepad throttle is held after messages stop arriving
+const GAMEPAD_GRACE_PERIOD: f32 = 0.5;
+/// How long without gamepad messages before the controller is considered disconnected
+const GAMEPAD_STALE_TIME: f32 = 1.0;
+/// How long a key or button must be held for a long press (seconds)
+const LONG_PRESS_TIME: f32 = 0.5;
+/// How far a stick must be pushed to be picked for rebinding
+const AXIS_CAPTURE: f32 = 0.6;
+/// Fraction of the window across which mouse steering goes from full left to full right
+const MOUSE_STEER_SPAN: f32 = 0.5;
+/// Fraction of `MOUSE_STEER_SPAN` in the middle which doesn't steer, so the pointer can rest
+const MOUSE_DEAD_ZONE: f32 = 0.1;
+
+/// Keys which an action can be rebound to
+const BINDABLE_KEYS: &[KeyCode] = &[
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Space,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Up,
+    KeyCode::Down,
+];
+
+/// A key, button or stick axis used this frame, which could be bound to an action
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BindableInput {
+    Key(KeyCode),
+    Button(Button),
+    Axis(Axis),
+}
+
+/// Which key and gamepad button does what
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    /// Full throttle; `None` leaves the throttle to the auto-throttle
+    pub throttle: Option<KeyCode>,
+    pub brake: KeyCode,
+    /// Strafe instead, under `ControlScheme::Strafe`
+    pub roll_left: KeyCode,
+    pub roll_right: KeyCode,
+    /// The second stick, under `ControlScheme::Strafe`
+    pub yaw_left: KeyCode,
+    pub yaw_right: KeyCode,
+    pub pitch_up: KeyCode,
+    pub pitch_down: KeyCode,
+    pub use_item: KeyCode,
+    /// Cycle the spectator view; `None` moves it to a long press of `use_item`
+    pub cycle_view: Option<KeyCode>,
+    /// Go back to the furthest point driven to; `None` moves it to a long press of `use_item`,
+    /// which only it uses while racing
+    pub respawn: Option<KeyCode>,
+    /// Gamepad button for full throttle; `None` leaves the throttle to the auto-throttle
+    pub pad_throttle: Option<Button>,
+    pub pad_brake: Button,
+    pub pad_use_item: Button,
+    /// As `cycle_view`, moving to a long press of `pad_use_item` when `None`
+    pub pad_cycle_view: Option<Button>,
+    /// As `respawn`, moving to a long press of `pad_use_item` when `None`
+    pub pad_respawn: Option<Button>,
+    /// Buttons stepping backwards and forwards through the ships
+    pub pad_cycle_ship: Option<(Button, Button)>,
+    /// Take yaw, pitch and throttle from the sticks as well as roll; otherwise steering is
+    /// `pad_steer` alone
+    pub pad_sticks: bool,
+    /// Stick axis for roll, or strafing under `ControlScheme::Strafe`
+    pub pad_steer: Axis,
+    /// Stick axis for pitch, or climbing under `ControlScheme::Strafe`
+    pub pad_pitch: Axis,
+    pub pad_yaw: Axis,
+    /// Stick axis for the throttle, or pitch under `ControlScheme::Strafe`
+    pub pad_throttle_axis: Axis,
+}
+
+impl KeyBindings {
+    pub const STANDARD: KeyBindings = KeyBindings {
+        throttle: Some(KeyCode::W),
+        brake: KeyCode::S,
+        roll_left: KeyCode::A,
+        roll_right: KeyCode::D,
+        yaw_left: KeyCode::J,
+        yaw_right: KeyCode::L,
+        pitch_up: KeyCode::I,
+        pitch_down: KeyCode::K,
+        use_item: KeyCode::Space,
+        cycle_view: Some(KeyCode::C),
+        respawn: Some(KeyCode::R),
+        pad_throttle: Some(Button::RightTrigger2),
+        pad_brake: Button::LeftTrigger2,
+        pad_use_item: Button::South,
+        pad_cycle_view: Some(Button::North),
+        pad_respawn: Some(Button::West),
+        pad_cycle_ship: Some((Button::LeftTrigger, Button::RightTrigger)),
+        pad_sticks: true,
+        pad_steer: Axis::LeftStickX,
+        pad_pitch: Axis::LeftStickY,
+        pad_yaw: Axis::RightStickX,
+        pad_throttle_axis: Axis::RightStickY,
+    };
+
+    /// The bindings as a short line of text, to be pasted back into `from_chat_text`
+    pub fn to_chat_text(&self) -> String {
+        let bytes = bincode::serialize(self).unwrap_or_default();
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn from_chat_text(text: &str) -> Option<Self> {
+        let bytes = (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        bincode::deserialize(&bytes).ok()
+    }
+}
+
+/// A named bundle of bindings and the assists they're meant to be played with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ControlPreset {
+    pub name: Key,
+    pub bindings: KeyBindings,
+    pub auto_throttle: bool,
+    /// Steer with the pointer's place across the window, as well as with the keys and stick
+    pub mouse_steer: bool,
+}
+
+/// Everything under one hand: the arrow keys, the mouse, or the left side of the gamepad.
+/// Holding the item control respawns while racing, and changes the view while spectating.
+pub const ONE_HANDED: ControlPreset = ControlPreset {
+    name: Key::OneHanded,
+    bindings: KeyBindings {
+        throttle: None,
+        brake: KeyCode::Down,
+        roll_left: KeyCode::Left,
+        roll_right: KeyCode::Right,
+        yaw_left: KeyCode::J,
+        yaw_right: KeyCode::L,
+        pitch_up: KeyCode::I,
+        pitch_down: KeyCode::K,
+        use_item: KeyCode::Up,
+        cycle_view: None,
+        respawn: None,
+        pad_throttle: None,
+        pad_brake: Button::LeftTrigger2,
+        pad_use_item: Button::LeftTrigger,
+        pad_cycle_view: None,
+        pad_respawn: None,
+        pad_cycle_ship: None,
+        pad_sticks: false,
+        pad_steer: Axis::LeftStickX,
+        pad_pitch: Axis::LeftStickY,
+        pad_yaw: Axis::RightStickX,
+        pad_throttle_axis: Axis::RightStickY,
+    },
+    auto_throttle: true,
+    mouse_steer: true,
+};
+
+/// Tells taps of a key or button from long presses, given whether it's held each frame
+#[derive(Default)]
+struct PressTimer {
+    /// When the current press began, and whether it has counted as long yet
+    down: Option<(f32, bool)>,
+}
+
+impl PressTimer {
+    /// Returns whether a tap ended, and whether a long press began, at `time`. Taps count on
+    /// release, as until then they may yet turn out to be long.
+    fn update(&mut self, held: bool, time: f32) -> (bool, bool) {
+        match (held, self.down) {
+            (true, None) => {
+                self.down = Some((time, false));
+                (false, false)
+            }
+            (true, Some((since, false))) if time - since >= LONG_PRESS_TIME => {
+                self.down = Some((since, true));
+                (false, true)
+            }
+            (false, Some((_, long))) => {
+                self.down = None;
+                (!long, false)
+            }
+            _ => (false, false),
+        }
+    }
+}
+
+/// Steering for the pointer at `x` in a window `width` across (pixels): full lock at either
+/// end of the middle `MOUSE_STEER_SPAN` of it, and none around the middle
+fn mouse_steer(x: f32, width: f32) -> f32 {
+    let across = (2. * x / width - 1.) / MOUSE_STEER_SPAN;
+    let beyond = (across.abs() - MOUSE_DEAD_ZONE).max(0.) / (1. - MOUSE_DEAD_ZONE);
+    (beyond * across.signum()).clamp(-1., 1.)
+}
+
+/// Tracks whether the gamepad is still sending us input
+#[derive(Default)]
+struct GamepadHealth {
+    /// Time and throttle value of the last gamepad message
+    last_seen: Option<(f32, f32)>,
+    /// Number of gamepads in the last message
+    n_gamepads: usize,
+    /// Whether the gamepad had gone stale as of the last check
+    was_stale: bool,
+    /// "Controller disconnected" warning, if shown
+    warning: Option<UiHandle>,
+}
+
+impl GamepadHealth {
+    fn seen(&mut self, time: f32, throttle: f32) {
+        self.last_seen = Some((time, throttle));
+    }
+
+    /// Throttle to keep applying while within the grace period of a gamepad going quiet
+    fn held_throttle(&self, time: f32) -> Option<f32> {
+        let (last_time, throttle) = self.last_seen?;
+        (time - last_time < GAMEPAD_GRACE_PERIOD).then_some(throttle)
+    }
+
+    /// Show or hide the disconnect warning. Returns true when the gamepad has just gone stale.
+    fn check_stale(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        time: f32,
+        visible: bool,
+    ) -> bool {
+        let is_stale = self
+            .last_seen
+            .map(|(last_time, _)| time - last_time > GAMEPAD_STALE_TIME)
+            .unwrap_or(false);
+
+        let went_stale = is_stale && !self.was_stale;
+        self.was_stale = is_stale;
+
+        match (is_stale && visible, self.warning) {
+            (true, None) => {
+                let schema = vec![Schema::Label];
+                let state = vec![State::Label {
+                    text: "Controller disconnected".into(),
+                }];
+                self.warning = Some(gui.add(io, "Controller", schema, state));
+            }
+            (false, Some(handle)) => {
+                gui.delete(io, handle);
+                self.warning = None;
+            }
+            _ => (),
+        }
+
+        went_stale
+    }
+}
+
+/// The player's controls, gathered from the keyboard and gamepad each frame
+pub struct InputState {
+    helper: InputHelper,
+    input: InputAbstraction,
+    gamepad_health: GamepadHealth,
+    /// Whether the use item control was pressed this frame
+    use_item: bool,
+    /// Whether the HUD toggle was pressed this frame
+    toggle_hud: bool,
+    /// Whether the drop marker key was pressed this frame
+    drop_marker: bool,
+    /// Whether the respawn key was pressed this frame
+    respawn: bool,
+    /// Ship selection steps requested this frame, negative for backwards
+    cycle_ship: i32,
+    /// Steps through the ships to spectate requested this frame, negative for backwards
+    cycle_watched: i32,
+    /// Left and right bumpers as of the last gamepad message, to catch presses
+    bumpers: (bool, bool),
+    /// Whether the spectator view control was pressed this frame
+    cycle_view: bool,
+    /// View button as of the last gamepad message, to catch presses
+    north: bool,
+    /// A key, button or axis which could be bound to an action, if one was used this frame
+    pressed: Option<BindableInput>,
+    /// Buttons held, and axes pushed past `AXIS_CAPTURE`, as of the last gamepad message
+    held_buttons: HashSet<Button>,
+    pushed_axes: HashSet<Axis>,
+    /// Taps and long presses of the use item key and button, while they share the view's or
+    /// respawn's job
+    item_key: PressTimer,
+    item_button: PressTimer,
+    /// Whether to steer with the mouse
+    mouse_steer: bool,
+    /// Pointer's last position across the window, and the window's width (pixels)
+    mouse_x: Option<f32>,
+    window_width: Option<f32>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self {
+            helper: InputHelper::new(),
+            input: InputAbstraction::default(),
+            gamepad_health: GamepadHealth::default(),
+            use_item: false,
+            toggle_hud: false,
+            drop_marker: false,
+            respawn: false,
+            cycle_ship: 0,
+            cycle_watched: 0,
+            bumpers: (false, false),
+            cycle_view: false,
+            north: false,
+            pressed: None,
+            held_buttons: HashSet::new(),
+            pushed_axes: HashSet::new(),
+            item_key: PressTimer::default(),
+            item_button: PressTimer::default(),
+            mouse_steer: false,
+            mouse_x: None,
+            window_width: None,
+        }
+    }
+
+    pub fn input(&self) -> InputAbstraction {
+        self.input
+    }
+
+    pub fn use_item(&self) -> bool {
+        self.use_item
+    }
+
+    pub fn toggle_hud(&self) -> bool {
+        self.toggle_hud
+    }
+
+    pub fn drop_marker(&self) -> bool {
+        self.drop_marker
+    }
+
+    pub fn respawn(&self) -> bool {
+        self.respawn
+    }
+
+    pub fn cycle_ship(&self) -> i32 {
+        self.cycle_ship
+    }
+
+    pub fn cycle_watched(&self) -> i32 {
+        self.cycle_watched
+    }
+
+    pub fn cycle_view(&self) -> bool {
+        self.cycle_view
+    }
+
+    pub fn pressed(&self) -> Option<BindableInput> {
+        self.pressed
+    }
+
+    /// Steer with the mouse from the next update on, e.g. under a preset
+    pub fn set_mouse_steer(&mut self, mouse_steer: bool) {
+        self.mouse_steer = mouse_steer;
+    }
+
+    /// Read this frame's controls for `scheme`, with the given `bindings`. `gui` must be the
+    /// HUD's, since it may show a warning there.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        time: f32,
+        show_hud: bool,
+        bindings: &KeyBindings,
+        scheme: ControlScheme,
+    ) {
+        self.input = InputAbstraction {
+            scheme,
+            ..Default::default()
+        };
+        let strafe = scheme == ControlScheme::Strafe;
+        self.use_item = false;
+        self.cycle_ship = 0;
+        self.cycle_view = false;
+        self.respawn = false;
+        self.pressed = None;
+
+        let gamepad_state = io.inbox_first::<GamepadState>();
+
+        // A device appearing or disappearing invalidates any held keys
+        if let Some(GamepadState(gamepads)) = &gamepad_state {
+            if gamepads.len() != self.gamepad_health.n_gamepads {
+                self.gamepad_health.n_gamepads = gamepads.len();
+                self.helper = InputHelper::new();
+            }
+        }
+
+        // Only the first gamepad drives; borrowed rather than taking the list apart
+        let gamepad = gamepad_state
+            .as_ref()
+            .and_then(|GamepadState(gamepads)| gamepads.first());
+
+        if let Some(gamepad) = gamepad {
+            // Rebound to anything this gamepad has, which another one might not
+            let axis = |axis: Axis| gamepad.axes.get(&axis).copied().unwrap_or(0.);
+            let button = |button: Button| gamepad.buttons.get(&button).copied().unwrap_or(false);
+
+            if strafe {
+                // Thrusters on the steering stick, aim on the other; throttle is left to the
+                // triggers
+                self.input.lateral = axis(bindings.pad_steer);
+                self.input.vertical = axis(bindings.pad_pitch);
+                self.input.yaw = axis(bindings.pad_yaw);
+                self.input.pitch = axis(bindings.pad_throttle_axis);
+            } else {
+                self.input.roll = axis(bindings.pad_steer);
+            }
+            if bindings.pad_sticks && !strafe {
+                self.input.yaw = axis(bindings.pad_yaw);
+                self.input.pitch = axis(bindings.pad_pitch);
+                self.input.throttle = axis(bindings.pad_throttle_axis);
+            }
+            if bindings.pad_throttle.map_or(false, button) {
+                self.input.throttle = 1.;
+            }
+            if button(bindings.pad_brake) {
+                self.input.throttle = -1.;
+            }
+
+            // A long press of the item button does the job of whichever action is unbound
+            let item_held = button(bindings.pad_use_item);
+            if bindings.pad_cycle_view.is_none() || bindings.pad_respawn.is_none() {
+                let (tapped, long) = self.item_button.update(item_held, time);
+                self.use_item = tapped;
+                self.cycle_view = long && bindings.pad_cycle_view.is_none();
+                self.respawn = long && bindings.pad_respawn.is_none();
+            } else {
+                self.use_item = item_held;
+            }
+            if let Some(view) = bindings.pad_cycle_view {
+                let north = button(view);
+                self.cycle_view = north && !self.north;
+                self.north = north;
+            }
+            if let Some(respawn) = bindings.pad_respawn {
+                self.respawn |= button(respawn) && !self.held_buttons.contains(&respawn);
+            }
+
+            let bumpers = bindings
+                .pad_cycle_ship
+                .map_or((false, false), |(back, forward)| {
+                    (button(back), button(forward))
+                });
+            if bumpers.0 && !self.bumpers.0 {
+                self.cycle_ship -= 1;
+            }
+            if bumpers.1 && !self.bumpers.1 {
+                self.cycle_ship += 1;
+            }
+            self.bumpers = bumpers;
+
+            // Only what's newly pressed or pushed, so a stick left leaning isn't picked forever
+            let held_buttons: HashSet<Button> = gamepad
+                .buttons
+                .iter()
+                .filter(|(_, &held)| held)
+                .map(|(&button, _)| button)
+                .collect();
+            let pushed_axes: HashSet<Axis> = gamepad
+                .axes
+                .iter()
+                .filter(|(_, value)| value.abs() > AXIS_CAPTURE)
+                .map(|(&axis, _)| axis)
+                .collect();
+            self.pressed = held_buttons
+                .difference(&self.held_buttons)
+                .next()
+                .map(|&button| BindableInput::Button(button))
+                .or_else(|| {
+                    let axis = pushed_axes.difference(&self.pushed_axes).next();
+                    axis.map(|&axis| BindableInput::Axis(axis))
+                });
+            self.held_buttons = held_buttons;
+            self.pushed_axes = pushed_axes;
+
+            self.gamepad_health.seen(time, self.input.throttle);
+        } else if let Some(throttle) = self.gamepad_health.held_throttle(time) {
+            // Don't let a single dropped message kill momentum
+            self.input.throttle = throttle;
+        }
+
+        if self.gamepad_health.check_stale(io, gui, time, show_hud) {
+            self.helper = InputHelper::new();
+        }
+
+        self.helper.handle_input_events(io);
+        for event in io.inbox::<InputEvent>() {
+            match event {
+                InputEvent::Mouse(MouseEvent::Moved(x, _)) => self.mouse_x = Some(x),
+                InputEvent::Window(WindowEvent::Resized { width, .. }) => {
+                    self.window_width = Some(width as f32)
+                }
+                _ => (),
+            }
+        }
+
+        self.toggle_hud = self.helper.key_pressed(KeyCode::F1);
+        self.drop_marker = self.helper.key_pressed(KeyCode::M);
+        let pressed_key = BINDABLE_KEYS
+            .iter()
+            .copied()
+            .find(|&key| self.helper.key_pressed(key));
+        self.pressed = pressed_key.map(BindableInput::Key).or(self.pressed);
+        self.cycle_watched = self.helper.key_pressed(KeyCode::Right) as i32
+            - self.helper.key_pressed(KeyCode::Left) as i32;
+
+        if bindings.cycle_view.is_none() || bindings.respawn.is_none() {
+            let item_held = self.helper.key_held(bindings.use_item);
+            let (tapped, long) = self.item_key.update(item_held, time);
+            self.use_item |= tapped;
+            self.cycle_view |= long && bindings.cycle_view.is_none();
+            self.respawn |= long && bindings.respawn.is_none();
+        } else {
+            self.use_item |= self.helper.key_pressed(bindings.use_item);
+        }
+        if let Some(key) = bindings.cycle_view {
+            self.cycle_view |= self.helper.key_pressed(key);
+        }
+        if let Some(key) = bindings.respawn {
+            self.respawn |= self.helper.key_pressed(key);
+        }
+
+        if bindings
+            .throttle
+            .map_or(false, |key| self.helper.key_held(key))
+        {
+            self.input.throttle = 1.0;
+        }
+
+        if self.helper.key_held(bindings.brake) {
+            self.input.throttle = -1.0;
+        }
+
+        // Once we know where the pointer is across the window; the keys win while held
+        if let (true, Some(x), Some(width)) = (self.mouse_steer, self.mouse_x, self.window_width) {
+            let steer = mouse_steer(x, width);
+            if steer != 0. {
+                *self.input.steer_mut() = steer;
+            }
+        }
+
+        if self.helper.key_held(bindings.roll_left) {
+            *self.input.steer_mut() = -1.0;
+        }
+
+        if self.helper.key_held(bindings.roll_right) {
+            *self.input.steer_mut() = 1.0;
+        }
+
+        if strafe {
+            let axis = |helper: &InputHelper, neg: KeyCode, pos: KeyCode| {
+                helper.key_held(pos) as i32 as f32 - helper.key_held(neg) as i32 as f32
+            };
+            let yaw = axis(&self.helper, bindings.yaw_left, bindings.yaw_right);
+            if yaw != 0. {
+                self.input.yaw = yaw;
+            }
+            let pitch = axis(&self.helper, bindings.pitch_down, bindings.pitch_up);
+            if pitch != 0. {
+                self.input.pitch = pitch;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_round_trip_through_chat_text() {
+        for bindings in [KeyBindings::STANDARD, ONE_HANDED.bindings] {
+            let text = bindings.to_chat_text();
+            assert!(text.chars().all(|c| c.is_ascii_hexdigit()));
+            assert_eq!(KeyBindings::from_chat_text(&text), Some(bindings));
+        }
+    }
+
+    #[test]
+    fn long_presses_count_once_and_swallow_the_tap() {
+        let mut timer = PressTimer::default();
+        assert_eq!(timer.update(true, 0.), (false, false));
+        assert_eq!(timer.update(true, LONG_PRESS_TIME), (false, true));
+        assert_eq!(timer.update(true, 2.), (false, false));
+        assert_eq!(timer.update(false, 2.1), (false, false));
+
+        assert_eq!(timer.update(true, 3.), (false, false));
+        assert_eq!(timer.update(false, 3.1), (true, false));
+    }
+
+    #[test]
+    fn garbled_chat_text_is_rejected() {
+        let text = KeyBindings::STANDARD.to_chat_text();
+        assert_eq!(KeyBindings::from_chat_text(&text[..text.len() - 1]), None);
+        assert_eq!(KeyBindings::from_chat_text("not hex"), None);
+        assert_eq!(KeyBindings::from_chat_text(""), None);
+    }
+
+    #[test]
+    fn the_mouse_steers_from_the_middle_of_the_window() {
+        const WIDTH: f32 = 1000.;
+        // The span runs from 250 to 750, and the dead zone from 475 to 525
+        assert_eq!(mouse_steer(WIDTH / 2., WIDTH), 0.);
+        assert_eq!(mouse_steer(510., WIDTH), 0.);
+        assert_eq!(mouse_steer(490., WIDTH), 0.);
+        assert_eq!(mouse_steer(250., WIDTH), -1.);
+        assert_eq!(mouse_steer(0., WIDTH), -1.);
+        assert_eq!(mouse_steer(WIDTH, WIDTH), 1.);
+        assert!((mouse_steer(750. - 112.5, WIDTH) - 0.5).abs() < 1e-4);
+        assert!((mouse_steer(250. + 112.5, WIDTH) + 0.5).abs() < 1e-4);
+    }
+}
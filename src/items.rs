@@ -0,0 +1,175 @@
+use cimvr_common::{
+    glam::Vec3,
+    render::{MeshHandle, Primitive, Render, UploadMesh},
+    ui::UiStateHelper,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{
+    countdown::color_extra,
+    curve::Curve,
+    entity_pool::{EntityPool, Owner},
+    label_panel::LabelPanel,
+    pickups::{pickup_layout, PickupSpawn},
+    race_rng::RaceRng,
+    shapes::diamond_mesh,
+    PickupClaim, PickupState,
+};
+
+/// Race time a pickup takes to come back once taken (seconds)
+pub const PICKUP_RESPAWN_TIME: f32 = 10.;
+/// How long a boost item lasts once used (seconds)
+pub const BOOST_TIME: f32 = 1.5;
+/// Ships within this distance of a pickup collect it (meters)
+const PICKUP_RADIUS: f32 = 3.;
+
+/// Color of taken pickups, relative to available ones
+const DIMMED: [f32; 3] = [0.2; 3];
+
+/// Client side of the pickups: collecting them, holding and using their boost, and showing
+/// which are taken. Collection is optimistic; the server has the final say on who got a pickup.
+pub struct Items {
+    pickups: Vec<Pickup>,
+    /// Pickup whose boost is held but not yet used
+    held: Option<u32>,
+    /// Time left boosting
+    boost_left: f32,
+    slot: LabelPanel,
+}
+
+struct Pickup {
+    spawn: PickupSpawn,
+    entity: EntityId,
+    /// Race time at which the pickup is available again
+    taken_until: f32,
+    /// Whether the pickup is currently drawn as taken
+    dimmed: bool,
+}
+
+impl Items {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("Pickup"));
+
+    pub fn new(io: &mut EngineIo) -> Self {
+        io.send(&UploadMesh {
+            mesh: diamond_mesh(1., [1., 0.5, 0.]),
+            id: Self::RDR_ID,
+        });
+
+        Self {
+            pickups: vec![],
+            held: None,
+            boost_left: 0.,
+            slot: LabelPanel::new("Item"),
+        }
+    }
+
+    /// Lay out this race's pickups, and forget anything held from the last one
+    pub fn start(&mut self, io: &mut EngineIo, pool: &mut EntityPool, path: &Curve, rng: &RaceRng) {
+        self.clear(io, pool);
+        self.pickups = pickup_layout(path, rng)
+            .into_iter()
+            .map(|spawn| {
+                let render = Render::new(Self::RDR_ID).primitive(Primitive::Lines);
+                Pickup {
+                    spawn,
+                    entity: pool.spawn(io, Owner::Pickups, render, spawn.transform(path)),
+                    taken_until: f32::MIN,
+                    dimmed: false,
+                }
+            })
+            .collect();
+    }
+
+    /// Remove all pickups and held items
+    pub fn clear(&mut self, io: &mut EngineIo, pool: &mut EntityPool) {
+        for pickup in self.pickups.drain(..) {
+            pool.release(io, pickup.entity);
+        }
+        self.held = None;
+        self.boost_left = 0.;
+    }
+
+    /// Collect a pickup the ship at `pos` is touching, if the slot is free.
+    /// Returns the claim to send to the server.
+    pub fn collect(&mut self, path: &Curve, pos: Vec3, race_time: f32) -> Option<PickupClaim> {
+        if self.held.is_some() {
+            return None;
+        }
+
+        let pickup = self.pickups.iter_mut().find(|p| {
+            race_time >= p.taken_until
+                && (p.spawn.transform(path).pos - pos).length() < PICKUP_RADIUS
+        })?;
+
+        pickup.taken_until = race_time + PICKUP_RESPAWN_TIME;
+        self.held = Some(pickup.spawn.id);
+
+        Some(PickupClaim {
+            pickup_id: pickup.spawn.id,
+            race_time,
+        })
+    }
+
+    /// The server decided someone took a pickup
+    pub fn apply_state(&mut self, state: PickupState) {
+        if let Some(pickup) = self
+            .pickups
+            .iter_mut()
+            .find(|p| p.spawn.id == state.pickup_id)
+        {
+            pickup.taken_until = state.taken_until;
+        }
+    }
+
+    /// Our claim lost to someone else's, so give back the item if it's still held.
+    /// A boost already used stays used.
+    pub fn reject(&mut self, pickup_id: u32) {
+        if self.held == Some(pickup_id) {
+            self.held = None;
+        }
+    }
+
+    /// Use the held item, if any
+    pub fn use_item(&mut self) {
+        if self.held.take().is_some() {
+            self.boost_left = BOOST_TIME;
+        }
+    }
+
+    /// Boost without needing an item, e.g. after finishing
+    pub fn free_boost(&mut self) {
+        self.boost_left = BOOST_TIME;
+    }
+
+    /// Whether the ship is boosting, counting down the boost by `dt`
+    pub fn boosting(&mut self, dt: f32) -> bool {
+        let boosting = self.boost_left > 0.;
+        self.boost_left = (self.boost_left - dt).max(0.);
+        boosting
+    }
+
+    /// Dim taken pickups, and show the item slot
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        race_time: f32,
+        show_slot: bool,
+    ) {
+        for pickup in &mut self.pickups {
+            let dimmed = race_time < pickup.taken_until;
+            if dimmed != pickup.dimmed {
+                let color = if dimmed { DIMMED } else { [1.; 3] };
+                io.add_component(pickup.entity, color_extra(color));
+                pickup.dimmed = dimmed;
+            }
+        }
+
+        let text = match (self.held, self.boost_left > 0.) {
+            (_, true) => "Boosting!".to_string(),
+            (Some(_), false) => "Boost (Space)".to_string(),
+            (None, false) => "Empty".to_string(),
+        };
+        self.slot.update(io, gui, show_slot, vec![text]);
+    }
+}
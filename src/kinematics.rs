@@ -42,13 +42,43 @@ impl KinematicPhysics {
     }
 }
 
+/// Length of one fixed simulation step (seconds)
+pub const FIXED_DT: f32 = 1. / 120.;
+
+/// Most fixed steps taken in a single frame, so that a long hitch doesn't stall everything
+const MAX_STEPS_PER_FRAME: usize = 12;
+
+/// Accumulates frame time into a whole number of fixed steps
+#[derive(Default, Clone, Copy, Debug)]
+pub struct FixedStep {
+    accumulator: f32,
+}
+
+impl FixedStep {
+    /// Add a frame's delta, returning how many fixed steps should be run
+    pub fn steps(&mut self, delta: f32) -> usize {
+        self.accumulator += delta;
+        let n = (self.accumulator / FIXED_DT).floor() as usize;
+        self.accumulator -= n as f32 * FIXED_DT;
+        n.min(MAX_STEPS_PER_FRAME)
+    }
+
+    /// How far the time left over is into the next step, from 0 to 1
+    pub fn fraction(&self) -> f32 {
+        (self.accumulator / FIXED_DT).clamp(0., 1.)
+    }
+}
+
+/// Move a single object forward in time
+pub fn integrate(tf: &mut Transform, kine: &KinematicPhysics, dt: f32) {
+    tf.pos += kine.vel * dt;
+    tf.orient = Quat::from_scaled_axis(kine.ang_vel * dt) * tf.orient;
+}
+
 pub fn simulate(query: &mut QueryResult, dt: f32) {
     for key in query.iter("Kinematics") {
         let kine = query.read::<KinematicPhysics>(key);
-        query.modify::<Transform>(key, |t| {
-            t.pos += kine.vel * dt;
-            t.orient = Quat::from_scaled_axis(kine.ang_vel * dt) * t.orient;
-        })
+        query.modify::<Transform>(key, |t| integrate(t, &kine, dt))
     }
 }
 
@@ -61,3 +91,86 @@ pub fn gravity(query: &mut QueryResult, dt: f32, g: Vec3) {
 impl Component for KinematicPhysics {
     const ID: &'static str = pkg_namespace!("KinematicPhysics");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        controls::{ShipController, ShipState, TuningParams},
+        harness::{grid_start, long_straight},
+        race_rng::RaceRng,
+        InputAbstraction, ShipCharacteristics,
+    };
+
+    /// Time simulated, half a step over a second so that no chunking ends right on a step
+    const SECONDS: f32 = 1. + FIXED_DT / 2.;
+
+    /// Frame deltas adding up to `SECONDS`, each from `next`
+    fn chunks(mut next: impl FnMut() -> f32) -> Vec<f32> {
+        let (mut deltas, mut total) = (vec![], 0.);
+        while total < SECONDS {
+            let delta = next().min(SECONDS - total);
+            deltas.push(delta);
+            total += delta;
+        }
+        deltas
+    }
+
+    /// A ship at rest on the straight under full thrust for each of `deltas`, and the number
+    /// of steps it took
+    fn thrust(deltas: &[f32]) -> (ShipState, usize) {
+        let curve = long_straight();
+        let controller = ShipController::new(
+            ShipCharacteristics::standard(),
+            TuningParams::default(),
+            &curve,
+        );
+        let input = InputAbstraction {
+            throttle: 1.,
+            ..Default::default()
+        };
+        let mut state = grid_start(&curve);
+        state.tf = curve.ctrlps[10];
+
+        let (mut fixed, mut steps) = (FixedStep::default(), 0);
+        for &delta in deltas {
+            for _ in 0..fixed.steps(delta) {
+                (state, _) = controller.fixed_step(input, state);
+                steps += 1;
+            }
+        }
+        (state, steps)
+    }
+
+    #[test]
+    fn constant_thrust_goes_as_far_at_any_frame_rate() {
+        let mut rng = RaceRng::new(0).stream("frames");
+        let chunkings = [
+            chunks(|| 1. / 30.),
+            chunks(|| 1. / 60.),
+            chunks(|| 1. / 144.),
+            chunks(|| FIXED_DT),
+            // Hitches and all
+            chunks(|| rng.range(0., MAX_STEPS_PER_FRAME as f32 * FIXED_DT)),
+        ];
+
+        let (expected, steps) = thrust(&chunkings[0]);
+        assert_eq!(steps, 120);
+        let moved = expected.tf.pos.x - long_straight().ctrlps[10].pos.x;
+        assert!(moved > 1., "only moved {} m", moved);
+        for deltas in &chunkings[1..] {
+            assert_eq!(thrust(deltas), (expected, steps), "{} frames", deltas.len());
+        }
+    }
+
+    #[test]
+    fn long_frames_are_capped_and_leftovers_carried_over() {
+        let mut fixed = FixedStep::default();
+        assert_eq!(fixed.steps(1.), MAX_STEPS_PER_FRAME);
+        // The steps over the cap are dropped, but part of a step still counts
+        assert_eq!(fixed.steps(FIXED_DT * 0.25), 0);
+        assert!((fixed.fraction() - 0.25).abs() < 1e-3);
+        assert_eq!(fixed.steps(FIXED_DT * 0.75 + 1e-6), 1);
+        assert!(fixed.fraction() < 1e-3);
+    }
+}
@@ -0,0 +1,60 @@
+use cimvr_common::ui::{Schema, State, UiHandle, UiStateHelper};
+use cimvr_engine_interface::prelude::*;
+
+/// Panel showing a list of lines of text, such as live readouts or a scoreboard
+pub struct LabelPanel {
+    title: &'static str,
+    /// Panel and the lines it is showing
+    element: Option<(UiHandle, Vec<String>)>,
+}
+
+impl LabelPanel {
+    pub fn new(title: &'static str) -> Self {
+        Self {
+            title,
+            element: None,
+        }
+    }
+
+    /// Show the given lines, or remove the panel if not `visible`
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        visible: bool,
+        lines: Vec<String>,
+    ) {
+        // Rebuild if the layout changed
+        match &self.element {
+            Some((handle, shown)) if !visible || shown.len() != lines.len() => {
+                gui.delete(io, *handle);
+                self.element = None;
+            }
+            _ => (),
+        }
+
+        if !visible {
+            return;
+        }
+
+        match &mut self.element {
+            None => {
+                let schema = vec![Schema::Label; lines.len()];
+                let state = lines
+                    .iter()
+                    .map(|text| State::Label { text: text.clone() })
+                    .collect();
+                self.element = Some((gui.add(io, self.title, schema, state), lines));
+            }
+            Some((handle, shown)) if *shown != lines => {
+                gui.modify(io, *handle, |ui_state| {
+                    for (state, text) in ui_state.iter_mut().zip(&lines) {
+                        *state = State::Label { text: text.clone() };
+                    }
+                });
+                *shown = lines;
+            }
+            Some(_) => (),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::{HashMap, HashSet};
+
+use cimvr_common::{
+    glam::Vec3,
+    render::{Mesh, MeshHandle, Primitive, Render, UploadMesh, Vertex},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{
+    countdown::color_extra,
+    entity_pool::{EntityPool, Owner},
+    palette::Palette,
+    RaceProgress, ServerShipComponent,
+};
+
+/// Most ticks shown above a ship
+const MAX_TICKS: usize = 10;
+/// Indicators further than this from the camera are hidden (meters)
+const MAX_DISTANCE: f32 = 150.;
+/// Height of the indicator above the ship (meters)
+const HEIGHT: f32 = 2.5;
+/// Spacing between ticks (meters)
+const TICK_SPACING: f32 = 0.4;
+
+/// Row of tick marks above each remote racer, one per lap, colored by whether they are
+/// ahead of or behind the local player
+pub struct LapIndicators {
+    /// Lap counts from the last `RaceProgress`
+    laps: HashMap<ClientId, usize>,
+    /// Tick entities for each racer currently shown
+    ticks: HashMap<ClientId, Vec<EntityId>>,
+}
+
+/// What the indicators need to know about the local player
+pub struct LocalView {
+    pub client_id: Option<ClientId>,
+    pub lap: usize,
+    pub camera_pos: Vec3,
+    pub visible: bool,
+    pub palette: Palette,
+}
+
+impl LapIndicators {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("LapTick"));
+
+    pub fn new(io: &mut EngineIo) -> Self {
+        io.send(&UploadMesh {
+            mesh: tick_mesh(),
+            id: Self::RDR_ID,
+        });
+
+        Self {
+            laps: HashMap::new(),
+            ticks: HashMap::new(),
+        }
+    }
+
+    pub fn set_progress(&mut self, RaceProgress { laps, .. }: RaceProgress) {
+        self.laps = laps.into_iter().collect();
+    }
+
+    /// Forget all progress, e.g. when a new race starts
+    pub fn clear(&mut self, io: &mut EngineIo, pool: &mut EntityPool) {
+        self.laps.clear();
+        for (_, entities) in self.ticks.drain() {
+            entities.into_iter().for_each(|e| pool.release(io, e));
+        }
+    }
+
+    /// Create, move and remove ticks to follow the ships in the "ServerShips" query
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        pool: &mut EntityPool,
+        query: &mut QueryResult,
+        view: &LocalView,
+    ) {
+        let mut shown = HashSet::new();
+
+        for entity in query.iter("ServerShips") {
+            let shipc = query.read::<ServerShipComponent>(entity);
+            let tf = query.read::<Transform>(entity);
+
+            let is_remote_racer = shipc.is_racing && Some(shipc.client_id) != view.client_id;
+            let in_range = (tf.pos - view.camera_pos).length() < MAX_DISTANCE;
+            if !(is_remote_racer && in_range && view.visible) {
+                continue;
+            }
+
+            let lap = self.laps.get(&shipc.client_id).copied().unwrap_or(0);
+            let color = match lap.cmp(&view.lap) {
+                std::cmp::Ordering::Less => view.palette.behind(),
+                std::cmp::Ordering::Greater => view.palette.ahead(),
+                std::cmp::Ordering::Equal => view.palette.neutral(),
+            };
+
+            // Match the number of tick entities to the lap count
+            let n_ticks = lap.min(MAX_TICKS);
+            let ticks = self.ticks.entry(shipc.client_id).or_default();
+            while ticks.len() > n_ticks {
+                pool.release(io, ticks.pop().unwrap());
+            }
+            while ticks.len() < n_ticks {
+                let render = Render::new(Self::RDR_ID).primitive(Primitive::Lines);
+                ticks.push(pool.spawn(io, Owner::LapIndicators, render, tf));
+            }
+
+            for (idx, &tick) in ticks.iter().enumerate() {
+                let across = (idx as f32 - (n_ticks as f32 - 1.) / 2.) * TICK_SPACING;
+                let offset = Transform::new().with_position(Vec3::new(0., HEIGHT, across));
+                pool.add_component(io, tick, tf * offset);
+                pool.add_component(io, tick, color_extra(color));
+            }
+
+            shown.insert(shipc.client_id);
+        }
+
+        // Remove indicators of racers who finished, disconnected, or went out of view
+        self.ticks.retain(|client_id, entities| {
+            let keep = shown.contains(client_id);
+            if !keep {
+                entities.drain(..).for_each(|e| pool.release(io, e));
+            }
+            keep
+        });
+    }
+}
+
+/// A single vertical tick mark
+fn tick_mesh() -> Mesh {
+    let mut m = Mesh::new();
+    let bottom = m.push_vertex(Vertex::new([0., 0., 0.], [1.; 3]));
+    let top = m.push_vertex(Vertex::new([0., 0.8, 0.], [1.; 3]));
+    m.indices.extend([bottom, top]);
+    m
+}
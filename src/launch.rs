@@ -0,0 +1,152 @@
+//! Getting away from the grid: thrust from the exact moment of GO, and penalties for jumping it
+
+/// Throttle held within this long before GO is a jump start (seconds)
+const JUMP_WINDOW: f32 = 0.2;
+/// Throttle above which a ship counts as having jumped the start
+const JUMP_THRESHOLD: f32 = 0.25;
+/// How long a jump start locks out the throttle, from GO (seconds)
+const LOCKOUT_TIME: f32 = 1.;
+
+/// What the local ship may do this frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LaunchFrame {
+    /// Time to simulate the ship for. Less than a frame only on the frame the start falls in.
+    pub dt: f32,
+    /// Thrust is cut until the jump start penalty has been served
+    pub locked_out: bool,
+    /// The start was jumped, just now
+    pub jump_start: bool,
+}
+
+/// Watches the throttle over the end of the countdown
+#[derive(Default, Debug, Clone)]
+pub struct Launch {
+    jumped: bool,
+    /// Whether the frame containing GO has been simulated
+    launched: bool,
+}
+
+impl Launch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Jump the start some other way than by the throttle, e.g. by passing the pace line in a
+    /// rolling start. Returns whether this is the first time.
+    pub fn jump(&mut self) -> bool {
+        !std::mem::replace(&mut self.jumped, true)
+    }
+
+    /// Handle the frame which ends `since_go` after GO (negative before it), lasting `delta`
+    pub fn update(&mut self, since_go: f32, delta: f32, throttle: f32) -> LaunchFrame {
+        let mut frame = LaunchFrame {
+            dt: delta,
+            locked_out: false,
+            jump_start: false,
+        };
+
+        if since_go <= 0. {
+            if since_go > -JUMP_WINDOW && throttle > JUMP_THRESHOLD && !self.jumped {
+                self.jumped = true;
+                frame.jump_start = true;
+            }
+            return frame;
+        }
+
+        // Only the part of the first frame after GO counts, however the frames happened to fall
+        if !self.launched {
+            self.launched = true;
+            frame.dt = since_go.min(delta);
+        }
+        frame.locked_out = self.jumped && since_go < LOCKOUT_TIME;
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: f32 = 1. / 60.;
+    const FULL: f32 = 1.;
+
+    /// Frames of `FRAME` ending at `first` after GO and every frame after, up to `until`
+    fn frames(launch: &mut Launch, first: f32, until: f32, throttle: f32) -> Vec<LaunchFrame> {
+        let count = ((until - first) / FRAME).round() as usize;
+        (0..=count)
+            .map(|k| launch.update(first + k as f32 * FRAME, FRAME, throttle))
+            .collect()
+    }
+
+    #[test]
+    fn a_frame_ending_on_go_is_still_the_grid() {
+        let mut launch = Launch::new();
+        let on_go = launch.update(0., FRAME, FULL);
+        assert!(!on_go.jump_start && !on_go.locked_out);
+
+        // So the whole of the next frame is the launch
+        let next = launch.update(FRAME, FRAME, FULL);
+        assert_eq!(next.dt, FRAME);
+        assert!(!next.locked_out);
+    }
+
+    #[test]
+    fn a_frame_ending_just_after_go_only_counts_from_go() {
+        let mut launch = Launch::new();
+        let first = launch.update(0.004, FRAME, FULL);
+        assert!((first.dt - 0.004).abs() < 1e-6, "{}", first.dt);
+        assert!(!first.locked_out);
+
+        for frame in frames(&mut launch, 0.004 + FRAME, 2., FULL) {
+            assert_eq!(frame.dt, FRAME);
+        }
+    }
+
+    #[test]
+    fn a_long_frame_over_go_is_cut_to_the_part_after_it() {
+        let mut launch = Launch::new();
+        assert_eq!(launch.update(0.1, 0.5, FULL).dt, 0.1);
+    }
+
+    #[test]
+    fn throttle_just_before_go_jumps_the_start() {
+        let mut launch = Launch::new();
+        let early = launch.update(-0.001, FRAME, FULL);
+        assert!(early.jump_start);
+
+        // Only announced once, and locked out for a second from GO
+        let frames = frames(&mut launch, -0.001 + FRAME, 1.5, FULL);
+        assert!(frames.iter().all(|f| !f.jump_start));
+        for (k, frame) in frames.iter().enumerate() {
+            let since_go = -0.001 + (k + 1) as f32 * FRAME;
+            let expected = since_go > 0. && since_go < LOCKOUT_TIME;
+            assert_eq!(frame.locked_out, expected, "{} s after GO", since_go);
+        }
+    }
+
+    #[test]
+    fn throttle_before_the_window_or_below_the_threshold_is_fine() {
+        let mut launch = Launch::new();
+        assert!(!launch.update(-JUMP_WINDOW - 0.01, FRAME, FULL).jump_start);
+        assert!(!launch.update(-0.05, FRAME, JUMP_THRESHOLD * 0.9).jump_start);
+        assert!(frames(&mut launch, 0.01, 1.5, FULL)
+            .iter()
+            .all(|f| !f.locked_out));
+    }
+
+    #[test]
+    fn jumping_some_other_way_is_only_new_once() {
+        let mut launch = Launch::new();
+        assert!(launch.jump());
+        assert!(!launch.jump());
+        assert!(!launch.update(-0.01, FRAME, FULL).jump_start);
+        assert!(launch.update(0.5, FRAME, FULL).locked_out);
+
+        launch.reset();
+        assert!(launch.jump());
+    }
+}
@@ -1,37 +1,478 @@
-use cimvr_common::{render::MeshHandle, Transform};
+use cimvr_common::{glam::Vec3, render::MeshHandle, Transform};
 use cimvr_engine_interface::{make_app_state, pkg_namespace, prelude::*};
+use conditions::Conditions;
+use controls::ControlScheme;
+use envelope::{Envelope, NoPrevious, Versioned};
 use kinematics::KinematicPhysics;
+use player_colors::PlayerColor;
+use rating::Rating;
+use records::TrackRecords;
+use relay::RelayTeam;
+use rule_votes::Proposal;
 use serde::{Deserialize, Serialize};
+use settings::SavedSettings;
+use ship_class::ShipClass;
+use strings::Text;
+use track::TrackChunk;
+use wall_ride::WallRide;
 
 //mod client_tag;
+mod afk;
+mod assists;
+mod banners;
+mod barriers;
+mod billboards;
+mod bindings_panel;
+mod boost_pads;
+mod builtin_tracks;
+mod camera_rig;
+mod camera_walls;
+mod chat_panel;
 mod client;
+mod commands;
+mod conditions;
 mod controls;
 mod countdown;
 mod curve;
+#[cfg(any(test, feature = "dev-tracks"))]
+mod dev_tracks;
+#[cfg(feature = "tuning")]
+mod dummies;
+mod engine_sound;
+mod entity_pool;
+mod envelope;
+mod fade;
+mod fairness;
+mod gates;
+mod ghost;
+mod ghost_delta;
+#[cfg(test)]
+mod harness;
+mod hud;
+mod hud_state;
+mod input_state;
+mod items;
 mod kinematics;
+mod label_panel;
+mod lap_indicators;
+mod launch;
+mod markers;
+mod meshutil;
+mod minimap;
+mod nametags;
+mod net_stats;
 mod obj;
+mod off_track;
+mod palette;
+mod photo_finish;
+mod pickups;
+mod player_colors;
+mod position_graph;
+mod prediction;
+mod race_log;
+mod race_rng;
+mod race_rules;
+mod race_session;
+mod rating;
+mod records;
+mod relay;
+mod respawn;
+mod rolling_start;
+mod rule_votes;
+mod sanitize;
+mod scoreboard;
 mod server;
+mod settings;
 mod shapes;
+mod ship_class;
+mod ship_collision;
+mod ship_preview;
+mod ship_shadows;
+mod slipstream;
+mod smoothing;
+mod spectator;
+mod splits;
+mod strings;
+mod track;
+mod track_reveal;
+mod track_scene;
+mod track_summary;
+mod trackside;
+#[cfg(feature = "tuning")]
+mod tuning;
+mod upload_check;
+mod vote_panel;
+mod wall_ride;
+mod watchdog;
+mod watched_timing;
+mod wrong_way;
 use client::ClientState;
 use server::ServerState;
 
 pub const SHIP_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("Ship"));
 
-/// Clients own the ship positions; this message sends the positions of clients' ships
-/// to the server
+/// Version of the remote messages below. They're encoded field by field in order, so a client
+/// and server which disagree on any of them misread each other rather than failing cleanly.
+/// Bump this with any change to a remote message: adding, removing, reordering or retyping a
+/// field, or adding, removing or reordering an enum variant, including in the types they carry.
+/// Clients on another version are told so, and kept out of races.
+///
+/// The messages which grow most often (`ShipUpload`, `Finished` and `StartRace`) travel in
+/// versioned envelopes instead, which can still be read a version behind. Adding a field to
+/// one of those bumps its own `Versioned::VERSION` rather than this; see `envelope`.
+pub const PROTOCOL_VERSION: u32 = 17;
+
+/// Declares `$wire` as the remote message carrying `$message` in an `Envelope`
+macro_rules! sealed_message {
+    ($wire:ident, $message:ty) => {
+        #[doc = concat!("`", stringify!($message), "` in its envelope, as sent")]
+        #[derive(Message, Clone, Default, Serialize, Deserialize)]
+        #[locality("Remote")]
+        struct $wire(Envelope);
+
+        impl From<Envelope> for $wire {
+            fn from(envelope: Envelope) -> Self {
+                Self(envelope)
+            }
+        }
+
+        impl From<$wire> for Envelope {
+            fn from($wire(envelope): $wire) -> Self {
+                envelope
+            }
+        }
+    };
+}
+
+/// Exchanged when a client connects: the server sends its `PROTOCOL_VERSION`, and the client
+/// replies with its own. Unlike every other message, this one must never change.
 #[derive(Message, Copy, Clone, Default, Serialize, Deserialize)]
 #[locality("Remote")]
-struct ShipUpload(Transform, KinematicPhysics);
+struct Hello {
+    protocol: u32,
+}
+
+/// Clients own the ship positions; this message sends the positions of clients' ships
+/// to the server, with a sequence number counting up from each client
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+struct ShipUpload(Transform, KinematicPhysics, u32);
+
+sealed_message!(SealedShipUpload, ShipUpload);
 
-/// Sent to inform a given client is ready or not
+impl Versioned for ShipUpload {
+    const VERSION: u8 = 1;
+    type Previous = NoPrevious;
+    type Wire = SealedShipUpload;
+    fn upgrade(previous: NoPrevious) -> Self {
+        match previous {}
+    }
+}
+
+/// Sent to inform a given client is ready or not, and which ship they'll race with
 #[derive(Message, Copy, Clone, Default, Serialize, Deserialize)]
 #[locality("Remote")]
-struct ClientReady(bool);
+struct ClientReady(bool, ShipClass);
 
-/// A client finished the race! In the given time...
-#[derive(Message, Copy, Clone, Default, Serialize, Deserialize)]
+/// Sent by a client who wants to race on their own, now, rather than waiting for everyone to
+/// be ready. The server starts them a race of one if nobody is racing.
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[locality("Remote")]
+struct RequestTimeTrial;
+
+/// Server settings the host can change between races or during one, e.g. from an admin client.
+/// Only the host's are taken.
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[locality("Remote")]
+pub struct ServerConfig {
+    /// Time everyone else has to finish once the winner has (seconds)
+    pub reset_time: f32,
+    /// Fastest a racer's uploads may say they're going before they're dropped (meters per
+    /// second)
+    pub max_speed: f32,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            reset_time: 50.,
+            max_speed: 400.,
+        }
+    }
+}
+
+/// A client finished the race! In the given time, with the given assists and lap times...
+/// The server counts laps itself, and only takes the time if it's close to its own.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Finished(f32, Assists, Vec<LapTime>);
+
+sealed_message!(SealedFinished, Finished);
+
+impl Versioned for Finished {
+    const VERSION: u8 = 1;
+    type Previous = NoPrevious;
+    type Wire = SealedFinished;
+    fn upgrade(previous: NoPrevious) -> Self {
+        match previous {}
+    }
+}
+
+/// Time taken over one lap
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct LapTime {
+    pub time: f32,
+    /// Whether the lap counts, having not spent too long off the track
+    pub valid: bool,
+}
+
+/// Broadcast when the server accepts a racer's `Finished`
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[locality("Remote")]
+struct RacerFinished {
+    client_id: ClientId,
+    username: String,
+    /// Finishing place, counting from 1 in the order finishes reached the server
+    position: usize,
+    time: f32,
+}
+
+/// A client collected a pickup, the given time into the race
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct PickupClaim {
+    pickup_id: u32,
+    race_time: f32,
+}
+
+/// The server accepted a claim; the pickup is taken until the given race time
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct PickupState {
+    pickup_id: u32,
+    taken_until: f32,
+}
+
+/// Sent to a client whose `PickupClaim` lost to someone else's
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct PickupRejected {
+    pickup_id: u32,
+}
+
+/// Sent by the server to the admin client, asking for its copy of the track files
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[locality("Remote")]
+struct RequestTrack;
+
+/// Admin client uploading track files to the server
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[locality("Remote")]
+struct PushTrack(TrackChunk);
+
+/// Server distributing a new track to everyone. Clients drop back to the lobby once it's complete.
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[locality("Remote")]
+struct TrackData(TrackChunk);
+
+/// Server switching everyone to one of the built-in tracks, before a race starts and to new
+/// connections. Clients in the middle of a race switch once they're back to spectating.
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct TrackSelect {
+    track_id: u32,
+}
+
+/// A proposal open for voting, and its votes so far
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct OpenProposal {
+    id: u32,
+    proposal: Proposal,
+    yes: usize,
+    no: usize,
+}
+
+/// Rule changes open for voting, broadcast whenever they or their votes change
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone)]
+#[locality("Remote")]
+struct RuleVote(Vec<OpenProposal>);
+
+/// A client's vote on the open proposal `id`
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct CastVote {
+    id: u32,
+    yes: bool,
+}
+
+/// Something the server has to say, for each client to render in its own language. The same
+/// goes out as a `ChatDownload` in English, for anyone reading chat without us.
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[locality("Remote")]
+struct Announcement(Text);
+
+/// How the race went lap by lap, broadcast when it's over
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[locality("Remote")]
+struct RaceResults(Vec<RacerLaps>);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct RacerLaps {
+    client_id: ClientId,
+    name: String,
+    /// Race time at which each lap was completed, in order (seconds). Shorter than the race
+    /// for anyone who didn't finish.
+    completed: Vec<f32>,
+    /// Finishing time as the server took it, or `None` for a racer who didn't finish
+    finish_time: Option<f32>,
+}
+
+/// Usernames of everyone in a race, broadcast when it starts
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone)]
+#[locality("Remote")]
+struct RacerNames(Vec<(ClientId, String)>);
+
+/// Everything an audio plugin needs to know, and the only message meant for one. Each client
+/// sends these locally, for what its player can see and hear.
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[locality("Local")]
+pub enum SoundCue {
+    /// A ship's engine, a few times a second while it's near the camera
+    Engine(EngineState),
+    /// A countdown numeral appeared: 3, 2, 1, then 0 for GO
+    Countdown(u32),
+    /// The local ship hit the side of the track
+    Collision { pos: Vec3 },
+    /// The local ship began the given lap
+    Lap(usize),
+}
+
+/// How a ship's engine should sound
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EngineState {
+    /// `None` for the local ship
+    pub client: Option<ClientId>,
+    pub pos: Vec3,
+    /// Speed as a fraction of flat out, from 0 to 1
+    pub speed_frac: f32,
+    /// From 0 to 1. Guessed from how other players' ships speed up.
+    pub throttle: f32,
+    pub boosting: bool,
+}
+
+/// Version of `RaceEvents` and `RaceEvent`. Bumped whenever either changes in a way which
+/// would break a plugin reading them.
+pub const RACE_EVENT_VERSION: u32 = 1;
+
+/// What happened in a race, broadcast by the server for other plugins to follow along with,
+/// e.g. tournament overlays or chat bridges. Unlike the rest of the messages here, this is kept
+/// stable; other plugins should copy the definitions and check `version`.
+///
+/// For any one `race_id`, `Started` always arrives before anything else.
+///
+/// ```ignore
+/// fn tally(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+///     for RaceEvents { version, race_id, event } in io.inbox::<RaceEvents>() {
+///         if version != RACE_EVENT_VERSION {
+///             continue;
+///         }
+///         match event {
+///             RaceEvent::Started => self.laps.clear(),
+///             RaceEvent::LapCompleted { client, lap, .. } => {
+///                 self.laps.insert(client, lap);
+///             }
+///             _ => (),
+///         }
+///     }
+/// }
+/// ```
+#[derive(Message, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[locality("Remote")]
+pub struct RaceEvents {
+    pub version: u32,
+    /// Counts up from 1 for every race since the server started
+    pub race_id: u32,
+    pub event: RaceEvent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RaceEvent {
+    /// Ships are on the grid, and the countdown has begun
+    Started,
+    /// A racer finished their `lap`th lap (counting from 1), which took `time` seconds
+    LapCompleted {
+        client: ClientId,
+        lap: usize,
+        time: f32,
+    },
+    /// A racer finished the race in `position` (counting from 1), taking `time` seconds
+    Finished {
+        client: ClientId,
+        time: f32,
+        position: usize,
+    },
+    /// The race is over and everyone is back in the lobby
+    Reset,
+    /// A racer set the fastest valid lap on this track since the server started (seconds)
+    RecordBroken { client: ClientId, time: f32 },
+}
+
+/// Who is in the lobby, broadcast whenever that or anyone's status changes
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[locality("Remote")]
+struct LobbyStatus(Vec<LobbyEntry>);
+
+/// Part of the server's race log, sent to the host in answer to `/log`
+#[derive(Message, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[locality("Remote")]
+pub struct LogDump {
+    /// From 0 up to `parts - 1`
+    pub part: u32,
+    pub parts: u32,
+    pub lines: Vec<String>,
+}
+
+/// Keep these settings for the sending player, or forget any kept when `None`
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[locality("Remote")]
+struct SaveSettings(Option<SavedSettings>);
+
+/// Settings kept for a player under their name, sent when they connect
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[locality("Remote")]
+struct LoadSettings(SavedSettings);
+
+/// What the next race will be like, broadcast whenever it changes and to new clients
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[locality("Remote")]
+pub struct NextRace {
+    pub laps: usize,
+}
+
+/// Records on the current track, broadcast whenever they change and to new clients
+#[derive(Message, Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[locality("Remote")]
-struct Finished(f32);
+pub struct RecordsSync(pub TrackRecords);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LobbyEntry {
+    pub client_id: ClientId,
+    pub name: String,
+    pub ready: bool,
+    /// Hasn't done anything for `RaceSettings::afk_timeout`, so left out of races
+    pub afk: bool,
+    pub rating: Rating,
+    /// A newer connection is using the same name, and has the rating; this one isn't rated
+    pub duplicate: bool,
+    pub color: PlayerColor,
+}
+
+/// Player-side assists which were used at any point during a race
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Assists {
+    pub auto_throttle: bool,
+    pub one_handed: bool,
+    /// `ControlScheme::Strafe` if it was flown with at all
+    pub scheme: ControlScheme,
+}
 
 /// Denotes the single ship client-side
 #[derive(Component, serde::Serialize, serde::Deserialize, Default, Copy, Clone, PartialEq, Eq)]
@@ -43,9 +484,16 @@ struct ServerShipComponent {
     pub client_id: ClientId,
     pub is_racing: bool,
     pub is_ready: bool,
+    /// Disconnected mid-race, but may still come back; see `RaceSettings::disconnect_grace`
+    pub is_lagging: bool,
+    pub class: ShipClass,
+    /// Stays the same for as long as they're connected
+    pub color: PlayerColor,
+    /// Sequence number of the latest upload from this client, so others can judge its connection
+    pub upload_seq: u32,
 }
 
-#[derive(Clone, Default, Copy)]
+#[derive(Clone, Default, Copy, Debug)]
 pub struct ShipCharacteristics {
     /// Mass of the ship (Kg)
     pub mass: f32,
@@ -55,18 +503,257 @@ pub struct ShipCharacteristics {
     pub max_twirl: f32,
     /// Maximum thrust (Newtons)
     pub max_impulse: f32,
+    /// Thrust multiplier while boosting
+    pub boost_factor: f32,
+}
+
+impl ShipCharacteristics {
+    /// The ship everyone races with
+    pub fn standard() -> Self {
+        Self {
+            mass: 1000.,
+            moment: 1000. * 3_f32.powi(2),
+            max_twirl: 5.,
+            max_impulse: 30.,
+            boost_factor: controls::BOOST_FACTOR,
+        }
+    }
 }
 
 // Defines entry points for the engine to hook into.
 // Calls new() for the appropriate state.
 make_app_state!(ClientState, ServerState);
 
+/// Rules for a race, decided by the server and announced in `StartRace`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RaceSettings {
+    /// The server simulates ships from uploaded inputs, instead of trusting uploaded positions
+    pub authoritative: bool,
+    /// Seed for the race's shared random numbers; see `race_rng`
+    pub seed: u64,
+    /// Ships behind the leader are given extra thrust
+    pub catch_up: bool,
+    /// Extra thrust given to a ship a full lap or more behind the leader (fraction)
+    pub catch_up_strength: f32,
+    /// How long a racer who drops out may reconnect and carry on (seconds)
+    pub disconnect_grace: f32,
+    /// How long finished racers may keep driving around before spectating (seconds)
+    pub cooldown_time: f32,
+    /// Laps to complete after leaving the grid
+    pub laps: usize,
+    /// The server resets a race which goes this many times its expected length without
+    /// progressing
+    pub watchdog_factor: f32,
+    /// Laps which spend too long off the racing surface don't count towards best laps
+    pub invalidate_laps: bool,
+    /// Time a lap may spend off the racing surface before it's invalid (seconds)
+    pub off_track_limit: f32,
+    /// Clients who do nothing for this long are marked away, and sit out races until they
+    /// come back (minutes)
+    pub afk_timeout: f32,
+    /// Weather the race is held in
+    pub conditions: Conditions,
+    /// Give the front of the grid to the highest rated racers
+    pub seed_by_rating: bool,
+    /// Racers follow a pace line round to the finish line in formation, instead of starting
+    /// from a standstill; see `rolling_start`
+    pub rolling_start: bool,
+    /// Racers run in teams, one after another; `laps` is then each runner's share. See
+    /// `relay`.
+    pub relay: bool,
+}
+
+impl Default for RaceSettings {
+    fn default() -> Self {
+        Self {
+            authoritative: false,
+            seed: 0,
+            catch_up: false,
+            catch_up_strength: 0.08,
+            disconnect_grace: 10.,
+            cooldown_time: 20.,
+            laps: 3,
+            watchdog_factor: 3.,
+            invalidate_laps: false,
+            off_track_limit: 2.,
+            afk_timeout: 5.,
+            conditions: Conditions::Clear,
+            seed_by_rating: false,
+            rolling_start: false,
+            relay: false,
+        }
+    }
+}
+
 /// Message telling a client which ID it has
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct StartRace {
+    /// Incremented by the server for every race
+    race_id: u32,
+    client_id: ClientId,
+    position: Transform,
+    settings: RaceSettings,
+    /// Built-in track the race is on, or `None` for one the admin uploaded
+    track_id: Option<u32>,
+}
+
+sealed_message!(SealedStartRace, StartRace);
+
+impl Versioned for StartRace {
+    const VERSION: u8 = 1;
+    type Previous = NoPrevious;
+    type Wire = SealedStartRace;
+    fn upgrade(previous: NoPrevious) -> Self {
+        match previous {}
+    }
+}
+
+/// Sent when the race is over for everyone, and finished racers should stop driving
 #[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
 #[locality("Remote")]
-struct StartRace {
+struct RaceOver;
+
+/// Sent to racers still in a race the server has given up on, who go back to spectating
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct RaceAbort {
+    race_id: u32,
+}
+
+/// Sent to a racer who reconnects within the grace period, to put them back in their race
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct ResumeRace {
+    race_id: u32,
     client_id: ClientId,
     position: Transform,
+    /// Laps counted so far
+    lap: usize,
+    /// Checkpoints passed on the current lap
+    checkpoints: usize,
+    /// Time since the race started, including the countdown (seconds)
+    elapsed: f32,
+    settings: RaceSettings,
+}
+
+/// Sent to a relay runner whose teammate just handed off to them, to take over the team's run
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct YourLeg {
+    race_id: u32,
+    client_id: ClientId,
+    /// Where the last runner crossed the line, or disconnected
+    start_transform: Transform,
+    /// Laps counted for the team so far
+    lap: usize,
+    /// Checkpoints the team has passed on the current lap
+    checkpoints: usize,
+    /// Time since the race started, including the countdown (seconds)
+    elapsed_so_far: f32,
+    /// The race's settings, with `laps` counting to the end of this leg
+    settings: RaceSettings,
+}
+
+/// Every team in a relay race, sent to each runner whenever a leg ends or a team changes
+#[derive(Message, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[locality("Remote")]
+struct RelayStatus {
+    teams: Vec<RelayTeam>,
+    /// Which of `teams` the recipient runs for
+    team: usize,
+    /// The recipient's place in their team's running order
+    runner: usize,
+}
+
+/// Server's count of laps for each racer, broadcast a few times per second during a race
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone)]
+#[locality("Remote")]
+struct RaceProgress {
+    laps: Vec<(ClientId, usize)>,
+    /// Checkpoints each racer has passed on their current lap
+    checkpoints: Vec<(ClientId, usize)>,
+    /// Race time at which each racer began their current lap, once they've left the grid
+    lap_starts: Vec<(ClientId, f32)>,
+    /// Time since the race started as of sending, including the countdown (seconds)
+    race_time: f32,
+}
+
+/// Each racer's place, from 1, broadcast a few times per second during a race. Those who have
+/// finished come first, in the order they did.
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone)]
+#[locality("Remote")]
+struct RacePositions(Vec<(ClientId, u8)>);
+
+/// Multiplier on a racer's thrust from their place in the race, sent by the server whenever it
+/// changes while catch-up is on
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct HandicapFactor(f32);
+
+/// Emitted on the client when the race in progress is abandoned for a new one,
+/// so that per-race presentation state can be reset
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[locality("Local")]
+struct RaceAborted {
+    race_id: u32,
+}
+
+/// Emitted on the client whenever the local ship is teleported.
+/// If `to` is given, the teleport is yet to happen; otherwise it already has.
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[locality("Local")]
+struct TeleportEvent {
+    to: Option<Transform>,
+}
+
+/// Emitted on the client when a finish should be shown from the finish line camera: every
+/// finish for spectators, or our own for racers
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[locality("Local")]
+struct PhotoFinish {
+    client_id: ClientId,
+    username: String,
+    /// The finish line
+    transform: Transform,
+    /// Finishing time
+    time: f32,
+}
+
+/// A spectator asks for a marker at curve index `curve_t`, replacing any they already have
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct PlaceMarker {
+    curve_t: f32,
+}
+
+/// Broadcast by the server: `client_id` placed a marker, to be shown for `ttl` seconds
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct MarkerPlaced {
+    client_id: ClientId,
+    curve_t: f32,
+    color: [f32; 3],
+    ttl: f32,
+}
+
+/// In authoritative races, clients send their inputs once per fixed step instead of positions
+#[derive(Message, Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[locality("Remote")]
+struct InputUpload {
+    /// Sequence number of this fixed step
+    seq: u32,
+    input: InputAbstraction,
+}
+
+/// In authoritative races, the server's state of a client's ship after the input `seq`
+#[derive(Message, Serialize, Deserialize, Debug, Clone, Copy)]
+#[locality("Remote")]
+struct ShipStateAck {
+    seq: u32,
+    tf: Transform,
+    kt: KinematicPhysics,
+    wall_ride: WallRide,
+    boost_cooldown: f32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
@@ -79,4 +766,28 @@ pub struct InputAbstraction {
     roll: f32,
     /// Desired thrust
     throttle: f32,
+    /// Spending a boost item
+    boost: bool,
+    /// Desired lateral and vertical thruster power, under `ControlScheme::Strafe`
+    lateral: f32,
+    vertical: f32,
+    /// How the rest of the input is to be read
+    scheme: ControlScheme,
+}
+
+impl InputAbstraction {
+    /// Steering across the track, from roll or the lateral thrusters depending on the scheme
+    fn steer(&self) -> f32 {
+        match self.scheme {
+            ControlScheme::Banked => self.roll,
+            ControlScheme::Strafe => self.lateral,
+        }
+    }
+
+    fn steer_mut(&mut self) -> &mut f32 {
+        match self.scheme {
+            ControlScheme::Banked => &mut self.roll,
+            ControlScheme::Strafe => &mut self.lateral,
+        }
+    }
 }
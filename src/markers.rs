@@ -0,0 +1,137 @@
+//! Beacons spectators drop on the track, for pointing things out to whoever they're coaching
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use cimvr_common::{
+    glam::Vec3,
+    render::{MeshHandle, Primitive, Render, UploadMesh},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{
+    countdown::color_extra,
+    curve::Curve,
+    entity_pool::{EntityPool, Owner},
+    shapes::barrier_mesh,
+    MarkerPlaced,
+};
+
+/// How long a marker stands (seconds)
+pub const MARKER_TTL: f32 = 30.;
+/// Size of a beacon (meters)
+const BEACON_WIDTH: f32 = 1.;
+const BEACON_HEIGHT: f32 = 40.;
+/// Widest angle off the view direction at which the track still counts as aimed at (radians)
+const MAX_AIM_ANGLE: f32 = 0.1;
+/// Colors handed out to the players placing markers
+const MARKER_COLORS: [[f32; 3]; 6] = [
+    [1., 0.3, 0.3],
+    [0.3, 1., 0.3],
+    [0.3, 0.6, 1.],
+    [1., 1., 0.3],
+    [1., 0.4, 1.],
+    [0.3, 1., 1.],
+];
+
+/// Color of `client_id`'s markers, the same for each of their markers
+pub fn marker_color(client_id: ClientId) -> [f32; 3] {
+    let mut hasher = DefaultHasher::new();
+    client_id.hash(&mut hasher);
+    MARKER_COLORS[hasher.finish() as usize % MARKER_COLORS.len()]
+}
+
+/// Curve index of the point on the track nearest the line of sight of `camera_tf`, if it's
+/// looking at the track at all
+pub fn aimed_t(path: &Curve, camera_tf: Transform) -> Option<f32> {
+    let origin = camera_tf.pos;
+    let dir = camera_tf.orient * Vec3::NEG_Z;
+    let n = path.ctrlps.len();
+    (0..n)
+        .filter_map(|i| {
+            let (a, b) = (path.ctrlps[i].pos, path.ctrlps[(i + 1) % n].pos);
+            let (ahead, u, miss) = ray_segment(origin, dir, a, b);
+            // Judged by angle, so that near and far stretches of track are equally easy to hit
+            let angle = miss.atan2(ahead);
+            (ahead > 0. && angle < MAX_AIM_ANGLE).then_some((i as f32 + u, angle))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(t, _)| t)
+}
+
+/// Closest approach of the ray from `origin` along unit `dir` to the segment from `a` to `b`,
+/// as (distance along the ray, fraction along the segment, distance apart)
+fn ray_segment(origin: Vec3, dir: Vec3, a: Vec3, b: Vec3) -> (f32, f32, f32) {
+    let d = b - a;
+    let w = origin - a;
+    let (dd, dw, along_d, along_w) = (d.length_squared(), d.dot(w), dir.dot(d), dir.dot(w));
+    let denom = dd - along_d * along_d;
+    let u = match denom > f32::EPSILON {
+        true => ((dw - along_d * along_w) / denom).clamp(0., 1.),
+        // Parallel; any point will do
+        false => 0.,
+    };
+    let ahead = (along_d * u - along_w).max(0.);
+    let miss = (origin + dir * ahead).distance(a + d * u);
+    (ahead, u, miss)
+}
+
+/// Everyone's markers, at most one each, until they expire
+pub struct Markers {
+    /// Entity and expiry time of each player's marker
+    markers: HashMap<ClientId, (EntityId, f32)>,
+}
+
+impl Markers {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("Marker"));
+
+    pub fn new(io: &mut EngineIo) -> Self {
+        io.send(&UploadMesh {
+            mesh: barrier_mesh([BEACON_WIDTH, BEACON_HEIGHT, BEACON_WIDTH], [1.; 3]),
+            id: Self::RDR_ID,
+        });
+        Self {
+            markers: HashMap::new(),
+        }
+    }
+
+    /// Stand a marker up, in place of any the same player already had
+    pub fn place(
+        &mut self,
+        io: &mut EngineIo,
+        pool: &mut EntityPool,
+        path: &Curve,
+        marker: &MarkerPlaced,
+        now: f32,
+    ) {
+        let base = path.lerp_fast(marker.curve_t);
+        let up = Transform::new().with_position(Vec3::Y * BEACON_HEIGHT / 2.);
+        let render = Render::new(Self::RDR_ID).primitive(Primitive::Lines);
+        let entity = pool.spawn(io, Owner::Markers, render, base * up);
+        pool.add_component(io, entity, color_extra(marker.color));
+
+        let expires = now + marker.ttl;
+        if let Some((old, _)) = self.markers.insert(marker.client_id, (entity, expires)) {
+            pool.release(io, old);
+        }
+    }
+
+    /// Take down the markers which have stood for long enough
+    pub fn update(&mut self, io: &mut EngineIo, pool: &mut EntityPool, now: f32) {
+        self.markers.retain(|_, &mut (entity, expires)| {
+            let keep = now < expires;
+            if !keep {
+                pool.release(io, entity);
+            }
+            keep
+        });
+    }
+
+    pub fn clear(&mut self, io: &mut EngineIo, pool: &mut EntityPool) {
+        for (_, (entity, _)) in self.markers.drain() {
+            pool.release(io, entity);
+        }
+    }
+}
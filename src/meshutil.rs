@@ -0,0 +1,136 @@
+//! Simplifying line meshes, for clients which can't draw every line
+use std::collections::HashMap;
+
+use cimvr_common::{glam::Vec3, render::Mesh};
+
+/// A lighter copy of a line mesh. Vertices joining exactly two segments which carry on in
+/// nearly the same direction (within `max_angle` radians), or which are nearer than
+/// `min_length` to a neighbour, are removed and their segments joined into one. Stray
+/// segments shorter than `min_length` are dropped.
+///
+/// Junctions and line ends are kept, as are the most extreme vertices on each axis, so that
+/// the shape and bounds of the mesh stay the same.
+pub fn decimate_lines(mesh: &Mesh, max_angle: f32, min_length: f32) -> Mesh {
+    let n = mesh.vertices.len();
+    let pos = |idx: u32| Vec3::from(mesh.vertices[idx as usize].pos);
+
+    let mut neighbours: Vec<Vec<u32>> = vec![vec![]; n];
+    for segment in mesh.indices.chunks_exact(2) {
+        let (a, b) = (segment[0], segment[1]);
+        if a != b && !neighbours[a as usize].contains(&b) {
+            neighbours[a as usize].push(b);
+            neighbours[b as usize].push(a);
+        }
+    }
+
+    let (min, max) = (0..n as u32).map(pos).fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), p| (min.min(p), max.max(p)),
+    );
+    let extreme = |p: Vec3| p.cmpeq(min).any() || p.cmpeq(max).any();
+
+    for idx in 0..n as u32 {
+        let &[a, b] = &neighbours[idx as usize][..] else {
+            continue;
+        };
+        let p = pos(idx);
+        // Joining across a triangle would double up the segment between the other two
+        if extreme(p) || neighbours[a as usize].contains(&b) {
+            continue;
+        }
+
+        let (pa, pb) = (pos(a), pos(b));
+        let straight = (p - pa).angle_between(pb - p) < max_angle;
+        let short = p.distance(pa).min(p.distance(pb)) < min_length;
+        if !straight && !short {
+            continue;
+        }
+
+        for (from, to) in [(a, b), (b, a)] {
+            for neighbour in &mut neighbours[from as usize] {
+                if *neighbour == idx {
+                    *neighbour = to;
+                }
+            }
+        }
+        neighbours[idx as usize].clear();
+    }
+
+    // Keep only the vertices still in use, in their original order
+    let mut out = Mesh::new();
+    let mut remap: HashMap<u32, u32> = HashMap::new();
+    let mut keep = |out: &mut Mesh, idx: u32| {
+        *remap
+            .entry(idx)
+            .or_insert_with(|| out.push_vertex(mesh.vertices[idx as usize]))
+    };
+    for a in 0..n as u32 {
+        for &b in &neighbours[a as usize] {
+            let stray = neighbours[a as usize].len() == 1 && neighbours[b as usize].len() == 1;
+            if a > b || (stray && pos(a).distance(pos(b)) < min_length) {
+                continue;
+            }
+            let (a, b) = (keep(&mut out, a), keep(&mut out, b));
+            out.indices.extend([a, b]);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use cimvr_common::render::Vertex;
+
+    use super::*;
+    use crate::{builtin_tracks::BuiltinTrack, obj::obj_lines_to_mesh, settings::Quality};
+
+    fn bounds(mesh: &Mesh) -> (Vec3, Vec3) {
+        mesh.vertices.iter().map(|v| Vec3::from(v.pos)).fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), p| (min.min(p), max.max(p)),
+        )
+    }
+
+    /// A line through `points` in order
+    fn polyline(points: &[Vec3]) -> Mesh {
+        let mut mesh = Mesh::new();
+        for &p in points {
+            mesh.push_vertex(Vertex::new(p.into(), [1.; 3]));
+        }
+        for i in 1..points.len() as u32 {
+            mesh.indices.extend([i - 1, i]);
+        }
+        mesh
+    }
+
+    #[test]
+    fn medium_quality_drops_most_of_loop1s_environment() {
+        let (max_angle, min_length) = Quality::Medium.decimation().unwrap();
+        let environment = obj_lines_to_mesh(BuiltinTrack::Loop1.environment_obj());
+        let lighter = decimate_lines(&environment, max_angle, min_length);
+
+        let (before, after) = (environment.vertices.len(), lighter.vertices.len());
+        let dropped = 1. - after as f32 / before as f32;
+        assert!(dropped >= 0.4, "{} of {} vertices left", after, before);
+        assert_eq!(bounds(&lighter), bounds(&environment));
+    }
+
+    #[test]
+    fn straight_runs_join_into_one_segment() {
+        // Diagonally, so that only the ends are on the edges of the bounds
+        let points: Vec<Vec3> = (0..10).map(|i| Vec3::ONE * i as f32).collect();
+        let lighter = decimate_lines(&polyline(&points), 4_f32.to_radians(), 0.5);
+        assert_eq!(lighter.vertices.len(), 2);
+        assert_eq!(bounds(&lighter), bounds(&polyline(&points)));
+    }
+
+    #[test]
+    fn corners_are_kept() {
+        let points = [Vec3::ZERO, Vec3::ONE, Vec3::ONE * 2., Vec3::new(3., 4., 5.)];
+        let lighter = decimate_lines(&polyline(&points), 4_f32.to_radians(), 0.5);
+        // The middle of the first side goes, the corner stays
+        assert_eq!(lighter.vertices.len(), 3);
+        assert_eq!(lighter.indices.len(), 4);
+    }
+}
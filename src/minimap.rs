@@ -0,0 +1,159 @@
+//! A small map of the track from above, in the corner of the view while racing, with a dot
+//! for each ship
+use std::collections::{HashMap, HashSet};
+
+use cimvr_common::{
+    glam::{Quat, Vec3},
+    render::{Mesh, MeshHandle, Primitive, Render, UploadMesh, Vertex},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{countdown::color_extra, curve::Curve, hud::HudVisibility};
+
+/// Middle of the map, relative to the camera: low on the right, just in front (meters)
+const MAP_OFFSET: Vec3 = Vec3::new(1.05, -0.5, -2.);
+/// Width or depth of the map, whichever is larger (meters)
+const MAP_SIZE: f32 = 0.5;
+/// Width of a ship's dot (meters)
+const DOT_SIZE: f32 = 0.02;
+/// Lift of the dots off the map, towards the camera, so that they're drawn over the track
+/// (meters)
+const DOT_LIFT: f32 = 0.005;
+const TRACK_COLOR: [f32; 3] = [0.6, 0.6, 0.6];
+const OTHER_COLOR: [f32; 3] = [1., 0.3, 0.3];
+const OWN_COLOR: [f32; 3] = [0.2, 1., 0.2];
+
+/// Square in the XZ plane, `size` across and centered on the origin, filled in with a cross
+fn dot_mesh(size: f32) -> Mesh {
+    let mut m = Mesh::new();
+    let h = size / 2.;
+    let corners: Vec<u32> = [[h, h], [-h, h], [-h, -h], [h, -h]]
+        .into_iter()
+        .map(|[x, z]| m.push_vertex(Vertex::new([x, 0., z], [1.; 3])))
+        .collect();
+    for k in 0..corners.len() {
+        m.indices
+            .extend([corners[k], corners[(k + 1) % corners.len()]]);
+    }
+    m.indices
+        .extend([corners[0], corners[2], corners[1], corners[3]]);
+    m
+}
+
+/// The map's track and a dot per ship, placed in front of the camera
+pub struct Minimap {
+    track: EntityId,
+    dots: HashMap<ClientId, EntityId>,
+    /// Middle of the track's bounding box, which goes in the middle of the map
+    center: Vec3,
+    /// World XZ to map size, with Y flattened
+    scale: Vec3,
+    /// Whether the track was last drawn
+    shown: bool,
+}
+
+impl Minimap {
+    const TRACK_ID: MeshHandle = MeshHandle::new(pkg_namespace!("MinimapTrack"));
+    const DOT_ID: MeshHandle = MeshHandle::new(pkg_namespace!("MinimapDot"));
+
+    pub fn new(io: &mut EngineIo, path: &Curve) -> Self {
+        io.send(&UploadMesh {
+            mesh: dot_mesh(DOT_SIZE),
+            id: Self::DOT_ID,
+        });
+        let track = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(Self::render(false))
+            .build();
+
+        let mut minimap = Self {
+            track,
+            dots: HashMap::new(),
+            center: Vec3::ZERO,
+            scale: Vec3::ZERO,
+            shown: false,
+        };
+        minimap.set_track(io, path);
+        minimap
+    }
+
+    fn render(visible: bool) -> Render {
+        HudVisibility::render(
+            visible,
+            Render::new(Self::TRACK_ID).primitive(Primitive::Lines),
+        )
+    }
+
+    /// Draw the map of a new track, fitted into `MAP_SIZE`
+    pub fn set_track(&mut self, io: &mut EngineIo, path: &Curve) {
+        let (min, max) = path.bounds();
+        let extent = (max - min).x.max((max - min).z).max(f32::EPSILON);
+        let scale = MAP_SIZE / extent;
+        self.center = (min + max) / 2.;
+        self.scale = Vec3::new(scale, 0., scale);
+        io.send(&UploadMesh {
+            mesh: path.to_line_mesh(self.scale, TRACK_COLOR),
+            id: Self::TRACK_ID,
+        });
+    }
+
+    /// The map's plane in front of the camera, with world XZ across it and north up
+    fn frame(camera: Transform) -> Transform {
+        let face_camera = Quat::from_rotation_x(std::f32::consts::FRAC_PI_2);
+        camera
+            * Transform::new()
+                .with_position(MAP_OFFSET)
+                .with_rotation(face_camera)
+    }
+
+    /// Follow the camera, and move the dots to `ships`, with `own_id`'s marked out. Nothing is
+    /// shown unless `visible`.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        ships: impl IntoIterator<Item = (ClientId, Vec3)>,
+        own_id: Option<ClientId>,
+        camera: Transform,
+        visible: bool,
+    ) {
+        if visible != self.shown {
+            io.add_component(self.track, Self::render(visible));
+            self.shown = visible;
+        }
+
+        let frame = Self::frame(camera);
+        let mut shown = HashSet::new();
+        if visible {
+            let track_tf = Transform::new().with_position(-self.center * self.scale);
+            io.add_component(self.track, frame * track_tf);
+
+            for (client_id, pos) in ships {
+                let color = match Some(client_id) == own_id {
+                    true => OWN_COLOR,
+                    false => OTHER_COLOR,
+                };
+                let entity = *self.dots.entry(client_id).or_insert_with(|| {
+                    io.create_entity()
+                        .add_component(Transform::identity())
+                        .add_component(Render::new(Self::DOT_ID).primitive(Primitive::Lines))
+                        .add_component(color_extra(color))
+                        .build()
+                });
+                let local = (pos - self.center) * self.scale + Vec3::Y * DOT_LIFT;
+                io.add_component(entity, frame * Transform::new().with_position(local));
+                shown.insert(client_id);
+            }
+        }
+
+        // Remove dots of ships which left, or all of them while hidden
+        self.dots.retain(|client_id, entity| {
+            let keep = shown.contains(client_id);
+            if !keep {
+                io.remove_entity(*entity);
+            }
+            keep
+        });
+    }
+}
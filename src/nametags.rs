@@ -0,0 +1,128 @@
+//! Each racer's name, floating above their ship and turned to face the camera
+use std::collections::{HashMap, HashSet};
+
+use cimvr_common::{
+    glam::Vec3,
+    render::{MeshHandle, Primitive, Render, UploadMesh},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{
+    shapes::{text_mesh, text_width},
+    LobbyEntry,
+};
+
+/// Tags further than this from the camera are hidden (meters)
+const MAX_DISTANCE: f32 = 150.;
+/// Height of the tag's baseline above the ship (meters)
+const TAG_HEIGHT: f32 = 3.;
+/// Height of the lettering (meters)
+const LETTER_HEIGHT: f32 = 0.8;
+const TAG_COLOR: [f32; 3] = [0.9, 0.9, 0.9];
+
+/// A mesh for each tag shown at once; ships beyond these go without
+const SLOTS: [MeshHandle; 8] = [
+    MeshHandle::new(pkg_namespace!("Nametag0")),
+    MeshHandle::new(pkg_namespace!("Nametag1")),
+    MeshHandle::new(pkg_namespace!("Nametag2")),
+    MeshHandle::new(pkg_namespace!("Nametag3")),
+    MeshHandle::new(pkg_namespace!("Nametag4")),
+    MeshHandle::new(pkg_namespace!("Nametag5")),
+    MeshHandle::new(pkg_namespace!("Nametag6")),
+    MeshHandle::new(pkg_namespace!("Nametag7")),
+];
+
+struct Nametag {
+    entity: EntityId,
+    /// Index into `SLOTS`
+    slot: usize,
+    /// Name the slot's mesh was last drawn with
+    name: String,
+}
+
+/// A tag above each ship near the camera, with the name the server gave its client
+#[derive(Default)]
+pub struct Nametags {
+    names: HashMap<ClientId, String>,
+    tags: HashMap<ClientId, Nametag>,
+}
+
+impl Nametags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take everyone's names from a `LobbyStatus`
+    pub fn set_names(&mut self, entries: &[LobbyEntry]) {
+        self.names = entries
+            .iter()
+            .map(|entry| (entry.client_id, entry.name.clone()))
+            .collect();
+    }
+
+    /// Create, move and remove tags to follow `ships`, facing `camera`. None are shown unless
+    /// `visible`.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        ships: impl IntoIterator<Item = (ClientId, Transform)>,
+        camera: Transform,
+        visible: bool,
+    ) {
+        let mut shown = HashSet::new();
+
+        for (client_id, tf) in ships {
+            if !visible || (tf.pos - camera.pos).length() > MAX_DISTANCE {
+                continue;
+            }
+            let name = match self.names.get(&client_id) {
+                Some(name) if !name.is_empty() => name.clone(),
+                _ => format!("{:?}", client_id),
+            };
+
+            if !self.tags.contains_key(&client_id) {
+                let used: HashSet<usize> = self.tags.values().map(|tag| tag.slot).collect();
+                let Some(slot) = (0..SLOTS.len()).find(|slot| !used.contains(slot)) else {
+                    continue;
+                };
+                let entity = io
+                    .create_entity()
+                    .add_component(Transform::identity())
+                    .add_component(Render::new(SLOTS[slot]).primitive(Primitive::Lines))
+                    .build();
+                let name = String::new();
+                self.tags.insert(client_id, Nametag { entity, slot, name });
+            }
+            let tag = self.tags.get_mut(&client_id).unwrap();
+            if tag.name != name {
+                io.send(&UploadMesh {
+                    mesh: text_mesh(&name, LETTER_HEIGHT, TAG_COLOR),
+                    id: SLOTS[tag.slot],
+                });
+                tag.name = name;
+            }
+
+            // Turned the same way as the camera so it faces it, and centered over the ship
+            let width = text_width(&tag.name, LETTER_HEIGHT);
+            let pos = tf.pos
+                + tf.orient * Vec3::Y * TAG_HEIGHT
+                + camera.orient * Vec3::new(-width / 2., 0., 0.);
+            let tag_tf = Transform::new()
+                .with_position(pos)
+                .with_rotation(camera.orient);
+            io.add_component(tag.entity, tag_tf);
+
+            shown.insert(client_id);
+        }
+
+        // Remove tags of ships which left, or went out of view
+        self.tags.retain(|client_id, tag| {
+            let keep = shown.contains(client_id);
+            if !keep {
+                io.remove_entity(tag.entity);
+            }
+            keep
+        });
+    }
+}
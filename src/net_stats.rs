@@ -0,0 +1,316 @@
+//! Health of the streams of ship updates, as seen by whoever is receiving them
+use std::collections::{HashMap, VecDeque};
+
+use cimvr_common::glam::Vec3;
+use cimvr_engine_interface::prelude::*;
+
+use crate::{countdown::color_extra, kinematics::KinematicPhysics, ServerShipComponent};
+
+/// How far back the statistics look (seconds)
+const WINDOW: f32 = 2.;
+/// Silence after which a stream counts as stalled, and its ship stops being extrapolated (seconds)
+pub const STALE_TIME: f32 = 0.3;
+/// A sequence number this far below the last one means the sender started counting again
+const RESTART_THRESHOLD: u32 = 100;
+/// Brightness of a ship whose stream has stalled
+const STALE_BRIGHTNESS: f32 = 0.5;
+
+/// Arrival statistics for one stream of sequence-numbered messages
+#[derive(Default, Debug, Clone)]
+pub struct StreamStats {
+    /// Arrival time and sequence number of each message within the window
+    arrivals: VecDeque<(f32, u32)>,
+}
+
+impl StreamStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note a message arriving at `time`. Duplicates and stragglers are ignored.
+    pub fn record(&mut self, time: f32, seq: u32) {
+        if let Some(&(_, last)) = self.arrivals.back() {
+            if seq < last && last - seq > RESTART_THRESHOLD {
+                self.arrivals.clear();
+            } else if seq <= last {
+                return;
+            }
+        }
+
+        self.arrivals.push_back((time, seq));
+        while self
+            .arrivals
+            .front()
+            .map_or(false, |&(t, _)| t < time - WINDOW)
+        {
+            self.arrivals.pop_front();
+        }
+    }
+
+    /// Time since the last message, if there ever was one
+    pub fn silence(&self, now: f32) -> Option<f32> {
+        self.arrivals.back().map(|&(t, _)| now - t)
+    }
+
+    pub fn is_stale(&self, now: f32) -> bool {
+        self.silence(now).map_or(false, |s| s > STALE_TIME)
+    }
+
+    /// First and last arrivals in the window
+    fn ends(&self) -> Option<((f32, u32), (f32, u32))> {
+        Some((*self.arrivals.front()?, *self.arrivals.back()?))
+    }
+
+    /// Messages per second
+    pub fn rate(&self) -> f32 {
+        let Some(((first, _), (last, _))) = self.ends() else {
+            return 0.;
+        };
+        match last > first {
+            true => (self.arrivals.len() - 1) as f32 / (last - first),
+            false => 0.,
+        }
+    }
+
+    /// Longest wait between two messages (seconds)
+    pub fn largest_gap(&self) -> f32 {
+        let times: Vec<f32> = self.arrivals.iter().map(|&(t, _)| t).collect();
+        times.windows(2).map(|w| w[1] - w[0]).fold(0., f32::max)
+    }
+
+    /// Fraction of messages which never arrived, judging by the gaps in sequence numbers
+    pub fn loss(&self) -> f32 {
+        let Some(((_, first), (_, last))) = self.ends() else {
+            return 0.;
+        };
+        // Wide, for a sender which jumped a long way ahead
+        let expected = (last - first) as f32 + 1.;
+        (1. - self.arrivals.len() as f32 / expected).clamp(0., 1.)
+    }
+
+    /// Signal strength as bars, like a phone's
+    pub fn signal_bars(&self, now: f32) -> &'static str {
+        if self.is_stale(now) {
+            return "[    ]";
+        }
+        match self.loss() {
+            l if l < 0.02 => "[||||]",
+            l if l < 0.1 => "[||| ]",
+            l if l < 0.3 => "[||  ]",
+            _ => "[|   ]",
+        }
+    }
+
+    /// One line summary, for telemetry and logs
+    pub fn summary(&self, now: f32) -> String {
+        format!(
+            "{} {:.0}/s, gap {:.0} ms, loss {:.0}%",
+            self.signal_bars(now),
+            self.rate(),
+            self.largest_gap().max(self.silence(now).unwrap_or(0.)) * 1000.,
+            self.loss() * 100.,
+        )
+    }
+}
+
+struct RemoteShip {
+    stats: StreamStats,
+    /// Color the ship was last tinted: its player's, dimmer if it has stalled
+    tint: [f32; 3],
+}
+
+/// Client side statistics for every other ship, which also stop stalled ships from flying off
+/// along their last velocity
+pub struct RemoteShips {
+    ships: HashMap<ClientId, RemoteShip>,
+    /// Brightness of ships which are keeping up, e.g. brighter at night
+    brightness: f32,
+}
+
+impl RemoteShips {
+    pub fn new() -> Self {
+        Self {
+            ships: HashMap::new(),
+            brightness: 1.,
+        }
+    }
+
+    pub fn set_brightness(&mut self, brightness: f32) {
+        self.brightness = brightness;
+    }
+
+    /// Read the latest sequence numbers from the `query`'s ships, excluding our own
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        query: &mut QueryResult,
+        query_name: &str,
+        own_id: Option<ClientId>,
+        now: f32,
+    ) {
+        let mut seen = vec![];
+        for entity in query.iter(query_name) {
+            let shipc: ServerShipComponent = query.read(entity);
+            if Some(shipc.client_id) == own_id {
+                continue;
+            }
+            seen.push(shipc.client_id);
+
+            let ship = self
+                .ships
+                .entry(shipc.client_id)
+                .or_insert_with(|| RemoteShip {
+                    stats: StreamStats::new(),
+                    tint: [1.; 3],
+                });
+            ship.stats.record(now, shipc.upload_seq);
+
+            // Hold stalled ships still; fresh data snaps them back into place
+            let stale = ship.stats.is_stale(now);
+            if stale {
+                query.modify::<KinematicPhysics>(entity, |kt| {
+                    kt.vel = Vec3::ZERO;
+                    kt.ang_vel = Vec3::ZERO;
+                });
+            }
+            let health = if stale { STALE_BRIGHTNESS } else { 1. };
+            let tint = shipc.color.rgb().map(|c| c * health * self.brightness);
+            if tint != ship.tint {
+                ship.tint = tint;
+                io.add_component(entity, color_extra(tint));
+            }
+        }
+
+        self.ships.retain(|id, _| seen.contains(id));
+    }
+
+    pub fn telemetry_lines(&self, now: f32) -> Vec<String> {
+        let mut ships: Vec<_> = self.ships.iter().collect();
+        ships.sort_by_key(|(id, _)| id.0);
+        ships
+            .into_iter()
+            .map(|(id, ship)| format!("{:?}: {}", id, ship.stats.summary(now)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Time between messages of a steady stream (seconds)
+    const INTERVAL: f32 = 0.05;
+
+    /// Stats after the messages `seqs` arrive in order, one every `INTERVAL`
+    fn stream(seqs: impl IntoIterator<Item = u32>) -> StreamStats {
+        let mut stats = StreamStats::new();
+        for (k, seq) in seqs.into_iter().enumerate() {
+            stats.record(k as f32 * INTERVAL, seq);
+        }
+        stats
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn a_steady_stream_loses_nothing() {
+        let stats = stream(0..100);
+        assert!(
+            (stats.rate() - 1. / INTERVAL).abs() < 0.5,
+            "{}",
+            stats.rate()
+        );
+        assert!(close(stats.largest_gap(), INTERVAL));
+        assert_eq!(stats.loss(), 0.);
+        assert_eq!(stats.signal_bars(99. * INTERVAL), "[||||]");
+    }
+
+    #[test]
+    fn missing_sequence_numbers_are_loss() {
+        // Every fourth message lost on the way
+        let mut stats = StreamStats::new();
+        for seq in (0..100).filter(|seq| seq % 4 != 3) {
+            stats.record(seq as f32 * INTERVAL, seq);
+        }
+        assert!((stats.loss() - 0.25).abs() < 0.02, "{}", stats.loss());
+        assert!(close(stats.largest_gap(), 2. * INTERVAL));
+    }
+
+    #[test]
+    fn a_stall_is_the_largest_gap_and_goes_stale() {
+        let mut stats = stream(0..20);
+        let last = 19. * INTERVAL;
+        assert!(!stats.is_stale(last + STALE_TIME * 0.5));
+        assert!(stats.is_stale(last + STALE_TIME * 2.));
+
+        // Nothing was lost, the stream just paused
+        stats.record(last + 0.5, 20);
+        assert!(!stats.is_stale(last + 0.5));
+        assert!(close(stats.largest_gap(), 0.5));
+        assert_eq!(stats.loss(), 0.);
+    }
+
+    #[test]
+    fn duplicates_are_ignored() {
+        let stats = stream([0, 1, 1, 2, 3, 3, 3, 4]);
+        assert_eq!(stats.arrivals.len(), 5);
+        assert_eq!(stats.loss(), 0.);
+    }
+
+    #[test]
+    fn stragglers_count_as_lost() {
+        // 3 arrives after 4, too late to be any use
+        let stats = stream([1, 2, 4, 3, 5]);
+        let seqs: Vec<u32> = stats.arrivals.iter().map(|&(_, seq)| seq).collect();
+        assert_eq!(seqs, [1, 2, 4, 5]);
+        assert!(close(stats.loss(), 0.2));
+    }
+
+    #[test]
+    fn old_loss_drops_out_of_the_window() {
+        let mut stats = StreamStats::new();
+        let mut time = 0.;
+        for seq in (0..40).step_by(2) {
+            stats.record(time, seq);
+            time += INTERVAL;
+        }
+        assert!(stats.loss() > 0.4);
+
+        let clean = (WINDOW / INTERVAL) as u32 + 10;
+        for seq in 40..40 + clean {
+            stats.record(time, seq);
+            time += INTERVAL;
+        }
+        assert_eq!(stats.loss(), 0.);
+    }
+
+    #[test]
+    fn counting_again_from_zero_starts_afresh() {
+        // After a reconnect, or the counter wrapping round
+        for top in [5000, u32::MAX] {
+            let stats = stream((top - 20..=top).chain(0..20));
+            assert_eq!(stats.loss(), 0., "from {}", top);
+            assert_eq!(stats.arrivals.front().map(|&(_, seq)| seq), Some(0));
+            assert!((stats.rate() - 1. / INTERVAL).abs() < 0.5);
+        }
+    }
+
+    #[test]
+    fn a_sender_jumping_far_ahead_is_all_loss() {
+        let stats = stream([0, u32::MAX]);
+        assert_eq!(stats.loss(), 1.);
+    }
+
+    #[test]
+    fn empty_streams_have_no_statistics() {
+        let stats = StreamStats::new();
+        assert_eq!(
+            (stats.rate(), stats.largest_gap(), stats.loss()),
+            (0., 0., 0.)
+        );
+        assert_eq!(stats.silence(1.), None);
+        assert!(!stats.is_stale(100.));
+    }
+}
@@ -1,52 +1,301 @@
-use cimvr_common::render::{Mesh, Vertex};
+use std::fmt;
+
+use cimvr_common::{
+    glam::{Mat3, Quat, Vec3},
+    render::{Mesh, Primitive, Vertex},
+    Transform,
+};
+
+/// A line of an OBJ file which couldn't be read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObjError {
+    /// Line number (from 1) of a vertex with an unreadable coordinate
+    InvalidFloat { line: usize },
+    /// Line number (from 1) of an unreadable or zero index, or of a face with fewer than
+    /// three corners
+    InvalidIndex { line: usize },
+    /// Line number (from 1) of a face in a file of lines, or the other way round
+    MixedElements { line: usize },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidFloat { line } => write!(f, "invalid float on line {}", line),
+            Self::InvalidIndex { line } => write!(f, "invalid index on line {}", line),
+            Self::MixedElements { line } => {
+                write!(f, "lines and faces mixed, from line {}", line)
+            }
+        }
+    }
+}
+
+/// What an OBJ file is drawn with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ObjElements {
+    /// `l` lines, or nothing but vertices
+    #[default]
+    Lines,
+    /// `f` faces, split into triangles
+    Triangles,
+}
+
+impl ObjElements {
+    /// How to render the mesh
+    pub fn primitive(self) -> Primitive {
+        match self {
+            ObjElements::Lines => Primitive::Lines,
+            ObjElements::Triangles => Primitive::Triangles,
+        }
+    }
+}
 
 /// Read OBJ lines into the mesh
 pub fn obj_lines_to_mesh(obj: &str) -> Mesh {
+    try_obj_lines_to_mesh(obj).expect("Invalid OBJ")
+}
+
+/// Read OBJ lines into the mesh, for files which may be malformed
+pub fn try_obj_lines_to_mesh(obj: &str) -> Result<Mesh, ObjError> {
     let mut m = Mesh::new();
 
-    for line in obj.lines() {
-        // Split the line by whitespace
-        let mut line = line.split_whitespace();
-
-        // Break the first bit off
-        let (first, mut rest) = (line.next(), line);
-
-        // Which kind of line is it?
-        match first {
-            Some("v") => {
-                // Treat the line as two arrays of 3 elements
-                let mut parts = [[0.; 3], [1.; 3]];
-
-                for part in &mut parts {
-                    // Get strings from the rest of the line
-                    for dim in part {
-                        let Some(text) = rest.next() else { break };
-                        *dim = text.parse().expect("Invalid float");
-                    }
+    for (line_idx, line) in obj.lines().enumerate() {
+        read_line(&mut m, line, line_idx + 1, 0)?;
+    }
+
+    Ok(m)
+}
+
+/// Read an OBJ file of either lines or faces into a mesh, and which of them it's made of
+pub fn obj_to_mesh(obj: &str) -> (Mesh, ObjElements) {
+    try_obj_to_mesh(obj).expect("Invalid OBJ")
+}
+
+/// Read an OBJ file of either lines or faces, for files which may be malformed
+pub fn try_obj_to_mesh(obj: &str) -> Result<(Mesh, ObjElements), ObjError> {
+    let mut m = Mesh::new();
+    let mut elements = None;
+
+    for (line_idx, line) in obj.lines().enumerate() {
+        let Some(kind) = read_line(&mut m, line, line_idx + 1, 0)? else {
+            continue;
+        };
+        if *elements.get_or_insert(kind) != kind {
+            return Err(ObjError::MixedElements { line: line_idx + 1 });
+        }
+    }
+
+    Ok((m, elements.unwrap_or_default()))
+}
+
+/// One object of an OBJ file
+pub struct ObjObject {
+    /// As given by its `o` line
+    pub name: String,
+    pub mesh: Mesh,
+}
+
+/// Read OBJ lines into a mesh for each object. Anything before the first `o` line goes in an
+/// object named "", if there is any. Indices count from the object's own first vertex.
+pub fn try_obj_objects(obj: &str) -> Result<Vec<ObjObject>, ObjError> {
+    let mut objects = vec![ObjObject {
+        name: String::new(),
+        mesh: Mesh::new(),
+    }];
+    // OBJ numbers vertices across the whole file
+    let mut first_vertex = 0;
+
+    for (line_idx, line) in obj.lines().enumerate() {
+        let current = objects.last_mut().unwrap();
+        if let Some(name) = line.strip_prefix("o ") {
+            first_vertex += current.mesh.vertices.len() as u32;
+            objects.push(ObjObject {
+                name: name.trim().to_string(),
+                mesh: Mesh::new(),
+            });
+            continue;
+        }
+        read_line(&mut current.mesh, line, line_idx + 1, first_vertex)?;
+    }
+
+    objects.retain(|object| !object.name.is_empty() || !object.mesh.vertices.is_empty());
+    Ok(objects)
+}
+
+/// Every four vertices of `mesh` as the corners of a quad, in order round it: the quad's
+/// center, with local X along its first edge and Y out of its face (counterclockwise seen
+/// from above), and the distance to its furthest corner
+pub fn mesh_quads(mesh: &Mesh) -> Vec<(Transform, f32)> {
+    mesh.vertices
+        .chunks_exact(4)
+        .map(|corners| {
+            let corners: Vec<Vec3> = corners.iter().map(|v| Vec3::from(v.pos)).collect();
+            let center = corners.iter().sum::<Vec3>() / 4.;
+            let x = (corners[1] - corners[0]).normalize();
+            let y = (corners[3] - corners[0]).cross(x).normalize();
+            let orient = Mat3::from_cols(x, y, x.cross(y));
+            let radius = corners
+                .iter()
+                .map(|corner| corner.distance(center))
+                .fold(0., f32::max);
+            let transform = Transform {
+                pos: center,
+                orient: Quat::from_mat3(&orient),
+            };
+            (transform, radius)
+        })
+        .collect()
+}
+
+/// Read one line into `m`, whose first vertex is number `first_vertex` (from 0) in the file.
+/// Returns the kind of element the line added, if any.
+fn read_line(
+    m: &mut Mesh,
+    line: &str,
+    line_number: usize,
+    first_vertex: u32,
+) -> Result<Option<ObjElements>, ObjError> {
+    // Split the line by whitespace
+    let mut line = line.split_whitespace();
+
+    // Break the first bit off
+    let (first, mut rest) = (line.next(), line);
+
+    // Which kind of line is it?
+    match first {
+        Some("v") => {
+            // Treat the line as two arrays of 3 elements
+            let mut parts = [[0.; 3], [1.; 3]];
+
+            for part in &mut parts {
+                // Get strings from the rest of the line
+                for dim in part {
+                    let Some(text) = rest.next() else { break };
+                    *dim = text
+                        .parse()
+                        .map_err(|_| ObjError::InvalidFloat { line: line_number })?;
                 }
+            }
+
+            // Split the parts back up
+            let [pos, uvw] = parts;
 
-                // Split the parts back up
-                let [pos, uvw] = parts;
+            // Assemble the vertex
+            m.vertices.push(Vertex { pos, uvw });
+        }
+        Some("l") => {
+            // Do the same for indices
+            let mut indices = [0; 2];
+            for dim in &mut indices {
+                let Some(text) = rest.next() else { break };
+                let invalid = ObjError::InvalidIndex { line: line_number };
+                let index: u32 = text.parse().map_err(|_| invalid)?;
 
-                // Assemble the vertex
-                m.vertices.push(Vertex { pos, uvw });
+                // OBJ files are one-indexed
+                *dim = index.checked_sub(1 + first_vertex).ok_or(invalid)?;
+            }
+            m.indices.extend(indices);
+            return Ok(Some(ObjElements::Lines));
+        }
+        Some("f") => {
+            let invalid = ObjError::InvalidIndex { line: line_number };
+            let mut corners = vec![];
+            for corner in rest {
+                // Only the vertex of `v/vt/vn`; texture coordinates and normals are unused
+                let vertex = corner.split('/').next().unwrap_or_default();
+                let index: u32 = vertex.parse().map_err(|_| invalid)?;
+                corners.push(index.checked_sub(1 + first_vertex).ok_or(invalid)?);
+            }
+            if corners.len() < 3 {
+                return Err(invalid);
             }
-            Some("l") => {
-                // Do the same for indices
-                let mut indices = [0; 2];
-                for dim in &mut indices {
-                    let Some(text) = rest.next() else { break };
-                    *dim = text.parse().expect("Invalid index");
 
-                    // OBJ files are one-indexed
-                    *dim -= 1;
-                }
-                m.indices.extend(indices);
+            // Fan out from the first corner, which is right for the convex faces exporters write
+            for pair in corners[1..].windows(2) {
+                m.indices.extend([corners[0], pair[0], pair[1]]);
             }
-            // Ignore the rest
-            _ => (),
+            return Ok(Some(ObjElements::Triangles));
         }
+        // Ignore the rest
+        _ => (),
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unit cube as an exporter would write it, with texture coordinates and normals
+    const CUBE: &str = "\
+o Cube
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 1
+v 1 0 1
+v 1 1 1
+v 0 1 1
+vt 0 0
+vn 0 0 -1
+s off
+f 1/1/1 2/1/1 3/1/1 4/1/1
+f 5/1/1 6/1/1 7/1/1 8/1/1
+f 1/1/1 2/1/1 6/1/1 5/1/1
+f 2/1/1 3/1/1 7/1/1 6/1/1
+f 3/1/1 4/1/1 8/1/1 7/1/1
+f 4/1/1 1/1/1 5/1/1 8/1/1
+";
+
+    #[test]
+    fn a_cube_is_twelve_triangles() {
+        let (mesh, elements) = try_obj_to_mesh(CUBE).unwrap();
+        assert_eq!(elements, ObjElements::Triangles);
+        assert_eq!(mesh.vertices.len(), 8);
+        assert_eq!(mesh.vertices[6].pos, [1., 1., 1.]);
+        assert_eq!(mesh.indices.len(), 12 * 3);
+        // Each quad fans out from its first corner
+        assert_eq!(mesh.indices[..6], [0, 1, 2, 0, 2, 3]);
+        assert!(mesh.indices.iter().all(|&i| i < 8));
     }
 
-    m
+    #[test]
+    fn corners_may_leave_out_texture_coordinates_and_normals() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nf 1//1 2//1 3//1\nf 1/1 2/1 3/1\n";
+        let (mesh, _) = try_obj_to_mesh(obj).unwrap();
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn larger_faces_fan_out_from_the_first_corner() {
+        let vertices = "v 0 0 0\n".repeat(5);
+        let (mesh, _) = try_obj_to_mesh(&format!("{}f 1 2 3 4 5\n", vertices)).unwrap();
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+    }
+
+    #[test]
+    fn lines_and_faces_cant_be_mixed() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nl 1 2\nf 1 2 3\n";
+        assert_eq!(
+            try_obj_to_mesh(obj).err(),
+            Some(ObjError::MixedElements { line: 5 })
+        );
+        let (_, elements) = try_obj_to_mesh("v 0 0 0\nv 1 0 0\nl 1 2\n").unwrap();
+        assert_eq!(elements, ObjElements::Lines);
+    }
+
+    #[test]
+    fn bad_faces_are_errors() {
+        let vertices = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+        for (face, error) in [
+            ("f 1 2", ObjError::InvalidIndex { line: 4 }),
+            ("f 0 1 2", ObjError::InvalidIndex { line: 4 }),
+            ("f 1/1/1 x/1/1 3/1/1", ObjError::InvalidIndex { line: 4 }),
+        ] {
+            let obj = format!("{}{}\n", vertices, face);
+            assert_eq!(try_obj_to_mesh(&obj).err(), Some(error), "{}", face);
+        }
+    }
 }
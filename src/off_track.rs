@@ -0,0 +1,110 @@
+//! Time spent off the racing surface, which can cost a lap its validity
+use crate::{
+    race_rules::format_race_time,
+    strings::{tr, Key, Language, Text},
+    LapTime,
+};
+
+/// Width of the run-off either side of the racing surface, inside the walls (meters)
+const RUN_OFF: f32 = 4.;
+/// Blocks in the warning bar
+const BAR_LENGTH: usize = 10;
+
+/// Cumulative time off the racing surface over the current lap
+#[derive(Default, Debug, Clone, Copy)]
+pub struct OffTrackTimer {
+    time: f32,
+    invalid: bool,
+    /// Whether the last update was off track
+    off: bool,
+}
+
+impl OffTrackTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `dt` if `lateral`, the ship's sideways offset from the centre line, is off the racing
+    /// surface of a track `half_width` meters either side of it. Frames where the ship is being
+    /// put back from the walls (`excursion`) don't count. Returns true if this just invalidated
+    /// the lap.
+    pub fn update(
+        &mut self,
+        dt: f32,
+        lateral: f32,
+        half_width: f32,
+        excursion: bool,
+        limit: f32,
+    ) -> bool {
+        self.off = off_surface(lateral, half_width);
+        if !self.off || excursion {
+            return false;
+        }
+
+        self.time += dt;
+        let newly_invalid = !self.invalid && self.time > limit;
+        self.invalid |= newly_invalid;
+        newly_invalid
+    }
+
+    /// Start a new lap, returning whether the one just done is still valid
+    pub fn end_lap(&mut self) -> bool {
+        let valid = !self.invalid;
+        *self = Self::new();
+        valid
+    }
+
+    /// A bar filling up towards `limit`, while we're off track or have been this lap
+    pub fn warning_line(&self, language: Language, limit: f32) -> Option<String> {
+        if self.time <= 0. {
+            return None;
+        }
+        if self.invalid {
+            return Some(tr(language, Key::LapInvalid).into());
+        }
+
+        let filled = ((self.time / limit) * BAR_LENGTH as f32).ceil() as usize;
+        let bar: String = (0..BAR_LENGTH)
+            .map(|i| if i < filled { '█' } else { '░' })
+            .collect();
+        let state = match self.off {
+            true => tr(language, Key::OffTrack),
+            false => tr(language, Key::WasOffTrack),
+        };
+        Some(format!("{} {} {:.1}/{:.1} s", state, bar, self.time, limit))
+    }
+}
+
+/// Whether a ship `lateral` meters sideways from the centre line is off the racing surface,
+/// on a track `half_width` meters either side of it
+pub fn off_surface(lateral: f32, half_width: f32) -> bool {
+    lateral.abs() > half_width - RUN_OFF
+}
+
+/// Lines for the lap HUD; invalid laps are struck through
+pub fn lap_lines(language: Language, laps: &[LapTime], best: Option<f32>) -> Vec<String> {
+    let mut lines: Vec<String> = laps
+        .iter()
+        .enumerate()
+        .map(|(i, lap)| {
+            let text = Text::new(Key::LapLine)
+                .with(i + 1)
+                .with(format_race_time(lap.time))
+                .render(language);
+            match lap.valid {
+                true => text,
+                false => strike_through(&text),
+            }
+        })
+        .collect();
+    if let Some(best) = best {
+        let text = Text::new(Key::BestLap).with(format_race_time(best));
+        lines.push(text.render(language));
+    }
+    lines
+}
+
+/// Strike through each character, with a combining long stroke overlay
+fn strike_through(text: &str) -> String {
+    text.chars().flat_map(|c| [c, '\u{0336}']).collect()
+}
@@ -0,0 +1,56 @@
+use crate::settings::ClientSettings;
+
+/// Colors carrying meaning, switchable for colorblind players
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Palette {
+    Standard,
+    /// Blue/orange, from the Okabe-Ito set
+    Colorblind,
+}
+
+impl Palette {
+    pub fn from_settings(settings: &ClientSettings) -> Self {
+        if settings.colorblind {
+            Self::Colorblind
+        } else {
+            Self::Standard
+        }
+    }
+
+    /// Someone behind you
+    pub fn behind(self) -> [f32; 3] {
+        match self {
+            Self::Standard => [0.2, 0.4, 1.],
+            Self::Colorblind => [0., 0.45, 0.7],
+        }
+    }
+
+    /// Someone ahead of you
+    pub fn ahead(self) -> [f32; 3] {
+        match self {
+            Self::Standard => [1., 0.2, 0.2],
+            Self::Colorblind => [0.9, 0.6, 0.],
+        }
+    }
+
+    /// Someone level with you
+    pub fn neutral(self) -> [f32; 3] {
+        [1.; 3]
+    }
+
+    /// A checkpoint passed in order
+    pub fn passed(self) -> [f32; 3] {
+        match self {
+            Self::Standard => [0.2, 1., 0.2],
+            Self::Colorblind => [0.35, 0.7, 0.9],
+        }
+    }
+
+    /// A checkpoint reached having skipped one
+    pub fn missed(self) -> [f32; 3] {
+        match self {
+            Self::Standard => [1., 0.2, 0.2],
+            Self::Colorblind => [0.8, 0.4, 0.],
+        }
+    }
+}
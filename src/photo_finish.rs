@@ -0,0 +1,134 @@
+//! A moment's look at each finish from beside the line, as though for a finish photo
+use cimvr_common::{
+    glam::Vec3,
+    render::{MeshHandle, Primitive, Render, UploadMesh},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{
+    controls::TRACK_WIDTH, hud::HudVisibility, race_rules::format_race_time, shapes::panel_mesh,
+    trackside::look_at, PhotoFinish, ServerShipComponent,
+};
+
+/// How long the view stays on the finish line (seconds)
+const PHOTO_TIME: f32 = 0.7;
+/// Distance of the camera past the line, looking back down the straight (meters)
+const CAMERA_AHEAD: f32 = 40.;
+/// Height of the camera above the track (meters)
+const CAMERA_HEIGHT: f32 = 6.;
+/// Distance of the camera outside the edge of the track (meters)
+const CAMERA_OUTSET: f32 = 4.;
+/// Height of the nameplate above the ship (meters)
+const NAMEPLATE_HEIGHT: f32 = 3.;
+const NAMEPLATE_COLOR: [f32; 3] = [1., 1., 1.];
+
+/// The finish line camera, while it's on, and a nameplate with the finishing time over the
+/// ship it's for
+pub struct PhotoFinishCamera {
+    /// Camera transform, and the time left looking through it
+    shot: Option<(Transform, f32)>,
+    /// Ship which just crossed the line
+    subject: Option<ClientId>,
+    nameplate: EntityId,
+}
+
+impl PhotoFinishCamera {
+    const NAMEPLATE_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("PhotoNameplate"));
+
+    pub fn new(io: &mut EngineIo) -> Self {
+        let nameplate = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(Self::nameplate_render(false))
+            .build();
+
+        Self {
+            shot: None,
+            subject: None,
+            nameplate,
+        }
+    }
+
+    fn nameplate_render(shown: bool) -> Render {
+        let render = Render::new(Self::NAMEPLATE_RDR).primitive(Primitive::Lines);
+        HudVisibility::render(shown, render)
+    }
+
+    /// Look at a finish. Another finish while the camera's still on keeps it on for longer,
+    /// rather than starting over.
+    pub fn trigger(&mut self, io: &mut EngineIo, photo: &PhotoFinish) {
+        let text = format!("{} {}", photo.username, format_race_time(photo.time));
+        let width = text.len() as f32 + 2.;
+        io.send(&UploadMesh {
+            mesh: panel_mesh(width, 1.5, &text, NAMEPLATE_COLOR),
+            id: Self::NAMEPLATE_RDR,
+        });
+        if self.subject.is_none() {
+            io.add_component(self.nameplate, Self::nameplate_render(true));
+        }
+        self.subject = Some(photo.client_id);
+
+        let camera_tf = match self.shot {
+            Some((camera_tf, _)) => camera_tf,
+            None => finish_camera(photo.transform),
+        };
+        self.shot = Some((camera_tf, PHOTO_TIME));
+    }
+
+    /// Camera transform to show this frame, if a finish is being looked at. Expects
+    /// "ClientShip" and "ServerShips" queries; `own_id` is ours while we're in a race.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        query: &mut QueryResult,
+        own_id: Option<ClientId>,
+        delta: f32,
+    ) -> Option<Transform> {
+        let (camera_tf, left) = self.shot?;
+        let left = left - delta;
+        if left <= 0. {
+            self.shot = None;
+            self.subject = None;
+            io.add_component(self.nameplate, Self::nameplate_render(false));
+            return None;
+        }
+        self.shot = Some((camera_tf, left));
+
+        // Our own ship is the one we drive, rather than the server's copy
+        let ship_tf = match self.subject {
+            Some(subject) if Some(subject) == own_id => query
+                .iter("ClientShip")
+                .next()
+                .map(|entity| query.read::<Transform>(entity)),
+            Some(subject) => query.iter("ServerShips").find_map(|entity| {
+                let shipc = query.read::<ServerShipComponent>(entity);
+                (shipc.client_id == subject).then(|| query.read::<Transform>(entity))
+            }),
+            None => None,
+        };
+        if let Some(ship_tf) = ship_tf {
+            // Turned the same way as the camera, so it faces it
+            let pos = ship_tf.pos + ship_tf.orient * Vec3::Y * NAMEPLATE_HEIGHT;
+            let tf = Transform::new()
+                .with_position(pos)
+                .with_rotation(camera_tf.orient);
+            io.add_component(self.nameplate, tf);
+        }
+
+        Some(camera_tf)
+    }
+}
+
+/// Beside the track just past the line at `finish`, looking back at it
+fn finish_camera(finish: Transform) -> Transform {
+    let offset = Vec3::new(
+        CAMERA_AHEAD,
+        CAMERA_HEIGHT,
+        TRACK_WIDTH / 2. + CAMERA_OUTSET,
+    );
+    let pos = finish.pos + finish.orient * offset;
+    Transform::new()
+        .with_position(pos)
+        .with_rotation(look_at(finish.pos - pos, finish.orient * Vec3::Y))
+}
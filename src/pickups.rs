@@ -0,0 +1,40 @@
+use cimvr_common::{glam::Vec3, Transform};
+
+use crate::{controls::TRACK_WIDTH, curve::Curve, race_rng::RaceRng};
+
+/// Number of pickups placed around the track each race
+const N_PICKUPS: usize = 8;
+
+/// Height of pickups above the track (meters)
+const PICKUP_HEIGHT: f32 = 1.;
+
+/// Where a single pickup sits on the track
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PickupSpawn {
+    pub id: u32,
+    /// Position along the curve
+    pub t: f32,
+    /// Offset across the track from the center line (meters)
+    pub lateral: f32,
+}
+
+impl PickupSpawn {
+    pub fn transform(&self, curve: &Curve) -> Transform {
+        let offset = Transform::new().with_position(Vec3::new(0., PICKUP_HEIGHT, self.lateral));
+        curve.lerp(self.t) * offset
+    }
+}
+
+/// Lay out this race's pickups. Every participant computes the same layout from the race seed.
+pub fn pickup_layout(curve: &Curve, rng: &RaceRng) -> Vec<PickupSpawn> {
+    let mut rng = rng.stream("items");
+    let max_lateral = TRACK_WIDTH / 2. * 0.6;
+
+    (0..N_PICKUPS as u32)
+        .map(|id| PickupSpawn {
+            id,
+            t: rng.range(0., curve.ctrlps.len() as f32),
+            lateral: rng.range(-max_lateral, max_lateral),
+        })
+        .collect()
+}
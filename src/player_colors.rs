@@ -0,0 +1,46 @@
+//! A color for each player, so that their ship can be told apart from everyone else's
+use cimvr_engine_interface::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::strings::Key;
+
+/// Colors handed out to players, with their names
+const PALETTE: [([f32; 3], Key); 8] = [
+    ([1., 0.3, 0.3], Key::ColorRed),
+    ([1., 0.6, 0.2], Key::ColorOrange),
+    ([1., 1., 0.3], Key::ColorYellow),
+    ([0.3, 1., 0.4], Key::ColorGreen),
+    ([0.3, 1., 1.], Key::ColorCyan),
+    ([0.4, 0.5, 1.], Key::ColorBlue),
+    ([0.7, 0.4, 1.], Key::ColorPurple),
+    ([1., 0.5, 0.8], Key::ColorPink),
+];
+
+/// A player's color, as an index into the palette
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlayerColor(u8);
+
+impl PlayerColor {
+    /// Pick a color for a new connection: `preferred` if they had one before, otherwise one
+    /// from the client's own place in the palette, moved on past any in `taken`. Once every
+    /// color is taken, they're shared.
+    pub fn assign(client_id: ClientId, preferred: Option<Self>, taken: &[Self]) -> Self {
+        if let Some(color) = preferred.filter(|color| !taken.contains(color)) {
+            return color;
+        }
+        let n = PALETTE.len();
+        let start = client_id.0 as usize % n;
+        (0..n)
+            .map(|offset| Self(((start + offset) % n) as u8))
+            .find(|color| !taken.contains(color))
+            .unwrap_or(Self(start as u8))
+    }
+
+    pub fn rgb(self) -> [f32; 3] {
+        PALETTE[self.0 as usize % PALETTE.len()].0
+    }
+
+    pub fn name(self) -> Key {
+        PALETTE[self.0 as usize % PALETTE.len()].1
+    }
+}
@@ -0,0 +1,197 @@
+//! Everyone's position lap by lap, charted over the finish line once a race is over
+use cimvr_common::{
+    glam::{Quat, Vec3},
+    render::{Mesh, MeshHandle, Primitive, Render, UploadMesh, Vertex},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{
+    controls::TRACK_HEIGHT,
+    countdown::color_extra,
+    curve::Curve,
+    shapes::{text_mesh, text_width},
+    RaceResults,
+};
+
+/// Size of the plot area (meters)
+const CHART_WIDTH: f32 = 40.;
+const CHART_HEIGHT: f32 = 20.;
+/// Height of the chart's middle above the top of the track (meters)
+const CHART_LIFT: f32 = 24.;
+/// Height of the lettering (meters)
+const LABEL_HEIGHT: f32 = 1.2;
+/// Space between the plot and its labels (meters)
+const LABEL_GAP: f32 = 1.;
+/// Half the length of the mark for a single lap (meters)
+const TICK: f32 = 1.;
+const AXIS_COLOR: [f32; 3] = [0.6, 0.6, 0.6];
+/// Racers' lines, in finishing order, round again after the last
+const RACER_COLORS: [[f32; 3]; 6] = [
+    [1., 1., 0.],
+    [0., 1., 1.],
+    [1., 0., 1.],
+    [0., 1., 0.],
+    [1., 0.5, 0.],
+    [0.5, 0.5, 1.],
+];
+
+/// Each racer's position at the end of each lap they completed, counting from 1, in the same
+/// order as the results. Positions on a lap only count those who completed it.
+fn lap_positions(RaceResults(racers): &RaceResults) -> Vec<Vec<usize>> {
+    let mut positions = vec![vec![]; racers.len()];
+    let laps = racers.iter().map(|r| r.completed.len()).max().unwrap_or(0);
+    for lap in 0..laps {
+        let mut order: Vec<(usize, f32)> = racers
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, racer)| Some((idx, *racer.completed.get(lap)?)))
+            .collect();
+        order.sort_by(|a, b| a.1.total_cmp(&b.1));
+        for (place, (idx, _)) in order.into_iter().enumerate() {
+            positions[idx].push(place + 1);
+        }
+    }
+    positions
+}
+
+/// The chart: laps along X, first place at the top, with a line per racer and their names
+/// down the right edge, centered on the origin in the XY plane and facing +Z
+fn chart_mesh(results: &RaceResults) -> Mesh {
+    let RaceResults(racers) = results;
+    let positions = lap_positions(results);
+    let laps = positions.iter().map(Vec::len).max().unwrap_or(0);
+    let places = racers.len();
+
+    let (half_w, half_h) = (CHART_WIDTH / 2., CHART_HEIGHT / 2.);
+    // A single lap, or a single racer, sits in the middle
+    let x = |lap: usize| match laps {
+        0 | 1 => 0.,
+        _ => -half_w + CHART_WIDTH * lap as f32 / (laps - 1) as f32,
+    };
+    let y = |place: usize| match places {
+        0 | 1 => 0.,
+        _ => half_h - CHART_HEIGHT * (place - 1) as f32 / (places - 1) as f32,
+    };
+
+    let mut m = Mesh::new();
+
+    // Axes, with the laps numbered underneath
+    let (left, bottom) = (-half_w - LABEL_GAP, -half_h - LABEL_GAP);
+    push_line(
+        &mut m,
+        [left, bottom],
+        [half_w + LABEL_GAP, bottom],
+        AXIS_COLOR,
+    );
+    push_line(
+        &mut m,
+        [left, bottom],
+        [left, half_h + LABEL_GAP],
+        AXIS_COLOR,
+    );
+    for lap in 0..laps {
+        let number = (lap + 1).to_string();
+        let number_x = x(lap) - text_width(&number, LABEL_HEIGHT) / 2.;
+        let number_y = bottom - LABEL_GAP - LABEL_HEIGHT;
+        push_label(&mut m, &number, [number_x, number_y], AXIS_COLOR);
+    }
+
+    for (idx, (racer, places)) in racers.iter().zip(&positions).enumerate() {
+        let color = RACER_COLORS[idx % RACER_COLORS.len()];
+        let points: Vec<[f32; 2]> = places
+            .iter()
+            .enumerate()
+            .map(|(lap, &place)| [x(lap), y(place)])
+            .collect();
+        let Some(&[end_x, end_y]) = points.last() else {
+            continue;
+        };
+
+        match points[..] {
+            // Nothing to join, so mark the place
+            [[px, py]] => push_line(&mut m, [px - TICK, py], [px + TICK, py], color),
+            _ => {
+                for pair in points.windows(2) {
+                    push_line(&mut m, pair[0], pair[1], color);
+                }
+            }
+        }
+
+        // Names go at the right edge, level with where each line ended
+        let name = match places.len() == laps {
+            true => racer.name.clone(),
+            false => format!("{} (DNF)", racer.name),
+        };
+        let label_x = half_w.max(end_x + TICK) + LABEL_GAP;
+        push_label(&mut m, &name, [label_x, end_y - LABEL_HEIGHT / 2.], color);
+    }
+
+    m
+}
+
+fn push_line(m: &mut Mesh, a: [f32; 2], b: [f32; 2], color: [f32; 3]) {
+    let v0 = m.push_vertex(Vertex::new([a[0], a[1], 0.], color));
+    let v1 = m.push_vertex(Vertex::new([b[0], b[1], 0.], color));
+    m.indices.extend([v0, v1]);
+}
+
+/// Add `text` to `m` with its bottom left corner `at`
+fn push_label(m: &mut Mesh, text: &str, at: [f32; 2], color: [f32; 3]) {
+    let label = text_mesh(text, LABEL_HEIGHT, color);
+    let base = m.vertices.len() as u32;
+    for v in &label.vertices {
+        let pos = [v.pos[0] + at[0], v.pos[1] + at[1], v.pos[2]];
+        m.vertices.push(Vertex::new(pos, v.uvw));
+    }
+    m.indices.extend(label.indices.iter().map(|i| base + i));
+}
+
+/// The chart of the last race, if it's up
+pub struct PositionGraph {
+    entity: Option<EntityId>,
+}
+
+impl PositionGraph {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("PositionGraph"));
+
+    pub fn new() -> Self {
+        Self { entity: None }
+    }
+
+    /// Put up the chart for `results` over the finish line of `path`, facing oncoming ships
+    pub fn show(&mut self, io: &mut EngineIo, path: &Curve, results: &RaceResults) {
+        self.hide(io);
+        let RaceResults(racers) = results;
+        if racers.is_empty() {
+            return;
+        }
+
+        io.send(&UploadMesh {
+            mesh: chart_mesh(results),
+            id: Self::RDR_ID,
+        });
+
+        // The chart faces +Z; the track runs along local +X
+        let finish = path.lerp(path.finish_line());
+        let lift = TRACK_HEIGHT / 2. + CHART_LIFT;
+        let tf = Transform::new()
+            .with_position(finish.pos + finish.orient * Vec3::new(0., lift, 0.))
+            .with_rotation(finish.orient * Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2));
+
+        let entity = io
+            .create_entity()
+            .add_component(tf)
+            .add_component(Render::new(Self::RDR_ID).primitive(Primitive::Lines))
+            .add_component(color_extra([1.; 3]))
+            .build();
+        self.entity = Some(entity);
+    }
+
+    /// Take the chart down, e.g. when the next race starts
+    pub fn hide(&mut self, io: &mut EngineIo) {
+        if let Some(entity) = self.entity.take() {
+            io.remove_entity(entity);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+
+use crate::{
+    controls::{ControlEvent, ShipController, ShipState},
+    kinematics::FixedStep,
+    InputAbstraction, InputUpload, ShipStateAck,
+};
+
+/// Client-side prediction for authoritative races.
+/// Inputs are applied locally right away, and replayed on top of the server's state when it arrives.
+#[derive(Default)]
+pub struct Prediction {
+    /// Sequence number of the last input sent
+    seq: u32,
+    /// Inputs which the server has not yet acknowledged
+    pending: VecDeque<InputUpload>,
+    fixed: FixedStep,
+}
+
+impl Prediction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget everything; used when a new race starts
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Run as many fixed steps as fit into `delta`, returning the inputs to upload
+    /// and what happened to the ship
+    pub fn step(
+        &mut self,
+        controller: &ShipController,
+        delta: f32,
+        input: InputAbstraction,
+        state: &mut ShipState,
+    ) -> (Vec<InputUpload>, Vec<ControlEvent>) {
+        let mut uploads = vec![];
+        let mut events = vec![];
+        for _ in 0..self.fixed.steps(delta) {
+            self.seq += 1;
+            let upload = InputUpload {
+                seq: self.seq,
+                input,
+            };
+
+            let (new_state, step_events) = controller.fixed_step(input, *state);
+            *state = new_state;
+            events.extend(step_events);
+
+            self.pending.push_back(upload);
+            uploads.push(upload);
+        }
+        (uploads, events)
+    }
+
+    /// Adopt the server's state, then replay the inputs it hasn't seen yet
+    pub fn reconcile(&mut self, controller: &ShipController, ack: ShipStateAck) -> ShipState {
+        while self.pending.front().map_or(false, |p| p.seq <= ack.seq) {
+            self.pending.pop_front();
+        }
+
+        let mut state = ShipState {
+            tf: ack.tf,
+            kt: ack.kt,
+            wall_ride: ack.wall_ride,
+            boost_cooldown: ack.boost_cooldown,
+        };
+        for pending in &self.pending {
+            (state, _) = controller.fixed_step(pending.input, state);
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cimvr_common::glam::Vec3;
+
+    use super::*;
+    use crate::{
+        controls::{ControlScheme, TuningParams},
+        curve::Curve,
+        harness::{grid_start, loop1, RandomInput},
+        kinematics::FIXED_DT,
+        race_rng::RaceRng,
+        ShipCharacteristics,
+    };
+
+    const SEEDS: u64 = 4;
+    /// Frames in each run, of between nothing and `MAX_FRAME` fixed steps each
+    const FRAMES: usize = 200;
+    const MAX_FRAME: f32 = 5. * FIXED_DT;
+
+    fn controller(curve: &Curve) -> ShipController {
+        ShipController::new(
+            ShipCharacteristics::standard(),
+            TuningParams::default(),
+            curve,
+        )
+    }
+
+    /// One fixed step per input, straight through the controller
+    fn replay(controller: &ShipController, start: ShipState, uploads: &[InputUpload]) -> ShipState {
+        uploads.iter().fold(start, |state, upload| {
+            controller.fixed_step(upload.input, state).0
+        })
+    }
+
+    /// Frames of random lengths and inputs, returning every upload and the predicted state
+    fn predict(
+        controller: &ShipController,
+        prediction: &mut Prediction,
+        seed: u64,
+        start: ShipState,
+    ) -> (Vec<InputUpload>, ShipState) {
+        let mut rng = RaceRng::new(seed).stream("frames");
+        let mut input = RandomInput::new(seed, ControlScheme::Banked);
+        let (mut uploads, mut state) = (vec![], start);
+        for _ in 0..FRAMES {
+            let delta = rng.range(0., MAX_FRAME);
+            let (sent, _) = prediction.step(controller, delta, input.next(), &mut state);
+            uploads.extend(sent);
+        }
+        (uploads, state)
+    }
+
+    #[test]
+    fn chunking_frames_differently_gives_the_same_states() {
+        let curve = loop1();
+        let controller = controller(&curve);
+        let start = grid_start(&curve);
+        for seed in 0..SEEDS {
+            let mut prediction = Prediction::new();
+            let (uploads, state) = predict(&controller, &mut prediction, seed, start);
+            assert!(!uploads.is_empty());
+            for (k, upload) in uploads.iter().enumerate() {
+                assert_eq!(upload.seq, k as u32 + 1, "seed {}", seed);
+            }
+
+            // However the steps were grouped into frames, the same inputs give the same bits
+            assert_eq!(state, replay(&controller, start, &uploads), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn reconcile_replays_unacknowledged_inputs_onto_the_server_state() {
+        let curve = loop1();
+        let controller = controller(&curve);
+        let start = grid_start(&curve);
+        for seed in 0..SEEDS {
+            let mut prediction = Prediction::new();
+            let (uploads, predicted) = predict(&controller, &mut prediction, seed, start);
+            let acked = uploads.len() / 2;
+            let ack = |state: ShipState| ShipStateAck {
+                seq: uploads[acked - 1].seq,
+                tf: state.tf,
+                kt: state.kt,
+                wall_ride: state.wall_ride,
+                boost_cooldown: state.boost_cooldown,
+            };
+
+            // Agreeing with the prediction puts the ship just where it already was
+            let server = replay(&controller, start, &uploads[..acked]);
+            assert_eq!(prediction.reconcile(&controller, ack(server)), predicted);
+
+            // Disagreeing replays the rest from where the server says the ship was
+            let mut corrected = server;
+            corrected.tf.pos += Vec3::Y;
+            corrected.kt.vel *= 0.5;
+            let replayed = prediction.reconcile(&controller, ack(corrected));
+            assert_eq!(replayed, replay(&controller, corrected, &uploads[acked..]));
+            assert_ne!(replayed, predicted);
+        }
+    }
+
+    #[test]
+    fn reconcile_of_the_latest_input_is_the_server_state() {
+        let curve = loop1();
+        let controller = controller(&curve);
+        let mut prediction = Prediction::new();
+        let (uploads, predicted) = predict(&controller, &mut prediction, 0, grid_start(&curve));
+        let ack = ShipStateAck {
+            seq: uploads.last().unwrap().seq,
+            tf: predicted.tf,
+            kt: predicted.kt,
+            wall_ride: predicted.wall_ride,
+            boost_cooldown: predicted.boost_cooldown,
+        };
+        assert_eq!(prediction.reconcile(&controller, ack), predicted);
+        assert!(prediction.pending.is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+//! A record of what happened on the server, one `key=value` line per event, for operators and
+//! their tools
+use std::{collections::VecDeque, fmt::Display};
+
+/// Oldest lines are dropped beyond this many
+const MAX_LINES: usize = 5000;
+/// Lines sent in each `LogDump`
+pub const LINES_PER_DUMP: usize = 50;
+
+/// Recent events, oldest first. Each line is `t=<seconds> event=<name>` followed by the
+/// event's fields, with values containing spaces, quotes or `=` quoted.
+#[derive(Default)]
+pub struct RaceLog {
+    lines: VecDeque<String>,
+    /// Server time to stamp events with
+    now: f32,
+    /// Lines dropped to make room, since the server started
+    dropped: usize,
+}
+
+impl RaceLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stamp events from here on with server time `now`
+    pub fn tick(&mut self, now: f32) {
+        self.now = now;
+    }
+
+    pub fn push(&mut self, event: &str, fields: &[(&str, &dyn Display)]) {
+        let mut line = format!("t={:.2} event={}", self.now, event);
+        for (key, value) in fields {
+            line.push_str(&format!(" {}={}", key, quote(&value.to_string())));
+        }
+
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Everything held, oldest first, starting with a note of anything already dropped
+    pub fn lines(&self) -> Vec<String> {
+        let dropped = self.dropped;
+        let note = (dropped > 0).then(|| format!("event=truncated dropped={}", dropped));
+        note.into_iter().chain(self.lines.iter().cloned()).collect()
+    }
+}
+
+/// `value`, quoted if it wouldn't otherwise read back as one value
+fn quote(value: &str) -> String {
+    let plain = !value.is_empty() && !value.contains([' ', '"', '=', '\\']);
+    match plain {
+        true => value.to_string(),
+        false => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_scripted_race_logs_its_events_in_order() {
+        let mut log = RaceLog::new();
+        log.tick(12.);
+        log.push("start", &[("race", &1), ("laps", &3), ("racers", &2)]);
+        log.tick(75.5);
+        log.push(
+            "finish",
+            &[("race", &1), ("name", &"ann"), ("position", &1)],
+        );
+        log.tick(80.25);
+        log.push(
+            "finish",
+            &[("race", &1), ("name", &"bo b"), ("position", &2)],
+        );
+        log.push("reset", &[("race", &1)]);
+
+        assert_eq!(
+            log.lines(),
+            [
+                "t=12.00 event=start race=1 laps=3 racers=2",
+                "t=75.50 event=finish race=1 name=ann position=1",
+                "t=80.25 event=finish race=1 name=\"bo b\" position=2",
+                "t=80.25 event=reset race=1",
+            ]
+        );
+    }
+
+    #[test]
+    fn awkward_values_are_quoted() {
+        let mut log = RaceLog::new();
+        log.push(
+            "chat",
+            &[
+                ("empty", &""),
+                ("equals", &"a=b"),
+                ("quotes", &"say \"hi\""),
+                ("slash", &"C:\\"),
+            ],
+        );
+        assert_eq!(
+            log.lines(),
+            [r#"t=0.00 event=chat empty="" equals="a=b" quotes="say \"hi\"" slash="C:\\""#]
+        );
+    }
+
+    #[test]
+    fn the_oldest_lines_make_room_and_are_noted() {
+        let mut log = RaceLog::new();
+        for i in 0..MAX_LINES + 3 {
+            log.push("lap", &[("n", &i)]);
+        }
+        let lines = log.lines();
+        assert_eq!(lines.len(), MAX_LINES + 1);
+        assert_eq!(lines[0], "event=truncated dropped=3");
+        assert_eq!(lines[1], "t=0.00 event=lap n=3");
+        assert_eq!(
+            lines.last().unwrap(),
+            &format!("t=0.00 event=lap n={}", MAX_LINES + 2)
+        );
+    }
+}
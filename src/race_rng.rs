@@ -0,0 +1,136 @@
+/// Deterministic random numbers (PCG32) shared by every participant in a race.
+/// The server picks the seed at race start, and everyone constructs identical streams from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RaceRng {
+    seed: u64,
+    state: u64,
+    inc: u64,
+}
+
+const PCG_MULTIPLIER: u64 = 6364136223846793005;
+
+impl RaceRng {
+    pub fn new(seed: u64) -> Self {
+        Self::with_stream(seed, 0)
+    }
+
+    fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            seed,
+            state: 0,
+            // The increment must be odd
+            inc: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(splitmix64(seed));
+        rng.next_u32();
+        rng
+    }
+
+    /// An independent sub-stream for the named feature, so that one feature drawing numbers
+    /// doesn't perturb any other. Only depends on the seed, not on numbers drawn so far.
+    pub fn stream(&self, name: &str) -> Self {
+        let name_hash = fnv1a(name.as_bytes());
+        Self::with_stream(splitmix64(self.seed ^ name_hash), name_hash)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Uniformly distributed in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        // Use the top 24 bits, which is all the precision an f32 mantissa holds
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Uniformly distributed in [0, 1), and always the same for the same `index`, however many
+    /// numbers have been drawn
+    pub fn value_at(&self, index: u64) -> f32 {
+        let bits = splitmix64(self.seed ^ splitmix64(index));
+        (bits >> 40) as f32 / (1 << 24) as f32
+    }
+
+    /// Uniformly distributed in [min, max)
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(rng: &mut RaceRng, n: usize) -> Vec<u32> {
+        (0..n).map(|_| rng.next_u32()).collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_numbers() {
+        let (mut a, mut b) = (RaceRng::new(42), RaceRng::new(42));
+        assert_eq!(draw(&mut a, 100), draw(&mut b, 100));
+        assert_ne!(
+            draw(&mut RaceRng::new(42), 100),
+            draw(&mut RaceRng::new(43), 100)
+        );
+    }
+
+    #[test]
+    fn streams_depend_only_on_the_seed_and_name() {
+        let rng = RaceRng::new(7);
+        let mut used = rng;
+        draw(&mut used, 10);
+        let mut items = rng.stream("items");
+        assert_eq!(draw(&mut items, 50), draw(&mut used.stream("items"), 50));
+
+        // Drawing from one stream leaves the others alone
+        let mut ai = rng.stream("ai");
+        draw(&mut rng.stream("items"), 1000);
+        assert_eq!(draw(&mut ai, 50), draw(&mut rng.stream("ai"), 50));
+
+        assert_ne!(
+            draw(&mut rng.stream("items"), 50),
+            draw(&mut rng.stream("ai"), 50)
+        );
+        assert_ne!(
+            draw(&mut rng.stream("items"), 50),
+            draw(&mut RaceRng::new(8).stream("items"), 50)
+        );
+    }
+
+    #[test]
+    fn values_at_an_index_ignore_numbers_drawn() {
+        let rng = RaceRng::new(3);
+        let mut used = rng;
+        draw(&mut used, 10);
+        for index in 0..100 {
+            assert_eq!(rng.value_at(index), used.value_at(index));
+        }
+    }
+
+    #[test]
+    fn floats_are_in_range() {
+        let mut rng = RaceRng::new(1);
+        for _ in 0..10_000 {
+            assert!((0. ..1.).contains(&rng.next_f32()));
+            assert!((-2. ..5.).contains(&rng.range(-2., 5.)));
+        }
+    }
+}
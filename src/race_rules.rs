@@ -0,0 +1,652 @@
+//! Race rules shared verbatim by the client and server. Nothing in here touches the engine.
+use cimvr_common::{glam::Vec3, Transform};
+
+use crate::{controls::TRACK_WIDTH, curve::Curve};
+
+/// Number of checkpoints between finish line crossings
+pub const N_CHECKPOINTS: usize = 3;
+
+/// Crossings only count within this distance along the track of the plane, since the plane
+/// extends infinitely and other parts of the track may pass through it (meters)
+const SANITY_DISTANCE: f32 = 25.;
+/// Greatest fraction of the track the sanity window may cover, on each side of the plane
+const MAX_SANITY_FRACTION: f32 = 1. / 8.;
+
+/// Which way something crossed a plane
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Crossing {
+    Forward,
+    Backward,
+}
+
+/// A plane across the track, facing along it
+#[derive(Clone, Copy, Debug)]
+pub struct FinishPlane {
+    pub transform: Transform,
+    /// Curve position of the plane
+    pub t: f32,
+}
+
+impl FinishPlane {
+    pub fn new(curve: &Curve, t: f32) -> Self {
+        Self {
+            transform: curve.smooth_lerp(t),
+            t,
+        }
+    }
+
+    /// How (if at all) moving from `prev` to `pos` crosses this plane
+    pub fn crossing(&self, curve: &Curve, prev: Vec3, pos: Vec3) -> Option<Crossing> {
+        // Measured from the nearest point on the curve, not the nearest control point, which on
+        // a track of long segments may be further from the plane than the whole window
+        let window = curve.clamp_distance(SANITY_DISTANCE, MAX_SANITY_FRACTION);
+        let nearby = curve.loop_distance(curve.nearest_t(pos), self.t) < window;
+        if !nearby {
+            return None;
+        }
+
+        let inv = self.transform.inverse();
+        let before = (inv * Transform::new().with_position(prev)).pos.x;
+        let after = (inv * Transform::new().with_position(pos)).pos.x;
+
+        if before <= 0. && after > 0. {
+            Some(Crossing::Forward)
+        } else if before > 0. && after <= 0. {
+            Some(Crossing::Backward)
+        } else {
+            None
+        }
+    }
+}
+
+/// Something which happened to a racer, as determined by the `LapTracker`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LapEvent {
+    /// Crossed the finish line and began the given lap. The first crossing, from the grid, is lap 1.
+    Lap(usize),
+    /// Passed the given checkpoint in order
+    Checkpoint(usize),
+    /// Reached a checkpoint having skipped the one expected
+    MissedCheckpoint { expected: usize, reached: usize },
+    /// Crossed the finish line without passing every checkpoint; the lap doesn't count
+    IncompleteLap,
+    /// Crossed the finish line going the wrong way
+    Backwards,
+}
+
+/// Counts laps and checkpoints from a racer's successive positions
+#[derive(Clone, Debug)]
+pub struct LapTracker {
+    finish: FinishPlane,
+    checkpoints: Vec<FinishPlane>,
+    /// Number of finish line crossings which counted
+    lap: usize,
+    /// Index of the next checkpoint to pass
+    next_checkpoint: usize,
+    /// Whether a checkpoint was skipped this lap
+    missed: bool,
+    /// Forward crossings owed after crossing the finish line backwards
+    backwards_crossings: usize,
+    last_pos: Option<Vec3>,
+    /// Curve index nearest `last_pos`, followed from one update to the next
+    last_t: Option<f32>,
+}
+
+impl LapTracker {
+    pub fn new(curve: &Curve) -> Self {
+        // Evenly spaced by distance, however the control points are spread
+        let checkpoints = (1..=N_CHECKPOINTS)
+            .map(|k| {
+                let dist = curve.length() * k as f32 / (N_CHECKPOINTS + 1) as f32;
+                FinishPlane::new(curve, curve.advance(curve.finish_line(), dist))
+            })
+            .collect();
+
+        Self {
+            finish: FinishPlane::new(curve, curve.finish_line()),
+            checkpoints,
+            lap: 0,
+            next_checkpoint: 0,
+            missed: false,
+            backwards_crossings: 0,
+            last_pos: None,
+            last_t: None,
+        }
+    }
+
+    pub fn finish(&self) -> &FinishPlane {
+        &self.finish
+    }
+
+    pub fn checkpoints(&self) -> &[FinishPlane] {
+        &self.checkpoints
+    }
+
+    /// Number of finish line crossings which counted so far
+    pub fn lap(&self) -> usize {
+        self.lap
+    }
+
+    /// Number of checkpoints passed in order on the current lap
+    pub fn checkpoints_passed(&self) -> usize {
+        self.next_checkpoint
+    }
+
+    /// Whether the racer has completed `n_laps` full laps after leaving the grid
+    pub fn finished(&self, n_laps: usize) -> bool {
+        self.lap > n_laps
+    }
+
+    /// Distance covered (in control points, and fractions of them, for telling apart racers
+    /// side by side) since first crossing the finish line. Between the finish line and the first checkpoint it goes negative just
+    /// behind the line; so does anywhere behind it on the grid.
+    pub fn fine_progress(&self, curve: &Curve) -> f32 {
+        let n = curve.ctrlps.len() as f32;
+        let Some(pos) = self.last_pos else { return 0. };
+        let t = self.last_t.unwrap_or_else(|| curve.nearest_t(pos));
+
+        // Which side of the line we're on, where the curve index alone can't tell
+        let mut past_finish = (t - curve.finish_line()).rem_euclid(n);
+        if self.next_checkpoint == 0 && past_finish > n / 2. {
+            past_finish -= n;
+        } else if self.next_checkpoint == self.checkpoints.len() && past_finish < n / 2. {
+            past_finish += n;
+        }
+        self.lap.saturating_sub(1) as f32 * n + past_finish
+    }
+
+    /// Start over from the grid at `pos`
+    pub fn reset(&mut self, pos: Vec3) {
+        self.lap = 0;
+        self.next_checkpoint = 0;
+        self.missed = false;
+        self.backwards_crossings = 0;
+        self.last_pos = Some(pos);
+        self.last_t = None;
+    }
+
+    /// Pick up part way through a race, e.g. after reconnecting
+    pub fn resume(&mut self, lap: usize, checkpoints_passed: usize) {
+        self.lap = lap;
+        self.next_checkpoint = checkpoints_passed;
+    }
+
+    /// Account for the racer having moved to `pos`
+    pub fn update(&mut self, curve: &Curve, pos: Vec3) -> Vec<LapEvent> {
+        let mut events = vec![];
+        self.last_t = Some(match self.last_t {
+            Some(last) => curve.nearest_t_from(pos, last),
+            None => curve.nearest_t(pos),
+        });
+        let Some(prev) = self.last_pos.replace(pos) else {
+            return events;
+        };
+
+        // A deep grid, such as a rolling start's, may sit behind the last checkpoint
+        let checkpoints = match self.lap {
+            0 => &[][..],
+            _ => &self.checkpoints[..],
+        };
+        for (idx, checkpoint) in checkpoints.iter().enumerate() {
+            match checkpoint.crossing(curve, prev, pos) {
+                Some(Crossing::Forward) if idx == self.next_checkpoint => {
+                    self.next_checkpoint += 1;
+                    events.push(LapEvent::Checkpoint(idx));
+                }
+                Some(Crossing::Forward) if idx > self.next_checkpoint => {
+                    events.push(LapEvent::MissedCheckpoint {
+                        expected: self.next_checkpoint,
+                        reached: idx,
+                    });
+                    self.missed = true;
+                    self.next_checkpoint = idx + 1;
+                }
+                Some(Crossing::Backward) if idx + 1 == self.next_checkpoint => {
+                    self.next_checkpoint = idx;
+                }
+                _ => (),
+            }
+        }
+
+        match self.finish.crossing(curve, prev, pos) {
+            Some(Crossing::Forward) if self.backwards_crossings > 0 => {
+                // Just undoing a backwards crossing
+                self.backwards_crossings -= 1;
+            }
+            Some(Crossing::Forward) => {
+                // Leaving the grid needs no checkpoints
+                let complete = self.lap == 0
+                    || (!self.missed && self.next_checkpoint == self.checkpoints.len());
+                if complete {
+                    self.lap += 1;
+                    events.push(LapEvent::Lap(self.lap));
+                } else {
+                    events.push(LapEvent::IncompleteLap);
+                }
+                self.next_checkpoint = 0;
+                self.missed = false;
+            }
+            Some(Crossing::Backward) => {
+                self.backwards_crossings += 1;
+                events.push(LapEvent::Backwards);
+            }
+            None => (),
+        }
+
+        events
+    }
+}
+
+/// Most thrust catch-up may add or take away, as a fraction, whatever strength it's set to
+pub const MAX_CATCH_UP: f32 = 0.1;
+
+/// Thrust multiplier for each racer, given their places in `order`, best first: from
+/// `1 - strength` for the leader, evenly up to `1 + strength` for last place. A racer on their
+/// own is left as they are.
+pub fn handicap_factors<K: Copy>(order: &[K], strength: f32) -> Vec<(K, f32)> {
+    let strength = strength.clamp(0., MAX_CATCH_UP);
+    let last = order.len().saturating_sub(1);
+    order
+        .iter()
+        .enumerate()
+        .map(|(place, &id)| {
+            let behind = match last {
+                0 => 0.5,
+                _ => place as f32 / last as f32,
+            };
+            (id, 1. + strength * (2. * behind - 1.))
+        })
+        .collect()
+}
+
+/// Places in the race, best first: those who have `finished`, in the order they did, then
+/// everyone else by `progress` (`LapTracker::fine_progress`). Racers level on progress keep
+/// their order from `previous`, so that nobody flickers between places.
+pub fn race_order<K: Copy + PartialEq>(
+    previous: &[K],
+    finished: &[K],
+    progress: &[(K, f32)],
+) -> Vec<K> {
+    let mut racing: Vec<(K, f32)> = previous
+        .iter()
+        .filter_map(|id| progress.iter().find(|(other, _)| other == id))
+        .chain(progress.iter().filter(|(id, _)| !previous.contains(id)))
+        .filter(|(id, _)| !finished.contains(id))
+        .copied()
+        .collect();
+    // Stable, so ties stay as they were
+    racing.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    finished
+        .iter()
+        .copied()
+        .chain(racing.into_iter().map(|(id, _)| id))
+        .collect()
+}
+
+/// Race time as minutes, seconds and tenths, e.g. "2:41.3"
+pub fn format_race_time(seconds: f32) -> String {
+    let minutes = (seconds / 60.).floor();
+    format!("{}:{:04.1}", minutes, seconds - minutes * 60.)
+}
+
+/// Starting slots for a race, behind the finish line in two staggered columns
+pub struct GridLayout;
+
+/// Distance between rows of the grid (meters)
+const GRID_ROW_SPACING: f32 = 10.;
+/// Greatest fraction of the track the grid may occupy, for tiny tracks
+const MAX_GRID_FRACTION: f32 = 1. / 4.;
+/// Height of slots above the track surface, so ships don't start inside it (meters)
+const SPAWN_CLEARANCE: f32 = 0.25;
+/// Closest two slots may be, comfortably more than a ship's length (meters)
+const SLOT_SEPARATION: f32 = 6.;
+/// Distance a slot which conflicts is moved back along the track per attempt (meters)
+const SLOT_STEP: f32 = 1.;
+
+impl GridLayout {
+    pub fn compute(curve: &Curve, n: usize) -> Vec<Transform> {
+        Self::compute_behind(curve, n, 0.)
+    }
+
+    /// As `compute`, with the front of the grid a further `lead` behind the finish line
+    /// (meters), e.g. for a rolling start
+    pub fn compute_behind(curve: &Curve, n: usize, lead: f32) -> Vec<Transform> {
+        // The back of the right column is half a row behind the last row
+        let depth = (n + 1) / 2 + 1;
+        let spacing = GRID_ROW_SPACING.min(curve.length() * MAX_GRID_FRACTION / depth as f32);
+
+        // Slots pushed back any further would come round to just in front of the finish line
+        let finish = FinishPlane::new(curve, curve.finish_line());
+        let window = curve.clamp_distance(SANITY_DISTANCE, MAX_SANITY_FRACTION);
+        let max_behind = curve.length() - window;
+
+        let mut slots: Vec<Transform> = Vec::with_capacity(n);
+        for i in 0..n {
+            let row = i / 2;
+            let column = i % 2;
+
+            // Right column sits half a row further back
+            let mut behind = lead + spacing * (row as f32 + 1. + column as f32 / 2.);
+            let side = if column == 0 { -1. } else { 1. };
+
+            // Tight or banked corners can squeeze slots together, or round past the line
+            let mut slot = Self::slot(curve, behind, side);
+            while !Self::is_clear(curve, &finish, window, slot, &slots)
+                && behind + SLOT_STEP <= max_behind
+            {
+                behind += SLOT_STEP;
+                slot = Self::slot(curve, behind, side);
+            }
+            slots.push(slot);
+        }
+        slots
+    }
+
+    /// Slot `behind` the finish line, on the left (-1) or right (+1) of the track, lifted clear
+    /// of the surface
+    fn slot(curve: &Curve, behind: f32, side: f32) -> Transform {
+        let t = curve.advance(curve.finish_line(), -behind);
+        let offset = Vec3::new(0., 0., side * TRACK_WIDTH / 4.);
+        let naive = curve.lerp(t) * Transform::new().with_position(offset);
+
+        let (surface, _) = curve.project(naive.pos);
+        let up = surface.orient * Vec3::Y;
+        Transform::new()
+            .with_position(surface.pos + up * SPAWN_CLEARANCE)
+            .with_rotation(naive.orient)
+    }
+
+    /// Whether `slot` keeps its distance from those already `placed`, and doesn't start on or
+    /// past the finish line, which would count a lap straight away
+    fn is_clear(
+        curve: &Curve,
+        finish: &FinishPlane,
+        window: f32,
+        slot: Transform,
+        placed: &[Transform],
+    ) -> bool {
+        let near_finish = curve.loop_distance(curve.nearest_t(slot.pos), finish.t) < window;
+        let ahead = (finish.transform.inverse() * slot).pos.x;
+        if near_finish && ahead > -SLOT_SEPARATION / 2. {
+            return false;
+        }
+
+        placed
+            .iter()
+            .all(|other| other.pos.distance(slot.pos) >= SLOT_SEPARATION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        controls::{ControlOutput, ShipController, TuningParams},
+        harness::{loop1, ring},
+        ShipCharacteristics,
+    };
+
+    /// Control points of `lap_ring`, so that the checkpoints are at 4, 8 and 12
+    const RING_POINTS: usize = 16;
+    /// Distance moved along the track per update (control points)
+    const STEP: f32 = 0.1;
+
+    fn lap_ring() -> Curve {
+        ring(RING_POINTS, 100.)
+    }
+
+    /// A racer `side` meters across the track, at curve position `t`
+    fn on_track(curve: &Curve, t: f32, side: f32) -> Vec3 {
+        (curve.smooth_lerp(t) * Transform::new().with_position(Vec3::Z * side)).pos
+    }
+
+    /// Move `tracker` along the curve from `from` to `to`, either way, collecting its events.
+    /// Starting off a whole number of steps from a plane keeps every update off it.
+    fn drive(curve: &Curve, tracker: &mut LapTracker, from: f32, to: f32) -> Vec<LapEvent> {
+        let steps = ((to - from).abs() / STEP).round() as usize;
+        let step = (to - from).signum() * STEP;
+        (1..=steps)
+            .flat_map(|k| tracker.update(curve, on_track(curve, from + k as f32 * step, 0.)))
+            .collect()
+    }
+
+    fn from_grid(curve: &Curve) -> LapTracker {
+        let mut tracker = LapTracker::new(curve);
+        tracker.reset(on_track(curve, -0.55, 0.));
+        tracker
+    }
+
+    #[test]
+    fn checkpoints_are_passed_in_order_between_laps() {
+        let curve = lap_ring();
+        let mut tracker = from_grid(&curve);
+        let events = drive(&curve, &mut tracker, -0.55, 16.45);
+        let expected = [
+            LapEvent::Lap(1),
+            LapEvent::Checkpoint(0),
+            LapEvent::Checkpoint(1),
+            LapEvent::Checkpoint(2),
+            LapEvent::Lap(2),
+        ];
+        assert_eq!(events, expected);
+        assert_eq!(tracker.lap(), 2);
+        assert!(tracker.finished(1) && !tracker.finished(2));
+    }
+
+    #[test]
+    fn the_grid_needs_no_checkpoints() {
+        let curve = lap_ring();
+        let mut tracker = from_grid(&curve);
+        assert!(drive(&curve, &mut tracker, -0.55, -0.05).is_empty());
+        assert_eq!(tracker.lap(), 0);
+        assert_eq!(drive(&curve, &mut tracker, -0.05, 0.45), [LapEvent::Lap(1)]);
+    }
+
+    #[test]
+    fn skipping_a_checkpoint_voids_the_lap() {
+        let curve = lap_ring();
+        let mut tracker = from_grid(&curve);
+        assert_eq!(drive(&curve, &mut tracker, -0.55, 2.95), [LapEvent::Lap(1)]);
+
+        // Jumping well past the first checkpoint, as a shortcut would
+        assert!(tracker
+            .update(&curve, on_track(&curve, 6.05, 0.))
+            .is_empty());
+        let events = drive(&curve, &mut tracker, 6.05, 16.45);
+        let expected = [
+            LapEvent::MissedCheckpoint {
+                expected: 0,
+                reached: 1,
+            },
+            LapEvent::Checkpoint(2),
+            LapEvent::IncompleteLap,
+        ];
+        assert_eq!(events, expected);
+        assert_eq!(tracker.lap(), 1);
+
+        // The next lap starts afresh
+        let events = drive(&curve, &mut tracker, 16.45, 32.45);
+        assert_eq!(events.last(), Some(&LapEvent::Lap(2)));
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, LapEvent::MissedCheckpoint { .. })));
+    }
+
+    #[test]
+    fn backwards_crossings_must_be_undone_before_a_lap_counts() {
+        let curve = lap_ring();
+        let mut tracker = from_grid(&curve);
+        assert_eq!(drive(&curve, &mut tracker, -0.55, 0.45), [LapEvent::Lap(1)]);
+
+        // Back over the line, after which going forwards over it only undoes that
+        assert_eq!(
+            drive(&curve, &mut tracker, 0.45, -0.55),
+            [LapEvent::Backwards]
+        );
+        assert!(drive(&curve, &mut tracker, -0.55, 0.45).is_empty());
+        assert_eq!(tracker.lap(), 1);
+
+        let events = drive(&curve, &mut tracker, 0.45, 16.45);
+        assert_eq!(events.last(), Some(&LapEvent::Lap(2)));
+    }
+
+    #[test]
+    fn reversing_over_a_checkpoint_takes_it_back() {
+        let curve = lap_ring();
+        let mut tracker = from_grid(&curve);
+        drive(&curve, &mut tracker, -0.55, 4.45);
+        assert_eq!(tracker.checkpoints_passed(), 1);
+
+        assert!(drive(&curve, &mut tracker, 4.45, 3.45).is_empty());
+        assert_eq!(tracker.checkpoints_passed(), 0);
+        assert_eq!(
+            drive(&curve, &mut tracker, 3.45, 4.45),
+            [LapEvent::Checkpoint(0)]
+        );
+    }
+
+    #[test]
+    fn side_by_side_racers_finish_on_the_same_update() {
+        let curve = lap_ring();
+        let sides = [-TRACK_WIDTH / 4., TRACK_WIDTH / 4.];
+        let mut trackers: Vec<LapTracker> = sides
+            .iter()
+            .map(|&side| {
+                let mut tracker = LapTracker::new(&curve);
+                tracker.reset(on_track(&curve, -0.55, side));
+                tracker
+            })
+            .collect();
+
+        let (from, steps) = (-0.55, 170);
+        let mut finished_at = [None; 2];
+        for k in 1..=steps {
+            let t = from + k as f32 * STEP;
+            for (i, (tracker, &side)) in trackers.iter_mut().zip(&sides).enumerate() {
+                tracker.update(&curve, on_track(&curve, t, side));
+                if tracker.finished(1) && finished_at[i].is_none() {
+                    finished_at[i] = Some(k);
+                }
+            }
+        }
+        assert!(finished_at[0].is_some());
+        assert_eq!(finished_at[0], finished_at[1]);
+
+        // Level on progress too, so neither overtakes the other in the standings
+        let progress: Vec<(usize, f32)> = trackers
+            .iter()
+            .enumerate()
+            .map(|(i, tracker)| (i, tracker.fine_progress(&curve)))
+            .collect();
+        assert!((progress[0].1 - progress[1].1).abs() < STEP);
+        assert_eq!(race_order(&[1, 0], &[], &[(0, 1.), (1, 1.)]), [1, 0]);
+    }
+
+    #[test]
+    fn handicaps_run_from_leader_to_last() {
+        let factors = handicap_factors(&['a', 'b', 'c'], 0.08);
+        let expected = [('a', 0.92), ('b', 1.), ('c', 1.08)];
+        for ((id, factor), (want_id, want)) in factors.into_iter().zip(expected) {
+            assert_eq!(id, want_id);
+            assert!((factor - want).abs() < 1e-6, "{}: {}", id, factor);
+        }
+    }
+
+    #[test]
+    fn handicaps_never_pass_the_cap() {
+        let order: Vec<usize> = (0..8).collect();
+        for (_, factor) in handicap_factors(&order, 0.5) {
+            assert!((1. - MAX_CATCH_UP..=1. + MAX_CATCH_UP).contains(&factor));
+        }
+    }
+
+    #[test]
+    fn grid_slots_on_loop1_are_on_track_and_in_order() {
+        let curve = loop1();
+        let controller = ShipController::new(
+            ShipCharacteristics::standard(),
+            TuningParams::default(),
+            &curve,
+        );
+        let finish = FinishPlane::new(&curve, curve.finish_line());
+        let slots = GridLayout::compute(&curve, 8);
+        assert_eq!(slots.len(), 8);
+
+        let mut last_behind = 0.;
+        for (i, slot) in slots.iter().enumerate() {
+            let output = controller.bounds_check(&controller.track_frame(slot));
+            assert_eq!(
+                output,
+                ControlOutput::default(),
+                "slot {} is off the track",
+                i
+            );
+
+            // Each further back than the one before, and none over the line
+            let ahead = (finish.transform.inverse() * *slot).pos.x;
+            assert!(ahead < 0., "slot {} is {} m past the line", i, ahead);
+            let behind = curve.loop_distance(curve.nearest_t(slot.pos), curve.finish_line());
+            assert!(
+                behind > last_behind,
+                "slot {} isn't behind the one before",
+                i
+            );
+            last_behind = behind;
+
+            for other in &slots[..i] {
+                assert!(
+                    other.pos.distance(slot.pos) >= SLOT_SEPARATION,
+                    "slot {}",
+                    i
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn grid_slots_round_a_tight_bend_are_pulled_back_apart() {
+        // The inside column is so tight that a row apart is closer than the slots may be
+        let curve = ring(32, 25.);
+        let n = 8;
+        let slots = GridLayout::compute(&curve, n);
+        let depth = (n + 1) / 2 + 1;
+        let spacing = GRID_ROW_SPACING.min(curve.length() * MAX_GRID_FRACTION / depth as f32);
+        let finish = FinishPlane::new(&curve, curve.finish_line());
+
+        let mut pulled_back = vec![];
+        for (i, slot) in slots.iter().enumerate() {
+            assert!(slot.pos.is_finite(), "slot {}", i);
+            let ahead = (finish.transform.inverse() * *slot).pos.x;
+            assert!(ahead < 0., "slot {} is {} m past the line", i, ahead);
+            for (j, other) in slots[..i].iter().enumerate() {
+                let apart = other.pos.distance(slot.pos);
+                assert!(
+                    apart >= SLOT_SEPARATION,
+                    "slots {} and {}: {} m",
+                    j,
+                    i,
+                    apart
+                );
+            }
+
+            // Compared with where it would be without the others in the way
+            let behind = spacing * ((i / 2) as f32 + 1. + (i % 2) as f32 / 2.);
+            let side = if i % 2 == 0 { -1. } else { 1. };
+            let naive = GridLayout::slot(&curve, behind, side);
+            if naive.pos.distance(slot.pos) > SLOT_STEP / 2. {
+                let ahead = (naive.inverse() * *slot).pos.x;
+                assert!(ahead < 0., "slot {} was pushed {} m forwards", i, ahead);
+                pulled_back.push(i);
+            }
+        }
+        // All but the front of the inside column
+        assert_eq!(pulled_back, [3, 5, 7]);
+    }
+
+    #[test]
+    fn lone_racer_has_no_handicap() {
+        assert_eq!(handicap_factors(&['a'], 0.08), vec![('a', 1.)]);
+        assert!(handicap_factors::<char>(&[], 0.08).is_empty());
+    }
+}
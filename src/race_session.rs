@@ -0,0 +1,848 @@
+use chat::ChatUpload;
+use cimvr_common::{glam::Vec3, Transform};
+use cimvr_engine_interface::{prelude::*, println, FrameTime};
+
+use crate::{
+    billboards::TrackConfig,
+    conditions::Weather,
+    controls::{ControlEvent, ControlScheme, ShipController, ShipState, TuningParams},
+    countdown::{self, CountdownAnimation, CountdownEvent},
+    curve::Curve,
+    envelope,
+    kinematics::{FixedStep, FIXED_DT},
+    launch::Launch,
+    off_track::{lap_lines, OffTrackTimer},
+    prediction::Prediction,
+    race_rng::RaceRng,
+    race_rules::{LapEvent, LapTracker, MAX_CATCH_UP},
+    respawn::RespawnTracker,
+    rolling_start::Formation,
+    sanitize::{Boundary, Sanitizer},
+    ship_class::ShipClass,
+    splits::SplitTimes,
+    strings::{Key, Language, Text},
+    Assists, ClientReady, Finished, HandicapFactor, InputAbstraction, LapTime, RaceAbort,
+    RaceAborted, RaceSettings, ResumeRace, ShipCharacteristics, ShipStateAck, ShipUpload,
+    StartRace, TeleportEvent, YourLeg,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GameMode {
+    Spectator {
+        /// Whether the player is ready to enter the next race when it starts
+        ready: bool,
+    },
+    Racing {
+        /// Server-assigned identifier of the race
+        race_id: u32,
+        /// ID of this client, used to ascertain
+        client_id: ClientId,
+    },
+    /// Finished, but still free to drive around until the race is over
+    CoolDown {
+        client_id: ClientId,
+        /// Race time at which we stop driving
+        until: f32,
+    },
+}
+
+impl GameMode {
+    fn phase(&self) -> RacePhase {
+        match *self {
+            GameMode::Spectator { .. } => RacePhase::Spectating,
+            GameMode::Racing { client_id, .. } => RacePhase::Racing { client_id },
+            GameMode::CoolDown { client_id, .. } => RacePhase::CoolDown { client_id },
+        }
+    }
+
+    /// After crossing the line for the last time, at race time `time`: relay runners go back to
+    /// spectating, to get out of the way of the teammate taking over, and everyone else cools
+    /// down for `cooldown_time`
+    fn finished(&self, time: f32, relay: bool, cooldown_time: f32) -> Self {
+        match *self {
+            GameMode::Racing { client_id, .. } if !relay => GameMode::CoolDown {
+                client_id,
+                until: time + cooldown_time,
+            },
+            GameMode::Racing { .. } => GameMode::Spectator { ready: false },
+            other => other,
+        }
+    }
+
+    /// Whether the cool down is over at `race_time`, or because the server ended the race
+    fn cool_down_over(&self, race_time: f32, race_over: bool) -> bool {
+        match *self {
+            GameMode::CoolDown { until, .. } => race_over || race_time >= until,
+            _ => false,
+        }
+    }
+
+    /// Whether the server aborting `race_id` takes us out of it
+    fn aborted_by(&self, race_id: u32) -> bool {
+        matches!(*self, GameMode::Racing { race_id: current, .. } if current == race_id)
+    }
+}
+
+/// Height above the finish line at which the ship is parked after a race (meters)
+const PARK_HEIGHT: f32 = 60.;
+
+/// What the local player is doing, as seen by the rest of the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RacePhase {
+    Spectating,
+    Racing { client_id: ClientId },
+    CoolDown { client_id: ClientId },
+}
+
+impl RacePhase {
+    pub fn is_racing(self) -> bool {
+        matches!(self, RacePhase::Racing { .. })
+    }
+
+    /// Whether we're controlling the local ship, racing or not
+    pub fn is_driving(self) -> bool {
+        self != RacePhase::Spectating
+    }
+
+    /// Our ID, while we're in a race
+    pub fn client_id(self) -> Option<ClientId> {
+        match self {
+            RacePhase::Racing { client_id } | RacePhase::CoolDown { client_id } => Some(client_id),
+            RacePhase::Spectating => None,
+        }
+    }
+}
+
+/// How to react to a `StartRace` message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StartDecision {
+    /// Begin the new race
+    Start,
+    /// Abandon the race in progress, then begin the new one
+    AbortAndStart,
+    /// We've already started this race; a resend
+    Duplicate,
+}
+
+/// Decide what to do with a `StartRace` for `incoming`, given the race we're currently
+/// running (if any) and the last race we started
+fn start_decision(racing: Option<u32>, last_started: Option<u32>, incoming: u32) -> StartDecision {
+    if last_started == Some(incoming) {
+        StartDecision::Duplicate
+    } else if racing.is_some() {
+        StartDecision::AbortAndStart
+    } else {
+        StartDecision::Start
+    }
+}
+
+/// The local player's part in the races: whether we're in one, the clock, and our laps
+pub struct RaceSession {
+    mode: GameMode,
+    /// The last race we started, to catch duplicate `StartRace` messages
+    last_race_id: Option<u32>,
+    settings: RaceSettings,
+    prediction: Prediction,
+    /// Steps the ship in fixed time when it isn't being predicted, so that it handles the same
+    /// at any frame rate
+    fixed: FixedStep,
+    /// The ship before the latest fixed step, for drawing it between that and the latest
+    stepped_from: Option<Transform>,
+    /// Where the ship was last drawn, and where it really was then
+    drawn: Option<(Transform, Transform)>,
+    /// Thrust multiplier from the server's catch-up assist
+    catch_up: f32,
+    /// Assists used so far this race
+    assists_used: Assists,
+    countdown: CountdownAnimation,
+    /// When the countdown reached "GO" this race, once it has
+    green: Option<f32>,
+    lap_tracker: LapTracker,
+    /// Laps and checkpoints to restore once we've been moved back into a resumed race
+    resume_progress: Option<(usize, usize)>,
+    /// Ship we'll race with
+    class: ShipClass,
+    /// Sequence number of the next `ShipUpload`
+    upload_seq: u32,
+    launch: Launch,
+    /// Our place behind the pace line, in a rolling start
+    formation: Option<Formation>,
+    /// Banner text for things which happened to us, waiting to be shown
+    announcements: Vec<Text>,
+    off_track: OffTrackTimer,
+    /// Race time at which the current lap started, unless we joined part way through it
+    lap_start: Option<f32>,
+    /// Laps completed so far this race
+    laps: Vec<LapTime>,
+    /// Fastest valid lap on this track
+    best_lap: Option<f32>,
+    /// Time through each sector, and the fastest on this track
+    splits: SplitTimes,
+    /// Catches bad ship state before it's uploaded
+    sanitizer: Sanitizer,
+    /// Where to put the ship back after it leaves the track, where we're trusted with our
+    /// own position
+    respawn: RespawnTracker,
+}
+
+impl RaceSession {
+    pub fn new(countdown: CountdownAnimation, lap_tracker: LapTracker) -> Self {
+        Self {
+            mode: GameMode::Spectator { ready: false },
+            last_race_id: None,
+            settings: RaceSettings::default(),
+            prediction: Prediction::new(),
+            fixed: FixedStep::default(),
+            stepped_from: None,
+            drawn: None,
+            catch_up: 1.,
+            assists_used: Assists::default(),
+            countdown,
+            green: None,
+            lap_tracker,
+            resume_progress: None,
+            class: ShipClass::default(),
+            upload_seq: 0,
+            launch: Launch::new(),
+            formation: None,
+            announcements: vec![],
+            off_track: OffTrackTimer::new(),
+            lap_start: None,
+            laps: vec![],
+            best_lap: None,
+            splits: SplitTimes::new(),
+            sanitizer: Sanitizer::new(),
+            respawn: RespawnTracker::new(),
+        }
+    }
+
+    pub fn phase(&self) -> RacePhase {
+        self.mode.phase()
+    }
+
+    /// The lap we're on
+    pub fn progress(&self) -> usize {
+        self.lap_tracker.lap()
+    }
+
+    pub fn lap_tracker(&self) -> &LapTracker {
+        &self.lap_tracker
+    }
+
+    pub fn catch_up(&self) -> f32 {
+        self.catch_up
+    }
+
+    pub fn sanitizer(&self) -> &Sanitizer {
+        &self.sanitizer
+    }
+
+    /// Whether we'll join the next race; `None` while racing
+    pub fn ready(&self) -> Option<bool> {
+        match self.mode {
+            GameMode::Spectator { ready } => Some(ready),
+            GameMode::Racing { .. } | GameMode::CoolDown { .. } => None,
+        }
+    }
+
+    /// Tell the server whether we'll join the next race
+    pub fn set_ready(&mut self, io: &mut EngineIo, ready: bool, text: String) {
+        if let GameMode::Spectator { ready: current } = &mut self.mode {
+            *current = ready;
+            io.send(&ClientReady(ready, self.class));
+            io.send(&ChatUpload(text));
+        }
+    }
+
+    pub fn class(&self) -> ShipClass {
+        self.class
+    }
+
+    /// Pick the ship for the next race, telling the server if we're in the lobby
+    pub fn set_class(&mut self, io: &mut EngineIo, class: ShipClass) {
+        self.class = class;
+        if let GameMode::Spectator { ready } = self.mode {
+            io.send(&ClientReady(ready, class));
+        }
+    }
+
+    /// Seconds since the current race started
+    pub fn race_time(&self, time: FrameTime) -> f32 {
+        self.countdown.elapsed(time)
+    }
+
+    /// Whether the ship should respond to the controls, which includes following the pace
+    /// line in a rolling start
+    pub fn should_be_moving(&self, time: FrameTime) -> bool {
+        match self.phase() {
+            RacePhase::Racing { .. } if self.formation.is_some() => {
+                self.countdown.since_go(time).is_some()
+            }
+            RacePhase::Racing { .. } => self.green.is_some(),
+            RacePhase::CoolDown { .. } => true,
+            RacePhase::Spectating => false,
+        }
+    }
+
+    /// Seconds since "GO" for placing the moving barriers; held at zero outside of a race
+    pub fn barrier_clock(&self, time: FrameTime) -> f32 {
+        match self.phase().is_driving() {
+            true => self.countdown.since_go(time).unwrap_or(0.).max(0.),
+            false => 0.,
+        }
+    }
+
+    /// Whether we're in formation behind the pace line, with the throttle not ours to use
+    pub fn pacing(&self, time: FrameTime) -> bool {
+        let before_green = self.countdown.since_go(time).map_or(false, |t| t <= 0.);
+        self.formation.is_some() && self.phase().is_racing() && before_green
+    }
+
+    /// Whether the local ship is stepped by prediction, against the server's acknowledgements,
+    /// rather than simply uploaded
+    pub fn predicting(&self) -> bool {
+        self.settings.authoritative && self.phase().is_racing()
+    }
+
+    /// Banner text for things which happened to us since last asked
+    pub fn take_announcements(&mut self) -> Vec<Text> {
+        std::mem::take(&mut self.announcements)
+    }
+
+    /// Lap times so far and the best, the last sector time, then how long we've been off
+    /// track this lap
+    pub fn lap_lines(&self, language: Language) -> Vec<String> {
+        let mut lines = lap_lines(language, &self.laps, self.best_lap);
+        let limit = self.settings.off_track_limit;
+        lines.extend(
+            self.splits
+                .last()
+                .map(|split| split.text().render(language)),
+        );
+        lines.extend(self.off_track.warning_line(language, limit));
+        lines
+    }
+
+    /// Whether every lap of the race counted
+    pub fn laps_valid(&self) -> bool {
+        self.laps.iter().all(|lap| lap.valid)
+    }
+
+    pub fn used_auto_throttle(&mut self) {
+        self.assists_used.auto_throttle = true;
+    }
+
+    pub fn used_one_handed(&mut self) {
+        self.assists_used.one_handed = true;
+    }
+
+    pub fn used_scheme(&mut self, scheme: ControlScheme) {
+        if scheme != ControlScheme::Banked {
+            self.assists_used.scheme = scheme;
+        }
+    }
+
+    /// Handle a `StartRace`, returning the new race's settings if it should begin
+    pub fn start(
+        &mut self,
+        io: &mut EngineIo,
+        path: &Curve,
+        start: StartRace,
+    ) -> Option<RaceSettings> {
+        let StartRace {
+            race_id,
+            client_id,
+            position,
+            settings,
+            ..
+        } = start;
+
+        let racing_id = match self.mode {
+            GameMode::Racing {
+                race_id: current, ..
+            } => Some(current),
+            _ => None,
+        };
+
+        match start_decision(racing_id, self.last_race_id, race_id) {
+            StartDecision::Duplicate => {
+                println!("Ignoring duplicate StartRace for race {}", race_id);
+                return None;
+            }
+            StartDecision::AbortAndStart => {
+                let aborted = racing_id.unwrap();
+                println!("Race {} aborted by the start of race {}", aborted, race_id);
+                io.send(&RaceAborted { race_id: aborted });
+            }
+            StartDecision::Start => (),
+        }
+
+        self.last_race_id = Some(race_id);
+        self.mode = GameMode::Racing { race_id, client_id };
+        self.settings = settings;
+        self.prediction.reset();
+        self.catch_up = 1.;
+        self.assists_used = Assists::default();
+        self.resume_progress = None;
+        self.launch.reset();
+        self.formation = settings
+            .rolling_start
+            .then(|| Formation::new(path, position));
+        self.off_track = OffTrackTimer::new();
+        self.lap_start = None;
+        self.laps.clear();
+        self.splits.restart();
+
+        self.countdown.restart();
+        self.countdown
+            .set_position(countdown::ahead_of_start(path, position));
+        self.green = None;
+
+        // Reset ship position
+        io.send(&TeleportEvent { to: Some(position) });
+
+        Some(settings)
+    }
+
+    /// Rejoin a race after reconnecting, returning its settings
+    pub fn resume(&mut self, io: &mut EngineIo, resume: ResumeRace, now: f32) -> RaceSettings {
+        let ResumeRace {
+            race_id,
+            client_id,
+            position,
+            lap,
+            checkpoints,
+            elapsed,
+            settings,
+        } = resume;
+
+        println!("Resuming race {} on lap {}", race_id, lap);
+
+        self.last_race_id = Some(race_id);
+        self.mode = GameMode::Racing { race_id, client_id };
+        self.settings = settings;
+        self.prediction.reset();
+        self.catch_up = 1.;
+        self.assists_used = Assists::default();
+        self.resume_progress = Some((lap, checkpoints));
+        self.launch.reset();
+        // Too late to fall in behind the pace line
+        self.formation = None;
+        self.off_track = OffTrackTimer::new();
+        // We don't know when the lap we're rejoining started
+        self.lap_start = None;
+        self.laps.clear();
+        self.splits.restart();
+
+        self.countdown.resume(now - elapsed, now);
+        self.green = None;
+
+        io.send(&TeleportEvent { to: Some(position) });
+
+        settings
+    }
+
+    /// Take over a relay team's run from our teammate, returning the race's settings
+    pub fn take_leg(&mut self, io: &mut EngineIo, leg: YourLeg, now: f32) -> RaceSettings {
+        let YourLeg {
+            race_id,
+            client_id,
+            start_transform,
+            lap,
+            checkpoints,
+            elapsed_so_far,
+            settings,
+        } = leg;
+
+        // Picking up where they left off is just like coming back after a disconnection
+        let resume = ResumeRace {
+            race_id,
+            client_id,
+            position: start_transform,
+            lap,
+            checkpoints,
+            elapsed: elapsed_so_far,
+            settings,
+        };
+        self.resume(io, resume, now)
+    }
+
+    /// Switch to a new track, returning to the lobby
+    pub fn set_track(&mut self, path: &Curve, countdown_pos: Transform) {
+        self.lap_tracker = LapTracker::new(path);
+        self.countdown.set_position(countdown_pos);
+        self.mode = GameMode::Spectator { ready: false };
+        self.prediction.reset();
+        self.catch_up = 1.;
+        self.resume_progress = None;
+        self.laps.clear();
+        self.best_lap = None;
+        self.splits.reset();
+        self.respawn.reset();
+    }
+
+    /// The ship was moved without driving there
+    pub fn snap(&mut self, pos: Vec3) {
+        self.lap_tracker.reset(pos);
+        self.respawn.reset();
+        if let Some((lap, checkpoints)) = self.resume_progress.take() {
+            self.lap_tracker.resume(lap, checkpoints);
+        }
+    }
+
+    /// Put the ship back where it would go after leaving the track, as though it just had.
+    /// Returns whether it could be; only where we're trusted with our own position, and once
+    /// we've driven somewhere.
+    pub fn respawn(&mut self, path: &Curve, state: &mut ShipState) -> bool {
+        if self.predicting() {
+            return false;
+        }
+        let Some(point) = self.respawn.point(path) else {
+            return false;
+        };
+        state.tf = point;
+        state.kt.vel = Vec3::ZERO;
+        state.kt.ang_vel = Vec3::ZERO;
+        self.respawn.respawned();
+        true
+    }
+
+    pub fn animate(
+        &mut self,
+        io: &mut EngineIo,
+        time: FrameTime,
+        visible: bool,
+        camera: Transform,
+    ) {
+        self.countdown.set_visible(visible);
+        for event in self.countdown.update(io, time, camera) {
+            if let CountdownEvent::RaceStarted { at } = event {
+                self.green = Some(at);
+            }
+        }
+    }
+
+    /// Step the local ship forward in time, returning what the controller did to it
+    #[allow(clippy::too_many_arguments)]
+    pub fn drive(
+        &mut self,
+        io: &mut EngineIo,
+        mut ship: ShipCharacteristics,
+        tuning: TuningParams,
+        path: &Curve,
+        track: &TrackConfig,
+        mut input: InputAbstraction,
+        time: FrameTime,
+        state: &mut ShipState,
+    ) -> Vec<ControlEvent> {
+        let mut delta = time.delta;
+        let should_be_moving = self.should_be_moving(time);
+
+        // The ship is drawn between fixed steps, so carry on from where it really is, unless
+        // something else has moved it since
+        match self.drawn.take() {
+            Some((drawn, physical)) if drawn == state.tf => state.tf = physical,
+            _ => self.stepped_from = None,
+        }
+
+        // Get away from the exact moment of GO, unless we jumped it
+        if let (true, Some(since_go)) = (self.phase().is_racing(), self.countdown.since_go(time)) {
+            let launch = match &mut self.formation {
+                // Already moving, so only getting ahead of the pace line is a jump start
+                Some(formation) => {
+                    let mut launch = self.launch.update(since_go, delta, 0.);
+                    launch.dt = delta;
+                    if since_go <= 0. && formation.hold(path, since_go, state, &mut input) {
+                        launch.jump_start = self.launch.jump();
+                    }
+                    launch
+                }
+                None => self.launch.update(since_go, delta, input.throttle),
+            };
+            delta = launch.dt;
+            if launch.jump_start {
+                self.announcements.push(Text::new(Key::JumpStart));
+            }
+            if launch.locked_out {
+                input.throttle = 0.;
+                input.boost = false;
+            }
+        }
+
+        if let Some(HandicapFactor(factor)) = io.inbox::<HandicapFactor>().last() {
+            self.catch_up = factor.clamp(1. - MAX_CATCH_UP, 1. + MAX_CATCH_UP);
+        }
+        if !self.phase().is_racing() {
+            self.catch_up = 1.;
+        }
+        ship.max_impulse *= self.catch_up;
+
+        // Conditions only hold for the race itself
+        let weather = match self.phase().is_driving() {
+            true => Weather::new(self.settings.conditions, &RaceRng::new(self.settings.seed)),
+            false => Weather::calm(),
+        };
+        let mut controller = ShipController::new(ship, tuning, path)
+            .with_weather(weather)
+            .with_barriers(&track.barriers, self.barrier_clock(time))
+            .with_shielded_walls(&track.shielded_walls);
+        // The server puts predicted ships back at the nearest control point, knowing no better
+        if !self.predicting() {
+            let respawn = self.respawn.point(path);
+            controller = controller.with_respawn(respawn, self.respawn.in_grace());
+        }
+
+        let mut control_events = vec![];
+        // After finishing, the server goes back to taking our word for where we are
+        if should_be_moving && self.predicting() {
+            // Predict locally; the server simulates the same inputs and has the final say
+            if let Some(ack) = io.inbox::<ShipStateAck>().last() {
+                *state = self.prediction.reconcile(&controller, ack);
+            }
+            let (uploads, events) = self.prediction.step(&controller, delta, input, state);
+            for upload in uploads {
+                io.send(&upload);
+            }
+            control_events = events;
+            self.stepped_from = None;
+        } else {
+            if should_be_moving {
+                for _ in 0..self.fixed.steps(delta) {
+                    self.stepped_from = Some(state.tf);
+                    let (new_state, step_events) = controller.fixed_step(input, *state);
+                    *state = new_state;
+                    match step_events.contains(&ControlEvent::Reset) {
+                        true => self.respawn.respawned(),
+                        false => self.respawn.update(path, state.tf.pos, FIXED_DT),
+                    }
+                    control_events.extend(step_events);
+                }
+            } else {
+                state.kt.vel = Vec3::ZERO;
+                state.kt.ang_vel = Vec3::ZERO;
+                self.stepped_from = None;
+            }
+
+            self.sanitizer
+                .ship(Boundary::Upload, path, &mut state.tf, &mut state.kt);
+            envelope::send(io, &ShipUpload(state.tf, state.kt, self.upload_seq));
+            self.upload_seq = self.upload_seq.wrapping_add(1);
+        }
+
+        // Time off the racing surface, not counting being put back from the walls
+        if should_be_moving && self.phase().is_racing() && self.settings.invalidate_laps {
+            let frame = controller.track_frame(&state.tf);
+            let excursion = !control_events.is_empty();
+            let limit = self.settings.off_track_limit;
+            let (lateral, half_width) = (frame.local.pos.z, frame.cross_section.half_width());
+            if self
+                .off_track
+                .update(delta, lateral, half_width, excursion, limit)
+            {
+                self.announcements.push(Text::new(Key::LapInvalidated));
+            }
+        }
+
+        control_events
+    }
+
+    /// Where to draw the ship, which is really at `tf`: part way there from before the latest
+    /// fixed step, by how far the clock is into the next, so that it moves smoothly at frame
+    /// rates which don't divide into the step rate
+    pub fn drawn(&mut self, tf: Transform) -> Transform {
+        let drawn = match self.stepped_from {
+            Some(from) => from.lerp_slerp(&tf, self.fixed.fraction()),
+            None => tf,
+        };
+        self.drawn = Some((drawn, tf));
+        drawn
+    }
+
+    /// Count laps as the ship moves, telling chat our lap and sector times in `language`.
+    /// Returns what happened, and whether we just finished.
+    pub fn track_laps(
+        &mut self,
+        io: &mut EngineIo,
+        path: &Curve,
+        pos: Vec3,
+        time: FrameTime,
+        language: Language,
+    ) -> (Vec<LapEvent>, bool) {
+        if !self.phase().is_racing() {
+            return (vec![], false);
+        }
+
+        let events = self.lap_tracker.update(path, pos);
+        let mut finished = false;
+
+        for event in &events {
+            if let Some(split) = self.splits.update(event, self.countdown.elapsed(time)) {
+                io.send(&ChatUpload(split.text().render(language)));
+            }
+            let &LapEvent::Lap(lap) = event else { continue };
+
+            let time = self.countdown.elapsed(time);
+            let minutes = (time / 60.).floor();
+            let seconds = (time % 60.).floor();
+            let milliseconds = ((time % 60.).fract() * 1000.).floor();
+            let completed = lap - 1;
+            let text = Text::new(Key::LapChat)
+                .with(completed)
+                .with(format!("{minutes}:{seconds}:{milliseconds}"));
+            io.send(&ChatUpload(text.render(language)));
+
+            // Leaving the grid starts the first lap
+            let valid = self.off_track.end_lap();
+            if let (true, Some(start)) = (completed > 0, self.lap_start) {
+                let lap = LapTime {
+                    time: time - start,
+                    valid,
+                };
+                if valid {
+                    self.best_lap = Some(self.best_lap.map_or(lap.time, |best| best.min(lap.time)));
+                }
+                self.laps.push(lap);
+            }
+            self.lap_start = Some(time);
+
+            // We've finisehd the whole race!
+            if self.lap_tracker.finished(self.settings.laps) {
+                let finished = Finished(time, self.assists_used, self.laps.clone());
+                envelope::send(io, &finished);
+
+                let (relay, cooldown_time) = (self.settings.relay, self.settings.cooldown_time);
+                self.mode = self.mode.finished(time, relay, cooldown_time);
+                if relay {
+                    park(io, path);
+                }
+                finished = true;
+            }
+        }
+
+        (events, finished)
+    }
+
+    /// Stop driving once the cool down runs out or the server ends the race, and park the ship
+    /// off the track. Returns true if we've just gone back to spectating.
+    pub fn update_cool_down(
+        &mut self,
+        io: &mut EngineIo,
+        path: &Curve,
+        time: FrameTime,
+        race_over: bool,
+    ) -> bool {
+        if !self.mode.cool_down_over(self.race_time(time), race_over) {
+            return false;
+        }
+
+        self.mode = GameMode::Spectator { ready: false };
+        park(io, path);
+        true
+    }
+
+    /// Handle a `RaceAbort`, going back to spectating if it's for our race.
+    /// Returns true if we did.
+    pub fn abort(&mut self, io: &mut EngineIo, path: &Curve, abort: RaceAbort) -> bool {
+        let race_id = abort.race_id;
+        if !self.mode.aborted_by(race_id) {
+            return false;
+        }
+
+        println!("Race {} aborted by the server", race_id);
+        io.send(&RaceAborted { race_id });
+        self.mode = GameMode::Spectator { ready: false };
+        park(io, path);
+        true
+    }
+}
+
+/// Move the ship off the track, above the finish line
+fn park(io: &mut EngineIo, path: &Curve) {
+    let finish = path.lerp(path.finish_line());
+    let park = finish.with_position(finish.pos + Vec3::Y * PARK_HEIGHT);
+    io.send(&TeleportEvent { to: Some(park) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resent_start_is_a_duplicate() {
+        assert_eq!(
+            start_decision(Some(4), Some(4), 4),
+            StartDecision::Duplicate
+        );
+        // Also once its race is over, so a late resend doesn't start it afresh
+        assert_eq!(start_decision(None, Some(4), 4), StartDecision::Duplicate);
+    }
+
+    #[test]
+    fn new_race_while_racing_aborts_the_current_one() {
+        assert_eq!(
+            start_decision(Some(4), Some(4), 5),
+            StartDecision::AbortAndStart
+        );
+    }
+
+    #[test]
+    fn new_race_while_spectating_starts() {
+        assert_eq!(start_decision(None, None, 0), StartDecision::Start);
+        assert_eq!(start_decision(None, Some(4), 5), StartDecision::Start);
+    }
+
+    const RACER: ClientId = ClientId(3);
+    const COOLDOWN: f32 = 10.;
+
+    fn racing(race_id: u32) -> GameMode {
+        GameMode::Racing {
+            race_id,
+            client_id: RACER,
+        }
+    }
+
+    #[test]
+    fn spectate_race_finish_and_spectate_again() {
+        let lobby = GameMode::Spectator { ready: true };
+        assert_eq!(lobby.phase(), RacePhase::Spectating);
+        assert!(!lobby.cool_down_over(1000., true));
+
+        assert_eq!(start_decision(None, None, 1), StartDecision::Start);
+        let racing = racing(1);
+        assert_eq!(racing.phase(), RacePhase::Racing { client_id: RACER });
+        assert!(racing.phase().is_racing());
+        assert!(!racing.cool_down_over(1000., true));
+
+        // Still driving after the line, until the cool down is up
+        let finished = racing.finished(95., false, COOLDOWN);
+        assert_eq!(finished.phase(), RacePhase::CoolDown { client_id: RACER });
+        assert!(finished.phase().is_driving() && !finished.phase().is_racing());
+        assert!(!finished.cool_down_over(95. + COOLDOWN - 0.1, false));
+        assert!(finished.cool_down_over(95. + COOLDOWN, false));
+
+        // Finishing again changes nothing
+        assert_eq!(finished.finished(99., false, COOLDOWN), finished);
+    }
+
+    #[test]
+    fn the_end_of_the_race_cuts_the_cool_down_short() {
+        let finished = racing(1).finished(95., false, COOLDOWN);
+        assert!(finished.cool_down_over(96., true));
+    }
+
+    #[test]
+    fn relay_runners_spectate_straight_after_their_leg() {
+        let handed_over = racing(1).finished(95., true, COOLDOWN);
+        assert_eq!(handed_over, GameMode::Spectator { ready: false });
+        assert_eq!(handed_over.phase(), RacePhase::Spectating);
+    }
+
+    #[test]
+    fn only_aborting_our_race_stops_us() {
+        assert!(racing(2).aborted_by(2));
+        assert!(!racing(2).aborted_by(1));
+        // Too late once we've finished; the server counts our result
+        assert!(!racing(2).finished(95., false, COOLDOWN).aborted_by(2));
+        assert!(!GameMode::Spectator { ready: true }.aborted_by(2));
+    }
+}
@@ -0,0 +1,248 @@
+//! Elo-style ratings of players, from the order they finish races in
+use std::{cmp::Ordering, collections::HashMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// Rating of a player who hasn't raced yet
+pub const INITIAL_RATING: f32 = 1500.;
+/// Players are provisional for this many races, so their ratings settle quickly
+const PROVISIONAL_RACES: u32 = 10;
+/// Most a rating can move in a race against a single opponent, once settled
+const K_FACTOR: f32 = 20.;
+/// As `K_FACTOR`, while provisional
+const PROVISIONAL_K_FACTOR: f32 = 40.;
+/// Rating difference at which the better player is expected to win ten times out of eleven
+const SCALE: f32 = 400.;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub score: f32,
+    /// Rated races taken part in
+    pub races: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            score: INITIAL_RATING,
+            races: 0,
+        }
+    }
+}
+
+impl Rating {
+    pub fn is_provisional(&self) -> bool {
+        self.races < PROVISIONAL_RACES
+    }
+
+    fn k_factor(&self) -> f32 {
+        match self.is_provisional() {
+            true => PROVISIONAL_K_FACTOR,
+            false => K_FACTOR,
+        }
+    }
+}
+
+/// The score rounded, marked with a `?` while provisional
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0}", self.score)?;
+        if self.is_provisional() {
+            write!(f, "?")?;
+        }
+        Ok(())
+    }
+}
+
+/// Chance that a player rated `a` finishes ahead of one rated `b`
+pub fn expected(a: f32, b: f32) -> f32 {
+    1. / (1. + 10_f32.powf((b - a) / SCALE))
+}
+
+/// Rating changes for a race, as a comparison between every pair of racers. `finishers` are
+/// in finishing order; `dnf` lost to every finisher and drew with each other.
+///
+/// Each pair uses the mean of their K-factors, so whatever one player gains the other loses,
+/// and the changes sum to zero. Changes are divided by the number of opponents, so a race
+/// moves a rating about as far as a single head-to-head.
+pub fn rating_changes(
+    ratings: &HashMap<String, Rating>,
+    finishers: &[String],
+    dnf: &[String],
+) -> HashMap<String, f32> {
+    let rating = |name: &String| ratings.get(name).copied().unwrap_or_default();
+    let racers: Vec<(&String, usize)> = finishers
+        .iter()
+        .enumerate()
+        .chain(dnf.iter().map(|name| (finishers.len(), name)))
+        .map(|(place, name)| (name, place))
+        .collect();
+
+    let mut changes: HashMap<String, f32> = racers
+        .iter()
+        .map(|(name, _)| (name.to_string(), 0.))
+        .collect();
+    let opponents = racers.len().saturating_sub(1).max(1) as f32;
+    for (i, &(a, place_a)) in racers.iter().enumerate() {
+        for &(b, place_b) in &racers[i + 1..] {
+            let outcome = match place_a.cmp(&place_b) {
+                Ordering::Less => 1.,
+                Ordering::Equal => 0.5,
+                Ordering::Greater => 0.,
+            };
+            let (ra, rb) = (rating(a), rating(b));
+            let k = (ra.k_factor() + rb.k_factor()) / 2.;
+            let delta = k * (outcome - expected(ra.score, rb.score)) / opponents;
+            *changes.get_mut(a).unwrap() += delta;
+            *changes.get_mut(b).unwrap() -= delta;
+        }
+    }
+
+    changes
+}
+
+/// Apply the results of a race to `ratings`, adding anybody new; see `rating_changes`
+pub fn update_ratings(ratings: &mut HashMap<String, Rating>, finishers: &[String], dnf: &[String]) {
+    // A race against nobody says nothing
+    if finishers.len() + dnf.len() < 2 {
+        return;
+    }
+
+    for (name, delta) in rating_changes(ratings, finishers, dnf) {
+        let rating = ratings.entry(name).or_default();
+        rating.score += delta;
+        rating.races += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race_rng::RaceRng;
+
+    fn names(prefix: &str, n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("{}{}", prefix, i)).collect()
+    }
+
+    #[test]
+    fn races_are_zero_sum() {
+        let mut rng = RaceRng::new(7).stream("ratings");
+        let mut ratings = HashMap::new();
+        for race in 0..200 {
+            // Regulars who've settled, and now and then a newcomer, in a random order
+            let mut racers = names("regular", 8);
+            if rng.next_u32() % 4 == 0 {
+                racers.push(format!("newcomer{}", race));
+            }
+            for i in (1..racers.len()).rev() {
+                racers.swap(i, rng.next_u32() as usize % (i + 1));
+            }
+            racers.truncate(2 + rng.next_u32() as usize % (racers.len() - 1));
+            let dnf = racers.split_off(racers.len() - rng.next_u32() as usize % racers.len());
+
+            let sum = |ratings: &HashMap<String, Rating>| -> f32 {
+                let in_race = racers.iter().chain(&dnf);
+                in_race
+                    .map(|name| ratings.get(name).copied().unwrap_or_default().score)
+                    .sum()
+            };
+            let before = sum(&ratings);
+            update_ratings(&mut ratings, &racers, &dnf);
+            let after = sum(&ratings);
+            assert!(
+                (after - before).abs() < 0.05,
+                "race {}: {} -> {}",
+                race,
+                before,
+                after
+            );
+        }
+    }
+
+    #[test]
+    fn evenly_matched_winner_gains_what_the_loser_loses() {
+        let mut ratings = HashMap::new();
+        update_ratings(&mut ratings, &names("p", 2), &[]);
+        let (winner, loser) = (ratings["p0"], ratings["p1"]);
+        assert!(winner.score > INITIAL_RATING);
+        assert!((winner.score - INITIAL_RATING + loser.score - INITIAL_RATING).abs() < 1e-3);
+        assert_eq!((winner.races, loser.races), (1, 1));
+    }
+
+    #[test]
+    fn racing_alone_changes_nothing() {
+        let mut ratings = HashMap::new();
+        update_ratings(&mut ratings, &names("p", 1), &[]);
+        update_ratings(&mut ratings, &[], &names("p", 1));
+        assert!(ratings.is_empty());
+    }
+
+    #[test]
+    fn dnfs_draw_with_each_other() {
+        let changes = rating_changes(&HashMap::new(), &names("f", 1), &names("d", 2));
+        assert!((changes["d0"] - changes["d1"]).abs() < 1e-6);
+        assert!(changes["d0"] < 0.);
+    }
+
+    /// Ratings of `n` players who've each raced `races` times, all at the initial rating
+    fn field(prefix: &str, n: usize, races: u32) -> HashMap<String, Rating> {
+        let rating = Rating {
+            score: INITIAL_RATING,
+            races,
+        };
+        names(prefix, n)
+            .into_iter()
+            .map(|name| (name, rating))
+            .collect()
+    }
+
+    #[test]
+    fn newcomers_move_further_than_regulars() {
+        let newcomers = rating_changes(&field("p", 2, 0), &names("p", 2), &[]);
+        let regulars = rating_changes(&field("p", 2, PROVISIONAL_RACES), &names("p", 2), &[]);
+        assert!((newcomers["p0"] - PROVISIONAL_K_FACTOR / 2.).abs() < 1e-3);
+        assert!((regulars["p0"] - K_FACTOR / 2.).abs() < 1e-3);
+        assert!(newcomers["p0"] > regulars["p0"]);
+        assert!(newcomers["p1"] < regulars["p1"]);
+    }
+
+    #[test]
+    fn a_regular_moves_further_against_newcomers() {
+        // A pair races with the mean of their K-factors, so a newcomer raises a regular's too
+        let mut ratings = field("regular", 2, PROVISIONAL_RACES);
+        let against_regular = rating_changes(&ratings, &names("regular", 2), &[]);
+
+        ratings.extend(field("newcomer", 1, 0));
+        let racers = ["regular0".to_string(), "newcomer0".to_string()];
+        let against_newcomer = rating_changes(&ratings, &racers, &[]);
+        assert!(against_newcomer["regular0"] > against_regular["regular0"]);
+        assert!(against_newcomer["regular0"] < PROVISIONAL_K_FACTOR / 2.);
+    }
+
+    #[test]
+    fn ratings_settle_after_the_provisional_races() {
+        // Two players taking turns to win, so they stay evenly matched
+        let mut ratings = HashMap::new();
+        let mut gains = vec![];
+        for race in 0..2 * PROVISIONAL_RACES {
+            let mut racers = names("p", 2);
+            if race % 2 == 1 {
+                racers.reverse();
+            }
+            let before = ratings.get(&racers[0]).copied().unwrap_or_default();
+            assert_eq!(before.is_provisional(), race < PROVISIONAL_RACES);
+            update_ratings(&mut ratings, &racers, &[]);
+            gains.push(ratings[&racers[0]].score - before.score);
+        }
+
+        let (provisional, settled) = gains.split_at(PROVISIONAL_RACES as usize);
+        let least_provisional = provisional.iter().copied().fold(f32::MAX, f32::min);
+        let most_settled = settled.iter().copied().fold(0., f32::max);
+        assert!(
+            most_settled < least_provisional * 0.6,
+            "{:?} then {:?}",
+            provisional,
+            settled
+        );
+    }
+}
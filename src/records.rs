@@ -0,0 +1,90 @@
+//! Fastest laps and races on each track since the server started, and who set them
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    race_rules::format_race_time,
+    strings::{Key, Text},
+};
+
+/// A best time, and the name of whoever set it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Seconds
+    pub time: f32,
+    pub holder: String,
+}
+
+impl Record {
+    fn beaten_by(record: Option<&Record>, time: f32) -> bool {
+        record.map_or(true, |record| time < record.time)
+    }
+}
+
+/// Records on one track
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TrackRecords {
+    /// Fastest valid lap
+    pub lap: Option<Record>,
+    /// Fastest race, for each number of laps raced
+    pub races: HashMap<usize, Record>,
+}
+
+impl TrackRecords {
+    /// Rows for the lobby panel: the lap record, and the race record over `laps` if there is
+    /// one
+    pub fn lines(&self, laps: Option<usize>) -> Vec<Text> {
+        let mut lines = vec![match &self.lap {
+            Some(record) => Text::new(Key::TrackRecord)
+                .with(format_race_time(record.time))
+                .with(&record.holder),
+            None => Text::new(Key::NoTrackRecord),
+        }];
+        if let Some(record) = laps.and_then(|laps| self.races.get(&laps)) {
+            let text = Text::new(Key::RaceRecord)
+                .with(format_race_time(record.time))
+                .with(&record.holder);
+            lines.push(text);
+        }
+        lines
+    }
+}
+
+/// Records on every track raced, by track id
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Records {
+    tracks: HashMap<String, TrackRecords>,
+}
+
+impl Records {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track(&self, track: &str) -> TrackRecords {
+        self.tracks.get(track).cloned().unwrap_or_default()
+    }
+
+    /// Take a valid lap of `time` seconds, returning whether it set the lap record
+    pub fn lap(&mut self, track: &str, time: f32, holder: &str) -> bool {
+        let lap = &mut self.tracks.entry(track.to_string()).or_default().lap;
+        let beaten = Record::beaten_by(lap.as_ref(), time);
+        if beaten {
+            let holder = holder.to_string();
+            *lap = Some(Record { time, holder });
+        }
+        beaten
+    }
+
+    /// Take a race of `laps` finished in `time` seconds, returning whether it set the record
+    pub fn race(&mut self, track: &str, laps: usize, time: f32, holder: &str) -> bool {
+        let races = &mut self.tracks.entry(track.to_string()).or_default().races;
+        let beaten = Record::beaten_by(races.get(&laps), time);
+        if beaten {
+            let holder = holder.to_string();
+            races.insert(laps, Record { time, holder });
+        }
+        beaten
+    }
+}
@@ -0,0 +1,249 @@
+//! Relay races: racers are split into teams, and only one runner per team is on the track at a
+//! time. Each runs their share of the laps, then hands off to the next teammate where they
+//! crossed the line. The team's time is the last runner's finish, plus any penalties.
+use cimvr_common::ui::UiStateHelper;
+use cimvr_engine_interface::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    label_panel::LabelPanel,
+    race_rules::format_race_time,
+    strings::{Key, Language, Text},
+    RelayStatus,
+};
+
+/// Most runners on a team
+pub const TEAM_SIZE: usize = 2;
+/// Added to a team's time when their runner disconnects mid-leg (seconds)
+pub const DROP_PENALTY: f32 = 10.;
+
+/// One team's running order, and how far along it they are
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RelayTeam {
+    /// Runners and their names, in the order they run
+    pub runners: Vec<(ClientId, String)>,
+    /// Index of the runner out on their leg; `runners.len()` once the team is done
+    pub leg: usize,
+    /// Race time at which each leg so far ended (seconds)
+    pub splits: Vec<f32>,
+    /// Time added for legs cut short by a disconnection (seconds)
+    pub penalty: f32,
+    /// Lost their last runner before the end, so they won't finish
+    pub dnf: bool,
+}
+
+impl RelayTeam {
+    /// The runner on their leg, if any
+    pub fn active(&self) -> Option<ClientId> {
+        self.runners.get(self.leg).map(|&(client_id, _)| client_id)
+    }
+
+    /// Team time, penalties included; only final once every leg is run
+    pub fn total(&self) -> f32 {
+        self.splits.last().copied().unwrap_or(0.) + self.penalty
+    }
+
+    /// Time taken over each leg run so far
+    pub fn leg_times(&self) -> Vec<f32> {
+        let starts = std::iter::once(0.).chain(self.splits.iter().copied());
+        self.splits
+            .iter()
+            .zip(starts)
+            .map(|(end, start)| end - start)
+            .collect()
+    }
+
+    /// e.g. "alice & bob"
+    pub fn name(&self) -> String {
+        let names: Vec<&str> = self.runners.iter().map(|(_, name)| name.as_str()).collect();
+        names.join(" & ")
+    }
+}
+
+/// What happens once a leg ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handoff {
+    /// The given teammate runs next
+    Next(ClientId),
+    /// That was the team's last leg
+    Finished,
+    /// Nobody is left to run, so the team is out
+    Out,
+}
+
+/// Server-side state of every team in a relay race
+#[derive(Default)]
+pub struct Relay {
+    teams: Vec<RelayTeam>,
+}
+
+impl Relay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Split `racers` into teams of up to `TEAM_SIZE`. Racers are dealt out in turn, so the
+    /// first ones given each run first for their team, and take the grid in that order.
+    pub fn assign(&mut self, racers: &[(ClientId, String)]) {
+        let n_teams = racers.len().div_ceil(TEAM_SIZE);
+        self.teams = (0..n_teams)
+            .map(|_| RelayTeam {
+                runners: vec![],
+                leg: 0,
+                splits: vec![],
+                penalty: 0.,
+                dnf: false,
+            })
+            .collect();
+        for (idx, racer) in racers.iter().enumerate() {
+            self.teams[idx % n_teams].runners.push(racer.clone());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.teams.clear();
+    }
+
+    pub fn teams(&self) -> &[RelayTeam] {
+        &self.teams
+    }
+
+    /// The team and place in its running order of `client_id`, if they're in one
+    pub fn place_of(&self, client_id: ClientId) -> Option<(usize, usize)> {
+        self.teams.iter().enumerate().find_map(|(team_idx, team)| {
+            let runner = team.runners.iter().position(|&(id, _)| id == client_id)?;
+            Some((team_idx, runner))
+        })
+    }
+
+    /// Whether `client_id` is still to run their leg
+    pub fn is_waiting(&self, client_id: ClientId) -> bool {
+        self.place_of(client_id)
+            .map_or(false, |(team, runner)| runner > self.teams[team].leg)
+    }
+
+    /// The team `client_id` is running for, if they're out on their leg
+    fn running(&mut self, client_id: ClientId) -> Option<&mut RelayTeam> {
+        self.teams
+            .iter_mut()
+            .find(|team| team.active() == Some(client_id))
+    }
+
+    /// End the leg of `client_id`, who crossed the line at race time `time`
+    pub fn end_leg(&mut self, client_id: ClientId, time: f32) -> Option<Handoff> {
+        let team = self.running(client_id)?;
+        team.splits.push(time);
+        team.leg += 1;
+        Some(match team.active() {
+            Some(next) => Handoff::Next(next),
+            None => Handoff::Finished,
+        })
+    }
+
+    /// `client_id` disconnected mid-leg at race time `time`. The next teammate takes over
+    /// from where they were, at the cost of `DROP_PENALTY`.
+    pub fn drop_out(&mut self, client_id: ClientId, time: f32) -> Option<Handoff> {
+        let team = self.running(client_id)?;
+        team.leg += 1;
+        match team.active() {
+            Some(next) => {
+                team.splits.push(time);
+                team.penalty += DROP_PENALTY;
+                Some(Handoff::Next(next))
+            }
+            None => {
+                team.dnf = true;
+                Some(Handoff::Out)
+            }
+        }
+    }
+
+    /// Take a runner who hasn't had their leg yet out of their team, e.g. on disconnecting
+    pub fn remove_waiting(&mut self, client_id: ClientId) {
+        if !self.is_waiting(client_id) {
+            return;
+        }
+        for team in &mut self.teams {
+            team.runners.retain(|&(id, _)| id != client_id);
+        }
+    }
+}
+
+/// The local player's team, while they're in a relay race
+pub struct RelayPanel {
+    status: Option<RelayStatus>,
+    panel: LabelPanel,
+}
+
+impl RelayPanel {
+    pub fn new() -> Self {
+        Self {
+            status: None,
+            panel: LabelPanel::new("Relay"),
+        }
+    }
+
+    pub fn set_status(&mut self, status: RelayStatus) {
+        self.status = Some(status);
+    }
+
+    /// Forget the race, e.g. once it's over
+    pub fn clear(&mut self) {
+        self.status = None;
+    }
+
+    /// Whether we're on a team in the current race
+    pub fn in_relay(&self) -> bool {
+        self.status.is_some()
+    }
+
+    /// The teammate out on their leg, unless it's us
+    pub fn teammate_running(&self) -> Option<ClientId> {
+        let status = self.status.as_ref()?;
+        let team = status.teams.get(status.team)?;
+        team.active().filter(|_| team.leg != status.runner)
+    }
+
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        visible: bool,
+        language: Language,
+    ) {
+        let lines = self.lines(language);
+        self.panel
+            .update(io, gui, visible && !lines.is_empty(), lines);
+    }
+
+    /// Each of our team's runners and their leg, then any penalty
+    fn lines(&self, language: Language) -> Vec<String> {
+        let Some(status) = &self.status else {
+            return vec![];
+        };
+        let Some(team) = status.teams.get(status.team) else {
+            return vec![];
+        };
+
+        let leg_times = team.leg_times();
+        let mut lines = vec![Text::new(Key::RelayTeam)
+            .with(status.team + 1)
+            .with(team.name())
+            .render(language)];
+        for (idx, (_, name)) in team.runners.iter().enumerate() {
+            let leg = match leg_times.get(idx) {
+                Some(&time) => format_race_time(time),
+                None if team.dnf => "DNF".to_string(),
+                None if idx == team.leg => Text::new(Key::RelayRunning).render(language),
+                None => Text::new(Key::RelayWaiting).render(language),
+            };
+            let marker = if idx == status.runner { ">" } else { " " };
+            lines.push(format!("{} {}: {}", marker, name, leg));
+        }
+        if team.penalty > 0. {
+            let penalty = format!("{:.0}", team.penalty);
+            lines.push(Text::new(Key::RelayPenalty).with(penalty).render(language));
+        }
+        lines
+    }
+}
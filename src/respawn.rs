@@ -0,0 +1,78 @@
+//! Where a ship is put back after leaving the track: the furthest control point it has driven
+//! to, rather than the nearest, which after clipping a corner can be further on
+use cimvr_common::{glam::Vec3, Transform};
+
+use crate::curve::Curve;
+
+/// Bounds aren't checked for this long after a respawn, so that it can't set off another
+/// (seconds)
+const GRACE_TIME: f32 = 1.;
+/// Most control points the ship can move on by in one update and still be driving there
+const MAX_STEP: usize = 3;
+/// Longest the ship may be out of step with the furthest control point before it's taken to
+/// be where it is, e.g. after driving the wrong way (seconds)
+const RESYNC_TIME: f32 = 3.;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RespawnTracker {
+    /// Control point the ship has driven furthest to, counting forwards from where it was
+    /// first seen
+    furthest: Option<usize>,
+    /// Time the ship has been too far from `furthest` to have driven there
+    out_of_step: f32,
+    /// Time left with the bounds off, after a respawn
+    grace: f32,
+}
+
+impl RespawnTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start again from wherever the ship is next seen, e.g. once it's been moved
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The ship drove to `pos` over the last `dt` seconds
+    pub fn update(&mut self, path: &Curve, pos: Vec3, dt: f32) {
+        self.grace = (self.grace - dt).max(0.);
+        let n = path.ctrlps.len();
+        let nearest = path.nearest_ctrlp(pos);
+        let Some(furthest) = self.furthest else {
+            self.furthest = Some(nearest);
+            return;
+        };
+
+        let ahead = (nearest + n - furthest) % n;
+        if ahead <= MAX_STEP {
+            self.furthest = Some(nearest);
+            self.out_of_step = 0.;
+        } else if ahead >= n - MAX_STEP {
+            // Wavering just behind
+            self.out_of_step = 0.;
+        } else {
+            self.out_of_step += dt;
+            if self.out_of_step > RESYNC_TIME {
+                self.furthest = Some(nearest);
+                self.out_of_step = 0.;
+            }
+        }
+    }
+
+    /// Where to put the ship back, facing along the track, once it's been seen
+    pub fn point(&self, path: &Curve) -> Option<Transform> {
+        self.furthest.map(|idx| path.ctrlps[idx])
+    }
+
+    /// Whether the bounds are off, just after a respawn
+    pub fn in_grace(&self) -> bool {
+        self.grace > 0.
+    }
+
+    /// The ship was just put back
+    pub fn respawned(&mut self) {
+        self.grace = GRACE_TIME;
+        self.out_of_step = 0.;
+    }
+}
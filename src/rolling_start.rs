@@ -0,0 +1,127 @@
+//! Rolling starts: the grid follows a virtual pace line round to the finish line, and the race
+//! goes green as the line crosses it, at the countdown's "GO"
+use cimvr_common::{
+    glam::{Quat, Vec3},
+    Transform,
+};
+
+use crate::{controls::ShipState, countdown::GO_TIME, curve::Curve, InputAbstraction};
+
+/// Typical speed flat out, which the pace is a fraction of (meters per second)
+pub const RACE_SPEED: f32 = 80.;
+/// Fraction of race speed the pace line moves at
+const PACE_FRACTION: f32 = 0.4;
+/// Greatest fraction of the track the pace line may start behind the finish line
+const MAX_LEAD_FRACTION: f32 = 1. / 8.;
+/// Throttle per meter per second short of the speed wanted
+const SPEED_GAIN: f32 = 0.2;
+/// Extra speed wanted per meter behind the formation slot (per second)
+const GAP_GAIN: f32 = 0.5;
+/// Roll added per meter to the side of the formation slot
+const SLOT_GAIN: f32 = 0.02;
+/// Roll taken off per meter per second of drift across the track
+const SLOT_DAMPING: f32 = 0.05;
+/// Most roll the formation slot may add, so it never overpowers the player's steering
+const MAX_SLOT_ROLL: f32 = 0.15;
+/// How far ahead of the pace line a ship may get before it has jumped the start (meters)
+const JUMP_TOLERANCE: f32 = 1.;
+
+/// The virtual line leading the formation, the same for every racer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PaceLine {
+    /// Distance behind the finish line when the countdown begins (meters)
+    pub lead: f32,
+    /// Meters per second
+    pub speed: f32,
+}
+
+impl PaceLine {
+    pub fn new(path: &Curve) -> Self {
+        // Shorter tracks get a slower pace, so the line still reaches the finish at "GO"
+        let lead = path.clamp_distance(RACE_SPEED * PACE_FRACTION * GO_TIME, MAX_LEAD_FRACTION);
+        Self {
+            lead,
+            speed: lead / GO_TIME,
+        }
+    }
+
+    /// Distance of the line behind the finish at `since_go` seconds after "GO"
+    pub fn behind(&self, since_go: f32) -> f32 {
+        (-since_go * self.speed).max(0.)
+    }
+}
+
+/// The local ship's place in the formation. Until the green flag it drives the throttle, and
+/// gently steers back towards the slot's line across the track.
+pub struct Formation {
+    pace: PaceLine,
+    /// Orientation of the slot, which the ship sets off at pace in
+    orient: Quat,
+    /// Distance to keep behind the pace line (meters)
+    gap: f32,
+    /// Offset across the track to keep to (meters)
+    lateral: f32,
+    /// Whether the ship has been set moving at pace
+    rolling: bool,
+}
+
+impl Formation {
+    /// Formation behind the pace line, starting from the grid `slot`
+    pub fn new(path: &Curve, slot: Transform) -> Self {
+        let pace = PaceLine::new(path);
+        let (behind, lateral, _) = behind_finish(path, slot.pos);
+        Self {
+            pace,
+            orient: slot.orient,
+            gap: (behind - pace.lead).max(0.),
+            lateral,
+            rolling: false,
+        }
+    }
+
+    /// Hold the slot at `since_go`, before the green flag, by overriding the throttle and
+    /// adding to the steering in `input`. Returns whether the ship is ahead of the pace line.
+    pub fn hold(
+        &mut self,
+        path: &Curve,
+        since_go: f32,
+        state: &mut ShipState,
+        input: &mut InputAbstraction,
+    ) -> bool {
+        // The grid is already on the move
+        if !self.rolling {
+            self.rolling = true;
+            state.kt.vel = self.orient * Vec3::X * self.pace.speed;
+        }
+
+        let (behind, lateral, orient) = behind_finish(path, state.tf.pos);
+        let pace_behind = self.pace.behind(since_go);
+        let track_rel_vel = orient.inverse() * state.kt.vel;
+
+        let wanted_speed = self.pace.speed + (behind - pace_behind - self.gap) * GAP_GAIN;
+        input.throttle = ((wanted_speed - track_rel_vel.x) * SPEED_GAIN).clamp(-1., 1.);
+        input.boost = false;
+
+        let nudge = (self.lateral - lateral) * SLOT_GAIN - track_rel_vel.z * SLOT_DAMPING;
+        let steer = input.steer_mut();
+        *steer = (*steer + nudge.clamp(-MAX_SLOT_ROLL, MAX_SLOT_ROLL)).clamp(-1., 1.);
+
+        behind < pace_behind - JUMP_TOLERANCE
+    }
+}
+
+/// Distance along the track behind the finish line (negative past it), offset across the
+/// track, and the track's orientation, at `pos`
+fn behind_finish(path: &Curve, pos: Vec3) -> (f32, f32, Quat) {
+    let length = path.length();
+    let t = path.nearest_t(pos);
+    let finish = path.arc_length(path.finish_line());
+    let mut behind = (finish - path.arc_length(t)).rem_euclid(length);
+    if behind > length / 2. {
+        behind -= length;
+    }
+
+    let frame = path.lerp(t);
+    let lateral = (frame.orient.inverse() * (pos - frame.pos)).z;
+    (behind, lateral, frame.orient)
+}
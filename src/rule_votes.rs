@@ -0,0 +1,180 @@
+use std::{collections::HashMap, fmt};
+
+use cimvr_engine_interface::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{conditions::Conditions, OpenProposal, RaceSettings, RuleVote};
+
+/// Most laps a race can be voted up to
+const MAX_LAPS: usize = 20;
+
+/// A change to the race rules, which the lobby votes on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Proposal {
+    Laps(usize),
+    CatchUp(bool),
+    Authoritative(bool),
+    /// Laps spending too long off the racing surface don't count
+    InvalidateLaps(bool),
+    Conditions(Conditions),
+    /// Highest rated racers start at the front
+    SeedByRating(bool),
+    /// Start behind a pace line instead of from a standstill
+    RollingStart(bool),
+    /// Race in teams, taking turns
+    Relay(bool),
+}
+
+impl Proposal {
+    /// Proposals offered between races, based on the current rules
+    pub fn defaults(settings: &RaceSettings) -> Vec<Self> {
+        vec![
+            Proposal::Laps((settings.laps + 2).min(MAX_LAPS)),
+            Proposal::CatchUp(!settings.catch_up),
+        ]
+    }
+
+    pub fn apply(self, settings: &mut RaceSettings) {
+        match self {
+            Proposal::Laps(laps) => settings.laps = laps.clamp(1, MAX_LAPS),
+            Proposal::CatchUp(enabled) => settings.catch_up = enabled,
+            Proposal::Authoritative(enabled) => settings.authoritative = enabled,
+            Proposal::InvalidateLaps(enabled) => settings.invalidate_laps = enabled,
+            Proposal::Conditions(conditions) => settings.conditions = conditions,
+            Proposal::SeedByRating(enabled) => settings.seed_by_rating = enabled,
+            Proposal::RollingStart(enabled) => settings.rolling_start = enabled,
+            Proposal::Relay(enabled) => settings.relay = enabled,
+        }
+    }
+}
+
+impl fmt::Display for Proposal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Proposal::Laps(laps) => write!(f, "{} laps", laps),
+            Proposal::CatchUp(true) => write!(f, "Enable catch-up"),
+            Proposal::CatchUp(false) => write!(f, "Disable catch-up"),
+            Proposal::Authoritative(true) => write!(f, "Server authoritative physics"),
+            Proposal::Authoritative(false) => write!(f, "Client authoritative physics"),
+            Proposal::InvalidateLaps(true) => write!(f, "Invalidate laps cut off track"),
+            Proposal::InvalidateLaps(false) => write!(f, "Count every lap"),
+            Proposal::Conditions(conditions) => {
+                write!(f, "Race in {} conditions", conditions.name())
+            }
+            Proposal::SeedByRating(true) => write!(f, "Grid ordered by rating"),
+            Proposal::SeedByRating(false) => write!(f, "Grid in any order"),
+            Proposal::RollingStart(true) => write!(f, "Rolling start"),
+            Proposal::RollingStart(false) => write!(f, "Standing start"),
+            Proposal::Relay(true) => write!(f, "Team relay race"),
+            Proposal::Relay(false) => write!(f, "Individual race"),
+        }
+    }
+}
+
+struct Ballot {
+    id: u32,
+    proposal: Proposal,
+    votes: HashMap<ClientId, bool>,
+}
+
+/// Server-side tally of the open rule votes
+pub struct RuleVotes {
+    next_id: u32,
+    open: Vec<Ballot>,
+    /// Whether clients need to be sent the current votes
+    changed: bool,
+}
+
+impl RuleVotes {
+    pub fn new() -> Self {
+        Self {
+            next_id: 0,
+            open: vec![],
+            changed: false,
+        }
+    }
+
+    /// Open a vote, unless the same proposal is already open. Returns true if it was opened.
+    pub fn propose(&mut self, proposal: Proposal) -> bool {
+        if self.open.iter().any(|ballot| ballot.proposal == proposal) {
+            return false;
+        }
+
+        self.open.push(Ballot {
+            id: self.next_id,
+            proposal,
+            votes: HashMap::new(),
+        });
+        self.next_id += 1;
+        self.changed = true;
+        true
+    }
+
+    /// Record a vote; a later vote from the same client replaces their earlier one
+    pub fn vote(&mut self, client_id: ClientId, id: u32, yes: bool) {
+        if let Some(ballot) = self.open.iter_mut().find(|ballot| ballot.id == id) {
+            ballot.votes.insert(client_id, yes);
+            self.changed = true;
+        }
+    }
+
+    /// Close every vote which has a majority either way among `n_voters`,
+    /// returning each closed proposal and whether it passed
+    pub fn tally(&mut self, n_voters: usize) -> Vec<(Proposal, bool)> {
+        let mut decided = vec![];
+        if n_voters == 0 {
+            return decided;
+        }
+
+        self.open.retain(|ballot| {
+            let yes = ballot.votes.values().filter(|&&yes| yes).count();
+            let no = ballot.votes.len() - yes;
+            let result = if yes * 2 > n_voters {
+                Some(true)
+            } else if no * 2 >= n_voters {
+                Some(false)
+            } else {
+                None
+            };
+            if let Some(passed) = result {
+                decided.push((ballot.proposal, passed));
+            }
+            result.is_none()
+        });
+        self.changed |= !decided.is_empty();
+        decided
+    }
+
+    /// Stop voting, e.g. when a race starts
+    pub fn close_all(&mut self) {
+        self.changed |= !self.open.is_empty();
+        self.open.clear();
+    }
+
+    /// Send the votes again, e.g. to a newly connected client
+    pub fn resend(&mut self) {
+        self.changed = true;
+    }
+
+    /// The votes to broadcast, if they have changed since last time
+    pub fn take_message(&mut self) -> Option<RuleVote> {
+        if !std::mem::take(&mut self.changed) {
+            return None;
+        }
+
+        let proposals = self
+            .open
+            .iter()
+            .map(|ballot| {
+                let yes = ballot.votes.values().filter(|&&yes| yes).count();
+                OpenProposal {
+                    id: ballot.id,
+                    proposal: ballot.proposal,
+                    yes,
+                    no: ballot.votes.len() - yes,
+                }
+            })
+            .collect();
+        Some(RuleVote(proposals))
+    }
+}
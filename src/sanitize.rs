@@ -0,0 +1,222 @@
+//! Guards against NaN and infinity. One bad frame, e.g. a degenerate curve frame, spreads
+//! through the ship uploads to every client and breaks their camera math, so ship state and
+//! the camera are checked at the boundaries: before uploading, before the server writes an
+//! upload, after integrating and before placing the camera.
+use std::collections::HashMap;
+
+use cimvr_common::Transform;
+use cimvr_engine_interface::{prelude::*, println};
+
+use crate::{curve::Curve, kinematics::KinematicPhysics, ShipCharacteristics};
+
+pub fn transform_is_finite(tf: &Transform) -> bool {
+    tf.pos.is_finite() && tf.orient.is_finite()
+}
+
+pub fn kinematics_is_finite(kt: &KinematicPhysics) -> bool {
+    kt.vel.is_finite()
+        && kt.ang_vel.is_finite()
+        && kt.mass.is_finite()
+        && kt.mass > 0.
+        && kt.moment.is_finite()
+        && kt.moment > 0.
+}
+
+/// Put a ship back onto the track nearest it, or at the finish line if even its position is
+/// bad, at rest, with the standard mass if its own is bad
+fn put_back(path: &Curve, tf: &mut Transform, kt: &mut KinematicPhysics) {
+    *tf = match tf.pos.is_finite() {
+        true => path.ctrlps[path.nearest_ctrlp(tf.pos)],
+        false => path.lerp(path.finish_line()),
+    };
+    kt.vel = Default::default();
+    kt.ang_vel = Default::default();
+    if !kinematics_is_finite(kt) {
+        let standard = ShipCharacteristics::standard();
+        kt.mass = standard.mass;
+        kt.moment = standard.moment;
+    }
+}
+
+/// Where a bad value was caught
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Boundary {
+    /// Local ship, about to be uploaded
+    Upload,
+    /// A client's upload, about to be written on the server
+    ServerUpdate,
+    /// An entity just integrated by `kinematics::simulate`, or smoothed on the client
+    Simulate,
+    /// The camera, about to be placed
+    Camera,
+}
+
+/// Counts what was caught where, and puts it right
+#[derive(Default)]
+pub struct Sanitizer {
+    caught: HashMap<Boundary, u32>,
+}
+
+impl Sanitizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn caught(&mut self, boundary: Boundary, what: &str) {
+        let count = self.caught.entry(boundary).or_default();
+        *count += 1;
+        // Loud in development, where the cause can be chased down; recovered from otherwise
+        if cfg!(debug_assertions) {
+            panic!("Non-finite {} at {:?}", what, boundary);
+        }
+        println!(
+            "Non-finite {} at {:?} (#{}); recovering",
+            what, boundary, count
+        );
+    }
+
+    /// Put a ship with a non-finite transform or kinematics back onto the track nearest it,
+    /// at rest. Returns whether it had to be.
+    pub fn ship(
+        &mut self,
+        boundary: Boundary,
+        path: &Curve,
+        tf: &mut Transform,
+        kt: &mut KinematicPhysics,
+    ) -> bool {
+        if transform_is_finite(tf) && kinematics_is_finite(kt) {
+            return false;
+        }
+        self.caught(boundary, "ship state");
+        put_back(path, tf, kt);
+        true
+    }
+
+    /// Check every entity of a "Kinematics" query, with `Transform` and `KinematicPhysics`
+    /// writable, after moving them
+    pub fn simulated(&mut self, query: &mut QueryResult, path: &Curve) {
+        for key in query.iter("Kinematics") {
+            let mut tf = query.read::<Transform>(key);
+            let mut kt = query.read::<KinematicPhysics>(key);
+            if self.ship(Boundary::Simulate, path, &mut tf, &mut kt) {
+                query.write(key, &tf);
+                query.write(key, &kt);
+            }
+        }
+    }
+
+    /// A finite camera transform; `last_good` in place of a bad one
+    pub fn camera(&mut self, tf: Transform, last_good: Transform) -> Transform {
+        if transform_is_finite(&tf) {
+            return tf;
+        }
+        self.caught(Boundary::Camera, "camera transform");
+        last_good
+    }
+
+    /// Everything caught so far
+    pub fn total(&self) -> u32 {
+        self.caught.values().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cimvr_common::glam::{Quat, Vec3};
+
+    use super::*;
+    use crate::harness::ring;
+
+    const BAD: [f32; 3] = [f32::NAN, f32::INFINITY, f32::NEG_INFINITY];
+
+    fn ship() -> (Transform, KinematicPhysics) {
+        let tf = Transform::new().with_position(Vec3::new(95., 1., 10.));
+        let kt = KinematicPhysics {
+            vel: Vec3::new(30., 0., -2.),
+            ang_vel: Vec3::Y,
+            ..KinematicPhysics::new(1.)
+        };
+        (tf, kt)
+    }
+
+    /// A ship with one of its values set to `bad`, for each value in turn
+    fn poisoned(bad: f32) -> Vec<(Transform, KinematicPhysics)> {
+        let mut ships = vec![];
+        for axis in 0..3 {
+            let (mut tf, mut kt) = ship();
+            tf.pos[axis] = bad;
+            ships.push((tf, kt));
+            (tf, kt) = ship();
+            kt.vel[axis] = bad;
+            ships.push((tf, kt));
+            (tf, kt) = ship();
+            kt.ang_vel[axis] = bad;
+            ships.push((tf, kt));
+        }
+        let (mut tf, kt) = ship();
+        tf.orient = Quat::from_xyzw(bad, 0., 0., 1.);
+        ships.push((tf, kt));
+        for mass in [bad, 0., -1.] {
+            let (tf, mut kt) = ship();
+            kt.mass = mass;
+            ships.push((tf, kt));
+            let (tf, mut kt) = ship();
+            kt.moment = mass;
+            ships.push((tf, kt));
+        }
+        ships
+    }
+
+    #[test]
+    fn any_bad_value_is_caught() {
+        let (tf, kt) = ship();
+        assert!(transform_is_finite(&tf) && kinematics_is_finite(&kt));
+        for bad in BAD {
+            for (i, (tf, kt)) in poisoned(bad).iter().enumerate() {
+                let caught = !transform_is_finite(tf) || !kinematics_is_finite(kt);
+                assert!(caught, "{} in ship {}: {:?} {:?}", bad, i, tf, kt);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_ships_are_put_back_on_the_track_at_rest() {
+        let path = ring(16, 100.);
+        for bad in BAD {
+            for (i, (mut tf, mut kt)) in poisoned(bad).into_iter().enumerate() {
+                let lost = !tf.pos.is_finite();
+                put_back(&path, &mut tf, &mut kt);
+                assert!(transform_is_finite(&tf), "{} in ship {}", bad, i);
+                assert!(kinematics_is_finite(&kt), "{} in ship {}", bad, i);
+                assert_eq!((kt.vel, kt.ang_vel), (Vec3::ZERO, Vec3::ZERO));
+
+                // Where it was, if that much is known
+                let expected = match lost {
+                    true => path.lerp(path.finish_line()).pos,
+                    false => path.ctrlps[0].pos,
+                };
+                assert_eq!(tf.pos, expected, "{} in ship {}", bad, i);
+            }
+        }
+    }
+
+    #[test]
+    fn good_values_pass_untouched() {
+        let mut sanitizer = Sanitizer::new();
+        let path = ring(16, 100.);
+        let (mut tf, mut kt) = ship();
+        assert!(!sanitizer.ship(Boundary::Upload, &path, &mut tf, &mut kt));
+        assert_eq!((tf.pos, kt.vel), (ship().0.pos, ship().1.vel));
+        let camera = Transform::new().with_position(Vec3::Y);
+        assert_eq!(sanitizer.camera(camera, Transform::new()).pos, Vec3::Y);
+        assert_eq!(sanitizer.total(), 0);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "Non-finite camera transform at Camera")]
+    fn bad_values_are_loud_in_development() {
+        let camera = Transform::new().with_position(Vec3::NAN);
+        Sanitizer::new().camera(camera, Transform::new());
+    }
+}
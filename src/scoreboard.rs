@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use cimvr_common::ui::UiStateHelper;
+use cimvr_engine_interface::prelude::*;
+
+use crate::{
+    label_panel::LabelPanel, race_rules::format_race_time, rating::Rating, LobbyStatus,
+    RaceProgress, RacerFinished, RacerNames,
+};
+
+/// Standings of the race in progress, shown to spectators
+pub struct Scoreboard {
+    names: HashMap<ClientId, String>,
+    laps: HashMap<ClientId, usize>,
+    /// Finishes in the order the server announced them
+    finished: Vec<RacerFinished>,
+    /// Ratings as of the last lobby update
+    ratings: HashMap<ClientId, Rating>,
+    panel: LabelPanel,
+}
+
+impl Scoreboard {
+    pub fn new() -> Self {
+        Self {
+            names: HashMap::new(),
+            laps: HashMap::new(),
+            finished: vec![],
+            ratings: HashMap::new(),
+            panel: LabelPanel::new("Standings"),
+        }
+    }
+
+    /// Start over for a new race between the given racers
+    pub fn start(&mut self, RacerNames(names): RacerNames) {
+        self.names = names.into_iter().collect();
+        self.laps.clear();
+        self.finished.clear();
+    }
+
+    pub fn set_progress(&mut self, progress: &RaceProgress) {
+        self.laps = progress.laps.iter().copied().collect();
+    }
+
+    pub fn set_ratings(&mut self, LobbyStatus(entries): &LobbyStatus) {
+        self.ratings = entries
+            .iter()
+            .filter(|entry| !entry.duplicate)
+            .map(|entry| (entry.client_id, entry.rating))
+            .collect();
+    }
+
+    /// `name`, followed by their rating if they have one
+    fn rated_name(&self, client_id: ClientId, name: &str) -> String {
+        match self.ratings.get(&client_id) {
+            Some(rating) => format!("{} ({})", name, rating),
+            None => name.to_string(),
+        }
+    }
+
+    pub fn finish(&mut self, finish: RacerFinished) {
+        self.finished.push(finish);
+        self.finished.sort_by_key(|f| f.position);
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .finished
+            .iter()
+            .map(|f| {
+                let time = format_race_time(f.time);
+                let name = self.rated_name(f.client_id, &f.username);
+                format!("P{} {} - {} (finished)", f.position, name, time)
+            })
+            .collect();
+
+        // Everyone else, furthest along first
+        let mut racing: Vec<(&ClientId, &String)> = self
+            .names
+            .iter()
+            .filter(|(id, _)| !self.finished.iter().any(|f| f.client_id == **id))
+            .collect();
+        racing.sort_by_key(|(id, _)| std::cmp::Reverse(self.laps.get(id).copied().unwrap_or(0)));
+        for (id, name) in racing {
+            let lap = self.laps.get(id).copied().unwrap_or(0);
+            lines.push(format!("{} - lap {}", self.rated_name(*id, name), lap));
+        }
+
+        lines
+    }
+
+    pub fn update(&mut self, io: &mut EngineIo, gui: &mut UiStateHelper, visible: bool) {
+        let visible = visible && !self.names.is_empty();
+        let lines = self.lines();
+        self.panel.update(io, gui, visible, lines);
+    }
+}
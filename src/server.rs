@@ -1,29 +1,250 @@
 use std::{
     collections::{HashMap, HashSet},
-    fmt::format,
+    fmt::Display,
 };
 
 use chat::{ChatDownload, ChatUpload};
-use cimvr_common::{
-    glam::Vec3,
-    render::{Primitive, Render},
-    Transform,
-};
+use cimvr_common::{glam::Vec3, render::Render, Transform};
 use cimvr_engine_interface::{dbg, prelude::*, println, FrameTime};
 use kinematics::KinematicPhysics;
 
 use crate::{
-    kinematics, ClientReady, Finished, ServerShipComponent, ShipUpload, StartRace, SHIP_RDR,
+    afk::AfkTracker,
+    barriers::BarrierPlacement,
+    billboards::TrackConfig,
+    builtin_tracks::BuiltinTrack,
+    commands::{parse_command, Command},
+    conditions::{Conditions, Weather},
+    controls::{ControlScheme, ShipController, ShipState, TuningParams},
+    countdown::GO_TIME,
+    curve::{parse_path, path_obj_to_curve, Curve, PathError},
+    envelope::{self, LegacyDecodes},
+    fairness::{FairnessMonitor, RaceDiagnostics, Thresholds},
+    hud::HudVisibility,
+    input_state::KeyBindings,
+    items::{BOOST_TIME, PICKUP_RESPAWN_TIME},
+    kinematics::{self, FIXED_DT},
+    markers::{marker_color, MARKER_TTL},
+    net_stats::StreamStats,
+    obj::try_obj_to_mesh,
+    pickups::pickup_layout,
+    player_colors::PlayerColor,
+    race_log::{RaceLog, LINES_PER_DUMP},
+    race_rng::RaceRng,
+    race_rules::{
+        format_race_time, handicap_factors, race_order, GridLayout, LapEvent, LapTracker,
+        MAX_CATCH_UP,
+    },
+    rating::{update_ratings, Rating},
+    records::{Records, TrackRecords},
+    relay::{Handoff, Relay, DROP_PENALTY, TEAM_SIZE},
+    rolling_start::PaceLine,
+    rule_votes::{Proposal, RuleVotes},
+    sanitize::{Boundary, Sanitizer},
+    settings::SavedSettings,
+    ship_class::ShipClass,
+    strings::{Key, Language, Text},
+    track::TrackAssembler,
+    upload_check::{UploadChecks, Verdict, MAX_STRIKES},
+    wall_ride::{ShieldedSection, WallRide},
+    watchdog::{MonotonicClock, Watchdog},
+    Announcement, Assists, CastVote, ClientReady, Finished, HandicapFactor, Hello, InputUpload,
+    LapTime, LoadSettings, LobbyEntry, LobbyStatus, LogDump, MarkerPlaced, NextRace, PickupClaim,
+    PickupRejected, PickupState, PlaceMarker, PushTrack, RaceAbort, RaceEvent, RaceEvents,
+    RaceOver, RacePositions, RaceProgress, RaceResults, RaceSettings, RacerFinished, RacerLaps,
+    RacerNames, RecordsSync, RelayStatus, RequestTimeTrial, RequestTrack, ResumeRace, SaveSettings,
+    SealedFinished, SealedShipUpload, ServerConfig, ServerShipComponent, ShipStateAck, ShipUpload,
+    StartRace, TrackData, TrackSelect, YourLeg, PROTOCOL_VERSION, RACE_EVENT_VERSION,
 };
 
+#[cfg(feature = "dev-tracks")]
+use crate::track::TrackFiles;
+
 // All state associated with server-side behaviour
 pub struct ServerState {
     winner: Option<(ClientId, f32)>,
-    reset_countdown: f32,
+    /// Whoever is on a time trial: a race of their own, which nobody else had to be ready for
+    time_trial: Option<ClientId>,
+    /// Time since the winner finished, counted in frame deltas so that it stands still while
+    /// no frames arrive (seconds)
+    since_winner: f32,
+    config: ServerConfig,
+    path: Curve,
+    /// Built-in track being raced, or `None` once the admin has uploaded their own
+    track: Option<BuiltinTrack>,
+    /// Settings to be used for the next race
+    settings: RaceSettings,
+    /// Settings of the current race. Only changes when a race starts, so that modes never mix.
+    race_settings: RaceSettings,
+    /// Last input sequence number simulated for each client, in authoritative races
+    acked_seq: HashMap<ClientId, u32>,
+    /// Number of races started so far
+    races_started: u64,
+    /// Server-side lap counting of each racer
+    lap_trackers: HashMap<ClientId, LapTracker>,
+    /// Time since the last `RaceProgress` broadcast
+    progress_timer: f32,
+    /// Thrust multiplier last sent to each racer, from their place while catch-up is on
+    handicaps: HashMap<ClientId, f32>,
+    /// Racers who have finished the current race, in the order they did
+    finish_order: Vec<ClientId>,
+    /// Finishing time of each of them, a relay team's total for its last runner (seconds)
+    finish_times: HashMap<ClientId, f32>,
+    /// Everyone who started the current race, so that those who left still get a result
+    entrants: Vec<ClientId>,
+    /// Everyone in the current race, as placed last frame
+    race_order: Vec<ClientId>,
+    /// Time since the last `RacePositions` broadcast
+    positions_timer: f32,
+    /// Finishes racers have told us of which our own lap counting hasn't confirmed yet, with
+    /// the server time each arrived
+    finish_claims: HashMap<ClientId, (Finished, f32)>,
+    /// Race time at which our lap counting saw each racer finish, while we wait for their claim
+    finish_seen: HashMap<ClientId, f32>,
+    usernames: HashMap<ClientId, String>,
+    /// Number of pickups in the current race
+    n_pickups: usize,
+    /// Race time until which each taken pickup is unavailable
+    pickups_taken: HashMap<u32, f32>,
+    /// Boost time each racer has collected but not yet spent, in authoritative races
+    boost_allowance: HashMap<ClientId, f32>,
+    /// Longest connected client, who may use admin commands
+    admin: Option<ClientId>,
+    /// Track files being uploaded by the admin
+    track_upload: TrackAssembler,
+    /// Dev track asked for by the admin, applied as though they'd uploaded it
+    #[cfg(feature = "dev-tracks")]
+    dev_track: Option<(ClientId, TrackFiles)>,
+    /// Server time at which the current race started
+    race_start: f32,
+    /// Moving barriers on the track, which racers collide with
+    barriers: Vec<BarrierPlacement>,
+    /// Stretches of wall which racers can ride
+    shielded_walls: Vec<ShieldedSection>,
+    /// Each racer's hold on the shielded walls, in authoritative races
+    wall_rides: HashMap<ClientId, WallRide>,
+    /// Time before a boost pad can fire again for each racer, in authoritative races
+    boost_cooldowns: HashMap<ClientId, f32>,
+    /// Statistics of each racer's uploads in the current race, for fairness review
+    fairness: FairnessMonitor,
+    /// Catches bad uploads and simulation results before they're passed on
+    sanitizer: Sanitizer,
+    /// Limits above which a racer is flagged in the fairness review
+    fairness_thresholds: Thresholds,
+    /// Fairness review of the last race to finish
+    diagnostics: Option<RaceDiagnostics>,
+    /// Server time at which the pace line reaches the finish line, while a rolling start is
+    /// still under way
+    green_flag: Option<f32>,
+    /// Racers who disconnected mid-race, by username, while they may still come back
+    lagging: HashMap<String, LaggingRacer>,
+    /// Open votes on changes to `settings`
+    rule_votes: RuleVotes,
+    /// Arrival of each client's ship or input uploads
+    upload_stats: HashMap<ClientId, StreamStats>,
+    /// Racers' uploads so far this race, for dropping impossible ones
+    upload_checks: UploadChecks,
+    /// Clients whose uploads have stalled, so their ships are held still
+    stalled: HashSet<ClientId>,
+    /// Server time which never runs backwards, for deadlines
+    clock: MonotonicClock,
+    /// Resets races which have got stuck
+    watchdog: Watchdog,
+    /// When each client last did anything
+    afk: AfkTracker,
+    /// Last `LobbyStatus` broadcast
+    lobby: LobbyStatus,
+    /// Last `NextRace` broadcast, if it's still current for every client
+    next_race: Option<NextRace>,
+    /// Race events sent so far, keeping them in order
+    race_events: RaceEventStream,
+    /// Server time at which each racer began their current lap
+    lap_starts: HashMap<ClientId, f32>,
+    /// Race time at which each racer completed each of their laps so far
+    lap_completions: HashMap<ClientId, Vec<f32>>,
+    /// Fastest laps and races on each track since the server started
+    records: Records,
+    /// Last `RecordsSync` broadcast, if it's still current for every client
+    records_sent: Option<TrackRecords>,
+    /// Rating of every player who has raced since the server started, by username
+    ratings: HashMap<String, Rating>,
+    /// Settings each player asked us to keep for them, by username
+    saved_settings: HashMap<String, SavedSettings>,
+    /// Color each player last had, by username, to give back when they reconnect
+    player_colors: HashMap<String, PlayerColor>,
+    /// Names of the racers in the current race whose results count towards their ratings
+    rated: HashMap<ClientId, String>,
+    /// Clients using the same name as a newer connection, who are left out of ratings
+    duplicates: HashSet<ClientId>,
+    /// What happened since the server started, for the host to download
+    log: RaceLog,
+    /// Scratch space for the latest `ShipUpload` from each client, reused every frame
+    ship_uploads: HashMap<ClientId, ShipUpload>,
+    /// Scratch space for each client's `InputUpload`s, reused every frame
+    input_uploads: HashMap<ClientId, Vec<InputUpload>>,
+    /// Teams and their legs, in a relay race
+    relay: Relay,
+    /// Protocol version each client replied to our `Hello` with
+    protocols: HashMap<ClientId, u32>,
+    /// Enveloped messages from clients which weren't in the current layout
+    legacy_decodes: LegacyDecodes,
+}
+
+/// A racer who has disconnected, whose ship is kept for a while in case they return
+struct LaggingRacer {
+    /// ID they had before disconnecting
+    client_id: ClientId,
+    entity: EntityId,
+    /// Server time at which they disconnected
+    since: f32,
+}
+
+/// Numbers race events with their race, and holds back any about a race before it's
+/// `Started`, so that other plugins always see that first
+#[derive(Default, Debug)]
+struct RaceEventStream {
+    /// Race the events are about, once it's started
+    started: Option<u32>,
+}
+
+impl RaceEventStream {
+    /// The message for `event` in race number `race_id`, unless it would come out of order
+    fn stamp(&mut self, race_id: u32, event: RaceEvent) -> Option<RaceEvents> {
+        if event == RaceEvent::Started {
+            self.started = Some(race_id);
+        }
+        (self.started == Some(race_id)).then_some(RaceEvents {
+            version: RACE_EVENT_VERSION,
+            race_id,
+            event,
+        })
+    }
 }
 
-// All players have 50 seconds after the winner
-const RESET_TIME: f32 = 50.;
+/// Longest frame counted towards the reset, so that a stall doesn't count either
+/// (seconds)
+const MAX_RESET_STEP: f32 = 0.25;
+
+/// Shortest and longest reset times the host can set (seconds)
+const RESET_TIME_RANGE: (f32, f32) = (5., 600.);
+
+/// Lowest and highest speed limits the host can set on uploads (meters per second)
+const MAX_SPEED_RANGE: (f32, f32) = (100., 2000.);
+
+/// Rough time a lap takes, for judging when a race has got stuck (seconds)
+const EXPECTED_LAP_TIME: f32 = 90.;
+
+/// Time between `RaceProgress` broadcasts (seconds)
+const PROGRESS_INTERVAL: f32 = 0.25;
+
+/// Time between `RacePositions` broadcasts (seconds)
+const POSITIONS_INTERVAL: f32 = 0.25;
+
+/// Furthest a racer's own finish time may be from ours before ours is used instead (seconds)
+const FINISH_TOLERANCE: f32 = 0.5;
+
+/// Longest a finish claim and our own lap counting are given to agree (seconds)
+const FINISH_CLAIM_WAIT: f32 = 2.;
 
 impl UserState for ServerState {
     // Implement a constructor
@@ -33,9 +254,13 @@ impl UserState for ServerState {
             .add_system(Self::conn_update)
             .stage(Stage::PreUpdate)
             .subscribe::<Connections>()
+            .subscribe::<FrameTime>()
             .query(
                 "ServerShip",
-                Query::new().intersect::<ServerShipComponent>(Access::Write),
+                Query::new()
+                    .intersect::<ServerShipComponent>(Access::Write)
+                    .intersect::<Transform>(Access::Write)
+                    .intersect::<KinematicPhysics>(Access::Write),
             )
             .build();
 
@@ -54,9 +279,14 @@ impl UserState for ServerState {
         sched
             .add_system(Self::client_state_update)
             .subscribe::<ClientReady>()
+            .subscribe::<RequestTimeTrial>()
+            .subscribe::<FrameTime>()
             .query(
                 "ServerShips",
-                Query::new().intersect::<ServerShipComponent>(Access::Write),
+                Query::new()
+                    .intersect::<ServerShipComponent>(Access::Write)
+                    .intersect::<Transform>(Access::Write)
+                    .intersect::<KinematicPhysics>(Access::Write),
             )
             .build();
 
@@ -64,45 +294,292 @@ impl UserState for ServerState {
             .add_system(Self::win_reset)
             .query(
                 "Clients",
-                Query::new().intersect::<ServerShipComponent>(Access::Write),
+                Query::new()
+                    .intersect::<ServerShipComponent>(Access::Write)
+                    .intersect::<Transform>(Access::Write)
+                    .intersect::<KinematicPhysics>(Access::Write),
             )
-            .subscribe::<Finished>()
+            .subscribe::<SealedFinished>()
             .subscribe::<FrameTime>()
             .subscribe::<Connections>()
             .build();
 
         sched
             .add_system(Self::ship_update)
-            .subscribe::<ShipUpload>()
+            .subscribe::<SealedShipUpload>()
+            .subscribe::<FrameTime>()
+            .query(
+                "ServerShips",
+                Query::new()
+                    .intersect::<ServerShipComponent>(Access::Write)
+                    .intersect::<Transform>(Access::Write)
+                    .intersect::<KinematicPhysics>(Access::Write),
+            )
+            .build();
+
+        sched
+            .add_system(Self::input_update)
+            .subscribe::<InputUpload>()
+            .subscribe::<FrameTime>()
             .query(
                 "ServerShips",
                 Query::new()
-                    .intersect::<ServerShipComponent>(Access::Read)
+                    .intersect::<ServerShipComponent>(Access::Write)
                     .intersect::<Transform>(Access::Write)
                     .intersect::<KinematicPhysics>(Access::Write),
             )
             .build();
 
+        sched
+            .add_system(Self::chat_commands)
+            .subscribe::<ChatUpload>()
+            .subscribe::<FrameTime>()
+            .build();
+
+        sched
+            .add_system(Self::server_config)
+            .subscribe::<ServerConfig>()
+            .build();
+
+        sched
+            .add_system(Self::track_upload)
+            .subscribe::<PushTrack>()
+            .query(
+                "ServerShips",
+                Query::new().intersect::<ServerShipComponent>(Access::Write),
+            )
+            .build();
+
+        sched
+            .add_system(Self::pickup_claims)
+            .subscribe::<PickupClaim>()
+            .build();
+
+        sched
+            .add_system(Self::rule_votes)
+            .subscribe::<CastVote>()
+            .subscribe::<FrameTime>()
+            .build();
+
+        sched
+            .add_system(Self::handshake)
+            .subscribe::<Hello>()
+            .build();
+
+        sched
+            .add_system(Self::markers)
+            .subscribe::<PlaceMarker>()
+            .build();
+
+        sched
+            .add_system(Self::settings_store)
+            .subscribe::<SaveSettings>()
+            .build();
+
+        sched
+            .add_system(Self::progress_broadcast)
+            .subscribe::<FrameTime>()
+            .build();
+
+        sched
+            .add_system(Self::race_positions)
+            .subscribe::<FrameTime>()
+            .build();
+
+        sched
+            .add_system(Self::watchdog)
+            .subscribe::<FrameTime>()
+            .query(
+                "Clients",
+                Query::new().intersect::<ServerShipComponent>(Access::Write),
+            )
+            .build();
+
+        let track = BuiltinTrack::default();
+        let path = builtin_path(track);
+
+        let settings = RaceSettings::default();
+        let mut rule_votes = RuleVotes::new();
+        for proposal in Proposal::defaults(&settings) {
+            rule_votes.propose(proposal);
+        }
+
         Self {
             winner: None,
-            reset_countdown: 0.,
+            time_trial: None,
+            since_winner: 0.,
+            config: ServerConfig::default(),
+            path,
+            track: Some(track),
+            settings,
+            race_settings: RaceSettings::default(),
+            acked_seq: HashMap::new(),
+            races_started: 0,
+            lap_trackers: HashMap::new(),
+            progress_timer: 0.,
+            handicaps: HashMap::new(),
+            finish_order: vec![],
+            finish_times: HashMap::new(),
+            entrants: vec![],
+            race_order: vec![],
+            positions_timer: 0.,
+            finish_claims: HashMap::new(),
+            finish_seen: HashMap::new(),
+            usernames: HashMap::new(),
+            n_pickups: 0,
+            pickups_taken: HashMap::new(),
+            boost_allowance: HashMap::new(),
+            admin: None,
+            track_upload: TrackAssembler::new(),
+            #[cfg(feature = "dev-tracks")]
+            dev_track: None,
+            race_start: 0.,
+            barriers: TrackConfig::default().barriers,
+            shielded_walls: TrackConfig::default().shielded_walls,
+            wall_rides: HashMap::new(),
+            boost_cooldowns: HashMap::new(),
+            fairness: FairnessMonitor::new(),
+            sanitizer: Sanitizer::new(),
+            fairness_thresholds: Thresholds::default(),
+            diagnostics: None,
+            green_flag: None,
+            lagging: HashMap::new(),
+            rule_votes,
+            upload_stats: HashMap::new(),
+            upload_checks: UploadChecks::new(),
+            stalled: HashSet::new(),
+            clock: MonotonicClock::new(),
+            watchdog: Watchdog::new(),
+            afk: AfkTracker::new(),
+            lobby: LobbyStatus::default(),
+            next_race: None,
+            race_events: RaceEventStream::default(),
+            lap_starts: HashMap::new(),
+            lap_completions: HashMap::new(),
+            records: Records::new(),
+            records_sent: None,
+            ratings: HashMap::new(),
+            saved_settings: HashMap::new(),
+            player_colors: HashMap::new(),
+            rated: HashMap::new(),
+            duplicates: HashSet::new(),
+            log: RaceLog::new(),
+            ship_uploads: HashMap::new(),
+            legacy_decodes: LegacyDecodes::new(),
+            input_uploads: HashMap::new(),
+            relay: Relay::new(),
+            protocols: HashMap::new(),
         }
     }
 }
 
 impl ServerState {
     fn win_reset(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
-        let Some(Connections { clients }) = io.inbox_first() else { return };
-        let Some(FrameTime { time: server_time, .. }) = io.inbox_first() else { return };
+        let Some(Connections { clients }) = io.inbox_first() else {
+            return;
+        };
+        let Some(FrameTime { time, delta }) = io.inbox_first() else {
+            return;
+        };
+        let server_time = self.clock.now(time);
+        if self.winner.is_some() {
+            self.since_winner += delta.clamp(0., MAX_RESET_STEP);
+        }
+
+        let claims = envelope::inbox_clients::<Finished>(io, &mut self.legacy_decodes);
+        for (client_id, claim) in claims {
+            // Only racers can finish, and only once
+            let mut was_racing = false;
+            for entity in query.iter("Clients") {
+                let shipc = query.read::<ServerShipComponent>(entity);
+                was_racing |= shipc.client_id == client_id && shipc.is_racing;
+            }
+            if !was_racing || self.finish_order.contains(&client_id) {
+                println!("Ignoring finish from {:?}", client_id);
+                continue;
+            }
+            self.finish_claims.insert(client_id, (claim, time));
+        }
 
-        for (client_id, Finished(finish_time)) in io.inbox_clients().collect::<Vec<_>>() {
+        let finishes = self.confirmed_finishes(query, time);
+        for (client_id, Finished(finish_time, assists, laps)) in finishes {
             // Mark this client as having finished
+            let mut finish_tf = Transform::identity();
             for entity in query.iter("Clients") {
                 if query.read::<ServerShipComponent>(entity).client_id == client_id {
                     query.modify::<ServerShipComponent>(entity, |s| s.is_racing = false);
+                    finish_tf = query.read(entity);
+                }
+            }
+            self.log_laps(io, client_id, &laps);
+            // A relay team's time is shared between its runners, so it isn't anyone's record
+            if !self.race_settings.relay {
+                self.race_record(client_id, finish_time);
+            }
+
+            // A time trial is only against the clock, so there's nothing to win
+            if self.time_trial == Some(client_id) {
+                let username = self.usernames.get(&client_id).cloned().unwrap_or_default();
+                let time = format_race_time(finish_time);
+                announce(
+                    io,
+                    Text::new(Key::TimeTrialFinished).with(username).with(time),
+                );
+                continue;
+            }
+
+            // A relay runner's finish only ends their leg, unless they ran the last one
+            let mut username = self.usernames.get(&client_id).cloned().unwrap_or_default();
+            let mut finish_time = finish_time;
+            if self.race_settings.relay {
+                let progress = self.progress_of(client_id);
+                let handoff = self.relay.end_leg(client_id, finish_time);
+                self.send_relay_status(io);
+                if let Some(Handoff::Next(next)) = handoff {
+                    let next_name = self.usernames.get(&next).cloned().unwrap_or_default();
+                    announce(
+                        io,
+                        Text::new(Key::RelayHandoff).with(&username).with(next_name),
+                    );
+                    self.hand_off(io, query, "Clients", next, finish_tf, progress, time);
+                    continue;
+                }
+                if let Some((team, _)) = self.relay.place_of(client_id) {
+                    let team = &self.relay.teams()[team];
+                    username = team.name();
+                    finish_time = team.total();
                 }
             }
 
+            // Places are given in the order finishes arrive, so nobody else can also be P1
+            self.finish_order.push(client_id);
+            self.finish_times.insert(client_id, finish_time);
+            let finish = RacerFinished {
+                client_id,
+                username,
+                position: self.finish_order.len(),
+                time: finish_time,
+            };
+            announce(io, finished_text(&finish));
+            io.send(&finish);
+            if let Some((team, _)) = self.relay.place_of(client_id) {
+                let team = &self.relay.teams()[team];
+                let legs: Vec<String> =
+                    team.leg_times().into_iter().map(format_race_time).collect();
+                let text = Text::new(Key::RelaySplits)
+                    .with(team.name())
+                    .with(legs.join(", "));
+                announce(io, text);
+            }
+            self.race_event(
+                io,
+                RaceEvent::Finished {
+                    client: client_id,
+                    time: finish_time,
+                    position: finish.position,
+                },
+            );
+
             // Decide winner
             if let Some((_, winning_time)) = self.winner {
                 if finish_time > winning_time {
@@ -111,14 +588,23 @@ impl ServerState {
                 }
             }
             self.winner = Some((client_id, finish_time));
-            self.reset_countdown = server_time + RESET_TIME;
+            self.since_winner = 0.;
+            self.watchdog.transition(server_time);
 
             // Write the winner in a chat message
-            if let Some(winner_client) = clients.iter().find(|c| c.id == client_id) {
-                io.send(&ChatDownload {
-                    username: "Server".into(),
-                    text: format!("Winner: {}", winner_client.username),
-                });
+            if clients.iter().any(|c| c.id == client_id) {
+                let note = result_notes(&self.race_settings, assists, &laps);
+                self.log.push(
+                    "winner",
+                    &[
+                        ("race", &self.races_started),
+                        ("name", &finish.username),
+                        ("time", &format!("{:.3}", finish_time)),
+                        ("notes", &note.trim()),
+                    ],
+                );
+                let text = Text::new(Key::Winner).with(&finish.username).with(note);
+                announce(io, text);
             }
         }
 
@@ -128,51 +614,1337 @@ impl ServerState {
             anybody_racing |= query.read::<ServerShipComponent>(entity).is_racing;
         }
 
+        // A time trial is over once its driver finishes or leaves
+        if self.time_trial.is_some() && !anybody_racing {
+            self.time_trial = None;
+            self.watchdog.transition(server_time);
+            io.send(&RaceOver);
+            self.race_event(io, RaceEvent::Reset);
+        }
+
         // Reset
-        let awaiting_losers = server_time > self.reset_countdown;
-        if self.winner.is_some() && (awaiting_losers || !anybody_racing) {
+        let has_winner = self.winner.is_some();
+        if reset_due(
+            has_winner,
+            self.since_winner,
+            self.config.reset_time,
+            anybody_racing,
+        ) {
             dbg!("Reset");
             self.winner = None;
+            self.watchdog.transition(server_time);
+            io.send(&self.race_results());
+            io.send(&RaceOver);
+            self.race_event(io, RaceEvent::Reset);
+            self.update_ratings();
+            self.review_fairness();
+            self.relay.clear();
+
+            // Offer the usual rule changes for the next race
+            for proposal in Proposal::defaults(&self.settings) {
+                self.rule_votes.propose(proposal);
+            }
+        }
+    }
+
+    /// Finishes to count this frame. Our own lap counting decides who has finished; a racer's
+    /// claimed time stands if it's close to ours, and ours is used otherwise, or if they never
+    /// claim. Their claim also carries the assists and lap validity which only they know.
+    fn confirmed_finishes(
+        &mut self,
+        query: &mut QueryResult,
+        time: f32,
+    ) -> Vec<(ClientId, Finished)> {
+        let race_time = time - self.race_start;
+        let mut confirmed = vec![];
+        for entity in query.iter("Clients") {
+            let shipc = query.read::<ServerShipComponent>(entity);
+            let client_id = shipc.client_id;
+            let Some(tracker) = self.lap_trackers.get(&client_id) else {
+                continue;
+            };
+            if !shipc.is_racing || !tracker.finished(self.laps_to_finish(client_id)) {
+                continue;
+            }
+
+            let ours = *self.finish_seen.entry(client_id).or_insert(race_time);
+            let finish = match self.finish_claims.remove(&client_id) {
+                Some((Finished(theirs, assists, laps), _)) => {
+                    if (theirs - ours).abs() > FINISH_TOLERANCE {
+                        self.finish_mismatch(client_id, Some(theirs), ours);
+                        Finished(ours, assists, laps)
+                    } else {
+                        Finished(theirs, assists, laps)
+                    }
+                }
+                None if race_time - ours < FINISH_CLAIM_WAIT => continue,
+                None => {
+                    self.finish_mismatch(client_id, None, ours);
+                    Finished(ours, Assists::default(), vec![])
+                }
+            };
+            self.finish_seen.remove(&client_id);
+            confirmed.push((client_id, finish));
+        }
+
+        // Claims of finishes our lap counting never saw
+        let rejected: Vec<ClientId> = self
+            .finish_claims
+            .iter()
+            .filter(|(_, &(_, arrived))| time - arrived > FINISH_CLAIM_WAIT)
+            .map(|(&client_id, _)| client_id)
+            .collect();
+        for client_id in rejected {
+            let Some((Finished(theirs, ..), _)) = self.finish_claims.remove(&client_id) else {
+                continue;
+            };
+            let (lap, _) = self.progress_of(client_id);
+            println!("Rejected finish from {:?} on lap {}", client_id, lap);
+            let client = format!("{:?}", client_id);
+            self.log.push(
+                "finish_rejected",
+                &[
+                    ("race", &self.races_started),
+                    ("client", &client),
+                    ("claimed", &format!("{:.3}", theirs)),
+                    ("lap", &lap),
+                ],
+            );
+        }
+
+        // Places go by time between racers finishing in the same frame
+        confirmed.sort_by(|(_, a), (_, b)| a.0.total_cmp(&b.0));
+        confirmed
+    }
+
+    /// Note a finish whose claimed time, if there was a claim, disagreed with ours
+    fn finish_mismatch(&mut self, client_id: ClientId, claimed: Option<f32>, counted: f32) {
+        let claimed = claimed.map_or("none".to_string(), |t| format!("{:.3}", t));
+        println!(
+            "Finish of {:?} claimed {}, counted {:.3}",
+            client_id, claimed, counted
+        );
+        let client = format!("{:?}", client_id);
+        self.log.push(
+            "finish_mismatch",
+            &[
+                ("race", &self.races_started),
+                ("client", &client),
+                ("claimed", &claimed),
+                ("counted", &format!("{:.3}", counted)),
+            ],
+        );
+    }
+
+    /// Laps a racer's tracker counts by the end of their race, or of their leg in a relay
+    fn laps_to_finish(&self, client_id: ClientId) -> usize {
+        match self.relay.place_of(client_id) {
+            Some((_, runner)) if self.race_settings.relay => (runner + 1) * self.race_settings.laps,
+            _ => self.race_settings.laps,
         }
     }
 
     fn ship_update(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
+        let time = io.inbox_first::<FrameTime>().map_or(0., |t| t.time);
+
         // Interpret the last shipupload message we received from each client,
         // and use it to set the position of each ship entity
-        let ship_updates: HashMap<ClientId, ShipUpload> =
-            io.inbox_clients::<ShipUpload>().collect();
+        let mut ship_updates = std::mem::take(&mut self.ship_uploads);
+        ship_updates.clear();
+        for (client_id, upload) in
+            envelope::inbox_clients::<ShipUpload>(io, &mut self.legacy_decodes)
+        {
+            let now = self.clock.now(time);
+            self.watchdog.activity(now);
+            let ShipUpload(tf, _, seq) = upload;
+            self.afk.moved(client_id, tf.pos, now);
+            self.upload_stats
+                .entry(client_id)
+                .or_default()
+                .record(time, seq);
+            ship_updates.insert(client_id, upload);
+        }
 
+        let mut excluded = vec![];
         for entity in query.iter("ServerShips") {
-            let ServerShipComponent { client_id, .. } = query.read(entity);
-            if let Some(ShipUpload(transform, kt)) = ship_updates.get(&client_id) {
+            let ServerShipComponent {
+                client_id,
+                is_racing,
+                ..
+            } = query.read(entity);
+
+            // Stop extrapolating ships whose uploads have stalled, rather than letting them
+            // fly off along their last velocity
+            if self.check_stalled(client_id, time) {
+                query.modify::<KinematicPhysics>(entity, |kt| {
+                    kt.vel = Vec3::ZERO;
+                    kt.ang_vel = Vec3::ZERO;
+                });
+            }
+
+            // Authoritative races simulate racers from their inputs instead
+            if is_racing && self.race_settings.authoritative {
+                continue;
+            }
+
+            if let Some(ShipUpload(transform, kt, seq)) = ship_updates.get_mut(&client_id) {
+                self.sanitizer
+                    .ship(Boundary::ServerUpdate, &self.path, transform, kt);
+                if !is_racing {
+                    self.upload_checks.remove(client_id);
+                } else {
+                    let max_speed = self.config.max_speed;
+                    let (pos, vel) = (transform.pos, kt.vel);
+                    let checks = &mut self.upload_checks;
+                    match checks.check(client_id, &self.path, max_speed, time, pos, vel) {
+                        Verdict::Accept => (),
+                        Verdict::Drop(_) => continue,
+                        Verdict::Exclude(rejection) => {
+                            excluded.push((entity, client_id, rejection));
+                            continue;
+                        }
+                    }
+                }
                 query.write(entity, transform);
                 query.write(entity, kt);
+                query.modify::<ServerShipComponent>(entity, |s| s.upload_seq = *seq);
+                if is_racing {
+                    let pos = transform.pos;
+                    self.fairness.record(client_id, time, pos, kt.vel);
+                    let lap = track_laps(&mut self.lap_trackers, &self.path, client_id, pos);
+                    if let Some(lap) = lap {
+                        self.lap_begun(io, client_id, lap, time);
+                    }
+                }
+            }
+        }
+        self.ship_uploads = ship_updates;
+
+        for (entity, client_id, rejection) in excluded {
+            println!(
+                "{:?} taken out of the race after {} dropped uploads, the last {:?}",
+                client_id, MAX_STRIKES, rejection
+            );
+            let client = format!("{:?}", client_id);
+            self.log.push(
+                "racer_excluded",
+                &[
+                    ("race", &self.races_started),
+                    ("client", &client),
+                    ("reason", &format!("{:?}", rejection)),
+                ],
+            );
+            io.send_to_client(
+                &RaceAbort {
+                    race_id: self.races_started as u32,
+                },
+                client_id,
+            );
+            query.modify::<ServerShipComponent>(entity, |s| s.is_racing = false);
+            self.upload_checks.remove(client_id);
+        }
+    }
+
+    /// Whether the client's uploads have stalled, logging when that changes
+    fn check_stalled(&mut self, client_id: ClientId, time: f32) -> bool {
+        let Some(stats) = self.upload_stats.get(&client_id) else {
+            return false;
+        };
+        let stale = stats.is_stale(time);
+        let was_stale = self.stalled.contains(&client_id);
+        if stale != was_stale {
+            let state = if stale { "stalled" } else { "resumed" };
+            println!("{:?} uploads {}: {}", client_id, state, stats.summary(time));
+            match stale {
+                true => self.stalled.insert(client_id),
+                false => self.stalled.remove(&client_id),
+            };
+        }
+        stale
+    }
+
+    fn input_update(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
+        if !self.race_settings.authoritative {
+            return;
+        }
+
+        // Keep the buffers of clients who are still sending
+        let mut inputs = std::mem::take(&mut self.input_uploads);
+        inputs.retain(|_, uploads| {
+            let sending = !uploads.is_empty();
+            uploads.clear();
+            sending
+        });
+        let time = io.inbox_first::<FrameTime>().map_or(0., |t| t.time);
+        let rng = RaceRng::new(self.race_settings.seed);
+        let weather = Weather::new(self.race_settings.conditions, &rng);
+        let barrier_clock = (time - self.race_start - GO_TIME).max(0.);
+        for (client_id, upload) in io.inbox_clients::<InputUpload>() {
+            self.afk.activity(client_id, self.clock.now(time));
+            self.upload_stats
+                .entry(client_id)
+                .or_default()
+                .record(time, upload.seq);
+            inputs.entry(client_id).or_default().push(upload);
+        }
+
+        for entity in query.iter("ServerShips") {
+            let ServerShipComponent {
+                client_id,
+                is_racing,
+                class,
+                ..
+            } = query.read(entity);
+            if !is_racing {
+                continue;
+            }
+            let Some(uploads) = inputs.get_mut(&client_id).filter(|u| !u.is_empty()) else {
+                continue;
+            };
+
+            // Same thrust the client is predicting with
+            let mut ship = class.characteristics();
+            ship.max_impulse *= self.handicap(client_id);
+            let controller = ShipController::new(ship, TuningParams::default(), &self.path)
+                .with_weather(weather)
+                .with_barriers(&self.barriers, barrier_clock)
+                .with_shielded_walls(&self.shielded_walls);
+
+            // Run one fixed step per input, exactly as the client predicted it
+            uploads.sort_by_key(|upload| upload.seq);
+            let last_seq = self.acked_seq.entry(client_id).or_insert(0);
+            let wall_ride = self.wall_rides.entry(client_id).or_default();
+            let boost_cooldown = self.boost_cooldowns.entry(client_id).or_default();
+            let mut state = ShipState {
+                tf: query.read(entity),
+                kt: query.read(entity),
+                wall_ride: *wall_ride,
+                boost_cooldown: *boost_cooldown,
+            };
+            let allowance = self.boost_allowance.entry(client_id).or_insert(0.);
+            for upload in uploads.iter() {
+                // Drop duplicates and stragglers
+                if upload.seq <= *last_seq {
+                    continue;
+                }
+
+                // Only boost with boost that was actually picked up
+                let mut input = upload.input;
+                if input.boost && *allowance >= FIXED_DT {
+                    *allowance -= FIXED_DT;
+                } else {
+                    input.boost = false;
+                }
+
+                (state, _) = controller.fixed_step(input, state);
+                *last_seq = upload.seq;
+            }
+
+            query.write(entity, &state.tf);
+            query.write(entity, &state.kt);
+            *wall_ride = state.wall_ride;
+            *boost_cooldown = state.boost_cooldown;
+            let seq = *last_seq;
+            query.modify::<ServerShipComponent>(entity, |s| s.upload_seq = seq);
+            let lap = track_laps(&mut self.lap_trackers, &self.path, client_id, state.tf.pos);
+            if let Some(lap) = lap {
+                self.lap_begun(io, client_id, lap, time);
             }
+
+            io.send_to_client(
+                &ShipStateAck {
+                    seq,
+                    tf: state.tf,
+                    kt: state.kt,
+                    wall_ride: state.wall_ride,
+                    boost_cooldown: state.boost_cooldown,
+                },
+                client_id,
+            );
         }
+        self.input_uploads = inputs;
+    }
+
+    /// Receive track files from the admin, and switch everyone over once they check out
+    fn track_upload(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
+        let mut complete = None;
+        for (client_id, PushTrack(chunk)) in io.inbox_clients().collect::<Vec<_>>() {
+            if Some(client_id) != self.admin {
+                continue;
+            }
+            if let Some(files) = self.track_upload.push(chunk) {
+                complete = Some((client_id, files));
+            }
+        }
+        #[cfg(feature = "dev-tracks")]
+        if let Some(dev_track) = self.dev_track.take() {
+            complete = Some(dev_track);
+        }
+        let Some((admin, files)) = complete else {
+            return;
+        };
+
+        // Don't apply a broken track
+        let checked = parse_path(&files.path).and_then(|path| {
+            try_obj_to_mesh(&files.environment)
+                .map(|_| path)
+                .map_err(PathError::Obj)
+        });
+        let path = match checked {
+            Ok(path) => path,
+            Err(e) => {
+                announce_to(io, Text::new(Key::TrackRejected).with(e), admin);
+                return;
+            }
+        };
+
+        println!("Reloaded track with {} control points", path.ctrlps.len());
+        self.path = path;
+        self.track = None;
+        let length = format!("{:.1}", self.path.length());
+        let points = self.path.ctrlps.len();
+        self.log.push(
+            "track",
+            &[("track_points", &points), ("track_length", &length)],
+        );
+        if !self.lap_trackers.is_empty() {
+            self.race_event(io, RaceEvent::Reset);
+        }
+
+        // Everyone goes back to the lobby, and nobody has a race to come back to
+        let lagging: Vec<String> = self.lagging.keys().cloned().collect();
+        for username in lagging {
+            self.drop_lagging(io, &username);
+        }
+        self.winner = None;
+        self.time_trial = None;
+        self.lap_trackers.clear();
+        self.finish_order.clear();
+        self.finish_times.clear();
+        self.race_order.clear();
+        self.finish_claims.clear();
+        self.finish_seen.clear();
+        self.pickups_taken.clear();
+        self.boost_allowance.clear();
+        self.rated.clear();
+        self.relay.clear();
+        self.green_flag = None;
+        for entity in query.iter("ServerShips") {
+            query.modify::<ServerShipComponent>(entity, |s| {
+                s.is_racing = false;
+                s.is_ready = false;
+            });
+        }
+
+        for chunk in files.chunks() {
+            io.send(&TrackData(chunk));
+        }
+        announce(io, Text::new(Key::TrackReloaded));
+    }
+
+    /// Switch everyone to a built-in track, ahead of a race starting on it
+    fn select_track(&mut self, io: &mut EngineIo, track: BuiltinTrack) {
+        if self.track == Some(track) {
+            return;
+        }
+        println!("Switching to {}", track.name());
+        self.track = Some(track);
+        self.path = builtin_path(track);
+        let length = format!("{:.1}", self.path.length());
+        let points = self.path.ctrlps.len();
+        self.log.push(
+            "track",
+            &[
+                ("track_name", &track.name()),
+                ("track_points", &points),
+                ("track_length", &length),
+            ],
+        );
+        io.send(&TrackSelect {
+            track_id: track.id(),
+        });
+        announce(io, Text::new(Key::TrackSelected).with(track.name()));
+    }
+
+    /// Give each pickup to the earliest claim, and tell the losers
+    fn pickup_claims(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let mut claims: Vec<(ClientId, PickupClaim)> = io.inbox_clients().collect();
+
+        // Earliest in race time first; the sort is stable, so ties go to whoever arrived first
+        claims.sort_by(|(_, a), (_, b)| a.race_time.total_cmp(&b.race_time));
+
+        for (client_id, claim) in claims {
+            let is_racer = self.lap_trackers.contains_key(&client_id);
+            let valid = is_racer && (claim.pickup_id as usize) < self.n_pickups;
+            let taken_until = self.pickups_taken.get(&claim.pickup_id).copied();
+            let available = taken_until.map_or(true, |until| claim.race_time >= until);
+
+            if !(valid && available) {
+                io.send_to_client(
+                    &PickupRejected {
+                        pickup_id: claim.pickup_id,
+                    },
+                    client_id,
+                );
+                continue;
+            }
+
+            let taken_until = claim.race_time + PICKUP_RESPAWN_TIME;
+            self.pickups_taken.insert(claim.pickup_id, taken_until);
+            *self.boost_allowance.entry(client_id).or_insert(0.) += BOOST_TIME;
+            io.send(&PickupState {
+                pickup_id: claim.pickup_id,
+                taken_until,
+            });
+        }
+    }
+
+    /// Note which protocol each client speaks, and tell those who can't race with us why
+    fn handshake(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        for (client_id, Hello { protocol }) in io.inbox_clients().collect::<Vec<_>>() {
+            self.protocols.insert(client_id, protocol);
+            if protocol == PROTOCOL_VERSION {
+                let username = self.usernames.get(&client_id);
+                if let Some(&saved) = username.and_then(|name| self.saved_settings.get(name)) {
+                    io.send_to_client(&LoadSettings(saved), client_id);
+                }
+                continue;
+            }
+
+            println!(
+                "{:?} speaks protocol {}, not {}",
+                client_id, protocol, PROTOCOL_VERSION
+            );
+            let client = format!("{:?}", client_id);
+            self.log.push(
+                "protocol_mismatch",
+                &[("client", &client), ("protocol", &protocol)],
+            );
+            let text = Text::new(Key::ProtocolMismatch)
+                .with(PROTOCOL_VERSION)
+                .with(protocol);
+            announce_to(io, text, client_id);
+        }
+    }
+
+    /// Pass spectators' markers on to everyone, in the placer's color
+    fn markers(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        for (client_id, PlaceMarker { curve_t }) in io.inbox_clients().collect::<Vec<_>>() {
+            if !curve_t.is_finite() {
+                continue;
+            }
+            io.send(&MarkerPlaced {
+                client_id,
+                curve_t: curve_t.rem_euclid(self.path.ctrlps.len() as f32),
+                color: marker_color(client_id),
+                ttl: MARKER_TTL,
+            });
+        }
+    }
+
+    /// Show a player their saved bindings as text, or restore them from it. Only the saved copy
+    /// is at hand here, which may be a few seconds behind what they're playing with.
+    fn bindings_command(
+        &mut self,
+        io: &mut EngineIo,
+        client_id: ClientId,
+        text: Option<String>,
+    ) -> String {
+        let Some(username) = self.usernames.get(&client_id).cloned() else {
+            return "Connect with a name to keep bindings".to_string();
+        };
+        let saved = self.saved_settings.get(&username).copied();
+        let Some(text) = text else {
+            return match saved.and_then(SavedSettings::restore) {
+                Some(settings) => format!("/bindings {}", settings.bindings.to_chat_text()),
+                None => "No bindings saved yet".to_string(),
+            };
+        };
+        let Some(bindings) = KeyBindings::from_chat_text(&text) else {
+            return "Those aren't bindings from this version".to_string();
+        };
+        let saved = saved
+            .unwrap_or_else(|| SavedSettings::new(Default::default()))
+            .with_bindings(bindings);
+        self.saved_settings.insert(username, saved);
+        io.send_to_client(&LoadSettings(saved), client_id);
+        "Bindings restored".to_string()
+    }
+
+    /// Keep each player's settings for when they next connect
+    fn settings_store(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        for (client_id, SaveSettings(saved)) in io.inbox_clients().collect::<Vec<_>>() {
+            let Some(username) = self.usernames.get(&client_id).cloned() else {
+                continue;
+            };
+            match saved {
+                Some(saved) => self.saved_settings.insert(username, saved),
+                None => self.saved_settings.remove(&username),
+            };
+        }
+    }
+
+    fn progress_broadcast(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let Some(FrameTime { delta, time }) = io.inbox_first() else {
+            return;
+        };
+
+        self.progress_timer += delta;
+        if self.progress_timer < PROGRESS_INTERVAL || self.lap_trackers.is_empty() {
+            return;
+        }
+        self.progress_timer = 0.;
+
+        let laps = self
+            .lap_trackers
+            .iter()
+            .map(|(&client_id, tracker)| (client_id, tracker.lap()))
+            .collect();
+        let checkpoints = self
+            .lap_trackers
+            .iter()
+            .map(|(&client_id, tracker)| (client_id, tracker.checkpoints_passed()))
+            .collect();
+        let lap_starts = self
+            .lap_starts
+            .iter()
+            .filter(|(client_id, _)| self.lap_trackers.contains_key(client_id))
+            .map(|(&client_id, &start)| (client_id, start - self.race_start))
+            .collect();
+        io.send(&RaceProgress {
+            laps,
+            checkpoints,
+            lap_starts,
+            race_time: time - self.race_start,
+        });
+    }
+
+    /// Place the racers by how far round they are, broadcasting the places a few times a second
+    fn race_positions(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let Some(FrameTime { delta, .. }) = io.inbox_first() else {
+            return;
+        };
+        if self.lap_trackers.is_empty() {
+            return;
+        }
+
+        let progress: Vec<(ClientId, f32)> = self
+            .lap_trackers
+            .iter()
+            .map(|(&client_id, tracker)| (client_id, tracker.fine_progress(&self.path)))
+            .collect();
+        self.race_order = race_order(&self.race_order, &self.finish_order, &progress);
+
+        // Handicaps follow the places every frame, among those still racing; each racer only
+        // hears when theirs changes
+        if self.race_settings.catch_up {
+            let racing: Vec<ClientId> = self
+                .race_order
+                .iter()
+                .copied()
+                .filter(|client_id| !self.finish_order.contains(client_id))
+                .collect();
+            let strength = self.race_settings.catch_up_strength;
+            for (client_id, factor) in handicap_factors(&racing, strength) {
+                if self.handicaps.insert(client_id, factor) != Some(factor) {
+                    io.send_to_client(&HandicapFactor(factor), client_id);
+                }
+            }
+        }
+
+        self.positions_timer += delta;
+        if self.positions_timer < POSITIONS_INTERVAL {
+            return;
+        }
+        self.positions_timer = 0.;
+
+        let positions = self
+            .race_order
+            .iter()
+            .enumerate()
+            .map(|(idx, &client_id)| (client_id, (idx + 1).min(u8::MAX as usize) as u8))
+            .collect();
+        io.send(&RacePositions(positions));
+    }
+
+    /// Count votes on rule changes, applying those which pass to the next race
+    fn rule_votes(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let time = io.inbox_first::<FrameTime>().map_or(0., |t| t.time);
+        for (client_id, CastVote { id, yes }) in io.inbox_clients().collect::<Vec<_>>() {
+            self.afk.activity(client_id, self.clock.now(time));
+            self.rule_votes.vote(client_id, id, yes);
+        }
+
+        for (proposal, passed) in self.rule_votes.tally(self.usernames.len()) {
+            let key = match passed {
+                true => {
+                    proposal.apply(&mut self.settings);
+                    Key::VotePassed
+                }
+                false => Key::VoteFailed,
+            };
+            announce(io, Text::new(key).with(proposal));
+        }
+
+        if let Some(message) = self.rule_votes.take_message() {
+            io.send(&message);
+        }
+    }
+
+    /// Send everyone back to the lobby if the race hasn't progressed in far too long, e.g.
+    /// because a finish never arrived
+    fn watchdog(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
+        let Some(FrameTime { time, .. }) = io.inbox_first() else {
+            return;
+        };
+        let now = self.clock.now(time);
+
+        let racing: Vec<EntityId> = query
+            .iter("Clients")
+            .filter(|&entity| query.read::<ServerShipComponent>(entity).is_racing)
+            .collect();
+        let in_race = self.winner.is_some() || !racing.is_empty();
+        // Relay teams run their laps once per runner
+        let legs = if self.race_settings.relay {
+            TEAM_SIZE
+        } else {
+            1
+        };
+        let limit = self.race_settings.watchdog_factor
+            * (self.race_settings.laps * legs) as f32
+            * EXPECTED_LAP_TIME;
+        if !self.watchdog.stuck(now, in_race, limit) {
+            return;
+        }
+
+        println!(
+            "Watchdog: race {} stuck, {}. Winner {:?}, reset countdown {:.1} s, \
+            {} racing, finished {:?}",
+            self.races_started,
+            self.watchdog.describe(now),
+            self.winner,
+            self.config.reset_time - self.since_winner,
+            racing.len(),
+            self.finish_order,
+        );
+
+        for entity in racing {
+            let client_id = query.read::<ServerShipComponent>(entity).client_id;
+            io.send_to_client(
+                &RaceAbort {
+                    race_id: self.races_started as u32,
+                },
+                client_id,
+            );
+            query.modify::<ServerShipComponent>(entity, |s| s.is_racing = false);
+        }
+
+        // Nobody has a race to come back to
+        let lagging: Vec<String> = self.lagging.keys().cloned().collect();
+        for username in lagging {
+            self.drop_lagging(io, &username);
+        }
+        self.winner = None;
+        self.time_trial = None;
+        self.lap_trackers.clear();
+        self.finish_order.clear();
+        self.finish_times.clear();
+        self.race_order.clear();
+        self.finish_claims.clear();
+        self.finish_seen.clear();
+        self.pickups_taken.clear();
+        self.boost_allowance.clear();
+        // Nobody's rating should suffer for a race which never finished
+        self.rated.clear();
+        self.relay.clear();
+        self.green_flag = None;
+        self.watchdog.transition(now);
+
+        // Release anybody who finished
+        io.send(&RaceOver);
+        self.race_event(io, RaceEvent::Reset);
+        announce(io, Text::new(Key::WatchdogReset));
+        for proposal in Proposal::defaults(&self.settings) {
+            self.rule_votes.propose(proposal);
+        }
+    }
+
+    /// Log a finished racer's lap times, and check them against the lap record
+    fn log_laps(&mut self, io: &mut EngineIo, client_id: ClientId, laps: &[LapTime]) {
+        for (idx, lap) in laps.iter().enumerate() {
+            self.log.push(
+                "lap",
+                &[
+                    ("race", &self.races_started),
+                    ("client", &format!("{:?}", client_id)),
+                    ("lap", &(idx + 1)),
+                    ("time", &format!("{:.3}", lap.time)),
+                    ("valid", &lap.valid),
+                ],
+            );
+        }
+
+        let best_lap = laps
+            .iter()
+            .filter(|lap| lap.valid)
+            .map(|lap| lap.time)
+            .min_by(f32::total_cmp);
+        if let Some(best_lap) = best_lap {
+            let track = self.track_id();
+            let holder = self.usernames.get(&client_id).cloned().unwrap_or_default();
+            if self.records.lap(&track, best_lap, &holder) {
+                let event = RaceEvent::RecordBroken {
+                    client: client_id,
+                    time: best_lap,
+                };
+                self.race_event(io, event);
+            }
+        }
+    }
+
+    /// Check a racer's finishing time against the race record over this many laps
+    fn race_record(&mut self, client_id: ClientId, time: f32) {
+        let track = self.track_id();
+        let holder = self.usernames.get(&client_id).cloned().unwrap_or_default();
+        let laps = self.race_settings.laps;
+        if self.records.race(&track, laps, time, &holder) {
+            println!(
+                "Race record over {} laps: {} by {}",
+                laps,
+                format_race_time(time),
+                holder
+            );
+            let time = format!("{:.3}", time);
+            self.log.push(
+                "race_record",
+                &[
+                    ("race", &self.races_started),
+                    ("name", &holder),
+                    ("laps", &laps),
+                    ("time", &time),
+                ],
+            );
+        }
+    }
+
+    /// Which records the current track's times count towards: a built-in track's name, or a
+    /// loaded track's shape
+    fn track_id(&self) -> String {
+        match self.track {
+            Some(track) => track.name().to_string(),
+            None => format!(
+                "custom:{}:{:.0}",
+                self.path.ctrlps.len(),
+                self.path.length()
+            ),
+        }
+    }
+
+    /// Laps counted and checkpoints passed on the current lap by a racer
+    fn progress_of(&self, client_id: ClientId) -> (usize, usize) {
+        self.lap_trackers.get(&client_id).map_or((0, 0), |tracker| {
+            (tracker.lap(), tracker.checkpoints_passed())
+        })
+    }
+
+    /// Send the next runner of a relay team out from `start`, carrying on from the team's
+    /// `progress`. `ships` names a query of ship entities with their positions.
+    #[allow(clippy::too_many_arguments)]
+    fn hand_off(
+        &mut self,
+        io: &mut EngineIo,
+        query: &mut QueryResult,
+        ships: &str,
+        next: ClientId,
+        start: Transform,
+        (lap, checkpoints): (usize, usize),
+        time: f32,
+    ) {
+        let Some((_, runner)) = self.relay.place_of(next) else {
+            return;
+        };
+        self.log.push(
+            "handoff",
+            &[
+                ("race", &self.races_started),
+                ("to", &format!("{:?}", next)),
+                ("lap", &lap),
+            ],
+        );
+
+        let mut tracker = LapTracker::new(&self.path);
+        tracker.reset(start.pos);
+        tracker.resume(lap, checkpoints);
+        self.lap_trackers.insert(next, tracker);
+
+        for entity in query.iter(ships) {
+            if query.read::<ServerShipComponent>(entity).client_id != next {
+                continue;
+            }
+            query.modify::<ServerShipComponent>(entity, |s| {
+                s.is_ready = false;
+                s.is_racing = true;
+            });
+            if self.race_settings.authoritative {
+                query.write(entity, &start);
+                query.write(entity, &KinematicPhysics::new(1.));
+            }
+        }
+
+        // Each runner's leg ends once the team has done their laps and everyone before's
+        let settings = RaceSettings {
+            laps: (runner + 1) * self.race_settings.laps,
+            ..self.race_settings
+        };
+        io.send_to_client(
+            &YourLeg {
+                race_id: self.races_started as u32,
+                client_id: next,
+                start_transform: start,
+                lap,
+                checkpoints,
+                elapsed_so_far: time - self.race_start,
+                settings,
+            },
+            next,
+        );
+    }
+
+    /// A relay runner disconnected. Their teammate takes over from where their ship was, or
+    /// they're simply dropped from the running order if they weren't on their leg.
+    fn relay_drop_out(
+        &mut self,
+        io: &mut EngineIo,
+        query: &mut QueryResult,
+        entity: EntityId,
+        client_id: ClientId,
+        time: f32,
+    ) {
+        let progress = self.progress_of(client_id);
+        let username = self.usernames.get(&client_id).cloned().unwrap_or_default();
+        self.lap_trackers.remove(&client_id);
+        self.lap_starts.remove(&client_id);
+        match self.relay.drop_out(client_id, time - self.race_start) {
+            Some(Handoff::Next(next)) => {
+                let next_name = self.usernames.get(&next).cloned().unwrap_or_default();
+                let text = Text::new(Key::RelayDropped)
+                    .with(&username)
+                    .with(next_name)
+                    .with(DROP_PENALTY);
+                announce(io, text);
+                let start = query.read(entity);
+                self.hand_off(io, query, "ServerShip", next, start, progress, time);
+            }
+            Some(_) => announce(io, Text::new(Key::DidNotFinish).with(&username)),
+            None => self.relay.remove_waiting(client_id),
+        }
+        self.send_relay_status(io);
+    }
+
+    /// Tell every relay runner how the teams stand
+    fn send_relay_status(&self, io: &mut EngineIo) {
+        let teams = self.relay.teams();
+        for (team_idx, team) in teams.iter().enumerate() {
+            for (runner, &(client_id, _)) in team.runners.iter().enumerate() {
+                let status = RelayStatus {
+                    teams: teams.to_vec(),
+                    team: team_idx,
+                    runner,
+                };
+                io.send_to_client(&status, client_id);
+            }
+        }
+    }
+
+    /// Note when a racer began `lap`, telling other plugins about the lap they just completed
+    fn lap_begun(&mut self, io: &mut EngineIo, client_id: ClientId, lap: usize, time: f32) {
+        let Some(start) = self.lap_starts.insert(client_id, time) else {
+            return;
+        };
+        if lap > 1 {
+            let completed = self.lap_completions.entry(client_id).or_default();
+            completed.push(time - self.race_start);
+
+            let event = RaceEvent::LapCompleted {
+                client: client_id,
+                lap: lap - 1,
+                time: time - start,
+            };
+            self.race_event(io, event);
+        }
+    }
+
+    /// Tell other plugins what's happening in the current race, and note it in the log
+    fn race_event(&mut self, io: &mut EngineIo, event: RaceEvent) {
+        let race = self.races_started;
+        match event {
+            // Logged with the settings when the race starts, and with validity at the finish
+            RaceEvent::Started | RaceEvent::LapCompleted { .. } => (),
+            RaceEvent::Finished {
+                client,
+                time,
+                position,
+            } => {
+                let name = self.usernames.get(&client).cloned().unwrap_or_default();
+                self.log.push(
+                    "finish",
+                    &[
+                        ("race", &race),
+                        ("client", &format!("{:?}", client)),
+                        ("name", &name),
+                        ("position", &position),
+                        ("time", &format!("{:.3}", time)),
+                    ],
+                );
+            }
+            RaceEvent::Reset => self.log.push("reset", &[("race", &race)]),
+            RaceEvent::RecordBroken { client, time } => {
+                let name = self.usernames.get(&client).cloned().unwrap_or_default();
+                let time = format!("{:.3}", time);
+                self.log.push(
+                    "record",
+                    &[("race", &race), ("name", &name), ("time", &time)],
+                );
+            }
+        }
+
+        if let Some(message) = self.race_events.stamp(self.races_started as u32, event) {
+            io.send(&message);
+        }
+    }
+
+    /// Lap completions of everyone who was in the race, finishers first, including those who
+    /// dropped out
+    fn race_results(&self) -> RaceResults {
+        // Finishers in order, then everyone who didn't finish, including those who left
+        let mut racers = self.finish_order.clone();
+        let others = self.entrants.iter().chain(self.lap_trackers.keys());
+        for &client_id in others.chain(self.lap_completions.keys()) {
+            if !racers.contains(&client_id) {
+                racers.push(client_id);
+            }
+        }
+        let racers = racers
+            .into_iter()
+            .map(|client_id| RacerLaps {
+                client_id,
+                name: self.usernames.get(&client_id).cloned().unwrap_or_default(),
+                completed: self
+                    .lap_completions
+                    .get(&client_id)
+                    .cloned()
+                    .unwrap_or_default(),
+                finish_time: self.finish_times.get(&client_id).copied(),
+            })
+            .collect();
+        RaceResults(racers)
+    }
+
+    /// Log what was seen of each racer's uploads, and keep it for `/diag`
+    fn review_fairness(&mut self) {
+        let race = self.races_started;
+        let diagnostics =
+            self.fairness
+                .diagnostics(race, &self.usernames, self.fairness_thresholds);
+        for racer in &diagnostics.racers {
+            let fields = racer.log_fields(race);
+            let fields: Vec<(&str, &dyn Display)> = fields
+                .iter()
+                .map(|(key, value)| (*key, value as &dyn Display))
+                .collect();
+            self.log.push("diagnostics", &fields);
+        }
+        self.diagnostics = Some(diagnostics);
+    }
+
+    fn rating(&self, username: &str) -> Rating {
+        self.ratings.get(username).copied().unwrap_or_default()
+    }
+
+    /// Score the race which just finished. Racers who didn't finish lost to everyone who did.
+    fn update_ratings(&mut self) {
+        let finishers: Vec<String> = self
+            .finish_order
+            .iter()
+            .filter_map(|client_id| self.rated.get(client_id).cloned())
+            .collect();
+        let dnf: Vec<String> = self
+            .rated
+            .values()
+            .filter(|name| !finishers.contains(name))
+            .cloned()
+            .collect();
+        update_ratings(&mut self.ratings, &finishers, &dnf);
+        self.rated.clear();
+    }
+
+    fn handicap(&self, client_id: ClientId) -> f32 {
+        match self.race_settings.catch_up {
+            true => self.handicaps.get(&client_id).copied().unwrap_or(1.),
+            false => 1.,
+        }
+    }
+
+    fn chat_commands(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        let time = io.inbox_first::<FrameTime>().map_or(0., |t| t.time);
+        for (client_id, ChatUpload(text)) in io.inbox_clients().collect::<Vec<_>>() {
+            self.afk.activity(client_id, self.clock.now(time));
+            let Some(command) = parse_command(&text) else {
+                continue;
+            };
+            let client = format!("{:?}", client_id);
+            let admin = self.admin == Some(client_id);
+            self.log.push(
+                "command",
+                &[("client", &client), ("admin", &admin), ("text", &text)],
+            );
+
+            let reply = match command {
+                Command::Authoritative(true) if admin => {
+                    self.settings.authoritative = true;
+                    "Next race is server authoritative".to_string()
+                }
+                Command::Authoritative(false) if admin => {
+                    self.settings.authoritative = false;
+                    "Next race is client authoritative".to_string()
+                }
+                Command::Authoritative(_) => {
+                    "Only the host can change who is authoritative".to_string()
+                }
+                Command::CatchUp(enabled) if admin => {
+                    self.settings.catch_up = enabled;
+                    // Unlike other settings, this applies to the race in progress too
+                    self.race_settings.catch_up = enabled;
+                    if !enabled {
+                        self.handicaps.clear();
+                        io.send(&HandicapFactor(1.));
+                    }
+                    catch_up_text(&self.settings).render(Language::English)
+                }
+                Command::CatchUp(_) => "Only the host can switch catch-up".to_string(),
+                Command::CatchUpStrength(strength) if admin => {
+                    self.settings.catch_up_strength = strength.clamp(0., MAX_CATCH_UP);
+                    catch_up_text(&self.settings).render(Language::English)
+                }
+                Command::CatchUpStrength(_) => {
+                    "Only the host can change the catch-up strength".to_string()
+                }
+                Command::ReloadTrack if self.admin == Some(client_id) => {
+                    io.send_to_client(&RequestTrack, client_id);
+                    "Reloading track...".to_string()
+                }
+                Command::ReloadTrack => "Only the host can reload the track".to_string(),
+                Command::ResetTime(seconds) if admin => self.set_config(ServerConfig {
+                    reset_time: seconds,
+                    ..self.config
+                }),
+                Command::ResetTime(_) => "Only the host can change the reset time".to_string(),
+                Command::MaxSpeed(max_speed) if admin => self.set_config(ServerConfig {
+                    max_speed,
+                    ..self.config
+                }),
+                Command::MaxSpeed(_) => "Only the host can change the speed limit".to_string(),
+                // Held to the same limits as a vote
+                Command::Laps(laps) if admin => {
+                    Proposal::Laps(laps).apply(&mut self.settings);
+                    format!("Next race is {} laps", self.settings.laps)
+                }
+                Command::Laps(_) => "Only the host can set the laps".to_string(),
+                #[cfg(feature = "dev-tracks")]
+                Command::DevTrack(track) if admin => {
+                    self.dev_track = Some((client_id, track.files()));
+                    format!("Loading dev track {}...", track.name())
+                }
+                #[cfg(feature = "dev-tracks")]
+                Command::DevTrack(_) => "Only the host can load a dev track".to_string(),
+                Command::Propose(proposal) if self.admin == Some(client_id) => {
+                    match self.rule_votes.propose(proposal) {
+                        true => format!("Vote opened: {}", proposal),
+                        false => format!("Already voting on: {}", proposal),
+                    }
+                }
+                Command::Propose(_) => "Only the host can propose rule changes".to_string(),
+                Command::Log if admin => {
+                    let lines = self.log.lines();
+                    let parts = lines.chunks(LINES_PER_DUMP).len().max(1) as u32;
+                    for (part, chunk) in lines.chunks(LINES_PER_DUMP).enumerate() {
+                        let dump = LogDump {
+                            part: part as u32,
+                            parts,
+                            lines: chunk.to_vec(),
+                        };
+                        io.send_to_client(&dump, client_id);
+                    }
+                    // Only the host needs to hear about it
+                    let text = format!("Sent {} log lines", lines.len());
+                    announce_to(io, Text::raw(text), client_id);
+                    continue;
+                }
+                Command::Log => "Only the host can download the log".to_string(),
+                Command::Diagnostics if admin => {
+                    let mut lines = match &self.diagnostics {
+                        Some(diagnostics) => diagnostics.report(),
+                        None => vec!["No race has finished yet".to_string()],
+                    };
+                    let LegacyDecodes { legacy, unreadable } = self.legacy_decodes;
+                    if legacy > 0 || unreadable > 0 {
+                        lines.push(format!(
+                            "Messages read from the previous layout: {}, dropped: {}",
+                            legacy, unreadable
+                        ));
+                    }
+                    // Evidence for the host to weigh, not an accusation for everyone to see
+                    for line in lines {
+                        announce_to(io, Text::raw(line), client_id);
+                    }
+                    continue;
+                }
+                Command::Diagnostics => "Only the host can see race diagnostics".to_string(),
+                Command::Bindings(text) => {
+                    let reply = self.bindings_command(io, client_id, text);
+                    announce_to(io, Text::raw(reply), client_id);
+                    continue;
+                }
+            };
+
+            println!("{:?} set {:?}", client_id, self.settings);
+            announce(io, Text::raw(reply));
+        }
+    }
+
+    fn server_config(&mut self, io: &mut EngineIo, _query: &mut QueryResult) {
+        for (client_id, config) in io.inbox_clients::<ServerConfig>().collect::<Vec<_>>() {
+            if self.admin != Some(client_id) {
+                continue;
+            }
+            let reply = self.set_config(config);
+            announce(io, Text::raw(reply));
+        }
+    }
+
+    /// Apply the host's config, within limits, returning what to tell everyone
+    fn set_config(&mut self, config: ServerConfig) -> String {
+        let (shortest, longest) = RESET_TIME_RANGE;
+        self.config.reset_time = config.reset_time.clamp(shortest, longest);
+        let (slowest, fastest) = MAX_SPEED_RANGE;
+        self.config.max_speed = config.max_speed.clamp(slowest, fastest);
+        println!("Server config {:?}", self.config);
+        format!(
+            "Everyone has {:.0} s to finish after the winner, and uploads over {:.0} m/s are \
+            dropped",
+            self.config.reset_time, self.config.max_speed
+        )
     }
 
     fn client_state_update(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
+        let time = io.inbox_first::<FrameTime>().map_or(0., |t| t.time);
+
         // Update ready-states
         let mut client_state_updated = false;
-        for (client_id, ClientReady(is_ready)) in io.inbox_clients() {
+        let now = self.clock.now(time);
+        for (client_id, ClientReady(is_ready, class)) in io.inbox_clients() {
+            // Clients on another protocol would misread the race, so can't join one
+            if is_ready && self.protocols.get(&client_id) != Some(&PROTOCOL_VERSION) {
+                println!(
+                    "Ignoring ready from {:?}, who hasn't matched our protocol",
+                    client_id
+                );
+                continue;
+            }
+            self.watchdog.activity(now);
+            self.afk.activity(client_id, now);
             for entity in query.iter("ServerShips") {
-                if query.read::<ServerShipComponent>(entity).client_id == client_id {
-                    query.modify::<ServerShipComponent>(entity, |s| s.is_ready = is_ready);
+                let shipc = query.read::<ServerShipComponent>(entity);
+                if shipc.client_id == client_id {
+                    if shipc.class != class {
+                        io.add_component(entity, ship_render(true, class));
+                    }
+                    query.modify::<ServerShipComponent>(entity, |s| {
+                        s.is_ready = is_ready;
+                        s.class = class;
+                    });
                     println!(
-                        "{:?} is {}",
+                        "{:?} is {} in a {} ship",
                         client_id,
-                        if is_ready { "ready" } else { "not ready" }
+                        if is_ready { "ready" } else { "not ready" },
+                        class.name(),
                     );
-                    client_state_updated = true;
+                    // Changing ship alone isn't worth announcing
+                    if shipc.is_ready != is_ready {
+                        let client = format!("{:?}", client_id);
+                        let class = class.name();
+                        self.log.push(
+                            "ready",
+                            &[("client", &client), ("ready", &is_ready), ("class", &class)],
+                        );
+                        client_state_updated = true;
+                    }
                 }
             }
         }
 
+        // Clients who are away neither hold up the start nor take part
+        let mut present = vec![];
+        let mut lobby = vec![];
+        for entity in query.iter("ServerShips") {
+            let shipc = query.read::<ServerShipComponent>(entity);
+            // Nobody walks away mid-race as far as we're concerned; they're just slow
+            if shipc.is_racing || self.relay.is_waiting(shipc.client_id) {
+                self.afk.activity(shipc.client_id, now);
+            }
+            let afk = self
+                .afk
+                .is_afk(shipc.client_id, now, self.settings.afk_timeout);
+            if !afk {
+                present.push(entity);
+            }
+            let name = self
+                .usernames
+                .get(&shipc.client_id)
+                .cloned()
+                .unwrap_or_default();
+            let rating = self.rating(&name);
+            // Named somehow, for the nametags, until they've said who they are
+            let name = match name.is_empty() {
+                true => format!("{:?}", shipc.client_id),
+                false => name,
+            };
+            lobby.push(LobbyEntry {
+                client_id: shipc.client_id,
+                rating,
+                name,
+                ready: shipc.is_ready,
+                afk,
+                duplicate: self.duplicates.contains(&shipc.client_id),
+                color: shipc.color,
+            });
+        }
+
+        let lobby = LobbyStatus(lobby);
+        if lobby != self.lobby {
+            io.send(&lobby);
+            self.lobby = lobby;
+        }
+
+        let next_race = NextRace {
+            laps: self.settings.laps,
+        };
+        if Some(next_race) != self.next_race {
+            io.send(&next_race);
+            self.next_race = Some(next_race);
+        }
+
+        let records = self.records.track(&self.track_id());
+        if Some(&records) != self.records_sent.as_ref() {
+            io.send(&RecordsSync(records.clone()));
+            self.records_sent = Some(records);
+        }
+
         // Check if all ships are ready
         let mut all_ready = true;
         let mut any_ready = false;
         let mut ready_clients = 0;
-        for entity in query.iter("ServerShips") {
+        for &entity in &present {
             let shipc = query.read::<ServerShipComponent>(entity);
             let is_ready = shipc.is_ready;
 
@@ -184,57 +1956,324 @@ impl ServerState {
             }
         }
 
-        let n_clients = query.iter("ServerShips").count();
+        let n_clients = present.len();
         if client_state_updated {
-            let text = format!("{}/{} ready.", ready_clients, n_clients);
-            io.send(&ChatDownload {
-                username: "Server".into(),
-                text,
-            })
+            let text = Text::new(Key::ReadyCount)
+                .with(ready_clients)
+                .with(n_clients);
+            announce(io, text);
         }
 
         // Start the race!
         if any_ready && all_ready {
+            self.start_race(io, query, time, present, false);
+        }
+
+        // A time trial needs nobody else to be ready, only for nobody to be racing
+        let requests: Vec<ClientId> = io
+            .inbox_clients::<RequestTimeTrial>()
+            .map(|(id, _)| id)
+            .collect();
+        for client_id in requests {
+            if self.protocols.get(&client_id) != Some(&PROTOCOL_VERSION) {
+                println!(
+                    "Ignoring time trial from {:?}, who hasn't matched our protocol",
+                    client_id
+                );
+                continue;
+            }
+            let mut busy = false;
+            let mut requester = None;
+            for entity in query.iter("ServerShips") {
+                let shipc = query.read::<ServerShipComponent>(entity);
+                busy |= shipc.is_racing || self.relay.is_waiting(shipc.client_id);
+                if shipc.client_id == client_id {
+                    requester = Some(entity);
+                }
+            }
+            let Some(entity) = requester else { continue };
+            if busy {
+                announce_to(io, Text::new(Key::TimeTrialBusy), client_id);
+                continue;
+            }
+            self.afk.activity(client_id, now);
+            self.start_race(io, query, time, vec![entity], true);
+            self.time_trial = Some(client_id);
+        }
+    }
+
+    /// Start a race of the `present` clients' ships, or a time trial of the one ship given
+    fn start_race(
+        &mut self,
+        io: &mut EngineIo,
+        query: &mut QueryResult,
+        time: f32,
+        mut present: Vec<EntityId>,
+        time_trial: bool,
+    ) {
+        let n_clients = present.len();
+        if time_trial {
+            let client_id = query.read::<ServerShipComponent>(present[0]).client_id;
+            let username = self.usernames.get(&client_id).cloned().unwrap_or_default();
+            println!("Starting time trial for {}!", username);
+            announce(io, Text::new(Key::TimeTrialStarted).with(username));
+        } else {
             println!("Starting race!");
-            io.send(&ChatDownload {
-                username: "Server".to_string(),
-                text: "RACE STARTED".to_string(),
-            });
+            // Take the next built-in track in turn, unless the admin has uploaded their own
+            if let Some(track) = self.track.filter(|_| self.races_started > 0) {
+                self.select_track(io, track.next());
+            }
+            announce(io, Text::new(Key::RaceStarted));
+        }
+        if self.settings.catch_up && !time_trial {
+            announce(io, catch_up_text(&self.settings));
+        }
+        let conditions = self.settings.conditions;
+        if conditions != Conditions::Clear {
+            announce(
+                io,
+                Text::new(Key::ConditionsAnnounced).with(conditions.name()),
+            );
+        }
+        if self.settings.rolling_start {
+            announce(io, Text::new(Key::RollingStart));
+        }
+        if self.settings.relay && !time_trial {
+            let text = Text::new(Key::RelayRace)
+                .with(TEAM_SIZE)
+                .with(self.settings.laps);
+            announce(io, text);
+        }
 
-            let mut position = Transform::new().with_position(Vec3::new(0., 0., -5.));
+        // Pick a fresh seed for the race's random numbers
+        self.race_start = time;
+        self.green_flag = self.settings.rolling_start.then(|| time + GO_TIME);
+        self.watchdog.transition(self.clock.now(time));
+        self.races_started += 1;
+        self.settings.seed = (time.to_bits() as u64) ^ (self.races_started << 32);
 
-            for entity in query.iter("ServerShips") {
-                let client_id = query.read::<ServerShipComponent>(entity).client_id;
+        // Settings are fixed for the duration of the race, so voting is over. A time trial
+        // leaves the votes to the next race, and is nobody's relay.
+        self.race_settings = self.settings;
+        self.race_settings.catch_up_strength = self.settings.catch_up_strength.min(MAX_CATCH_UP);
+        if time_trial {
+            self.race_settings.relay = false;
+        } else {
+            self.rule_votes.close_all();
+        }
+        self.time_trial = None;
+        let s = self.race_settings;
+        self.log.push(
+            "start",
+            &[
+                ("race", &self.races_started),
+                ("racers", &n_clients),
+                ("laps", &s.laps),
+                ("authoritative", &s.authoritative),
+                ("catch_up", &s.catch_up),
+                ("invalidate_laps", &s.invalidate_laps),
+                ("conditions", &s.conditions.name()),
+                ("rolling_start", &s.rolling_start),
+                ("relay", &s.relay),
+                ("time_trial", &time_trial),
+                ("seed", &s.seed),
+                ("track_points", &self.path.ctrlps.len()),
+                ("track_length", &format!("{:.1}", self.path.length())),
+            ],
+        );
+        self.race_event(io, RaceEvent::Started);
+        self.acked_seq.clear();
+        self.wall_rides.clear();
+        self.boost_cooldowns.clear();
+        self.fairness.clear();
+        self.upload_checks.clear();
+        // Racers may switch from uploading positions to inputs, which count separately
+        self.upload_stats.clear();
+        self.handicaps.clear();
+        self.finish_order.clear();
+        self.finish_times.clear();
+        self.race_order.clear();
+        self.finish_claims.clear();
+        self.finish_seen.clear();
+        self.lap_starts.clear();
+        self.lap_completions.clear();
+        self.pickups_taken.clear();
+        self.boost_allowance.clear();
+        let rng = RaceRng::new(self.race_settings.seed);
+        self.n_pickups = pickup_layout(&self.path, &rng).len();
 
-                io.send_to_client(
-                    &StartRace {
-                        position,
-                        client_id,
-                    },
-                    client_id,
+        self.lap_trackers.clear();
+
+        let mut names = vec![];
+        for &entity in &present {
+            let client_id = query.read::<ServerShipComponent>(entity).client_id;
+            let username = self.usernames.get(&client_id).cloned().unwrap_or_default();
+            names.push((client_id, username));
+        }
+        self.entrants = names.iter().map(|(client_id, _)| *client_id).collect();
+        // Driving alone isn't a measure of anyone against anyone else
+        self.rated = names
+            .iter()
+            .filter(|(client_id, _)| !time_trial && !self.duplicates.contains(client_id))
+            .cloned()
+            .collect();
+        io.send(&RacerNames(names.clone()));
+
+        // Grid slots are handed out in order, so the best go first
+        if self.race_settings.seed_by_rating {
+            let ratings: Vec<f32> = names.iter().map(|(_, n)| self.rating(n).score).collect();
+            let mut order: Vec<usize> = (0..present.len()).collect();
+            order.sort_by(|&a, &b| ratings[b].total_cmp(&ratings[a]));
+            present = order.into_iter().map(|idx| present[idx]).collect();
+        }
+
+        // Relay teams are dealt out in grid order, and only their first runners start
+        self.relay.clear();
+        let mut starters = present.clone();
+        if self.race_settings.relay {
+            let order: Vec<(ClientId, String)> = present
+                .iter()
+                .map(|&entity| query.read::<ServerShipComponent>(entity).client_id)
+                .map(|id| (id, self.usernames.get(&id).cloned().unwrap_or_default()))
+                .collect();
+            self.relay.assign(&order);
+            for (idx, team) in self.relay.teams().iter().enumerate() {
+                announce(
+                    io,
+                    Text::new(Key::RelayTeam).with(idx + 1).with(team.name()),
                 );
+            }
+            starters.retain(|&entity| {
+                let client_id = query.read::<ServerShipComponent>(entity).client_id;
+                !self.relay.is_waiting(client_id)
+            });
+            // Teams aren't a measure of anyone's own driving
+            self.rated.clear();
+            self.send_relay_status(io);
+            for &entity in &present {
+                query.modify::<ServerShipComponent>(entity, |s| s.is_ready = false);
+            }
+        }
 
-                position.pos.x -= 5.;
-                position.pos.z = -position.pos.z;
+        // A rolling start's grid sits behind the pace line
+        let pace = PaceLine::new(&self.path);
+        let grid = match self.race_settings.rolling_start {
+            true => GridLayout::compute_behind(&self.path, starters.len(), pace.lead),
+            false => GridLayout::compute(&self.path, starters.len()),
+        };
 
-                query.modify::<ServerShipComponent>(entity, |s| {
-                    s.is_ready = false;
-                    s.is_racing = true;
-                });
+        for (&entity, position) in starters.iter().zip(grid) {
+            let client_id = query.read::<ServerShipComponent>(entity).client_id;
+
+            let mut tracker = LapTracker::new(&self.path);
+            tracker.reset(position.pos);
+            self.lap_trackers.insert(client_id, tracker);
+
+            envelope::send_to_client(
+                io,
+                &StartRace {
+                    race_id: self.races_started as u32,
+                    position,
+                    client_id,
+                    settings: self.race_settings,
+                    track_id: self.track.map(BuiltinTrack::id),
+                },
+                client_id,
+            );
+
+            // The server owns positions from here on
+            if self.race_settings.authoritative {
+                query.write(entity, &position);
+                // Same as the client's ship, which sets off at pace in a rolling start
+                let mut kt = KinematicPhysics::new(1.);
+                if self.race_settings.rolling_start {
+                    kt.vel = position.orient * Vec3::X * pace.speed;
+                }
+                query.write(entity, &kt);
             }
+
+            query.modify::<ServerShipComponent>(entity, |s| {
+                s.is_ready = false;
+                s.is_racing = true;
+            });
         }
     }
 
     fn conn_update(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
+        let time = io.inbox_first::<FrameTime>().map_or(0., |t| t.time);
+        // Runs first each frame, so everything logged this frame gets the time
+        self.log.tick(self.clock.now(time));
+
+        // Clients release their throttles at the same moment, from the race's start time
+        if self.green_flag.map_or(false, |green| time >= green) {
+            self.green_flag = None;
+            announce(io, Text::new(Key::GreenFlag));
+            self.log.push("green", &[("race", &self.races_started)]);
+        }
+
+        // Racers who didn't make it back in time are out of the race
+        let grace = self.race_settings.disconnect_grace;
+        let expired: Vec<String> = self
+            .lagging
+            .iter()
+            .filter(|(_, lagging)| time - lagging.since > grace)
+            .map(|(username, _)| username.clone())
+            .collect();
+        for username in expired {
+            self.drop_lagging(io, &username);
+            announce(io, Text::new(Key::DidNotFinish).with(&username));
+        }
+
         if let Some(Connections { clients }) = io.inbox_first() {
-            let current_connections: HashSet<ClientId> =
-                clients.into_iter().map(|c| c.id).collect();
+            // The admin is whoever has been here longest
+            let admin_here = clients.iter().any(|c| Some(c.id) == self.admin);
+            if !admin_here {
+                self.admin = clients.first().map(|c| c.id);
+            }
 
-            // Remove entities corresponding to disconnected clients
+            let current_connections: HashSet<ClientId> = clients.iter().map(|c| c.id).collect();
+
+            // Remove entities corresponding to disconnected clients. Racers get a grace period.
             for entity in query.iter("ServerShip") {
-                let ServerShipComponent { client_id, .. } = query.read(entity);
-                if !current_connections.contains(&client_id) {
-                    io.remove_entity(entity);
+                let shipc: ServerShipComponent = query.read(entity);
+                if shipc.is_lagging || current_connections.contains(&shipc.client_id) {
+                    continue;
+                }
+
+                println!("{:?} disconnected", shipc.client_id);
+                let username = self.usernames.get(&shipc.client_id).cloned();
+                let client = format!("{:?}", shipc.client_id);
+                let name = username.clone().unwrap_or_default();
+                let racing = shipc.is_racing;
+                self.log.push(
+                    "disconnect",
+                    &[("client", &client), ("name", &name), ("racing", &racing)],
+                );
+                // A relay team can't wait for their runner, so the leg passes on straight away
+                let relay_runner = self.relay.place_of(shipc.client_id).is_some();
+                if relay_runner {
+                    self.relay_drop_out(io, query, entity, shipc.client_id, time);
+                }
+                match username {
+                    Some(username) if shipc.is_racing && grace > 0. && !relay_runner => {
+                        query.modify::<ServerShipComponent>(entity, |s| s.is_lagging = true);
+                        io.add_component(entity, ship_render(false, shipc.class));
+                        announce(io, Text::new(Key::LostConnection).with(&username));
+                        let lagging = LaggingRacer {
+                            client_id: shipc.client_id,
+                            entity,
+                            since: time,
+                        };
+                        self.lagging.insert(username, lagging);
+                    }
+                    _ => {
+                        io.remove_entity(entity);
+                        self.protocols.remove(&shipc.client_id);
+                        self.afk.remove(shipc.client_id);
+                        self.upload_stats.remove(&shipc.client_id);
+                        self.upload_checks.remove(shipc.client_id);
+                        self.stalled.remove(&shipc.client_id);
+                    }
                 }
             }
 
@@ -242,32 +2281,385 @@ impl ServerState {
             let mut new_connections = current_connections;
             for entity in query.iter("ServerShip") {
                 let ServerShipComponent { client_id, .. } = query.read(entity);
-                if !new_connections.remove(&client_id) {
-                    println!("{:?} disconnected", client_id);
-                }
+                new_connections.remove(&client_id);
             }
 
-            // Add a new ship entity for each new connection
+            // Add a new ship entity for each new connection, unless they're returning mid-race
+            let mut taken: Vec<PlayerColor> = query
+                .iter("ServerShip")
+                .map(|entity| query.read::<ServerShipComponent>(entity).color)
+                .collect();
             for client_id in new_connections {
+                let username = clients
+                    .iter()
+                    .find(|c| c.id == client_id)
+                    .map(|c| c.username.clone())
+                    .unwrap_or_default();
+                let client = format!("{:?}", client_id);
+                io.send_to_client(
+                    &Hello {
+                        protocol: PROTOCOL_VERSION,
+                    },
+                    client_id,
+                );
+                // Spectate, or come back to their race, on the track everyone else is on
+                if let Some(track) = self.track {
+                    let select = TrackSelect {
+                        track_id: track.id(),
+                    };
+                    io.send_to_client(&select, client_id);
+                }
+                if let Some(lagging) = self.lagging.remove(&username) {
+                    println!("{:?} reconnected as {:?}", lagging.client_id, client_id);
+                    let was = format!("{:?}", lagging.client_id);
+                    self.log.push(
+                        "reconnect",
+                        &[("client", &client), ("name", &username), ("was", &was)],
+                    );
+                    self.resume_racer(io, query, lagging, client_id, time);
+                    continue;
+                }
+
                 println!("{:?} connected", client_id);
+                self.log
+                    .push("connect", &[("client", &client), ("name", &username)]);
+                self.afk.activity(client_id, self.clock.now(time));
+                self.rule_votes.resend();
+                self.next_race = None;
+                self.records_sent = None;
+                let preferred = self.player_colors.get(&username).copied();
+                let color = PlayerColor::assign(client_id, preferred, &taken);
+                taken.push(color);
+                if !username.is_empty() {
+                    self.player_colors.insert(username.clone(), color);
+                }
                 io.create_entity()
                     .add_component(Transform::identity())
-                    .add_component(Render::new(SHIP_RDR).primitive(Primitive::Lines))
+                    .add_component(ship_render(true, ShipClass::default()))
                     .add_component(ServerShipComponent {
                         client_id,
                         is_racing: false,
                         is_ready: false,
+                        is_lagging: false,
+                        class: ShipClass::default(),
+                        color,
+                        upload_seq: 0,
                     })
                     .add_component(Synchronized)
                     .add_component(KinematicPhysics::default())
                     .build();
             }
+
+            // Keep the names of lagging racers, who are no longer in the list
+            self.usernames = clients.iter().map(|c| (c.id, c.username.clone())).collect();
+            for (username, lagging) in &self.lagging {
+                self.usernames.insert(lagging.client_id, username.clone());
+            }
+
+            // Clients are listed oldest first, and the newest connection under a name owns it
+            let mut seen = HashSet::new();
+            self.duplicates = clients
+                .iter()
+                .rev()
+                .filter(|c| !seen.insert(c.username.clone()))
+                .map(|c| c.id)
+                .collect();
         }
     }
 
+    /// Hand a lagging racer's ship and standing over to their new connection
+    fn resume_racer(
+        &mut self,
+        io: &mut EngineIo,
+        query: &mut QueryResult,
+        lagging: LaggingRacer,
+        client_id: ClientId,
+        time: f32,
+    ) {
+        let old_id = lagging.client_id;
+        query.modify::<ServerShipComponent>(lagging.entity, |s| {
+            s.client_id = client_id;
+            s.is_lagging = false;
+        });
+        let class = query.read::<ServerShipComponent>(lagging.entity).class;
+        io.add_component(lagging.entity, ship_render(true, class));
+
+        rekey(&mut self.lap_trackers, old_id, client_id);
+        rekey(&mut self.handicaps, old_id, client_id);
+        rekey(&mut self.boost_allowance, old_id, client_id);
+        rekey(&mut self.wall_rides, old_id, client_id);
+        rekey(&mut self.boost_cooldowns, old_id, client_id);
+        rekey(&mut self.lap_starts, old_id, client_id);
+        rekey(&mut self.lap_completions, old_id, client_id);
+        rekey(&mut self.finish_times, old_id, client_id);
+        for entrant in &mut self.entrants {
+            if *entrant == old_id {
+                *entrant = client_id;
+            }
+        }
+        rekey(&mut self.rated, old_id, client_id);
+        // Their client starts counting inputs from scratch
+        self.acked_seq.remove(&old_id);
+
+        let Some(tracker) = self.lap_trackers.get(&client_id) else {
+            return;
+        };
+        io.send_to_client(
+            &ResumeRace {
+                race_id: self.races_started as u32,
+                client_id,
+                position: query.read(lagging.entity),
+                lap: tracker.lap(),
+                checkpoints: tracker.checkpoints_passed(),
+                elapsed: time - self.race_start,
+                settings: self.race_settings,
+            },
+            client_id,
+        );
+    }
+
+    /// Give up on a lagging racer, removing their ship
+    fn drop_lagging(&mut self, io: &mut EngineIo, username: &str) {
+        let Some(lagging) = self.lagging.remove(username) else {
+            return;
+        };
+        println!("Dropping lagging racer {:?}", lagging.client_id);
+        io.remove_entity(lagging.entity);
+        self.lap_trackers.remove(&lagging.client_id);
+        self.handicaps.remove(&lagging.client_id);
+        self.boost_allowance.remove(&lagging.client_id);
+        self.lap_starts.remove(&lagging.client_id);
+        self.acked_seq.remove(&lagging.client_id);
+        self.wall_rides.remove(&lagging.client_id);
+        self.boost_cooldowns.remove(&lagging.client_id);
+        self.fairness.remove(lagging.client_id);
+        self.upload_stats.remove(&lagging.client_id);
+        self.upload_checks.remove(lagging.client_id);
+        self.stalled.remove(&lagging.client_id);
+        self.afk.remove(lagging.client_id);
+        self.protocols.remove(&lagging.client_id);
+        self.usernames.remove(&lagging.client_id);
+    }
+
     /// Simulate kinematics
     fn kinematics_update(&mut self, io: &mut EngineIo, query: &mut QueryResult) {
-        let Some(FrameTime { delta, .. }) = io.inbox_first() else { return };
-        kinematics::simulate(query, delta);
+        let Some(FrameTime { delta, .. }) = io.inbox_first() else {
+            return;
+        };
+        // Authoritative races integrate ships in fixed steps along with their inputs
+        if !self.race_settings.authoritative {
+            kinematics::simulate(query, delta);
+            self.sanitizer.simulated(query, &self.path);
+        }
+    }
+}
+
+/// Whether the race is over, `since_winner` seconds of frames after somebody won it. The
+/// others get `reset_time` seconds to finish, unless none of them are left racing.
+fn reset_due(has_winner: bool, since_winner: f32, reset_time: f32, anybody_racing: bool) -> bool {
+    has_winner && (since_winner > reset_time || !anybody_racing)
+}
+
+/// Curve along the middle of a built-in track
+fn builtin_path(track: BuiltinTrack) -> Curve {
+    path_obj_to_curve(track.path_obj())
+}
+
+/// Ship render component, hidden while the racer is lagging
+fn ship_render(shown: bool, class: ShipClass) -> Render {
+    HudVisibility::render(shown, class.render())
+}
+
+/// Move a racer's entry over to their new ID
+fn rekey<V>(map: &mut HashMap<ClientId, V>, from: ClientId, to: ClientId) {
+    if let Some(value) = map.remove(&from) {
+        map.insert(to, value);
+    }
+}
+
+/// Assists in effect for a result, e.g. " (catch-up on, auto-throttle)"
+fn result_notes(settings: &RaceSettings, assists: Assists, laps: &[LapTime]) -> String {
+    let mut notes = vec![];
+    if settings.catch_up {
+        notes.push("catch-up on".to_string());
+    }
+    if assists.auto_throttle {
+        notes.push("auto-throttle".to_string());
+    }
+    if assists.one_handed {
+        notes.push("one-handed".to_string());
+    }
+    if assists.scheme == ControlScheme::Strafe {
+        notes.push("hover strafe".to_string());
+    }
+    if settings.conditions != Conditions::Clear {
+        notes.push(format!("{} conditions", settings.conditions.name()));
+    }
+    let invalid = laps.iter().filter(|lap| !lap.valid).count();
+    if invalid > 0 {
+        notes.push(format!("{} invalid laps", invalid));
+    }
+
+    match notes.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", notes.join(", ")),
+    }
+}
+
+/// e.g. "NAME finished P1 - 2:41.3"
+fn finished_text(finish: &RacerFinished) -> Text {
+    Text::new(Key::RacerFinished)
+        .with(&finish.username)
+        .with(finish.position)
+        .with(format_race_time(finish.time))
+}
+
+fn catch_up_text(settings: &RaceSettings) -> Text {
+    match settings.catch_up {
+        true => {
+            let strength = settings.catch_up_strength.clamp(0., MAX_CATCH_UP);
+            Text::new(Key::CatchUpOn).with(format!("{:.0}", strength * 100.))
+        }
+        false => Text::new(Key::CatchUpOff),
+    }
+}
+
+/// Tell everyone, in their own language
+fn announce(io: &mut EngineIo, text: Text) {
+    io.send(&ChatDownload {
+        username: "Server".into(),
+        text: text.render(Language::English),
+    });
+    io.send(&Announcement(text));
+}
+
+/// Tell one client, in their own language
+fn announce_to(io: &mut EngineIo, text: Text, client_id: ClientId) {
+    let chat = ChatDownload {
+        username: "Server".into(),
+        text: text.render(Language::English),
+    };
+    io.send_to_client(&chat, client_id);
+    io.send_to_client(&Announcement(text), client_id);
+}
+
+/// Follow a racer's progress using the same rules as the client. Returns the lap they began,
+/// if they just crossed the finish line.
+fn track_laps(
+    trackers: &mut HashMap<ClientId, LapTracker>,
+    path: &Curve,
+    client_id: ClientId,
+    pos: Vec3,
+) -> Option<usize> {
+    let tracker = trackers.get_mut(&client_id)?;
+    let mut begun = None;
+    for event in tracker.update(path, pos) {
+        match event {
+            LapEvent::Lap(lap) => {
+                println!("{:?} started lap {}", client_id, lap);
+                begun = Some(lap);
+            }
+            LapEvent::MissedCheckpoint { expected, .. } => {
+                println!("{:?} missed checkpoint {}", client_id, expected)
+            }
+            _ => (),
+        }
+    }
+    begun
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_reset_without_a_winner() {
+        assert!(!reset_due(false, 0., 50., true));
+        assert!(!reset_due(false, 1000., 50., false));
+    }
+
+    #[test]
+    fn reset_once_the_window_after_the_winner_runs_out() {
+        assert!(!reset_due(true, 49.9, 50., true));
+        assert!(reset_due(true, 50.1, 50., true));
+    }
+
+    #[test]
+    fn reset_as_soon_as_nobody_is_left_racing() {
+        assert!(reset_due(true, 0., 50., false));
+    }
+
+    fn lap(client: u32, lap: usize) -> RaceEvent {
+        RaceEvent::LapCompleted {
+            client: ClientId(client),
+            lap,
+            time: 60.,
+        }
+    }
+
+    /// The messages which get through of `events`, each in the race given
+    fn stream(events: &[(u32, RaceEvent)]) -> Vec<(u32, RaceEvent)> {
+        let mut stream = RaceEventStream::default();
+        events
+            .iter()
+            .filter_map(|&(race_id, event)| stream.stamp(race_id, event))
+            .map(|message| (message.race_id, message.event))
+            .collect()
+    }
+
+    #[test]
+    fn race_events_start_with_started() {
+        // A reset before anything has started, then two races
+        let events = [
+            (0, RaceEvent::Reset),
+            (1, RaceEvent::Started),
+            (1, lap(1, 1)),
+            (1, lap(2, 1)),
+            (1, RaceEvent::Reset),
+            (2, RaceEvent::Started),
+            (2, lap(2, 1)),
+            (2, RaceEvent::Reset),
+        ];
+        assert_eq!(stream(&events), events[1..]);
+
+        let mut seen = HashSet::new();
+        for (race_id, event) in stream(&events) {
+            if event == RaceEvent::Started {
+                assert!(seen.insert(race_id), "race {} started twice", race_id);
+            }
+            assert!(
+                seen.contains(&race_id),
+                "{:?} before race {}",
+                event,
+                race_id
+            );
+        }
+    }
+
+    #[test]
+    fn race_events_before_the_start_are_held_back() {
+        let events = [
+            (1, lap(1, 1)),
+            (1, RaceEvent::Reset),
+            (1, RaceEvent::Started),
+            (1, lap(1, 1)),
+            // Stragglers from a race which hasn't started yet, or is long gone
+            (2, lap(1, 2)),
+            (0, RaceEvent::Reset),
+        ];
+        assert_eq!(stream(&events), [(1, RaceEvent::Started), (1, lap(1, 1))]);
+    }
+
+    #[test]
+    fn race_events_carry_the_version() {
+        let message = RaceEventStream::default().stamp(3, RaceEvent::Started);
+        assert_eq!(
+            message,
+            Some(RaceEvents {
+                version: RACE_EVENT_VERSION,
+                race_id: 3,
+                event: RaceEvent::Started,
+            })
+        );
     }
 }
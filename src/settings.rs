@@ -0,0 +1,402 @@
+use cimvr_common::ui::{Schema, State, UiHandle, UiStateHelper};
+use cimvr_engine_interface::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controls::ControlScheme,
+    input_state::{ControlPreset, KeyBindings, ONE_HANDED},
+    strings::{tr, Key, Language},
+    SaveSettings,
+};
+
+/// Bump this with any change to `ClientSettings`, so that copies saved by older clients are
+/// dropped rather than misread
+pub const SETTINGS_VERSION: u32 = 6;
+
+/// Time the settings must stay unchanged before they're saved, so that clicking through the
+/// panel saves once (seconds)
+const SAVE_DELAY: f32 = 3.;
+
+/// Player preferences. Saved on the server under the player's name, since plugins have no
+/// storage of their own.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClientSettings {
+    /// Fade the view out and in around teleports
+    pub teleport_fade: bool,
+    /// Use the colorblind-friendly palette
+    pub colorblind: bool,
+    /// Show live readouts of the local ship
+    pub telemetry: bool,
+    /// Throttle automatically, easing off for turns
+    pub auto_throttle: bool,
+    /// Keep the chat panel open during races
+    pub chat_while_racing: bool,
+    /// Race against a replay of our last completed run
+    pub mirror_ghost: bool,
+    /// Use the one-handed control preset in place of the usual bindings
+    pub one_handed: bool,
+    /// Cut to the finish line for a moment when a race is won or lost
+    pub photo_finish: bool,
+    /// Fly with `ControlScheme::Strafe` rather than banking
+    pub hover_strafe: bool,
+    /// Draw only the track already driven in full, in races of our own
+    pub track_reveal: bool,
+    /// Language of the HUD and the server's announcements
+    pub language: Language,
+    /// Detail of the environment, for weaker graphics cards
+    pub quality: Quality,
+    /// Keys chosen in the controls panel, kept as they are while a preset is on
+    pub bindings: KeyBindings,
+}
+
+/// How much of the environment is drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Quality {
+    #[default]
+    High,
+    Medium,
+    Low,
+}
+
+impl Quality {
+    pub const ALL: [Quality; 3] = [Quality::High, Quality::Medium, Quality::Low];
+
+    pub fn name(self, language: Language) -> &'static str {
+        let key = match self {
+            Quality::High => Key::QualityHigh,
+            Quality::Medium => Key::QualityMedium,
+            Quality::Low => Key::QualityLow,
+        };
+        tr(language, key)
+    }
+
+    /// The next quality along, wrapping around
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|&q| q == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    /// Tolerances to simplify line meshes with, as (radians, meters); see
+    /// `meshutil::decimate_lines`. `None` draws everything.
+    pub fn decimation(self) -> Option<(f32, f32)> {
+        match self {
+            Quality::High => None,
+            Quality::Medium => Some((4_f32.to_radians(), 0.5)),
+            Quality::Low => Some((12_f32.to_radians(), 2.)),
+        }
+    }
+}
+
+impl ClientSettings {
+    /// The control preset switched on, if any
+    pub fn preset(&self) -> Option<&'static ControlPreset> {
+        self.one_handed.then_some(&ONE_HANDED)
+    }
+
+    /// Bindings to play with: the preset's if one is on, otherwise our own
+    pub fn active_bindings(&self) -> &KeyBindings {
+        self.preset()
+            .map_or(&self.bindings, |preset| &preset.bindings)
+    }
+
+    pub fn scheme(&self) -> ControlScheme {
+        match self.hover_strafe {
+            true => ControlScheme::Strafe,
+            false => ControlScheme::Banked,
+        }
+    }
+
+    /// Whether to steer with the mouse as well, which only a preset asks for
+    pub fn mouse_steer(&self) -> bool {
+        self.preset().map_or(false, |preset| preset.mouse_steer)
+    }
+
+    /// Whether to throttle automatically, asked for either directly or by the preset
+    pub fn auto_throttle(&self) -> bool {
+        self.auto_throttle || self.preset().map_or(false, |preset| preset.auto_throttle)
+    }
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        Self {
+            teleport_fade: true,
+            colorblind: false,
+            telemetry: false,
+            auto_throttle: false,
+            chat_while_racing: false,
+            mirror_ghost: false,
+            one_handed: false,
+            photo_finish: true,
+            hover_strafe: false,
+            track_reveal: false,
+            language: Language::default(),
+            quality: Quality::default(),
+            bindings: KeyBindings::STANDARD,
+        }
+    }
+}
+
+/// Settings as saved, tagged with the version of `ClientSettings` they were saved by
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedSettings {
+    version: u32,
+    settings: ClientSettings,
+}
+
+impl SavedSettings {
+    pub fn new(settings: ClientSettings) -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            settings,
+        }
+    }
+
+    /// The settings, unless they were saved by another version
+    pub fn restore(self) -> Option<ClientSettings> {
+        (self.version == SETTINGS_VERSION).then_some(self.settings)
+    }
+
+    /// These settings with other bindings, or the defaults with them if these are from another
+    /// version
+    pub fn with_bindings(self, bindings: KeyBindings) -> Self {
+        let settings = self.restore().unwrap_or_default();
+        Self::new(ClientSettings {
+            bindings,
+            ..settings
+        })
+    }
+}
+
+/// Keeps the saved copy of the settings up to date
+pub struct SettingsSync {
+    /// Settings as last saved or loaded
+    saved: ClientSettings,
+    /// Time the settings first differed from the saved copy
+    dirty_since: Option<f32>,
+}
+
+impl SettingsSync {
+    pub fn new(settings: ClientSettings) -> Self {
+        Self {
+            saved: settings,
+            dirty_since: None,
+        }
+    }
+
+    /// The saved copy was loaded
+    pub fn loaded(&mut self, settings: ClientSettings) {
+        self.saved = settings;
+        self.dirty_since = None;
+    }
+
+    /// Save `settings` once they've been left alone for a moment
+    pub fn update(&mut self, io: &mut EngineIo, time: f32, settings: ClientSettings) {
+        if settings == self.saved {
+            self.dirty_since = None;
+            return;
+        }
+        let since = *self.dirty_since.get_or_insert(time);
+        if time - since >= SAVE_DELAY {
+            io.send(&SaveSettings(Some(SavedSettings::new(settings))));
+            self.loaded(settings);
+        }
+    }
+
+    /// Delete the saved copy, leaving the defaults in its place
+    pub fn wipe(&mut self, io: &mut EngineIo) {
+        io.send(&SaveSettings(None));
+        self.loaded(ClientSettings::default());
+    }
+}
+
+/// What a press on the settings panel did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingsChange {
+    Changed,
+    /// Everything went back to the defaults
+    Reset,
+}
+
+/// Names of the on/off settings, in panel order
+const TOGGLES: &[Key] = &[
+    Key::TeleportFade,
+    Key::ColorblindPalette,
+    Key::Telemetry,
+    Key::AutoThrottle,
+    Key::ChatWhileRacing,
+    Key::MirrorGhost,
+    ONE_HANDED.name,
+    Key::PhotoFinish,
+    Key::HoverStrafe,
+    Key::TrackReveal,
+];
+
+/// Each toggle is a button followed by a label showing its value
+const ELEMENTS_PER_TOGGLE: usize = 2;
+
+/// The language button, after the toggles, followed by a label naming the language
+const LANGUAGE_ELEMENT: usize = TOGGLES.len() * ELEMENTS_PER_TOGGLE;
+
+/// The quality button, after the language, followed by a label naming the quality
+const QUALITY_ELEMENT: usize = LANGUAGE_ELEMENT + 2;
+
+/// The reset button, last of all
+const RESET_ELEMENT: usize = QUALITY_ELEMENT + 2;
+
+fn toggle_mut(idx: usize, settings: &mut ClientSettings) -> &mut bool {
+    match idx {
+        0 => &mut settings.teleport_fade,
+        1 => &mut settings.colorblind,
+        2 => &mut settings.telemetry,
+        3 => &mut settings.auto_throttle,
+        4 => &mut settings.chat_while_racing,
+        5 => &mut settings.mirror_ghost,
+        6 => &mut settings.one_handed,
+        7 => &mut settings.photo_finish,
+        8 => &mut settings.hover_strafe,
+        9 => &mut settings.track_reveal,
+        _ => unreachable!("Toggle index out of range"),
+    }
+}
+
+fn toggle_text(language: Language, value: bool) -> String {
+    match value {
+        true => tr(language, Key::On).into(),
+        false => tr(language, Key::Off).into(),
+    }
+}
+
+/// Panel for changing `ClientSettings`
+pub struct SettingsPanel {
+    /// Removed while the HUD is hidden
+    element: Option<UiHandle>,
+}
+
+impl SettingsPanel {
+    pub fn new(io: &mut EngineIo, gui: &mut UiStateHelper, settings: ClientSettings) -> Self {
+        Self {
+            element: Some(Self::add(io, gui, settings)),
+        }
+    }
+
+    fn add(io: &mut EngineIo, gui: &mut UiStateHelper, mut settings: ClientSettings) -> UiHandle {
+        let language = settings.language;
+        let mut schema = vec![];
+        let mut init_state = vec![];
+        for (idx, &name) in TOGGLES.iter().enumerate() {
+            schema.push(Schema::Button {
+                text: tr(language, name).into(),
+            });
+            schema.push(Schema::Label);
+
+            init_state.push(State::Button { clicked: false });
+            init_state.push(State::Label {
+                text: toggle_text(language, *toggle_mut(idx, &mut settings)),
+            });
+        }
+
+        schema.push(Schema::Button {
+            text: tr(language, Key::Language).into(),
+        });
+        schema.push(Schema::Label);
+        init_state.push(State::Button { clicked: false });
+        init_state.push(State::Label {
+            text: language.name().into(),
+        });
+
+        schema.push(Schema::Button {
+            text: tr(language, Key::GraphicsQuality).into(),
+        });
+        schema.push(Schema::Label);
+        init_state.push(State::Button { clicked: false });
+        init_state.push(State::Label {
+            text: settings.quality.name(language).into(),
+        });
+
+        schema.push(Schema::Button {
+            text: tr(language, Key::ResetSettings).into(),
+        });
+        init_state.push(State::Button { clicked: false });
+
+        gui.add(io, tr(language, Key::Settings), schema, init_state)
+    }
+
+    /// Remove or rebuild the panel, showing the current values
+    pub fn set_visible(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        visible: bool,
+        settings: ClientSettings,
+    ) {
+        match (visible, self.element) {
+            (true, None) => self.element = Some(Self::add(io, gui, settings)),
+            (false, Some(handle)) => {
+                gui.delete(io, handle);
+                self.element = None;
+            }
+            _ => (),
+        }
+    }
+
+    /// Apply button presses. Expects `gui` to already be downloaded.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        settings: &mut ClientSettings,
+    ) -> Option<SettingsChange> {
+        let element = self.element?;
+
+        let clicked: Vec<bool> = gui
+            .read(element)
+            .iter()
+            .map(|state| *state == State::Button { clicked: true })
+            .collect();
+
+        let mut changed = false;
+        for idx in 0..TOGGLES.len() {
+            if clicked[idx * ELEMENTS_PER_TOGGLE] {
+                let value = toggle_mut(idx, settings);
+                *value = !*value;
+                changed = true;
+            }
+        }
+
+        // Every label changes, so start the panel over
+        if clicked[RESET_ELEMENT] {
+            *settings = ClientSettings::default();
+            gui.delete(io, element);
+            self.element = Some(Self::add(io, gui, *settings));
+            return Some(SettingsChange::Reset);
+        }
+        if clicked[LANGUAGE_ELEMENT] {
+            settings.language = settings.language.next();
+            gui.delete(io, element);
+            self.element = Some(Self::add(io, gui, *settings));
+            return Some(SettingsChange::Changed);
+        }
+
+        if clicked[QUALITY_ELEMENT] {
+            settings.quality = settings.quality.next();
+            changed = true;
+        }
+
+        if changed {
+            let language = settings.language;
+            gui.modify(io, element, |ui_state| {
+                for idx in 0..TOGGLES.len() {
+                    ui_state[idx * ELEMENTS_PER_TOGGLE + 1] = State::Label {
+                        text: toggle_text(language, *toggle_mut(idx, settings)),
+                    };
+                }
+                ui_state[QUALITY_ELEMENT + 1] = State::Label {
+                    text: settings.quality.name(language).into(),
+                };
+            });
+        }
+
+        changed.then_some(SettingsChange::Changed)
+    }
+}
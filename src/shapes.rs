@@ -1,25 +1,338 @@
 use cimvr_common::render::{Mesh, Vertex};
 
-pub fn grid_mesh(n: i32, scale: f32, color: [f32; 3]) -> Mesh {
+/// Ground grid which gets coarser with distance from the origin, fading towards `fade_color`
+/// at the outer edge. Grid lines are spaced `spacing` apart near the origin, doubling in each of
+/// `bands` square bands. Lines stay aligned with `ground_snap(spacing, bands)`, so moving the mesh
+/// by whole multiples of that looks static.
+pub fn ground_mesh(spacing: f32, bands: u32, color: [f32; 3], fade_color: [f32; 3]) -> Mesh {
+    // Cells per band, across half its width. Must be a multiple of the coarsest spacing ratio.
+    let cells = 2_i32.pow(bands) * 2;
+
     let mut m = Mesh::new();
 
-    let width = n as f32 * scale;
+    let max_dist = ground_extent(spacing, bands);
+    let fade = |x: f32, z: f32| {
+        let t = ((x * x + z * z).sqrt() / max_dist).min(1.);
+        let mix = |a: f32, b: f32| a + (b - a) * t * t;
+        [
+            mix(color[0], fade_color[0]),
+            mix(color[1], fade_color[1]),
+            mix(color[2], fade_color[2]),
+        ]
+    };
+
+    // Add a line from `a` to `b`, split into steps so that the fade is smooth
+    let mut line = |a: f32, b: f32, c: f32, step: f32, along_x: bool| {
+        let n = ((b - a) / step).round().max(1.) as i32;
+        let pos = |i: i32| {
+            let u = a + (b - a) * i as f32 / n as f32;
+            if along_x {
+                [u, 0., c]
+            } else {
+                [c, 0., u]
+            }
+        };
+        for i in 0..n {
+            let [x0, _, z0] = pos(i);
+            let [x1, _, z1] = pos(i + 1);
+            let v0 = m.push_vertex(Vertex::new(pos(i), fade(x0, z0)));
+            let v1 = m.push_vertex(Vertex::new(pos(i + 1), fade(x1, z1)));
+            m.indices.extend([v0, v1]);
+        }
+    };
+
+    let mut inner = 0.;
+    for band in 0..bands {
+        let step = spacing * 2_f32.powi(band as i32);
+        let outer = inner + cells as f32 * step;
+
+        let n_lines = (outer / step).round() as i32;
+        for j in -n_lines..=n_lines {
+            let c = j as f32 * step;
+            for along_x in [true, false] {
+                if c.abs() >= inner {
+                    line(-outer, outer, c, step, along_x);
+                } else {
+                    // Leave the hole for the finer bands
+                    line(-outer, -inner, c, step, along_x);
+                    line(inner, outer, c, step, along_x);
+                }
+            }
+        }
+
+        inner = outer;
+    }
+
+    m
+}
+
+/// Half-width of `ground_mesh`
+pub fn ground_extent(spacing: f32, bands: u32) -> f32 {
+    let cells = 2_i32.pow(bands) * 2;
+    (0..bands)
+        .map(|band| cells as f32 * spacing * 2_f32.powi(band as i32))
+        .sum()
+}
+
+/// Distance the `ground_mesh` may be moved by without its lines appearing to move
+pub fn ground_snap(spacing: f32, bands: u32) -> f32 {
+    spacing * 2_f32.powi(bands as i32 - 1)
+}
+
+/// Octahedron outline centered on the origin
+pub fn diamond_mesh(size: f32, color: [f32; 3]) -> Mesh {
+    let mut m = Mesh::new();
+
+    let tips = [
+        [size, 0., 0.],
+        [-size, 0., 0.],
+        [0., 0., size],
+        [0., 0., -size],
+    ];
+
+    let top = m.push_vertex(Vertex::new([0., size, 0.], color));
+    let bottom = m.push_vertex(Vertex::new([0., -size, 0.], color));
+    let tips: Vec<u32> = tips
+        .into_iter()
+        .map(|pos| m.push_vertex(Vertex::new(pos, color)))
+        .collect();
 
-    for i in -n..=n {
-        let j = i as f32 * scale;
+    // Spokes to the top and bottom
+    for &tip in &tips {
+        m.indices.extend([top, tip, bottom, tip]);
+    }
+
+    // Ring around the middle; tips are ordered +X, -X, +Z, -Z
+    for (a, b) in [(0, 2), (2, 1), (1, 3), (3, 0)] {
+        m.indices.extend([tips[a], tips[b]]);
+    }
+
+    m
+}
 
-        let positions = [
-            [j, 0.0, width],
-            [j, 0.0, -width],
-            [width, 0.0, j],
-            [-width, 0.0, j],
-        ];
+/// Outline of a box `size` across and centered on the origin, with a cross on each of the two
+/// faces which look along X
+pub fn barrier_mesh(size: [f32; 3], color: [f32; 3]) -> Mesh {
+    let mut m = Mesh::new();
+    let [hx, hy, hz] = size.map(|s| s / 2.);
 
-        for pos in positions {
-            let idx = m.push_vertex(Vertex::new(pos, color));
-            m.indices.push(idx);
+    // Corners, numbered by bits: 1 for +X, 2 for +Y, 4 for +Z
+    let corners: Vec<u32> = (0..8)
+        .map(|i| {
+            let sign = |bit: u32, half: f32| if i & bit != 0 { half } else { -half };
+            m.push_vertex(Vertex::new([sign(1, hx), sign(2, hy), sign(4, hz)], color))
+        })
+        .collect();
+    for a in 0..8 {
+        for bit in [1, 2, 4] {
+            if a & bit == 0 {
+                m.indices.extend([corners[a], corners[a | bit]]);
+            }
         }
     }
 
+    for x in [0, 1] {
+        m.indices
+            .extend([corners[x], corners[x | 6], corners[x | 2], corners[x | 4]]);
+    }
+
     m
 }
+
+/// Ring in the XZ plane with a cross through it, `radius` across and centered on the origin
+pub fn shadow_mesh(radius: f32, segments: u32, color: [f32; 3]) -> Mesh {
+    let mut m = Mesh::new();
+
+    let ring: Vec<u32> = (0..segments)
+        .map(|i| {
+            let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+            let pos = [angle.cos() * radius, 0., angle.sin() * radius];
+            m.push_vertex(Vertex::new(pos, color))
+        })
+        .collect();
+    for i in 0..ring.len() {
+        m.indices.extend([ring[i], ring[(i + 1) % ring.len()]]);
+    }
+
+    // Cross, reaching a little past the ring
+    let reach = radius * 1.25;
+    for [x, z] in [[reach, 0.], [0., reach]] {
+        let a = m.push_vertex(Vertex::new([x, 0., z], color));
+        let b = m.push_vertex(Vertex::new([-x, 0., -z], color));
+        m.indices.extend([a, b]);
+    }
+
+    m
+}
+
+/// Arch outline in the YZ plane, spanning `width` across Z and centered on the origin.
+/// The posts are `height` tall, with a shallow arc between their tops.
+pub fn gate_mesh(width: f32, height: f32, color: [f32; 3]) -> Mesh {
+    let mut m = Mesh::new();
+
+    const ARC_SEGMENTS: usize = 16;
+    let half_w = width / 2.;
+    let half_h = height / 2.;
+    let rise = width / 8.;
+
+    let left_foot = m.push_vertex(Vertex::new([0., -half_h, -half_w], color));
+    let right_foot = m.push_vertex(Vertex::new([0., -half_h, half_w], color));
+
+    // Arc from the top of the left post to the top of the right one
+    let arc: Vec<u32> = (0..=ARC_SEGMENTS)
+        .map(|i| {
+            let t = i as f32 / ARC_SEGMENTS as f32;
+            let z = -half_w + width * t;
+            let y = half_h + rise * (t * std::f32::consts::PI).sin();
+            m.push_vertex(Vertex::new([0., y, z], color))
+        })
+        .collect();
+
+    m.indices
+        .extend([left_foot, arc[0], right_foot, arc[ARC_SEGMENTS]]);
+    for pair in arc.windows(2) {
+        m.indices.extend([pair[0], pair[1]]);
+    }
+
+    m
+}
+
+/// Strokes of each character in the line font, drawn on a grid of points numbered like a
+/// keypad: 7 8 9 along the top, 4 5 6 through the middle and 1 2 3 along the bottom.
+/// Each string is a polyline through those points.
+const GLYPHS: &[(char, &[&str])] = &[
+    ('0', &["79317"]),
+    ('1', &["82", "78"]),
+    ('2', &["796413"]),
+    ('3', &["7931", "46"]),
+    ('4', &["746", "93"]),
+    ('5', &["974631"]),
+    ('6', &["971364"]),
+    ('7', &["793"]),
+    ('8', &["79317", "46"]),
+    ('9', &["647931"]),
+    ('A', &["1793", "46"]),
+    ('B', &["786317", "46"]),
+    ('C', &["9713"]),
+    ('D', &["786217"]),
+    ('E', &["9713", "45"]),
+    ('F', &["971", "45"]),
+    ('G', &["971365"]),
+    ('H', &["71", "93", "46"]),
+    ('I', &["79", "82", "13"]),
+    ('J', &["9314"]),
+    ('K', &["71", "943"]),
+    ('L', &["713"]),
+    ('M', &["17593"]),
+    ('N', &["1739"]),
+    ('O', &["79317"]),
+    ('P', &["17964"]),
+    ('Q', &["79317", "53"]),
+    ('R', &["17964", "53"]),
+    ('S', &["974631"]),
+    ('T', &["79", "82"]),
+    ('U', &["7139"]),
+    ('V', &["729"]),
+    ('W', &["71593"]),
+    ('X', &["73", "91"]),
+    ('Y', &["75", "95", "52"]),
+    ('Z', &["7913"]),
+    ('-', &["46"]),
+    ('(', &["842"]),
+    (')', &["862"]),
+];
+
+/// Horizontal distance from one character to the next, in glyph widths
+const GLYPH_ADVANCE: f32 = 1.5;
+/// Height of a glyph, in glyph widths
+const GLYPH_HEIGHT: f32 = 2.;
+/// Horizontal lines filling a panel, for the hologram look
+const PANEL_SCANLINES: usize = 12;
+/// Brightness of the scanlines relative to the border and text
+const SCANLINE_BRIGHTNESS: f32 = 0.25;
+
+/// Position of a keypad-numbered grid point within a glyph one unit wide
+fn glyph_point(digit: char) -> Option<[f32; 2]> {
+    let n = digit.to_digit(10)?.checked_sub(1)?;
+    Some([(n % 3) as f32 * 0.5, (n / 3) as f32])
+}
+
+/// Bordered rectangle in the XY plane, `width` across X and `height` up Y, centered on the
+/// origin and facing +Z, with `text` centered inside it. Lowercase letters are drawn as capitals;
+/// unknown characters are left blank.
+pub fn panel_mesh(width: f32, height: f32, text: &str, color: [f32; 3]) -> Mesh {
+    let mut m = Mesh::new();
+
+    let mut line = |a: [f32; 2], b: [f32; 2], color: [f32; 3]| {
+        let v0 = m.push_vertex(Vertex::new([a[0], a[1], 0.], color));
+        let v1 = m.push_vertex(Vertex::new([b[0], b[1], 0.], color));
+        m.indices.extend([v0, v1]);
+    };
+
+    let (half_w, half_h) = (width / 2., height / 2.);
+    let corners = [
+        [-half_w, -half_h],
+        [half_w, -half_h],
+        [half_w, half_h],
+        [-half_w, half_h],
+    ];
+    for i in 0..corners.len() {
+        line(corners[i], corners[(i + 1) % corners.len()], color);
+    }
+
+    let dim = color.map(|c| c * SCANLINE_BRIGHTNESS);
+    for i in 1..PANEL_SCANLINES {
+        let y = -half_h + height * i as f32 / PANEL_SCANLINES as f32;
+        line([-half_w, y], [half_w, y], dim);
+    }
+
+    // Scale the text to fit inside a margin, filling whichever way is tighter
+    let n_chars = text.chars().count();
+    if n_chars == 0 {
+        return m;
+    }
+    let margin = width.min(height) * 0.15;
+    let text_w = n_chars as f32 * GLYPH_ADVANCE - (GLYPH_ADVANCE - 1.);
+    let scale = ((width - 2. * margin) / text_w).min((height - 2. * margin) / GLYPH_HEIGHT);
+    let origin = [-text_w * scale / 2., -GLYPH_HEIGHT * scale / 2.];
+    push_text(&mut m, text, origin, scale, color);
+
+    m
+}
+
+/// `text` in the same lettering as `panel_mesh`, `height` tall in the XY plane, reading along
+/// +X from its bottom left corner at the origin
+pub fn text_mesh(text: &str, height: f32, color: [f32; 3]) -> Mesh {
+    let mut m = Mesh::new();
+    push_text(&mut m, text, [0., 0.], height / GLYPH_HEIGHT, color);
+    m
+}
+
+/// Width of `text` drawn `height` tall by `text_mesh`
+pub fn text_width(text: &str, height: f32) -> f32 {
+    let n_chars = text.chars().count() as f32;
+    (n_chars * GLYPH_ADVANCE - (GLYPH_ADVANCE - 1.)).max(0.) * height / GLYPH_HEIGHT
+}
+
+/// Add the strokes of `text` to `m`, with glyphs `scale` times their unit size and the bottom
+/// left corner at `origin`
+fn push_text(m: &mut Mesh, text: &str, origin: [f32; 2], scale: f32, color: [f32; 3]) {
+    for (idx, c) in text.chars().enumerate() {
+        let c = c.to_ascii_uppercase();
+        let Some((_, strokes)) = GLYPHS.iter().find(|(glyph, _)| *glyph == c) else {
+            continue;
+        };
+
+        let left = origin[0] + idx as f32 * GLYPH_ADVANCE * scale;
+        let place = |[x, y]: [f32; 2]| [left + x * scale, origin[1] + y * scale, 0.];
+
+        for stroke in strokes.iter() {
+            let points: Vec<[f32; 2]> = stroke.chars().filter_map(glyph_point).collect();
+            for pair in points.windows(2) {
+                let v0 = m.push_vertex(Vertex::new(place(pair[0]), color));
+                let v1 = m.push_vertex(Vertex::new(place(pair[1]), color));
+                m.indices.extend([v0, v1]);
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use cimvr_common::render::{MeshHandle, Primitive, Render, UploadMesh};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{obj::obj_lines_to_mesh, ShipCharacteristics, SHIP_RDR};
+
+/// Blocks in a full stat bar
+const BAR_LENGTH: usize = 10;
+
+/// Which ship a player races with
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ShipClass {
+    #[default]
+    Balanced,
+    /// Faster in a straight line, but slower to turn
+    Racer,
+    /// Turns and boosts hard, at the cost of top speed
+    Agile,
+}
+
+impl ShipClass {
+    pub const ALL: [ShipClass; 3] = [ShipClass::Balanced, ShipClass::Racer, ShipClass::Agile];
+
+    const RACER_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("ShipRacer"));
+    const AGILE_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("ShipAgile"));
+
+    /// Upload every class's mesh: the standard ship, stretched and colored to suit
+    pub fn assets(io: &mut EngineIo) {
+        for class in Self::ALL {
+            let (length, width) = match class {
+                ShipClass::Balanced => (1., 1.),
+                ShipClass::Racer => (1.3, 0.8),
+                ShipClass::Agile => (0.85, 1.2),
+            };
+
+            let mut mesh = obj_lines_to_mesh(include_str!("assets/ship.obj"));
+            for v in &mut mesh.vertices {
+                v.pos[0] *= length;
+                v.pos[2] *= width;
+                v.uvw = class.color();
+            }
+            io.send(&UploadMesh {
+                mesh,
+                id: class.mesh(),
+            });
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ShipClass::Balanced => "Balanced",
+            ShipClass::Racer => "Racer",
+            ShipClass::Agile => "Agile",
+        }
+    }
+
+    /// Color of the class's mesh, so that it can be told apart from a distance. Each ship is
+    /// tinted with its player's color on top of this.
+    pub fn color(self) -> [f32; 3] {
+        match self {
+            ShipClass::Balanced => [1.; 3],
+            ShipClass::Racer => [1., 0.55, 0.35],
+            ShipClass::Agile => [0.45, 1., 0.6],
+        }
+    }
+
+    pub fn characteristics(self) -> ShipCharacteristics {
+        let standard = ShipCharacteristics::standard();
+        match self {
+            ShipClass::Balanced => standard,
+            ShipClass::Racer => ShipCharacteristics {
+                max_impulse: 34.,
+                max_twirl: 4.,
+                boost_factor: 1.4,
+                ..standard
+            },
+            ShipClass::Agile => ShipCharacteristics {
+                max_impulse: 27.,
+                max_twirl: 6.5,
+                boost_factor: 1.7,
+                ..standard
+            },
+        }
+    }
+
+    pub fn mesh(self) -> MeshHandle {
+        match self {
+            ShipClass::Balanced => SHIP_RDR,
+            ShipClass::Racer => Self::RACER_RDR,
+            ShipClass::Agile => Self::AGILE_RDR,
+        }
+    }
+
+    pub fn render(self) -> Render {
+        Render::new(self.mesh()).primitive(Primitive::Lines)
+    }
+
+    /// The class `step` places along, wrapping around
+    pub fn cycle(self, step: i32) -> Self {
+        let n = Self::ALL.len() as i32;
+        let idx = Self::ALL.iter().position(|&c| c == self).unwrap_or(0) as i32;
+        Self::ALL[(idx + step).rem_euclid(n) as usize]
+    }
+
+    /// Speed, handling and boost, each relative to the best class at it
+    fn stats(self) -> [(&'static str, f32); 3] {
+        let raw = |class: ShipClass| {
+            let ship = class.characteristics();
+            [
+                ship.max_impulse / ship.mass,
+                ship.max_twirl / ship.moment,
+                ship.boost_factor,
+            ]
+        };
+
+        let mine = raw(self);
+        let best = Self::ALL.map(raw).into_iter().fold([0.; 3], |best, stats| {
+            [
+                best[0].max(stats[0]),
+                best[1].max(stats[1]),
+                best[2].max(stats[2]),
+            ]
+        });
+
+        [
+            ("Speed", mine[0] / best[0]),
+            ("Handling", mine[1] / best[1]),
+            ("Boost", mine[2] / best[2]),
+        ]
+    }
+
+    /// A line per stat, with a bar of block characters
+    pub fn stat_lines(self) -> Vec<String> {
+        self.stats()
+            .into_iter()
+            .map(|(name, value)| {
+                let filled = (value * BAR_LENGTH as f32).round() as usize;
+                let bar: String = (0..BAR_LENGTH)
+                    .map(|i| if i < filled { '█' } else { '░' })
+                    .collect();
+                format!("{:<8} {}", name, bar)
+            })
+            .collect()
+    }
+}
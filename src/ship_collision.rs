@@ -0,0 +1,61 @@
+//! Ships bumping into each other. Each client pushes only its own ship, off the others where
+//! the server last had them, so that between them the two clients apply the whole of an
+//! elastic collision.
+use cimvr_common::{glam::Vec3, Transform};
+
+use crate::{controls::TRACK_HEIGHT, curve::Curve, kinematics::KinematicPhysics};
+
+/// Another ship, as far as bumping into it is concerned
+#[derive(Clone, Copy, Debug)]
+pub struct OtherShip {
+    pub pos: Vec3,
+    pub vel: Vec3,
+    pub mass: f32,
+}
+
+/// Push the local ship at `tf` off any of `others` it overlaps, treating each ship as a sphere
+/// of `radius`, and exchange momentum with them along the line between. Only across the track:
+/// height above it is left to the track lock, so nobody is pushed under it. Returns whether
+/// the ship touched any of them.
+pub fn collide(
+    path: &Curve,
+    radius: f32,
+    tf: &mut Transform,
+    kt: &mut KinematicPhysics,
+    others: &[OtherShip],
+) -> bool {
+    let reach = 2. * radius;
+    let mut near = others
+        .iter()
+        .filter(|other| other.pos.distance(tf.pos) < reach + TRACK_HEIGHT)
+        .peekable();
+    if near.peek().is_none() {
+        return false;
+    }
+    let up = path.ctrlps[path.nearest_ctrlp(tf.pos)].orient * Vec3::Y;
+
+    let mut hit = false;
+    for other in near {
+        let offset = tf.pos - other.pos;
+        // On a stretch of track crossing over or under this one
+        if offset.dot(up).abs() > TRACK_HEIGHT / 2. {
+            continue;
+        }
+        let across = offset - up * offset.dot(up);
+        let dist = across.length();
+        if dist >= reach || dist <= f32::EPSILON {
+            continue;
+        }
+        let normal = across / dist;
+
+        // Our share of backing out and of the bounce, the lighter ship taking more of both
+        let share = other.mass / (kt.mass + other.mass);
+        tf.pos += normal * (reach - dist) * share;
+        let closing = (kt.vel - other.vel).dot(normal);
+        if closing < 0. {
+            kt.vel -= normal * closing * 2. * share;
+        }
+        hit = true;
+    }
+    hit
+}
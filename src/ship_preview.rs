@@ -0,0 +1,152 @@
+use cimvr_common::{
+    glam::{Quat, Vec3},
+    ui::{Schema, State, UiHandle, UiStateHelper},
+    Transform,
+};
+use cimvr_engine_interface::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{hud::HudVisibility, ship_class::ShipClass};
+
+/// Where the preview floats, relative to the camera (meters)
+const PREVIEW_OFFSET: Vec3 = Vec3::new(0., -1.5, -8.);
+/// How fast the preview turns (radians per second)
+const SPIN_SPEED: f32 = 0.6;
+
+/// Index of the first stat label in the panel, after the class name
+const STATS_ELEMENT: usize = 1;
+/// Number of stat labels, followed by the previous and next buttons
+const N_STATS: usize = 3;
+
+/// Marks the preview ship, which is never a racer
+#[derive(Component, Serialize, Deserialize, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ShipPreviewComponent;
+
+/// The ship the player has picked, turning in front of the lobby camera, and a panel
+/// for picking another
+pub struct ShipPreview {
+    entity: EntityId,
+    class: ShipClass,
+    /// Removed while hidden
+    element: Option<UiHandle>,
+    /// Whether the ship is currently rendered
+    shown: bool,
+}
+
+fn panel_states(class: ShipClass) -> Vec<State> {
+    let mut states = vec![State::Label {
+        text: class.name().into(),
+    }];
+    states.extend(
+        class
+            .stat_lines()
+            .into_iter()
+            .map(|text| State::Label { text }),
+    );
+    states
+}
+
+impl ShipPreview {
+    pub fn new(io: &mut EngineIo, class: ShipClass) -> Self {
+        let entity = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(HudVisibility::render(false, class.render()))
+            .add_component(ShipPreviewComponent)
+            .build();
+
+        Self {
+            entity,
+            class,
+            element: None,
+            shown: false,
+        }
+    }
+
+    fn add(&self, io: &mut EngineIo, gui: &mut UiStateHelper) -> UiHandle {
+        let mut schema = vec![Schema::Label; STATS_ELEMENT + N_STATS];
+        schema.push(Schema::Button { text: "<".into() });
+        schema.push(Schema::Button { text: ">".into() });
+
+        let mut init_state = panel_states(self.class);
+        init_state.push(State::Button { clicked: false });
+        init_state.push(State::Button { clicked: false });
+
+        gui.add(io, "Ship", schema, init_state)
+    }
+
+    /// Show or remove the panel, and move the selection by `step` plus any button presses.
+    /// Returns the new class if it changed. Expects `gui` to already be downloaded.
+    pub fn update_panel(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        visible: bool,
+        mut step: i32,
+    ) -> Option<ShipClass> {
+        match (visible, self.element) {
+            (true, None) => self.element = Some(self.add(io, gui)),
+            (false, Some(handle)) => {
+                gui.delete(io, handle);
+                self.element = None;
+            }
+            _ => (),
+        }
+
+        let element = self.element?;
+
+        let buttons = STATS_ELEMENT + N_STATS;
+        let clicked = |idx: usize| gui.read(element)[idx] == State::Button { clicked: true };
+        if clicked(buttons) {
+            step -= 1;
+        }
+        if clicked(buttons + 1) {
+            step += 1;
+        }
+
+        let class = self.class.cycle(step);
+        if class == self.class {
+            return None;
+        }
+        self.class = class;
+
+        let states = panel_states(class);
+        gui.modify(io, element, |ui_state| {
+            for (state, new) in ui_state.iter_mut().zip(&states) {
+                *state = new.clone();
+            }
+        });
+        self.write_render(io);
+
+        Some(class)
+    }
+
+    /// Turn the ship in front of the camera, or hide it
+    pub fn update(&mut self, io: &mut EngineIo, camera_tf: Transform, time: f32, visible: bool) {
+        if visible != self.shown {
+            self.shown = visible;
+            self.write_render(io);
+        }
+        if !visible {
+            return;
+        }
+
+        let spin = Transform::new()
+            .with_position(PREVIEW_OFFSET)
+            .with_rotation(Quat::from_rotation_y(time * SPIN_SPEED));
+        io.add_component(self.entity, camera_tf * spin);
+    }
+
+    /// Hide the ship straight away, e.g. when a race starts
+    pub fn hide(&mut self, io: &mut EngineIo) {
+        if self.shown {
+            self.shown = false;
+            self.write_render(io);
+        }
+    }
+
+    fn write_render(&self, io: &mut EngineIo) {
+        let render = HudVisibility::render(self.shown, self.class.render());
+        io.add_component(self.entity, render);
+    }
+}
@@ -0,0 +1,116 @@
+//! Markers on the track under each ship, to judge height and line by
+use std::collections::{HashMap, HashSet};
+
+use cimvr_common::{
+    glam::Vec3,
+    render::{MeshHandle, Primitive, Render, UploadMesh},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{countdown::color_extra, curve::Curve, shapes::shadow_mesh};
+
+/// Markers further than this from the camera are hidden (meters)
+const MAX_DISTANCE: f32 = 120.;
+/// Radius of the marker under a ship on the surface (meters)
+const RADIUS: f32 = 1.5;
+/// Ships this high above the surface have the smallest, faintest marker (meters)
+const FADE_HEIGHT: f32 = 15.;
+/// Brightness of the marker under a ship on the surface
+const BRIGHTNESS: f32 = 0.6;
+/// Brightness of the marker under a ship at `FADE_HEIGHT` or above
+const MIN_BRIGHTNESS: f32 = 0.1;
+/// Raise the marker off the surface so it isn't lost in the track's lines (meters)
+const LIFT: f32 = 0.05;
+
+/// Marker meshes, from the largest (on the surface) to the smallest (at `FADE_HEIGHT`)
+const SIZES: [(MeshHandle, f32); 4] = [
+    (MeshHandle::new(pkg_namespace!("ShipShadow0")), 1.),
+    (MeshHandle::new(pkg_namespace!("ShipShadow1")), 0.8),
+    (MeshHandle::new(pkg_namespace!("ShipShadow2")), 0.6),
+    (MeshHandle::new(pkg_namespace!("ShipShadow3")), 0.4),
+];
+
+struct Shadow {
+    entity: EntityId,
+    /// Index into `SIZES`
+    size: usize,
+}
+
+/// A ring under each ship near the camera, at its position projected onto the track, shrinking
+/// and fading as the ship rises
+pub struct ShipShadows {
+    shadows: HashMap<ClientId, Shadow>,
+}
+
+impl ShipShadows {
+    pub fn new(io: &mut EngineIo) -> Self {
+        for (id, scale) in SIZES {
+            io.send(&UploadMesh {
+                mesh: shadow_mesh(RADIUS * scale, 16, [1.; 3]),
+                id,
+            });
+        }
+
+        Self {
+            shadows: HashMap::new(),
+        }
+    }
+
+    /// Create, move and remove markers to follow `ships`. None are shown unless `visible`.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        path: &Curve,
+        ships: impl IntoIterator<Item = (ClientId, Transform)>,
+        camera_pos: Vec3,
+        visible: bool,
+    ) {
+        let mut shown = HashSet::new();
+
+        for (client_id, tf) in ships {
+            if !visible || (tf.pos - camera_pos).length() > MAX_DISTANCE {
+                continue;
+            }
+
+            let (surface, height) = path.project(tf.pos);
+            let fraction = (height / FADE_HEIGHT).clamp(0., 1.);
+            let size = ((fraction * SIZES.len() as f32) as usize).min(SIZES.len() - 1);
+            let brightness = BRIGHTNESS + (MIN_BRIGHTNESS - BRIGHTNESS) * fraction;
+            let tf = surface * Transform::new().with_position(Vec3::Y * LIFT);
+
+            let render = |size: usize| Render::new(SIZES[size].0).primitive(Primitive::Lines);
+            let shadow = self.shadows.entry(client_id).or_insert_with(|| Shadow {
+                entity: io
+                    .create_entity()
+                    .add_component(tf)
+                    .add_component(render(size))
+                    .build(),
+                size,
+            });
+            if shadow.size != size {
+                io.add_component(shadow.entity, render(size));
+                shadow.size = size;
+            }
+            io.add_component(shadow.entity, tf);
+            io.add_component(shadow.entity, color_extra([brightness; 3]));
+
+            shown.insert(client_id);
+        }
+
+        // Remove markers of ships which left, or went out of view
+        self.shadows.retain(|client_id, shadow| {
+            let keep = shown.contains(client_id);
+            if !keep {
+                io.remove_entity(shadow.entity);
+            }
+            keep
+        });
+    }
+
+    pub fn clear(&mut self, io: &mut EngineIo) {
+        for (_, shadow) in self.shadows.drain() {
+            io.remove_entity(shadow.entity);
+        }
+    }
+}
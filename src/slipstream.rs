@@ -0,0 +1,91 @@
+//! Drafting the other racers: keeping close behind one builds up a tow, which fades away
+//! once out of their wake
+use cimvr_common::{glam::Vec3, Transform};
+
+/// Furthest ahead a ship can be and still tow us (meters)
+const DRAFT_DISTANCE: f32 = 25.;
+/// Widest angle off our nose at which a ship ahead tows us (radians)
+const CONE_HALF_ANGLE: f32 = 0.25;
+/// Extra thrust, as a fraction, with the tow built all the way up
+const MAX_BOOST: f32 = 0.3;
+/// Time in a wake to build the tow all the way up (seconds)
+const BUILD_TIME: f32 = 2.;
+/// Time out of one to lose it all again (seconds)
+const DECAY_TIME: f32 = 1.5;
+/// Color the ship is tinted towards as the tow builds, over its usual one
+const DRAFT_TINT: [f32; 3] = [0.5, 0.8, 1.];
+/// Change in the tow before the tint is drawn again
+const TINT_STEP: f32 = 0.02;
+
+/// Whether a ship at `other` is in the cone ahead of a ship at `tf`, near enough to tow it
+pub fn in_wake(tf: Transform, other: Vec3) -> bool {
+    let offset = other - tf.pos;
+    let distance = offset.length();
+    if distance <= 0. || distance > DRAFT_DISTANCE {
+        return false;
+    }
+    // Ships face along their local +X
+    let forward = tf.orient * Vec3::X;
+    forward.dot(offset) / distance >= CONE_HALF_ANGLE.cos()
+}
+
+/// The tow the local ship has built up behind the other racers
+#[derive(Default)]
+pub struct Slipstream {
+    /// From none to all the way up
+    tow: f32,
+    /// Tow the tint was last drawn with
+    drawn: f32,
+}
+
+impl Slipstream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build up or lose the tow over `dt` seconds, for a ship at `tf` among `others`. Only
+    /// while `active`; otherwise it's gone at once, e.g. before "GO".
+    pub fn update(
+        &mut self,
+        tf: Transform,
+        others: impl IntoIterator<Item = Vec3>,
+        dt: f32,
+        active: bool,
+    ) {
+        if !active {
+            self.tow = 0.;
+            return;
+        }
+        let drafting = others.into_iter().any(|other| in_wake(tf, other));
+        let step = match drafting {
+            true => dt / BUILD_TIME,
+            false => -dt / DECAY_TIME,
+        };
+        self.tow = (self.tow + step).clamp(0., 1.);
+    }
+
+    pub fn reset(&mut self) {
+        self.tow = 0.;
+    }
+
+    /// Thrust multiplier from the tow
+    pub fn factor(&self) -> f32 {
+        1. + MAX_BOOST * self.tow
+    }
+
+    /// Whether the tow has changed enough since the tint was drawn to draw it again
+    pub fn tint_changed(&self) -> bool {
+        let gone = self.tow == 0. && self.drawn != 0.;
+        gone || (self.tow - self.drawn).abs() > TINT_STEP
+    }
+
+    /// The ship's usual color `base`, tinted by the tow, which is taken as drawn
+    pub fn tint(&mut self, base: [f32; 3]) -> [f32; 3] {
+        self.drawn = self.tow;
+        let mut tint = base;
+        for (c, draft) in tint.iter_mut().zip(DRAFT_TINT) {
+            *c *= 1. + self.tow * (draft - 1.);
+        }
+        tint
+    }
+}
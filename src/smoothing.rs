@@ -0,0 +1,105 @@
+//! Draws the other ships gliding between the updates the server sends of them, rather than
+//! popping to each one as it arrives
+use std::collections::HashMap;
+
+use cimvr_common::{glam::Vec3, Transform};
+use cimvr_engine_interface::prelude::*;
+
+use crate::{kinematics::KinematicPhysics, ServerShipComponent};
+
+/// A ship moving further than this between two updates has been teleported, e.g. onto the grid
+/// or back after leaving the track, and is snapped to rather than slid across (meters)
+const SNAP_DISTANCE: f32 = 20.;
+/// Longest a ship is carried on along its velocity past its latest update (seconds)
+const MAX_EXTRAPOLATION: f32 = 0.2;
+/// Limits on the time between updates, which is how far behind them a ship is drawn (seconds)
+const MIN_INTERVAL: f32 = 1. / 120.;
+const MAX_INTERVAL: f32 = 0.25;
+
+/// A transform received from the server, and when
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    time: f32,
+    tf: Transform,
+}
+
+struct SmoothedShip {
+    previous: Sample,
+    latest: Sample,
+    /// What we last drew the ship at, so that a fresh update can be told from it
+    drawn: Transform,
+}
+
+/// Where to draw a ship at `now` between its `previous` and `latest` updates. It's drawn one
+/// update interval behind, so that it's just reaching the latest as the next is due; if that
+/// doesn't come, it's carried on a little way along `vel`.
+fn smoothed(previous: Sample, latest: Sample, vel: Vec3, now: f32) -> Transform {
+    let interval = (latest.time - previous.time).clamp(MIN_INTERVAL, MAX_INTERVAL);
+    let behind = now - latest.time;
+    if behind <= interval {
+        let t = (behind / interval).max(0.);
+        return previous.tf.lerp_slerp(&latest.tf, t);
+    }
+
+    let ahead = (behind - interval).min(MAX_EXTRAPOLATION);
+    Transform::new()
+        .with_position(latest.tf.pos + vel * ahead)
+        .with_rotation(latest.tf.orient)
+}
+
+/// The last two updates of every other ship, and where each was drawn
+#[derive(Default)]
+pub struct RemoteSmoothing {
+    ships: HashMap<ClientId, SmoothedShip>,
+}
+
+impl RemoteSmoothing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take in any fresh updates of the `query`'s ships, and move each to where it's drawn at
+    /// `now`. Our own server copy is left where it is.
+    pub fn update(
+        &mut self,
+        query: &mut QueryResult,
+        query_name: &str,
+        own_id: Option<ClientId>,
+        now: f32,
+    ) {
+        let mut seen = vec![];
+        for entity in query.iter(query_name) {
+            let shipc: ServerShipComponent = query.read(entity);
+            if Some(shipc.client_id) == own_id {
+                continue;
+            }
+            seen.push(shipc.client_id);
+
+            // Anything other than what we drew last came from the server
+            let tf: Transform = query.read(entity);
+            let sample = Sample { time: now, tf };
+            let ship = self
+                .ships
+                .entry(shipc.client_id)
+                .or_insert_with(|| SmoothedShip {
+                    previous: sample,
+                    latest: sample,
+                    drawn: tf,
+                });
+            if tf.pos != ship.drawn.pos || tf.orient != ship.drawn.orient {
+                let teleported = tf.pos.distance(ship.latest.tf.pos) > SNAP_DISTANCE;
+                ship.previous = match teleported {
+                    true => sample,
+                    false => ship.latest,
+                };
+                ship.latest = sample;
+            }
+
+            let vel = query.read::<KinematicPhysics>(entity).vel;
+            ship.drawn = smoothed(ship.previous, ship.latest, vel, now);
+            query.write(entity, &ship.drawn);
+        }
+
+        self.ships.retain(|id, _| seen.contains(id));
+    }
+}
@@ -0,0 +1,172 @@
+use cimvr_common::{
+    glam::{EulerRot, Quat, Vec3},
+    Transform,
+};
+use cimvr_engine_interface::prelude::*;
+
+use crate::{race_session::RacePhase, InputAbstraction, RaceProgress, ServerShipComponent};
+
+/// Top speed of the free-flying camera (meters per second)
+const FLY_SPEED: f32 = 60.;
+/// Fastest the free-flying camera turns (radians per second)
+const FLY_TURN_RATE: f32 = 1.5;
+/// Furthest the free-flying camera tilts up or down, short of looking straight along the
+/// vertical (radians)
+const FLY_MAX_PITCH: f32 = 1.5;
+
+/// How we watch whoever we're spectating
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpectateView {
+    /// From behind their ship
+    #[default]
+    Chase,
+    /// From the nearest fixed camera beside the track
+    Trackside,
+    /// From a camera flown about with the driving controls, watching nobody in particular
+    FreeFly,
+}
+
+/// The free-flying camera, kept level as it turns
+#[derive(Clone, Copy, Debug)]
+struct FreeFly {
+    pos: Vec3,
+    yaw: f32,
+    pitch: f32,
+}
+
+impl FreeFly {
+    /// Start from wherever the camera is
+    fn from_camera(camera: Transform) -> Self {
+        let (yaw, pitch, _) = camera.orient.to_euler(EulerRot::YXZ);
+        Self {
+            pos: camera.pos,
+            yaw,
+            pitch,
+        }
+    }
+
+    /// Turn and fly on for `dt` with `input`: throttle flies forwards and back, steering and
+    /// yaw turn, and pitch tilts
+    fn fly(&mut self, input: &InputAbstraction, dt: f32) -> Transform {
+        self.yaw -= (input.steer() + input.yaw) * FLY_TURN_RATE * dt;
+        self.pitch += input.pitch * FLY_TURN_RATE * dt;
+        self.pitch = self.pitch.clamp(-FLY_MAX_PITCH, FLY_MAX_PITCH);
+
+        let orient = Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.);
+        self.pos += orient * -Vec3::Z * input.throttle * FLY_SPEED * dt;
+        Transform::new()
+            .with_position(self.pos)
+            .with_rotation(orient)
+    }
+}
+
+/// Who we're watching while not in a race
+#[derive(Default)]
+pub struct SpectatorState {
+    /// Which player to spectate (if any)
+    watching: Option<ClientId>,
+    view: SpectateView,
+    /// The free-flying camera, once it's been taken up
+    free_fly: Option<FreeFly>,
+    /// Our own ID, as of our last race, so that we needn't watch ourselves
+    own_id: Option<ClientId>,
+}
+
+impl SpectatorState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Who we're watching; nobody, while flying about freely
+    pub fn watching(&self) -> Option<ClientId> {
+        self.watching.filter(|_| self.view != SpectateView::FreeFly)
+    }
+
+    pub fn view(&self) -> SpectateView {
+        self.view
+    }
+
+    /// Switch to the next way of watching
+    pub fn cycle_view(&mut self) {
+        self.view = match self.view {
+            SpectateView::Chase => SpectateView::Trackside,
+            SpectateView::Trackside => SpectateView::FreeFly,
+            SpectateView::FreeFly => SpectateView::Chase,
+        };
+        self.free_fly = None;
+    }
+
+    /// Where the free-flying camera is after `dt` more of `input`, having taken off from
+    /// `camera`; `None` unless flying about freely
+    pub fn fly(
+        &mut self,
+        input: &InputAbstraction,
+        camera: Transform,
+        dt: f32,
+    ) -> Option<Transform> {
+        if self.view != SpectateView::FreeFly {
+            return None;
+        }
+        let free_fly = self
+            .free_fly
+            .get_or_insert_with(|| FreeFly::from_camera(camera));
+        Some(free_fly.fly(input, dt))
+    }
+
+    /// Stop watching anyone in particular; used whenever we return to spectating
+    pub fn reset(&mut self) {
+        self.watching = None;
+    }
+
+    /// Watch `client_id`, e.g. a relay teammate out on their leg
+    pub fn watch(&mut self, client_id: ClientId) {
+        self.watching = Some(client_id);
+    }
+
+    /// While spectating, find someone to watch if we aren't already or they've gone, and then
+    /// step `step` ships on through the others, e.g. -1 for the one before. Our own ship is
+    /// skipped. Expects a "ServerShips" query.
+    pub fn update(&mut self, query: &mut QueryResult, phase: RacePhase, step: i32) {
+        if let Some(client_id) = phase.client_id() {
+            self.own_id = Some(client_id);
+        }
+        if phase != RacePhase::Spectating {
+            return;
+        }
+
+        let mut ships: Vec<ClientId> = query
+            .iter("ServerShips")
+            .map(|entity| query.read::<ServerShipComponent>(entity).client_id)
+            .filter(|&client_id| Some(client_id) != self.own_id)
+            .collect();
+        ships.sort_by_key(|client_id| client_id.0);
+        self.watching = next_watched(&ships, self.watching, step);
+    }
+
+    /// Checkpoints passed by the racer we're watching, if they're in the race
+    pub fn watched_checkpoints(&self, progress: &RaceProgress) -> Option<usize> {
+        progress
+            .checkpoints
+            .iter()
+            .find(|(client_id, _)| Some(*client_id) == self.watching)
+            .map(|&(_, passed)| passed)
+    }
+}
+
+/// Who to watch out of `ships`, sorted, after stepping `step` on from `watching`. Someone who's
+/// gone is followed by the next ship after where they were, or else the first.
+fn next_watched(ships: &[ClientId], watching: Option<ClientId>, step: i32) -> Option<ClientId> {
+    if ships.is_empty() {
+        return None;
+    }
+    let n = ships.len() as i32;
+    let idx = match watching {
+        Some(watching) => match ships.iter().position(|&client_id| client_id == watching) {
+            Some(idx) => idx as i32 + step,
+            // Wherever they would have been is now the next ship on
+            None => ships.partition_point(|client_id| client_id.0 < watching.0) as i32,
+        },
+        None => 0,
+    };
+    Some(ships[idx.rem_euclid(n) as usize])
+}
@@ -0,0 +1,107 @@
+//! Times through each sector of a lap. The sectors are the stretches between the finish line
+//! and the checkpoints, as the signs along the track number them.
+use crate::{
+    race_rules::{LapEvent, N_CHECKPOINTS},
+    strings::{Key, Text},
+};
+
+/// Sectors in a lap: finish line to the first checkpoint, between each, and back to the line
+pub const N_SECTORS: usize = N_CHECKPOINTS + 1;
+
+/// Time through one sector
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Split {
+    /// Sector, from 0
+    pub sector: usize,
+    pub time: f32,
+    /// Time against the best through the sector before this one, if there was one
+    pub delta: Option<f32>,
+}
+
+impl Split {
+    /// e.g. "S2 12.431 (-0.212)"
+    pub fn text(&self) -> Text {
+        let time = format!("{:.3}", self.time);
+        match self.delta {
+            Some(delta) => Text::new(Key::SplitDelta)
+                .with(self.sector + 1)
+                .with(time)
+                .with(format!("{:+.3}", delta)),
+            None => Text::new(Key::Split).with(self.sector + 1).with(time),
+        }
+    }
+}
+
+/// Times the sectors from the lap tracker's events
+#[derive(Clone, Debug, Default)]
+pub struct SplitTimes {
+    /// Sector being driven, and the race time it was entered; `None` until the next lap
+    /// starts, e.g. on the grid or after a missed checkpoint
+    current: Option<(usize, f32)>,
+    /// Fastest time through each sector on this track
+    best: [Option<f32>; N_SECTORS],
+    /// Most recent split
+    last: Option<Split>,
+}
+
+impl SplitTimes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget the best times, for a new track
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Stop timing until the next lap starts, keeping the best times, e.g. for a new race
+    pub fn restart(&mut self) {
+        self.current = None;
+        self.last = None;
+    }
+
+    /// Follow a lap event at `race_time`, returning the sector it ended, if any. Only the
+    /// checkpoint ending the sector being driven counts, so crossing one again after going
+    /// back over it times nothing.
+    pub fn update(&mut self, event: &LapEvent, race_time: f32) -> Option<Split> {
+        let ended = match *event {
+            LapEvent::Checkpoint(idx) => idx,
+            LapEvent::Lap(_) => {
+                // Starting a lap always starts timing, whether or not one was running
+                let current = self.current.replace((0, race_time));
+                return current
+                    .filter(|&(sector, _)| sector == N_SECTORS - 1)
+                    .map(|(sector, start)| self.record(sector, race_time - start));
+            }
+            LapEvent::MissedCheckpoint { .. } | LapEvent::IncompleteLap | LapEvent::Backwards => {
+                self.current = None;
+                return None;
+            }
+        };
+
+        let (sector, start) = self.current?;
+        if sector != ended {
+            return None;
+        }
+        self.current = Some((sector + 1, race_time));
+        Some(self.record(sector, race_time - start))
+    }
+
+    fn record(&mut self, sector: usize, time: f32) -> Split {
+        let best = &mut self.best[sector];
+        let delta = best.map(|best| time - best);
+        *best = Some(best.map_or(time, |best| best.min(time)));
+        let split = Split {
+            sector,
+            time,
+            delta,
+        };
+        self.last = Some(split);
+        split
+    }
+
+    /// The most recent split, for the HUD
+    pub fn last(&self) -> Option<Split> {
+        self.last
+    }
+}
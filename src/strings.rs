@@ -0,0 +1,541 @@
+//! Player-facing text in every bundled language. Text sent over the network travels as a key
+//! and parameters, and is only rendered by whoever shows it.
+use serde::{Deserialize, Serialize};
+
+/// Languages the text is bundled in
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English,
+    Spanish,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::Spanish];
+
+    /// Name of the language, in itself
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Spanish => "Español",
+        }
+    }
+
+    /// The next language along, wrapping around
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|&l| l == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+/// A piece of player-facing text. `{0}`, `{1}`, ... in a template are replaced by parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// Untranslated text, given as the only parameter; e.g. replies to chat commands
+    Raw,
+
+    ToggleReady,
+    TimeTrial,
+    Ready,
+    NotReady,
+    Settings,
+    On,
+    Off,
+    Language,
+    TeleportFade,
+    ColorblindPalette,
+    Telemetry,
+    AutoThrottle,
+    ChatWhileRacing,
+    MirrorGhost,
+    OneHanded,
+    PhotoFinish,
+    HoverStrafe,
+    TrackReveal,
+    GraphicsQuality,
+    ResetSettings,
+    QualityHigh,
+    QualityMedium,
+    QualityLow,
+    Explored,
+    ResetExplored,
+    Afk,
+    DuplicateName,
+    /// Players' ship colors
+    ColorRed,
+    ColorOrange,
+    ColorYellow,
+    ColorGreen,
+    ColorCyan,
+    ColorBlue,
+    ColorPurple,
+    ColorPink,
+    Controls,
+    BindThrottle,
+    BindBrake,
+    BindSteerLeft,
+    BindSteerRight,
+    BindRespawn,
+    BindCycleView,
+    BindPadThrottle,
+    BindPadBrake,
+    BindPadUseItem,
+    BindPadCycleView,
+    BindPadRespawn,
+    BindPadSteer,
+    BindPadPitch,
+    BindPadYaw,
+    BindPadThrottleAxis,
+    PressAKey,
+    PressAButton,
+    MoveAStick,
+    Unbound,
+
+    /// Lap number completed, race time
+    LapChat,
+    /// Lap number, lap time
+    LapLine,
+    /// Lap time
+    BestLap,
+    /// Sector number, sector time
+    Split,
+    /// Sector number, sector time, difference from the best
+    SplitDelta,
+    /// Finishing place, name, race time
+    ResultLine,
+    /// Name
+    ResultDnf,
+    OffTrack,
+    WasOffTrack,
+    LapInvalid,
+    JumpStart,
+    LapInvalidated,
+    /// Position, race time
+    RaceComplete,
+    /// Position, number of racers
+    RacePosition,
+
+    /// Name, position, race time
+    RacerFinished,
+    /// Name, notes on the result
+    Winner,
+    /// Ready clients, all clients
+    ReadyCount,
+    RaceStarted,
+    RollingStart,
+    GreenFlag,
+    /// Name of the conditions
+    ConditionsAnnounced,
+    /// Percentage of extra thrust
+    CatchUpOn,
+    CatchUpOff,
+    /// Reason
+    TrackRejected,
+    TrackReloaded,
+    /// Track name
+    TrackSelected,
+    /// Name
+    TimeTrialStarted,
+    /// Name, race time
+    TimeTrialFinished,
+    TimeTrialBusy,
+    WatchdogReset,
+    /// Proposal
+    VotePassed,
+    /// Proposal
+    VoteFailed,
+    /// Name
+    LostConnection,
+    /// Name
+    DidNotFinish,
+    /// Server's protocol version, client's
+    ProtocolMismatch,
+    /// Runners per team, laps per runner
+    RelayRace,
+    /// Team number, runners' names
+    RelayTeam,
+    /// Name, next runner's name
+    RelayHandoff,
+    /// Name, next runner's name, seconds of penalty
+    RelayDropped,
+    /// Team, each leg's time
+    RelaySplits,
+    RelayYourLeg,
+    RelayRunning,
+    RelayWaiting,
+    /// Seconds
+    RelayPenalty,
+
+    /// Length in meters, number of corners
+    TrackLoaded,
+    /// Meters
+    TrackLength,
+    /// Number of corners
+    TrackCorners,
+    /// Meters between the lowest and highest points
+    TrackClimb,
+    /// Laps in the next race
+    TrackLaps,
+    /// Lap time, and who set it
+    TrackRecord,
+    NoTrackRecord,
+    /// Race time, and who set it
+    RaceRecord,
+
+    /// Lap number, time into the lap
+    WatchedLap,
+    WatchedGrid,
+    /// Race time
+    WatchedRaceTime,
+    /// Final race time
+    WatchedFinished,
+}
+
+/// Template for `key` in `language`
+pub fn tr(language: Language, key: Key) -> &'static str {
+    match language {
+        Language::English => english(key),
+        Language::Spanish => spanish(key),
+    }
+}
+
+fn english(key: Key) -> &'static str {
+    match key {
+        Key::Raw => "{0}",
+        Key::ToggleReady => "Toggle Ready",
+        Key::TimeTrial => "Time Trial",
+        Key::Ready => "Ready!",
+        Key::NotReady => "(Not ready)",
+        Key::Settings => "Settings",
+        Key::On => "On",
+        Key::Off => "Off",
+        Key::Language => "Language",
+        Key::TeleportFade => "Teleport fade",
+        Key::ColorblindPalette => "Colorblind palette",
+        Key::Telemetry => "Telemetry",
+        Key::AutoThrottle => "Auto-throttle",
+        Key::ChatWhileRacing => "Chat while racing",
+        Key::MirrorGhost => "Mirror ghost",
+        Key::OneHanded => "One-handed controls",
+        Key::PhotoFinish => "Finish line camera",
+        Key::HoverStrafe => "Hover strafe controls",
+        Key::TrackReveal => "Reveal the track as you drive (solo races)",
+        Key::GraphicsQuality => "Graphics quality",
+        Key::ResetSettings => "Reset all settings",
+        Key::QualityHigh => "High",
+        Key::QualityMedium => "Medium",
+        Key::QualityLow => "Low",
+        Key::Explored => "Explored",
+        Key::ResetExplored => "Hide the track again",
+        Key::Afk => "(afk)",
+        Key::DuplicateName => "(name in use, unrated)",
+        Key::ColorRed => "red",
+        Key::ColorOrange => "orange",
+        Key::ColorYellow => "yellow",
+        Key::ColorGreen => "green",
+        Key::ColorCyan => "cyan",
+        Key::ColorBlue => "blue",
+        Key::ColorPurple => "purple",
+        Key::ColorPink => "pink",
+        Key::Controls => "Controls",
+        Key::BindThrottle => "Throttle",
+        Key::BindBrake => "Brake",
+        Key::BindSteerLeft => "Steer left",
+        Key::BindSteerRight => "Steer right",
+        Key::BindRespawn => "Respawn",
+        Key::BindCycleView => "Spectator view",
+        Key::BindPadThrottle => "Gamepad throttle",
+        Key::BindPadBrake => "Gamepad brake",
+        Key::BindPadUseItem => "Gamepad item",
+        Key::BindPadCycleView => "Gamepad spectator view",
+        Key::BindPadRespawn => "Gamepad respawn",
+        Key::BindPadSteer => "Steering stick",
+        Key::BindPadPitch => "Pitch stick",
+        Key::BindPadYaw => "Yaw stick",
+        Key::BindPadThrottleAxis => "Throttle stick",
+        Key::PressAKey => "Press a key...",
+        Key::PressAButton => "Press a button...",
+        Key::MoveAStick => "Move a stick...",
+        Key::Unbound => "(none)",
+        Key::LapChat => "Lap {0}, time: {1}",
+        Key::LapLine => "Lap {0}: {1}",
+        Key::BestLap => "Best: {0}",
+        Key::Split => "S{0} {1}",
+        Key::SplitDelta => "S{0} {1} ({2})",
+        Key::ResultLine => "P{0} {1} - {2}",
+        Key::ResultDnf => "{0} - DNF",
+        Key::OffTrack => "OFF TRACK",
+        Key::WasOffTrack => "Off track",
+        Key::LapInvalid => "LAP INVALID",
+        Key::JumpStart => "JUMP START - thrust locked",
+        Key::LapInvalidated => "LAP INVALID - too long off track",
+        Key::RaceComplete => "Race complete - P{0}, {1}",
+        Key::RacePosition => "P{0} of {1}",
+        Key::RacerFinished => "{0} finished P{1} - {2}",
+        Key::Winner => "Winner: {0}{1}",
+        Key::ReadyCount => "{0}/{1} ready.",
+        Key::RaceStarted => "RACE STARTED",
+        Key::RollingStart => "Rolling start - hold formation behind the pace line",
+        Key::GreenFlag => "GREEN FLAG",
+        Key::ConditionsAnnounced => "Conditions: {0}",
+        Key::CatchUpOn => "Catch-up on: from -{0}% thrust for the leader to +{0}% for last place",
+        Key::CatchUpOff => "Catch-up off",
+        Key::TrackRejected => "Track rejected: {0}",
+        Key::TrackReloaded => "Track reloaded",
+        Key::TrackSelected => "Track: {0}",
+        Key::TimeTrialStarted => "{0} is on a time trial",
+        Key::TimeTrialFinished => "{0} finished a time trial in {1}",
+        Key::TimeTrialBusy => "Time trials have to wait until nobody is racing",
+        Key::WatchdogReset => "Race reset by watchdog",
+        Key::VotePassed => "Vote passed: {0}",
+        Key::VoteFailed => "Vote failed: {0}",
+        Key::LostConnection => "{0} lost connection",
+        Key::DidNotFinish => "{0} did not finish (disconnected)",
+        Key::ProtocolMismatch => {
+            "Version mismatch: the server speaks protocol {0}, this client {1}. \
+            Update to race."
+        }
+        Key::RelayRace => "Relay race - teams of {0}, {1} laps each",
+        Key::RelayTeam => "Team {0}: {1}",
+        Key::RelayHandoff => "{0} hands off to {1}",
+        Key::RelayDropped => "{0} dropped out - {1} takes over (+{2} s)",
+        Key::RelaySplits => "{0} legs: {1}",
+        Key::RelayYourLeg => "YOUR LEG - GO!",
+        Key::RelayRunning => "running",
+        Key::RelayWaiting => "waiting",
+        Key::RelayPenalty => "Penalty: +{0} s",
+        Key::TrackLoaded => "New track: {0} m, {1} corners",
+        Key::TrackLength => "Length: {0} m",
+        Key::TrackCorners => "Corners: {0}",
+        Key::TrackClimb => "Climb: {0} m",
+        Key::TrackLaps => "Laps: {0}",
+        Key::TrackRecord => "Lap record: {0} by {1}",
+        Key::NoTrackRecord => "Lap record: none yet",
+        Key::RaceRecord => "Race record: {0} by {1}",
+        Key::WatchedLap => "Lap {0}: {1}",
+        Key::WatchedGrid => "On the grid",
+        Key::WatchedRaceTime => "Race: {0}",
+        Key::WatchedFinished => "Finished - {0}",
+    }
+}
+
+fn spanish(key: Key) -> &'static str {
+    match key {
+        Key::Raw => "{0}",
+        Key::ToggleReady => "Listo / No listo",
+        Key::TimeTrial => "Contrarreloj",
+        Key::Ready => "¡Listo!",
+        Key::NotReady => "(No listo)",
+        Key::Settings => "Ajustes",
+        Key::On => "Sí",
+        Key::Off => "No",
+        Key::Language => "Idioma",
+        Key::TeleportFade => "Fundido al teletransportar",
+        Key::ColorblindPalette => "Paleta para daltónicos",
+        Key::Telemetry => "Telemetría",
+        Key::AutoThrottle => "Acelerador automático",
+        Key::ChatWhileRacing => "Chat durante la carrera",
+        Key::MirrorGhost => "Fantasma espejo",
+        Key::OneHanded => "Controles a una mano",
+        Key::PhotoFinish => "Cámara de meta",
+        Key::HoverStrafe => "Controles de desplazamiento lateral",
+        Key::TrackReveal => "Revelar la pista al conducir (carreras en solitario)",
+        Key::GraphicsQuality => "Calidad gráfica",
+        Key::ResetSettings => "Restablecer ajustes",
+        Key::QualityHigh => "Alta",
+        Key::QualityMedium => "Media",
+        Key::QualityLow => "Baja",
+        Key::Explored => "Explorado",
+        Key::ResetExplored => "Ocultar la pista de nuevo",
+        Key::Afk => "(ausente)",
+        Key::DuplicateName => "(nombre en uso, sin puntuar)",
+        Key::ColorRed => "rojo",
+        Key::ColorOrange => "naranja",
+        Key::ColorYellow => "amarillo",
+        Key::ColorGreen => "verde",
+        Key::ColorCyan => "cian",
+        Key::ColorBlue => "azul",
+        Key::ColorPurple => "morado",
+        Key::ColorPink => "rosa",
+        Key::Controls => "Controles",
+        Key::BindThrottle => "Acelerar",
+        Key::BindBrake => "Frenar",
+        Key::BindSteerLeft => "Girar a la izquierda",
+        Key::BindSteerRight => "Girar a la derecha",
+        Key::BindRespawn => "Reaparecer",
+        Key::BindCycleView => "Vista de espectador",
+        Key::BindPadThrottle => "Acelerar con el mando",
+        Key::BindPadBrake => "Frenar con el mando",
+        Key::BindPadUseItem => "Objeto con el mando",
+        Key::BindPadCycleView => "Vista de espectador con el mando",
+        Key::BindPadRespawn => "Reaparecer con el mando",
+        Key::BindPadSteer => "Palanca de giro",
+        Key::BindPadPitch => "Palanca de cabeceo",
+        Key::BindPadYaw => "Palanca de guiñada",
+        Key::BindPadThrottleAxis => "Palanca de aceleración",
+        Key::PressAKey => "Pulsa una tecla...",
+        Key::PressAButton => "Pulsa un botón...",
+        Key::MoveAStick => "Mueve una palanca...",
+        Key::Unbound => "(ninguna)",
+        Key::LapChat => "Vuelta {0}, tiempo: {1}",
+        Key::LapLine => "Vuelta {0}: {1}",
+        Key::BestLap => "Mejor: {0}",
+        Key::Split => "S{0} {1}",
+        Key::SplitDelta => "S{0} {1} ({2})",
+        Key::ResultLine => "P{0} {1} - {2}",
+        Key::ResultDnf => "{0} - abandono",
+        Key::OffTrack => "FUERA DE PISTA",
+        Key::WasOffTrack => "Fuera de pista",
+        Key::LapInvalid => "VUELTA NO VÁLIDA",
+        Key::JumpStart => "SALIDA EN FALSO - empuje bloqueado",
+        Key::LapInvalidated => "VUELTA NO VÁLIDA - demasiado tiempo fuera de pista",
+        Key::RaceComplete => "Carrera terminada - P{0}, {1}",
+        Key::RacePosition => "P{0} de {1}",
+        Key::RacerFinished => "{0} terminó P{1} - {2}",
+        Key::Winner => "Ganador: {0}{1}",
+        Key::ReadyCount => "{0}/{1} listos.",
+        Key::RaceStarted => "CARRERA INICIADA",
+        Key::RollingStart => "Salida lanzada - mantén la formación tras la línea de ritmo",
+        Key::GreenFlag => "BANDERA VERDE",
+        Key::ConditionsAnnounced => "Condiciones: {0}",
+        Key::CatchUpOn => "Ayuda activada: de -{0}% de empuje para el líder a +{0}% para el último",
+        Key::CatchUpOff => "Ayuda desactivada",
+        Key::TrackRejected => "Pista rechazada: {0}",
+        Key::TrackReloaded => "Pista recargada",
+        Key::TrackSelected => "Pista: {0}",
+        Key::TimeTrialStarted => "{0} corre una contrarreloj",
+        Key::TimeTrialFinished => "{0} terminó una contrarreloj en {1}",
+        Key::TimeTrialBusy => "Las contrarrelojes esperan a que nadie esté corriendo",
+        Key::WatchdogReset => "Carrera reiniciada por el vigilante",
+        Key::VotePassed => "Votación aprobada: {0}",
+        Key::VoteFailed => "Votación rechazada: {0}",
+        Key::LostConnection => "{0} perdió la conexión",
+        Key::DidNotFinish => "{0} no terminó (desconectado)",
+        Key::ProtocolMismatch => {
+            "Versión incompatible: el servidor usa el protocolo {0}, este cliente el {1}. \
+            Actualiza para correr."
+        }
+        Key::RelayRace => "Carrera de relevos - equipos de {0}, {1} vueltas cada uno",
+        Key::RelayTeam => "Equipo {0}: {1}",
+        Key::RelayHandoff => "{0} da el relevo a {1}",
+        Key::RelayDropped => "{0} abandonó - {1} toma el relevo (+{2} s)",
+        Key::RelaySplits => "Tramos de {0}: {1}",
+        Key::RelayYourLeg => "¡TU TRAMO - YA!",
+        Key::RelayRunning => "en pista",
+        Key::RelayWaiting => "esperando",
+        Key::RelayPenalty => "Penalización: +{0} s",
+        Key::TrackLoaded => "Nueva pista: {0} m, {1} curvas",
+        Key::TrackLength => "Longitud: {0} m",
+        Key::TrackCorners => "Curvas: {0}",
+        Key::TrackClimb => "Desnivel: {0} m",
+        Key::TrackLaps => "Vueltas: {0}",
+        Key::TrackRecord => "Récord de vuelta: {0} de {1}",
+        Key::NoTrackRecord => "Récord de vuelta: aún ninguno",
+        Key::RaceRecord => "Récord de carrera: {0} de {1}",
+        Key::WatchedLap => "Vuelta {0}: {1}",
+        Key::WatchedGrid => "En la parrilla",
+        Key::WatchedRaceTime => "Carrera: {0}",
+        Key::WatchedFinished => "Terminó - {0}",
+    }
+}
+
+/// A key and the parameters to fill it in with
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Text {
+    pub key: Key,
+    pub params: Vec<String>,
+}
+
+impl Text {
+    pub fn new(key: Key) -> Self {
+        Self {
+            key,
+            params: vec![],
+        }
+    }
+
+    /// Untranslated text
+    pub fn raw(text: impl Into<String>) -> Self {
+        Self::new(Key::Raw).with(text.into())
+    }
+
+    /// Add the next parameter
+    pub fn with(mut self, param: impl ToString) -> Self {
+        self.params.push(param.to_string());
+        self
+    }
+
+    pub fn render(&self, language: Language) -> String {
+        let mut text = tr(language, self.key).to_string();
+        for (idx, param) in self.params.iter().enumerate() {
+            text = text.replace(&format!("{{{}}}", idx), param);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every key, read back from its variant index since the enum can't list itself
+    fn all_keys() -> Vec<Key> {
+        (0u32..)
+            .map_while(|idx| bincode::deserialize(&idx.to_le_bytes()).ok())
+            .collect()
+    }
+
+    /// The `{n}` parameters a template takes
+    fn params(template: &str) -> Vec<usize> {
+        let mut params: Vec<usize> = template
+            .split('{')
+            .skip(1)
+            .filter_map(|rest| rest.split_once('}')?.0.parse().ok())
+            .collect();
+        params.sort_unstable();
+        params.dedup();
+        params
+    }
+
+    #[test]
+    fn every_key_is_listed() {
+        let keys = all_keys();
+        assert_eq!(keys.first(), Some(&Key::Raw));
+        assert!(keys.contains(&Key::WatchedFinished));
+    }
+
+    #[test]
+    fn every_key_has_text_in_every_language() {
+        for key in all_keys() {
+            for language in Language::ALL {
+                assert!(
+                    !tr(language, key).trim().is_empty(),
+                    "{:?} in {:?}",
+                    key,
+                    language
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_language_takes_the_same_parameters() {
+        for key in all_keys() {
+            let english = params(tr(Language::English, key));
+            for language in Language::ALL {
+                let theirs = params(tr(language, key));
+                assert_eq!(theirs, english, "{:?} in {:?}", key, language);
+            }
+        }
+    }
+
+    #[test]
+    fn parameters_are_filled_in() {
+        let text = Text::new(Key::RacerFinished)
+            .with("Ana")
+            .with(2)
+            .with("1:02.5");
+        assert_eq!(text.render(Language::English), "Ana finished P2 - 1:02.5");
+        assert_eq!(text.render(Language::Spanish), "Ana terminó P2 - 1:02.5");
+    }
+}
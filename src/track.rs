@@ -0,0 +1,105 @@
+//! Moving track files between the admin client, the server and everyone else.
+//! Files are sent as OBJ text, split into chunks which each fit in a message.
+use serde::{Deserialize, Serialize};
+
+/// Most bytes of OBJ text in one chunk
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Which track file a chunk belongs to
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackFile {
+    Path,
+    Environment,
+}
+
+/// One piece of a track file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrackChunk {
+    pub file: TrackFile,
+    pub index: usize,
+    pub total: usize,
+    pub text: String,
+}
+
+/// Full text of a track's files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackFiles {
+    pub path: String,
+    pub environment: String,
+}
+
+impl TrackFiles {
+    /// Split both files into chunks
+    pub fn chunks(&self) -> Vec<TrackChunk> {
+        let mut chunks = split(TrackFile::Path, &self.path);
+        chunks.extend(split(TrackFile::Environment, &self.environment));
+        chunks
+    }
+}
+
+/// Split on line boundaries, so chunks never cut a character in half
+fn split(file: TrackFile, text: &str) -> Vec<TrackChunk> {
+    let mut pieces = vec![String::new()];
+    for line in text.lines() {
+        let current = pieces.last_mut().unwrap();
+        if !current.is_empty() && current.len() + line.len() + 1 > CHUNK_SIZE {
+            pieces.push(String::new());
+        }
+        let current = pieces.last_mut().unwrap();
+        current.push_str(line);
+        current.push('\n');
+    }
+
+    let total = pieces.len();
+    pieces
+        .into_iter()
+        .enumerate()
+        .map(|(index, text)| TrackChunk {
+            file,
+            index,
+            total,
+            text,
+        })
+        .collect()
+}
+
+/// Collects chunks, in any order, until both files are complete
+#[derive(Default)]
+pub struct TrackAssembler {
+    path: Vec<Option<String>>,
+    environment: Vec<Option<String>>,
+}
+
+impl TrackAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a chunk, returning the files if they are now complete
+    pub fn push(&mut self, chunk: TrackChunk) -> Option<TrackFiles> {
+        let pieces = match chunk.file {
+            TrackFile::Path => &mut self.path,
+            TrackFile::Environment => &mut self.environment,
+        };
+
+        // A different total means a new upload has begun
+        if pieces.len() != chunk.total {
+            *pieces = vec![None; chunk.total];
+        }
+        if let Some(piece) = pieces.get_mut(chunk.index) {
+            *piece = Some(chunk.text);
+        }
+
+        let path = join(&self.path)?;
+        let environment = join(&self.environment)?;
+        *self = Self::new();
+        Some(TrackFiles { path, environment })
+    }
+}
+
+fn join(pieces: &[Option<String>]) -> Option<String> {
+    if pieces.is_empty() {
+        return None;
+    }
+    pieces.iter().map(|piece| piece.as_deref()).collect()
+}
@@ -0,0 +1,139 @@
+//! Practice aid: the track stays dim until it's been driven, so that a new track is learnt a
+//! stretch at a time. Only in races we have to ourselves; with anyone else on the track,
+//! everything is drawn.
+use cimvr_common::ui::{Schema, State, UiHandle, UiStateHelper};
+use cimvr_engine_interface::prelude::*;
+
+use crate::strings::{tr, Key, Language};
+
+/// Least time between redraws of the environment as more of it is explored (seconds)
+const REDRAW_INTERVAL: f32 = 0.5;
+
+/// What the environment should be redrawn with
+pub enum RevealRedraw<'a> {
+    /// Only the explored control points in full
+    Explored(&'a [bool]),
+    /// All of it
+    Everything,
+}
+
+/// How much of the track has been driven this session
+pub struct TrackReveal {
+    /// Control points driven past, since the track loaded or the last reset
+    explored: Vec<bool>,
+    /// Whether the fog was drawn last redraw
+    shown: bool,
+    /// More was explored since the last redraw
+    dirty: bool,
+    last_redraw: f32,
+    /// Percentage explored and a reset button, while shown
+    element: Option<UiHandle>,
+    /// Percentage on the readout
+    shown_percent: u32,
+}
+
+impl TrackReveal {
+    /// Nothing explored yet, on a track of `n` control points
+    pub fn new(n: usize) -> Self {
+        Self {
+            explored: vec![false; n],
+            shown: false,
+            dirty: false,
+            last_redraw: f32::MIN,
+            element: None,
+            shown_percent: 0,
+        }
+    }
+
+    /// Forget everything explored, e.g. for a new track, which starts out drawn in full
+    pub fn reset(&mut self, n: usize) {
+        self.explored = vec![false; n];
+        self.shown = false;
+    }
+
+    /// The ship reached control point `idx`
+    pub fn explore(&mut self, idx: usize) {
+        if let Some(explored) = self.explored.get_mut(idx) {
+            self.dirty |= !*explored;
+            *explored = true;
+        }
+    }
+
+    fn percent(&self) -> u32 {
+        let explored = self.explored.iter().filter(|&&explored| explored).count();
+        (explored * 100 / self.explored.len().max(1)) as u32
+    }
+
+    /// How to redraw the environment at `time`, if it's due, with the fog `active` or not
+    pub fn redraw(&mut self, active: bool, time: f32) -> Option<RevealRedraw> {
+        if active != self.shown {
+            self.shown = active;
+            self.last_redraw = time;
+            self.dirty = false;
+            return Some(match active {
+                true => RevealRedraw::Explored(&self.explored),
+                false => RevealRedraw::Everything,
+            });
+        }
+
+        let due = time - self.last_redraw >= REDRAW_INTERVAL;
+        if !(active && self.dirty && due) {
+            return None;
+        }
+        self.last_redraw = time;
+        self.dirty = false;
+        Some(RevealRedraw::Explored(&self.explored))
+    }
+
+    /// Show the readout while `visible`. Returns true when the reset button was pressed.
+    /// Expects `gui` to already be downloaded.
+    pub fn update_panel(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        language: Language,
+        visible: bool,
+    ) -> bool {
+        let percent = self.percent();
+        match (visible, self.element) {
+            (true, None) => {
+                let schema = vec![
+                    Schema::Label,
+                    Schema::Button {
+                        text: tr(language, Key::ResetExplored).into(),
+                    },
+                ];
+                let state = vec![
+                    State::Label {
+                        text: explored_text(language, percent),
+                    },
+                    State::Button { clicked: false },
+                ];
+                self.element = Some(gui.add(io, "Explored", schema, state));
+                self.shown_percent = percent;
+                false
+            }
+            (true, Some(handle)) => {
+                if percent != self.shown_percent {
+                    self.shown_percent = percent;
+                    gui.modify(io, handle, |ui_state| {
+                        ui_state[0] = State::Label {
+                            text: explored_text(language, percent),
+                        }
+                    });
+                }
+                gui.read(handle)[1] == State::Button { clicked: true }
+            }
+            (false, Some(handle)) => {
+                gui.delete(io, handle);
+                self.element = None;
+                false
+            }
+            (false, None) => false,
+        }
+    }
+}
+
+fn explored_text(language: Language, percent: u32) -> String {
+    format!("{}: {}%", tr(language, Key::Explored), percent)
+}
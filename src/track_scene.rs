@@ -0,0 +1,278 @@
+use cimvr_common::{
+    glam::Vec3,
+    render::{Mesh, MeshHandle, Primitive, Render, UploadMesh},
+    ui::UiStateHelper,
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{
+    barriers::Barriers,
+    billboards::{Billboards, TrackConfig},
+    boost_pads::BoostPadMarkings,
+    camera_walls::CameraWalls,
+    conditions::Conditions,
+    curve::Curve,
+    gates::CheckpointGates,
+    meshutil::decimate_lines,
+    obj::{obj_lines_to_mesh, ObjElements},
+    race_rules::{FinishPlane, LapTracker},
+    settings::Quality,
+    shapes::{ground_mesh, ground_snap},
+    trackside::TracksideCameras,
+    wall_ride::ShieldWalls,
+};
+
+/// Spacing of the finest floor grid lines (meters)
+const FLOOR_SPACING: f32 = 20.;
+/// Number of successively coarser bands of floor grid
+const FLOOR_BANDS: u32 = 3;
+/// Distance of the floor below the lowest point of the track (meters)
+const FLOOR_CLEARANCE: f32 = 10.;
+/// How far towards the clear color stretches of track not yet explored are faded
+const UNEXPLORED_FADE: f32 = 0.8;
+
+/// Every entity derived from the track, so that all of it can be torn down when the track
+/// is replaced
+pub struct TrackScene {
+    map_ent: EntityId,
+    /// Environment as loaded
+    environment: Mesh,
+    /// Whether the environment is drawn in lines or faces
+    elements: ObjElements,
+    /// Environment simplified for `quality`, before coloring for the conditions
+    detail: Mesh,
+    /// Nearest control point to each vertex of `detail`
+    detail_nearest: Vec<usize>,
+    /// Control points explored so far, while only the explored track is to be drawn in full
+    explored: Option<Vec<bool>>,
+    quality: Quality,
+    conditions: Conditions,
+    finish_ent: EntityId,
+    floor_ent: EntityId,
+    floor_height: f32,
+    /// Grid cell the floor is currently centered on
+    floor_cell: Option<(i32, i32)>,
+    pub gates: CheckpointGates,
+    pub billboards: Billboards,
+    pub barriers: Barriers,
+    boost_pads: BoostPadMarkings,
+    shield_walls: ShieldWalls,
+    pub trackside: TracksideCameras,
+    pub walls: CameraWalls,
+}
+
+impl TrackScene {
+    const MAP_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("Map"));
+    const FLOOR_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("Floor"));
+    const FINISH_RDR: MeshHandle = MeshHandle::new(pkg_namespace!("FinishLine"));
+
+    /// Upload the meshes which are the same for every track
+    pub fn assets(io: &mut EngineIo, clear_color: [f32; 3]) {
+        Self::upload_floor(io, Conditions::Clear, clear_color);
+
+        let mut finish_line_mesh = obj_lines_to_mesh(include_str!("assets/finish_line.obj"));
+        finish_line_mesh
+            .vertices
+            .iter_mut()
+            .for_each(|v| v.uvw = [1., 0., 0.]);
+
+        io.send(&UploadMesh {
+            mesh: finish_line_mesh,
+            id: Self::FINISH_RDR,
+        });
+    }
+
+    pub fn new(
+        io: &mut EngineIo,
+        path: &Curve,
+        tracker: &LapTracker,
+        environment: Mesh,
+        elements: ObjElements,
+        config: &TrackConfig,
+        quality: Quality,
+    ) -> Self {
+        let detail = simplify(&environment, elements, quality);
+        let detail_nearest = nearest_ctrlps(path, &detail);
+        let mut mesh = detail.clone();
+        mesh.recolor(Conditions::Clear.environment_color());
+        io.send(&UploadMesh {
+            mesh,
+            id: Self::MAP_RDR,
+        });
+
+        // Add environment
+        let map_ent = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(Render::new(Self::MAP_RDR).primitive(elements.primitive()))
+            .build();
+
+        // Add finish line
+        let finish_ent = io
+            .create_entity()
+            .add_component(FinishPlane::new(path, path.finish_line()).transform)
+            .add_component(Render::new(Self::FINISH_RDR).primitive(Primitive::Lines))
+            .build();
+
+        // Add floor, just below the lowest point of the track
+        let floor_height = path.bounds().0.y - FLOOR_CLEARANCE;
+        let floor_ent = io
+            .create_entity()
+            .add_component(Transform::new().with_position(Vec3::new(0., floor_height, 0.)))
+            .add_component(Render::new(Self::FLOOR_RDR).primitive(Primitive::Lines))
+            .build();
+
+        Self {
+            map_ent,
+            environment,
+            elements,
+            detail,
+            detail_nearest,
+            explored: None,
+            quality,
+            conditions: Conditions::Clear,
+            finish_ent,
+            floor_ent,
+            floor_height,
+            floor_cell: None,
+            gates: CheckpointGates::new(io, tracker),
+            billboards: Billboards::new(io, path, tracker, config),
+            barriers: Barriers::new(io, path, &config.barriers),
+            boost_pads: BoostPadMarkings::new(io, &path.boost_pads),
+            shield_walls: ShieldWalls::new(io, path, &config.shielded_walls),
+            trackside: TracksideCameras::new(path),
+            walls: CameraWalls::new(path),
+        }
+    }
+
+    /// Remove everything this scene created
+    pub fn teardown(self, io: &mut EngineIo, gui: &mut UiStateHelper) {
+        io.remove_entity(self.map_ent);
+        io.remove_entity(self.finish_ent);
+        io.remove_entity(self.floor_ent);
+        self.gates.teardown(io, gui);
+        self.billboards.teardown(io);
+        self.barriers.teardown(io);
+        self.boost_pads.teardown(io);
+        self.shield_walls.teardown(io);
+    }
+
+    /// Recolor the environment and floor for the given conditions, fading towards `clear_color`
+    pub fn set_conditions(
+        &mut self,
+        io: &mut EngineIo,
+        path: &Curve,
+        conditions: Conditions,
+        clear_color: [f32; 3],
+    ) {
+        self.conditions = conditions;
+        self.upload_map(io, path, clear_color);
+        Self::upload_floor(io, conditions, clear_color);
+    }
+
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    /// Draw the environment in more or less detail
+    pub fn set_quality(
+        &mut self,
+        io: &mut EngineIo,
+        path: &Curve,
+        quality: Quality,
+        clear_color: [f32; 3],
+    ) {
+        self.quality = quality;
+        self.detail = simplify(&self.environment, self.elements, quality);
+        self.detail_nearest = nearest_ctrlps(path, &self.detail);
+        self.upload_map(io, path, clear_color);
+    }
+
+    /// Fade out the stretches of track not marked in `explored`, or draw all of it if `None`
+    pub fn set_explored(
+        &mut self,
+        io: &mut EngineIo,
+        path: &Curve,
+        explored: Option<&[bool]>,
+        clear_color: [f32; 3],
+    ) {
+        self.explored = explored.map(<[bool]>::to_vec);
+        self.upload_map(io, path, clear_color);
+    }
+
+    fn upload_map(&self, io: &mut EngineIo, path: &Curve, clear_color: [f32; 3]) {
+        let conditions = self.conditions;
+        let color = conditions.environment_color();
+        let mut mesh = self.detail.clone();
+        mesh.recolor(color);
+
+        // Lines far from the track are lost in the murk, and unexplored ones in the dark
+        let visibility = conditions.visibility();
+        if visibility.is_some() || self.explored.is_some() {
+            for (vertex, &idx) in mesh.vertices.iter_mut().zip(&self.detail_nearest) {
+                let pos = Vec3::from(vertex.pos);
+                let murk = |visibility: f32| pos.distance(path.ctrlps[idx].pos) / visibility;
+                let mut fade = visibility.map_or(0., |visibility| murk(visibility).min(1.));
+                if self
+                    .explored
+                    .as_ref()
+                    .map_or(false, |explored| !explored[idx])
+                {
+                    fade = fade.max(UNEXPLORED_FADE);
+                }
+                vertex.uvw = std::array::from_fn(|i| color[i] + (clear_color[i] - color[i]) * fade);
+            }
+        }
+
+        io.send(&UploadMesh {
+            mesh,
+            id: Self::MAP_RDR,
+        });
+    }
+
+    fn upload_floor(io: &mut EngineIo, conditions: Conditions, clear_color: [f32; 3]) {
+        let color = conditions.floor_color();
+        io.send(&UploadMesh {
+            mesh: ground_mesh(FLOOR_SPACING, FLOOR_BANDS, color, clear_color),
+            id: Self::FLOOR_RDR,
+        });
+    }
+
+    /// Keep the floor under the camera, moving it in whole grid cells so it looks static
+    pub fn follow_floor(&mut self, io: &mut EngineIo, camera_tf: Transform) {
+        let snap = ground_snap(FLOOR_SPACING, FLOOR_BANDS);
+        let cell = (
+            (camera_tf.pos.x / snap).round() as i32,
+            (camera_tf.pos.z / snap).round() as i32,
+        );
+
+        if self.floor_cell != Some(cell) {
+            self.floor_cell = Some(cell);
+            let pos = Vec3::new(
+                cell.0 as f32 * snap,
+                self.floor_height,
+                cell.1 as f32 * snap,
+            );
+            io.add_component(self.floor_ent, Transform::new().with_position(pos));
+        }
+    }
+}
+
+/// Index of the control point nearest each vertex of `mesh`
+fn nearest_ctrlps(path: &Curve, mesh: &Mesh) -> Vec<usize> {
+    mesh.vertices
+        .iter()
+        .map(|vertex| path.nearest_ctrlp(Vec3::from(vertex.pos)))
+        .collect()
+}
+
+/// The environment as drawn at `quality`. Only lines are simplified.
+fn simplify(environment: &Mesh, elements: ObjElements, quality: Quality) -> Mesh {
+    match (elements, quality.decimation()) {
+        (ObjElements::Lines, Some((max_angle, min_length))) => {
+            decimate_lines(environment, max_angle, min_length)
+        }
+        _ => environment.clone(),
+    }
+}
@@ -0,0 +1,50 @@
+//! What a newly loaded track is like, for players deciding whether to ready up
+use crate::{
+    curve::Curve,
+    strings::{Key, Language, Text},
+    NextRace,
+};
+
+/// Stretches turning more sharply than this count as corners (radians per meter)
+const CORNER_CURVATURE: f32 = 0.02;
+
+/// Statistics of a track, worked out from its curve
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackSummary {
+    /// Length of a lap (meters)
+    pub length: f32,
+    pub corners: usize,
+    /// Difference in height between the lowest and highest points (meters)
+    pub climb: f32,
+}
+
+impl TrackSummary {
+    pub fn new(path: &Curve) -> Self {
+        let (low, high) = path.elevation_range();
+        Self {
+            length: path.length(),
+            corners: path.corner_count(CORNER_CURVATURE),
+            climb: high - low,
+        }
+    }
+
+    /// One line announcing the track
+    pub fn headline(&self) -> Text {
+        Text::new(Key::TrackLoaded)
+            .with(format!("{:.0}", self.length))
+            .with(self.corners)
+    }
+
+    /// Rows for the lobby panel, including the next race's laps if known
+    pub fn lines(&self, language: Language, next_race: Option<&NextRace>) -> Vec<String> {
+        let mut lines = vec![
+            Text::new(Key::TrackLength).with(format!("{:.0}", self.length)),
+            Text::new(Key::TrackCorners).with(self.corners),
+            Text::new(Key::TrackClimb).with(format!("{:.0}", self.climb)),
+        ];
+        if let Some(next_race) = next_race {
+            lines.push(Text::new(Key::TrackLaps).with(next_race.laps));
+        }
+        lines.iter().map(|text| text.render(language)).collect()
+    }
+}
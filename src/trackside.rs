@@ -0,0 +1,92 @@
+//! Fixed cameras along the track, for watching races like they're on TV
+use cimvr_common::{
+    glam::{Mat3, Quat, Vec3},
+    Transform,
+};
+
+use crate::{controls::TRACK_WIDTH, curve::Curve};
+
+/// Distance between cameras along the track (meters)
+const SPACING: f32 = 120.;
+/// Height of the cameras above the track (meters)
+const HEIGHT: f32 = 12.;
+/// Distance of the cameras outside the edge of the track (meters)
+const OUTSET: f32 = 10.;
+/// Another camera has to be this much closer than the current one before we cut to it
+const HYSTERESIS: f32 = 0.75;
+
+/// Cameras placed along the track, and the one we're watching from
+pub struct TracksideCameras {
+    /// Position of each camera, and the track's up direction there
+    cameras: Vec<(Vec3, Vec3)>,
+    current: Option<usize>,
+}
+
+impl TracksideCameras {
+    /// Place a camera every `SPACING` meters, raised up and on the outside of the track
+    pub fn new(path: &Curve) -> Self {
+        let (min, max) = path.bounds();
+        let centre = (min + max) / 2.;
+
+        let n = ((path.length() / SPACING) as usize).max(1);
+        let cameras = (0..n)
+            .map(|k| {
+                let frame = path.lerp(path.advance(0., k as f32 * SPACING));
+                let up = frame.orient * Vec3::Y;
+                let mut side = frame.orient * Vec3::Z;
+                if side.dot(frame.pos - centre) < 0. {
+                    side = -side;
+                }
+                let pos = frame.pos + side * (TRACK_WIDTH / 2. + OUTSET) + up * HEIGHT;
+                (pos, up)
+            })
+            .collect();
+
+        Self {
+            cameras,
+            current: None,
+        }
+    }
+
+    /// Forget the camera we were watching from, e.g. when we start watching someone else
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+
+    /// Camera transform looking at `target` from the nearest camera, and whether that's a
+    /// different camera to last time
+    pub fn view(&mut self, target: Vec3) -> (Transform, bool) {
+        let dist = |idx: usize| self.cameras[idx].0.distance(target);
+        let nearest = (0..self.cameras.len()).min_by(|&a, &b| dist(a).total_cmp(&dist(b)));
+        let Some(nearest) = nearest else {
+            return (Transform::identity(), false);
+        };
+
+        // Stick with the current camera until another is clearly better
+        let chosen = match self.current {
+            Some(current) if dist(nearest) > dist(current) * HYSTERESIS => current,
+            _ => nearest,
+        };
+        let cut = self.current != Some(chosen);
+        self.current = Some(chosen);
+
+        let (pos, up) = self.cameras[chosen];
+        let tf = Transform::new()
+            .with_position(pos)
+            .with_rotation(look_at(target - pos, up));
+        (tf, cut)
+    }
+}
+
+/// Orientation of a camera looking along `forward`, keeping `up` roughly up. Cameras look
+/// along their local -Z.
+pub fn look_at(forward: Vec3, up: Vec3) -> Quat {
+    let back = -forward.normalize_or_zero();
+    let right = up.cross(back).normalize_or_zero();
+    if right == Vec3::ZERO {
+        // Looking straight up or down
+        return Quat::from_rotation_arc(Vec3::NEG_Z, -back);
+    }
+    let up = back.cross(right);
+    Quat::from_mat3(&Mat3::from_cols(right, up, back))
+}
@@ -0,0 +1,162 @@
+use chat::ChatUpload;
+use cimvr_common::ui::{Schema, State, UiHandle, UiStateHelper};
+use cimvr_engine_interface::{prelude::*, println};
+
+use crate::{controls::TuningParams, ShipCharacteristics};
+
+/// Name and step size of each adjustable value, in panel order
+const TUNABLES: &[(&str, f32)] = &[
+    ("mass", 100.),
+    ("moment", 1000.),
+    ("max_twirl", 0.5),
+    ("max_impulse", 5.),
+    ("lerp_speed", 0.1),
+    ("lookahead", 2.5),
+    ("horiz_power_exp", 0.05),
+    ("max_bank", 0.05),
+    ("throttle_deadzone", 0.01),
+    ("roll_deadzone", 0.01),
+    ("ship_radius", 0.25),
+];
+
+/// Each tunable is a label followed by "-" and "+" buttons
+const ELEMENTS_PER_TUNABLE: usize = 3;
+
+/// Developer panel for adjusting ship handling without a rebuild
+pub struct TuningPanel {
+    /// Removed while the HUD is hidden
+    element: Option<UiHandle>,
+}
+
+impl TuningPanel {
+    pub fn new(
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        ship: ShipCharacteristics,
+        tuning: TuningParams,
+    ) -> Self {
+        Self {
+            element: Some(Self::add(io, gui, ship, tuning)),
+        }
+    }
+
+    /// Remove or rebuild the panel, showing the current values
+    pub fn set_visible(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        visible: bool,
+        ship: ShipCharacteristics,
+        tuning: TuningParams,
+    ) {
+        match (visible, self.element) {
+            (true, None) => self.element = Some(Self::add(io, gui, ship, tuning)),
+            (false, Some(handle)) => {
+                gui.delete(io, handle);
+                self.element = None;
+            }
+            _ => (),
+        }
+    }
+
+    fn add(
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        ship: ShipCharacteristics,
+        tuning: TuningParams,
+    ) -> UiHandle {
+        let mut schema = vec![];
+        let mut init_state = vec![];
+        for idx in 0..TUNABLES.len() {
+            schema.push(Schema::Label);
+            schema.push(Schema::Button { text: "-".into() });
+            schema.push(Schema::Button { text: "+".into() });
+
+            init_state.push(State::Label {
+                text: label_text(idx, ship, tuning),
+            });
+            init_state.push(State::Button { clicked: false });
+            init_state.push(State::Button { clicked: false });
+        }
+
+        schema.push(Schema::Button {
+            text: "Copy current values".into(),
+        });
+        init_state.push(State::Button { clicked: false });
+
+        gui.add(io, "FZ Tuning", schema, init_state)
+    }
+
+    /// Apply button presses to the given parameters. Expects `gui` to already be downloaded.
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        ship: &mut ShipCharacteristics,
+        tuning: &mut TuningParams,
+    ) {
+        let Some(element) = self.element else { return };
+
+        let clicked: Vec<bool> = gui
+            .read(element)
+            .iter()
+            .map(|state| *state == State::Button { clicked: true })
+            .collect();
+
+        let mut changed = false;
+        for (idx, &(_, step)) in TUNABLES.iter().enumerate() {
+            let base = idx * ELEMENTS_PER_TUNABLE;
+            if clicked[base + 1] {
+                *value_mut(idx, ship, tuning) -= step;
+                changed = true;
+            }
+            if clicked[base + 2] {
+                *value_mut(idx, ship, tuning) += step;
+                changed = true;
+            }
+        }
+
+        if changed {
+            gui.modify(io, element, |ui_state| {
+                for idx in 0..TUNABLES.len() {
+                    ui_state[idx * ELEMENTS_PER_TUNABLE] = State::Label {
+                        text: label_text(idx, *ship, *tuning),
+                    };
+                }
+            });
+        }
+
+        // Print as Rust literals, so that good values can be pasted back into the defaults
+        if clicked[TUNABLES.len() * ELEMENTS_PER_TUNABLE] {
+            let text = format!("{:?}\n{:?}", ship, tuning);
+            println!("{}", text);
+            io.send(&ChatUpload(text));
+        }
+    }
+}
+
+fn label_text(idx: usize, mut ship: ShipCharacteristics, mut tuning: TuningParams) -> String {
+    let (name, _) = TUNABLES[idx];
+    format!("{}: {}", name, value_mut(idx, &mut ship, &mut tuning))
+}
+
+fn value_mut<'a>(
+    idx: usize,
+    ship: &'a mut ShipCharacteristics,
+    tuning: &'a mut TuningParams,
+) -> &'a mut f32 {
+    match idx {
+        0 => &mut ship.mass,
+        1 => &mut ship.moment,
+        2 => &mut ship.max_twirl,
+        3 => &mut ship.max_impulse,
+        4 => &mut tuning.lerp_speed,
+        5 => &mut tuning.lookahead,
+        6 => &mut tuning.horiz_power_exp,
+        7 => &mut tuning.max_bank,
+        8 => &mut tuning.throttle_deadzone,
+        9 => &mut tuning.roll_deadzone,
+        10 => &mut tuning.ship_radius,
+        _ => unreachable!("Tunable index out of range"),
+    }
+}
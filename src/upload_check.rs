@@ -0,0 +1,195 @@
+//! Checks on the ships racers upload, so that a modified client can't put its ship wherever it
+//! likes. Unlike the fairness review, these act on their own: an upload which can't be right
+//! is dropped, and a racer who keeps sending them is taken out of the race.
+use std::collections::HashMap;
+
+use cimvr_common::glam::Vec3;
+use cimvr_engine_interface::prelude::*;
+
+use crate::curve::Curve;
+
+/// Distance a ship may move between uploads beyond what the top speed allows. Generous, since
+/// respawning puts ships back a few control points along the track (meters).
+const MOVE_SLACK: f32 = 150.;
+/// Furthest a ship may be outside the track's cross-section, e.g. while being reset (meters)
+const TRACK_SLACK: f32 = 20.;
+/// Dropped uploads, in one race, before the racer is taken out of it
+pub const MAX_STRIKES: u32 = 20;
+
+/// Why an upload was dropped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rejection {
+    /// Reported speed over the limit (meters per second)
+    TooFast(f32),
+    /// Distance from the last accepted upload, further than the limit allows (meters)
+    Teleported(f32),
+    /// Distance outside the track (meters)
+    OffTrack(f32),
+}
+
+/// Whether an upload at `time` of a ship at `pos`, moving at `vel`, could be genuine, given
+/// the time and position of the last one accepted
+pub fn check_upload(
+    path: &Curve,
+    max_speed: f32,
+    last: Option<(f32, Vec3)>,
+    time: f32,
+    pos: Vec3,
+    vel: Vec3,
+) -> Result<(), Rejection> {
+    let speed = vel.length();
+    if speed > max_speed {
+        return Err(Rejection::TooFast(speed));
+    }
+
+    if let Some((last_time, last_pos)) = last {
+        let moved = pos.distance(last_pos);
+        if moved > max_speed * (time - last_time).max(0.) + MOVE_SLACK {
+            return Err(Rejection::Teleported(moved));
+        }
+    }
+
+    let idx = path.nearest_ctrlp(pos);
+    let frame = path.ctrlps[idx];
+    let local = frame.orient.inverse() * (pos - frame.pos);
+    let size = path.cross_section_near(idx, local.x);
+    let outside = (local.z.abs() - size.half_width()).max(local.y.abs() - size.half_height());
+    if outside > TRACK_SLACK {
+        return Err(Rejection::OffTrack(outside));
+    }
+
+    Ok(())
+}
+
+/// What to do with an upload
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Accept,
+    Drop(Rejection),
+    /// Drop it, and take the racer out of the race: they've had `MAX_STRIKES`
+    Exclude(Rejection),
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct Racer {
+    /// Time and position of the last accepted upload
+    last: Option<(f32, Vec3)>,
+    strikes: u32,
+}
+
+/// Each racer's last accepted upload, and how many have been dropped this race
+#[derive(Default)]
+pub struct UploadChecks {
+    racers: HashMap<ClientId, Racer>,
+}
+
+impl UploadChecks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Judge the client's upload at `time` of a ship at `pos` moving at `vel`
+    pub fn check(
+        &mut self,
+        client_id: ClientId,
+        path: &Curve,
+        max_speed: f32,
+        time: f32,
+        pos: Vec3,
+        vel: Vec3,
+    ) -> Verdict {
+        let racer = self.racers.entry(client_id).or_default();
+        match check_upload(path, max_speed, racer.last, time, pos, vel) {
+            Ok(()) => {
+                racer.last = Some((time, pos));
+                Verdict::Accept
+            }
+            Err(rejection) => {
+                racer.strikes += 1;
+                match racer.strikes >= MAX_STRIKES {
+                    true => Verdict::Exclude(rejection),
+                    false => Verdict::Drop(rejection),
+                }
+            }
+        }
+    }
+
+    /// Forget the client, e.g. once they stop racing or leave, so that the server moving
+    /// their ship in the meantime doesn't count against them
+    pub fn remove(&mut self, client_id: ClientId) {
+        self.racers.remove(&client_id);
+    }
+
+    /// Forget everyone, for a new race
+    pub fn clear(&mut self) {
+        self.racers.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness;
+
+    const MAX_SPEED: f32 = 400.;
+    const RADIUS: f32 = 200.;
+    const RACER: ClientId = ClientId(1);
+
+    fn ring() -> Curve {
+        harness::ring(16, RADIUS)
+    }
+
+    fn radial(angle: f32) -> Vec3 {
+        Vec3::new(angle.cos(), 0., angle.sin())
+    }
+
+    #[test]
+    fn genuine_uploads_are_accepted() {
+        let (path, mut checks) = (ring(), UploadChecks::new());
+        let vel = Vec3::X * 100.;
+        for k in 0..10 {
+            let pos = radial(k as f32 * 0.01) * RADIUS;
+            let verdict = checks.check(RACER, &path, MAX_SPEED, k as f32 * 0.1, pos, vel);
+            assert_eq!(verdict, Verdict::Accept);
+        }
+    }
+
+    #[test]
+    fn impossible_uploads_are_dropped() {
+        let (path, mut checks) = (ring(), UploadChecks::new());
+        let on_track = radial(0.) * RADIUS;
+        let verdict = checks.check(RACER, &path, MAX_SPEED, 0., on_track, Vec3::ZERO);
+        assert_eq!(verdict, Verdict::Accept);
+
+        let too_fast = Vec3::X * (MAX_SPEED + 1.);
+        let verdict = checks.check(RACER, &path, MAX_SPEED, 0.1, on_track, too_fast);
+        assert!(matches!(verdict, Verdict::Drop(Rejection::TooFast(_))));
+
+        // Half way round the ring in a tenth of a second
+        let across = radial(std::f32::consts::PI) * RADIUS;
+        let verdict = checks.check(RACER, &path, MAX_SPEED, 0.1, across, Vec3::ZERO);
+        assert!(matches!(verdict, Verdict::Drop(Rejection::Teleported(_))));
+
+        let outside = radial(0.) * (RADIUS + 100.);
+        let verdict = checks.check(RACER, &path, MAX_SPEED, 0.1, outside, Vec3::ZERO);
+        assert!(matches!(verdict, Verdict::Drop(Rejection::OffTrack(_))));
+    }
+
+    #[test]
+    fn racers_are_excluded_after_max_strikes() {
+        let (path, mut checks) = (ring(), UploadChecks::new());
+        let pos = radial(0.) * RADIUS;
+        let too_fast = Vec3::X * MAX_SPEED * 2.;
+        for strike in 1..MAX_STRIKES {
+            let verdict = checks.check(RACER, &path, MAX_SPEED, 0., pos, too_fast);
+            assert!(matches!(verdict, Verdict::Drop(_)), "strike {}", strike);
+        }
+        let verdict = checks.check(RACER, &path, MAX_SPEED, 0., pos, too_fast);
+        assert!(matches!(verdict, Verdict::Exclude(Rejection::TooFast(_))));
+
+        // A new race starts them from a clean slate
+        checks.clear();
+        let verdict = checks.check(RACER, &path, MAX_SPEED, 0., pos, too_fast);
+        assert!(matches!(verdict, Verdict::Drop(_)));
+    }
+}
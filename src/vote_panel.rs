@@ -0,0 +1,111 @@
+use cimvr_common::ui::{Schema, State, UiHandle, UiStateHelper};
+use cimvr_engine_interface::prelude::*;
+
+use crate::{CastVote, OpenProposal, RuleVote};
+
+/// Each proposal is a label followed by "Yes" and "No" buttons
+const ELEMENTS_PER_PROPOSAL: usize = 3;
+
+/// Panel for voting on the server's rule proposals. Has its own element, so it never shares
+/// indices with any other panel.
+pub struct VotePanel {
+    proposals: Vec<OpenProposal>,
+    /// Panel and the IDs of the proposals it was built for
+    element: Option<(UiHandle, Vec<u32>)>,
+    /// Whether the tallies have changed since the panel was last updated
+    changed: bool,
+}
+
+fn proposal_text(open: &OpenProposal) -> String {
+    format!("{} ({} yes, {} no)", open.proposal, open.yes, open.no)
+}
+
+impl VotePanel {
+    pub fn new() -> Self {
+        Self {
+            proposals: vec![],
+            element: None,
+            changed: false,
+        }
+    }
+
+    pub fn set(&mut self, RuleVote(proposals): RuleVote) {
+        self.proposals = proposals;
+        self.changed = true;
+    }
+
+    fn add(&self, io: &mut EngineIo, gui: &mut UiStateHelper) -> UiHandle {
+        let mut schema = vec![];
+        let mut init_state = vec![];
+        for open in &self.proposals {
+            schema.push(Schema::Label);
+            schema.push(Schema::Button { text: "Yes".into() });
+            schema.push(Schema::Button { text: "No".into() });
+
+            init_state.push(State::Label {
+                text: proposal_text(open),
+            });
+            init_state.push(State::Button { clicked: false });
+            init_state.push(State::Button { clicked: false });
+        }
+
+        gui.add(io, "Rule votes", schema, init_state)
+    }
+
+    /// Show the open proposals and send any votes cast. Expects `gui` to already be downloaded.
+    pub fn update(&mut self, io: &mut EngineIo, gui: &mut UiStateHelper, visible: bool) {
+        let ids: Vec<u32> = self.proposals.iter().map(|open| open.id).collect();
+        let visible = visible && !ids.is_empty();
+
+        // Rebuild if the proposals themselves changed
+        match &self.element {
+            Some((handle, shown)) if !visible || *shown != ids => {
+                gui.delete(io, *handle);
+                self.element = None;
+            }
+            _ => (),
+        }
+
+        if !visible {
+            return;
+        }
+
+        let Some((handle, _)) = &self.element else {
+            self.element = Some((self.add(io, gui), ids));
+            self.changed = false;
+            return;
+        };
+        let handle = *handle;
+
+        let clicked: Vec<bool> = gui
+            .read(handle)
+            .iter()
+            .map(|state| *state == State::Button { clicked: true })
+            .collect();
+        for (idx, open) in self.proposals.iter().enumerate() {
+            let base = idx * ELEMENTS_PER_PROPOSAL;
+            if clicked[base + 1] {
+                io.send(&CastVote {
+                    id: open.id,
+                    yes: true,
+                });
+            }
+            if clicked[base + 2] {
+                io.send(&CastVote {
+                    id: open.id,
+                    yes: false,
+                });
+            }
+        }
+
+        if self.changed {
+            let texts: Vec<String> = self.proposals.iter().map(proposal_text).collect();
+            gui.modify(io, handle, |ui_state| {
+                for (idx, text) in texts.iter().enumerate() {
+                    ui_state[idx * ELEMENTS_PER_PROPOSAL] = State::Label { text: text.clone() };
+                }
+            });
+            self.changed = false;
+        }
+    }
+}
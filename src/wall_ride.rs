@@ -0,0 +1,250 @@
+//! Shielded walls: on marked stretches of track, a ship which meets the side wall can ride it
+//! through the corner on shield energy, instead of being put back onto the track
+use cimvr_common::{
+    glam::Vec3,
+    render::{Mesh, MeshHandle, Primitive, Render, UploadMesh, Vertex},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{curve::Curve, hud::HudVisibility};
+
+/// Shield energy used per second of riding, out of a full charge of 1
+const DRAIN_RATE: f32 = 0.25;
+/// Shield energy regained per second off the wall
+const RECHARGE_RATE: f32 = 0.1;
+/// Least energy to latch on to a wall with, so that an empty shield doesn't flicker on and off
+const MIN_ENTRY_ENERGY: f32 = 0.2;
+/// Distance from the wall at which a ship latches on (meters)
+const ENTRY_DISTANCE: f32 = 1.;
+/// Distance inside the wall at which a riding ship is held (meters)
+const RIDE_GAP: f32 = 0.5;
+/// Roll away from the wall which lets go of it
+const RELEASE_ROLL: f32 = 0.3;
+/// Spacing of the marks along shielded walls, in curve index
+const MARK_SPACING: f32 = 0.25;
+const SHIELD_COLOR: [f32; 3] = [0., 0.8, 1.];
+const SPARK_COLOR: [f32; 3] = [1., 0.9, 0.4];
+/// Tint of a ship riding a wall
+const RIDING_TINT: [f32; 3] = [0.5, 1., 1.];
+/// Half the width of a ship, where the sparks fly from (meters)
+const SHIP_HALF_WIDTH: f32 = 1.5;
+
+/// Stretch of track whose walls are shielded, from `start` to `end` in curve index. Both wrap
+/// around the loop, so the section may run over the finish line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShieldedSection {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl ShieldedSection {
+    /// Start and end on a track of `n` control points, with `end` past `start`
+    fn bounds(&self, n: usize) -> (f32, f32) {
+        let start = self.start.rem_euclid(n as f32);
+        let mut end = self.end.rem_euclid(n as f32);
+        if end < start {
+            end += n as f32;
+        }
+        (start, end)
+    }
+
+    /// Whether curve index `t` is in the section, on a track of `n` control points
+    pub fn contains(&self, t: f32, n: usize) -> bool {
+        let (start, end) = self.bounds(n);
+        let t = t.rem_euclid(n as f32);
+        (start..=end).contains(&t) || (start..=end).contains(&(t + n as f32))
+    }
+}
+
+/// A ship's hold on a shielded wall, and the energy it has left to hold on with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct WallRide {
+    /// Side of the track being ridden, as the sign of its local Z; `None` when not riding
+    pub side: Option<f32>,
+    /// From empty at 0 to full at 1
+    pub energy: f32,
+}
+
+impl Default for WallRide {
+    fn default() -> Self {
+        Self {
+            side: None,
+            energy: 1.,
+        }
+    }
+}
+
+impl WallRide {
+    /// Ride on or off the wall for `dt`, with the ship `lateral` meters across a track
+    /// `half_width` meters either side of the center, and steering with `roll`. Returns where
+    /// across the track to hold the ship, while riding. Once the energy runs out the ship lets
+    /// go, and the wall is solid again.
+    pub fn update(
+        &mut self,
+        shielded: bool,
+        lateral: f32,
+        half_width: f32,
+        roll: f32,
+        dt: f32,
+    ) -> Option<f32> {
+        let side = lateral.signum();
+        let steering_away = roll * side < -RELEASE_ROLL;
+        let hold = match self.side {
+            Some(riding) => riding == side && self.energy > 0.,
+            None => {
+                let at_wall = lateral.abs() > half_width - ENTRY_DISTANCE;
+                at_wall && self.energy >= MIN_ENTRY_ENERGY
+            }
+        };
+        self.side = (shielded && hold && !steering_away).then_some(side);
+
+        match self.side {
+            Some(side) => {
+                self.energy = (self.energy - DRAIN_RATE * dt).max(0.);
+                Some(side * (half_width - RIDE_GAP))
+            }
+            None => {
+                self.energy = (self.energy + RECHARGE_RATE * dt).min(1.);
+                None
+            }
+        }
+    }
+
+    /// Draw `amount` of energy for the thrusters, returning the fraction of it there was
+    pub fn spend(&mut self, amount: f32) -> f32 {
+        if amount <= 0. {
+            return 1.;
+        }
+        let spent = amount.min(self.energy);
+        self.energy -= spent;
+        spent / amount
+    }
+}
+
+/// Color to draw a ship of the player's `color` with at `brightness`, tinted while it's riding
+/// a wall
+pub fn ship_tint(ride: WallRide, color: [f32; 3], brightness: f32) -> [f32; 3] {
+    match ride.side {
+        Some(_) => RIDING_TINT.map(|c| c * brightness),
+        None => color.map(|c| c * brightness),
+    }
+}
+
+/// Marks along the walls of each shielded section, in their own color, in world space
+fn shield_mesh(path: &Curve, sections: &[ShieldedSection]) -> Mesh {
+    let n = path.ctrlps.len();
+    let mut m = Mesh::new();
+    for section in sections {
+        let (start, end) = section.bounds(n);
+        let marks = ((end - start) / MARK_SPACING).ceil().max(1.) as usize;
+        for side in [-1., 1.] {
+            let mut prev: Option<[u32; 2]> = None;
+            for k in 0..=marks {
+                let t = start + k as f32 * (end - start) / marks as f32;
+                let frame = path.lerp(t % n as f32);
+                let size = path.cross_section_at(t);
+                let (half_w, half_h) = (size.half_width(), size.half_height());
+                let corner = |y: f32| frame.pos + frame.orient * Vec3::new(0., y, side * half_w);
+                let bottom = m.push_vertex(Vertex::new(corner(-half_h).into(), SHIELD_COLOR));
+                let top = m.push_vertex(Vertex::new(corner(half_h).into(), SHIELD_COLOR));
+                m.indices.extend([bottom, top]);
+                if let Some([prev_bottom, prev_top]) = prev {
+                    m.indices.extend([prev_bottom, bottom, prev_top, top]);
+                }
+                prev = Some([bottom, top]);
+            }
+        }
+    }
+    m
+}
+
+/// Short streaks trailing back from the origin, in the ship's frame
+fn spark_mesh() -> Mesh {
+    let mut m = Mesh::new();
+    let origin = m.push_vertex(Vertex::new([0.; 3], SPARK_COLOR));
+    for [x, y, z] in [
+        [-3., 0.8, 0.],
+        [-4., 0., 0.3],
+        [-3., -0.8, 0.],
+        [-2., 0.4, 0.5],
+    ] {
+        let tip = m.push_vertex(Vertex::new([x, y, z], SPARK_COLOR));
+        m.indices.extend([origin, tip]);
+    }
+    m
+}
+
+/// The shielded walls of a track, drawn in their own color
+pub struct ShieldWalls {
+    entity: EntityId,
+}
+
+impl ShieldWalls {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("ShieldWalls"));
+
+    pub fn new(io: &mut EngineIo, path: &Curve, sections: &[ShieldedSection]) -> Self {
+        io.send(&UploadMesh {
+            mesh: shield_mesh(path, sections),
+            id: Self::RDR_ID,
+        });
+
+        let entity = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(Render::new(Self::RDR_ID).primitive(Primitive::Lines))
+            .build();
+        Self { entity }
+    }
+
+    pub fn teardown(self, io: &mut EngineIo) {
+        io.remove_entity(self.entity);
+    }
+}
+
+/// Sparks off the side of the local ship, while it's riding a wall
+pub struct WallSparks {
+    entity: EntityId,
+    shown: bool,
+}
+
+impl WallSparks {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("WallSparks"));
+
+    pub fn new(io: &mut EngineIo) -> Self {
+        io.send(&UploadMesh {
+            mesh: spark_mesh(),
+            id: Self::RDR_ID,
+        });
+
+        let entity = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(Self::render(false))
+            .build();
+        Self {
+            entity,
+            shown: false,
+        }
+    }
+
+    fn render(shown: bool) -> Render {
+        let render = Render::new(Self::RDR_ID).primitive(Primitive::Lines);
+        HudVisibility::render(shown, render)
+    }
+
+    /// Show the sparks on the side of `ship_tf` being ridden, if any
+    pub fn update(&mut self, io: &mut EngineIo, ship_tf: Transform, ride: WallRide) {
+        let shown = ride.side.is_some();
+        if shown != self.shown {
+            io.add_component(self.entity, Self::render(shown));
+            self.shown = shown;
+        }
+
+        if let Some(side) = ride.side {
+            let contact = Transform::new().with_position(Vec3::Z * side * SHIP_HALF_WIDTH);
+            io.add_component(self.entity, ship_tf * contact);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Catching races which never end, e.g. after the server hitches. Nothing in here touches
+//! the engine.
+
+/// Turns frame times which may jump backwards into ones which never do
+#[derive(Default, Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    /// Last raw time seen
+    last: Option<f32>,
+    /// Total of every backwards jump so far
+    offset: f32,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The time corresponding to the `raw` frame time. May be called any number of times a frame.
+    pub fn now(&mut self, raw: f32) -> f32 {
+        if let Some(last) = self.last {
+            if raw < last {
+                self.offset += last - raw;
+            }
+        }
+        self.last = Some(raw);
+        raw + self.offset
+    }
+}
+
+/// Remembers when the race last progressed, and when clients were last heard from
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Watchdog {
+    /// Time of the last phase transition: start, first finish, reset
+    transition: f32,
+    /// Time of the last `ShipUpload` or `ClientReady`
+    activity: f32,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn transition(&mut self, now: f32) {
+        self.transition = now;
+    }
+
+    pub fn activity(&mut self, now: f32) {
+        self.activity = now;
+    }
+
+    /// Whether a race has been running for more than `limit` seconds without progressing
+    pub fn stuck(&self, now: f32, in_race: bool, limit: f32) -> bool {
+        in_race && now - self.transition > limit
+    }
+
+    /// What we know about the stuck state, for the log
+    pub fn describe(&self, now: f32) -> String {
+        format!(
+            "no progress for {:.0} s, last client message {:.1} s ago",
+            now - self.transition,
+            now - self.activity,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: f32 = 100.;
+
+    /// Feed `raw` frame times through a clock, returning what it made of each
+    fn monotonic(raw: &[f32]) -> Vec<f32> {
+        let mut clock = MonotonicClock::new();
+        raw.iter().map(|&t| clock.now(t)).collect()
+    }
+
+    #[test]
+    fn the_clock_follows_time_going_forwards() {
+        assert_eq!(monotonic(&[0., 0.5, 1., 7.25]), [0., 0.5, 1., 7.25]);
+    }
+
+    #[test]
+    fn the_clock_never_goes_backwards() {
+        // Jumping back, twice, carries on from where it was instead
+        let times = monotonic(&[10., 11., 5., 6., 2., 3.]);
+        assert_eq!(times, [10., 11., 11., 12., 12., 13.]);
+    }
+
+    #[test]
+    fn the_clock_can_be_read_several_times_a_frame() {
+        assert_eq!(monotonic(&[10., 4., 4., 4., 5.]), [10., 10., 10., 10., 11.]);
+    }
+
+    #[test]
+    fn stuck_only_after_the_limit() {
+        let mut watchdog = Watchdog::new();
+        watchdog.transition(20.);
+        assert!(!watchdog.stuck(20. + LIMIT, true, LIMIT));
+        assert!(watchdog.stuck(20. + LIMIT + 0.1, true, LIMIT));
+
+        // Nothing to get stuck outside a race
+        assert!(!watchdog.stuck(20. + LIMIT * 10., false, LIMIT));
+    }
+
+    #[test]
+    fn progress_puts_off_being_stuck() {
+        let mut watchdog = Watchdog::new();
+        watchdog.transition(0.);
+        watchdog.transition(LIMIT * 0.9);
+        assert!(!watchdog.stuck(LIMIT * 1.5, true, LIMIT));
+
+        // Clients racing doesn't count as the race progressing
+        watchdog.activity(LIMIT * 1.8);
+        assert!(watchdog.stuck(LIMIT * 2., true, LIMIT));
+    }
+
+    #[test]
+    fn a_stalled_clock_stays_unstuck_until_it_moves_again() {
+        let (mut clock, mut watchdog) = (MonotonicClock::new(), Watchdog::new());
+        watchdog.transition(clock.now(30.));
+        for _ in 0..1000 {
+            assert!(!watchdog.stuck(clock.now(30.), true, LIMIT));
+        }
+        // A hitch as long as the limit catches up all at once
+        assert!(watchdog.stuck(clock.now(30. + LIMIT * 2.), true, LIMIT));
+    }
+
+    #[test]
+    fn time_going_backwards_doesnt_wedge_the_race() {
+        // Compared with the raw time, the race would look to have started in the future, and
+        // would never be stuck until the raw time caught up again
+        let (mut clock, mut watchdog) = (MonotonicClock::new(), Watchdog::new());
+        watchdog.transition(clock.now(500.));
+        let raw_after = 10.;
+        assert!(!watchdog.stuck(clock.now(raw_after), true, LIMIT));
+        assert!(watchdog.stuck(clock.now(raw_after + LIMIT + 1.), true, LIMIT));
+        assert!(watchdog
+            .describe(clock.now(raw_after + LIMIT + 1.))
+            .contains("101 s"));
+    }
+}
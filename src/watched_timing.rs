@@ -0,0 +1,118 @@
+//! Live lap and race time of whoever we're spectating, pieced together from the server's
+//! `RaceProgress` broadcasts and ticking on with local time in between
+use std::collections::HashMap;
+
+use cimvr_common::ui::UiStateHelper;
+use cimvr_engine_interface::prelude::*;
+
+use crate::{
+    label_panel::LabelPanel,
+    race_rules::format_race_time,
+    strings::{Key, Language, Text},
+    RaceProgress, RacerFinished, RacerNames,
+};
+
+/// Timing panel for the spectated racer
+pub struct WatchedTiming {
+    names: HashMap<ClientId, String>,
+    laps: HashMap<ClientId, usize>,
+    /// Race time at which each racer began their current lap
+    lap_starts: HashMap<ClientId, f32>,
+    /// Race time as of the last `RaceProgress`, and the local time it arrived
+    synced: Option<(f32, f32)>,
+    /// Race time each finisher finished in
+    finishes: HashMap<ClientId, f32>,
+    panel: LabelPanel,
+}
+
+impl WatchedTiming {
+    pub fn new() -> Self {
+        Self {
+            names: HashMap::new(),
+            laps: HashMap::new(),
+            lap_starts: HashMap::new(),
+            synced: None,
+            finishes: HashMap::new(),
+            panel: LabelPanel::new("Watching"),
+        }
+    }
+
+    /// Start over for a new race between the given racers
+    pub fn start(&mut self, RacerNames(names): &RacerNames) {
+        self.names = names.iter().cloned().collect();
+        self.laps.clear();
+        self.lap_starts.clear();
+        self.synced = None;
+        self.finishes.clear();
+    }
+
+    /// Nothing more to time once the race is over
+    pub fn stop(&mut self) {
+        self.names.clear();
+    }
+
+    /// Take in a broadcast which arrived at local time `now`
+    pub fn set_progress(&mut self, progress: &RaceProgress, now: f32) {
+        self.laps = progress.laps.iter().copied().collect();
+        self.lap_starts = progress.lap_starts.iter().copied().collect();
+        self.synced = Some((progress.race_time, now));
+    }
+
+    /// Race time at local time `now`, while a race is on and we've heard how far along it is
+    pub fn race_time(&self, now: f32) -> Option<f32> {
+        let (synced_time, synced_at) = self.synced.filter(|_| !self.names.is_empty())?;
+        Some(synced_time + now - synced_at)
+    }
+
+    pub fn finish(&mut self, finish: &RacerFinished) {
+        self.finishes.insert(finish.client_id, finish.time);
+    }
+
+    /// Show the timing of `watching` at local time `now`, if we know anything of them
+    pub fn update(
+        &mut self,
+        io: &mut EngineIo,
+        gui: &mut UiStateHelper,
+        watching: Option<ClientId>,
+        now: f32,
+        visible: bool,
+        language: Language,
+    ) {
+        let lines = watching.map_or(vec![], |client_id| self.lines(client_id, now, language));
+        self.panel
+            .update(io, gui, visible && !lines.is_empty(), lines);
+    }
+
+    /// Name, then lap and race time; or the final time, once they've finished. Outside a race
+    /// all we have is their ID.
+    fn lines(&self, client_id: ClientId, now: f32, language: Language) -> Vec<String> {
+        let Some(name) = self.names.get(&client_id) else {
+            return vec![format!("{:?}", client_id)];
+        };
+
+        if let Some(&time) = self.finishes.get(&client_id) {
+            let finished = Text::new(Key::WatchedFinished).with(format_race_time(time));
+            return vec![name.clone(), finished.render(language)];
+        }
+
+        // Nothing until the first broadcast, rather than a clock from nowhere
+        let Some((synced_time, synced_at)) = self.synced else {
+            return vec![];
+        };
+        let race_time = synced_time + now - synced_at;
+
+        let lap = self.laps.get(&client_id).copied().unwrap_or(0);
+        let lap_line = match self.lap_starts.get(&client_id) {
+            Some(&start) => Text::new(Key::WatchedLap)
+                .with(lap)
+                .with(format_race_time(race_time - start)),
+            None => Text::new(Key::WatchedGrid),
+        };
+        let race_line = Text::new(Key::WatchedRaceTime).with(format_race_time(race_time));
+        vec![
+            name.clone(),
+            lap_line.render(language),
+            race_line.render(language),
+        ]
+    }
+}
@@ -0,0 +1,215 @@
+//! A flashing sign ahead of the local ship while it's driven back round the track
+use cimvr_common::{
+    glam::Vec3,
+    render::{MeshHandle, Primitive, Render, UploadMesh},
+    Transform,
+};
+use cimvr_engine_interface::{pkg_namespace, prelude::*};
+
+use crate::{
+    curve::Curve,
+    hud::HudVisibility,
+    shapes::{text_mesh, text_width},
+};
+
+/// Time spent going backwards before the sign comes up (seconds)
+const WRONG_WAY_TIME: f32 = 2.;
+/// Time the sign is on, and then off, in each flash (seconds)
+const FLASH_TIME: f32 = 0.4;
+/// Distance of the sign ahead of the ship, and above it (meters)
+const SIGN_AHEAD: f32 = 15.;
+const SIGN_HEIGHT: f32 = 3.;
+/// Height of the lettering (meters)
+const LETTER_HEIGHT: f32 = 1.5;
+const SIGN_TEXT: &str = "WRONG WAY";
+const SIGN_COLOR: [f32; 3] = [1., 0.1, 0.1];
+
+/// Distance along a loop of `n` control points from curve position `from` to `to`,
+/// negative for going backwards. Takes the shorter way round, so that passing the first
+/// control point reads as a small step rather than most of a lap.
+pub fn path_step(n: f32, from: f32, to: f32) -> f32 {
+    let step = (to - from).rem_euclid(n);
+    match step > n / 2. {
+        true => step - n,
+        false => step,
+    }
+}
+
+/// Whether the ship has been going backwards for long enough to warn about, having gone
+/// `step` along the track over `dt` seconds, `backwards` being the time so far. Stopping
+/// leaves the time where it is; going forwards at all starts it again.
+pub fn wrong_way(step: f32, dt: f32, backwards: &mut f32) -> bool {
+    if step < 0. {
+        *backwards += dt;
+    } else if step > 0. {
+        *backwards = 0.;
+    }
+    *backwards > WRONG_WAY_TIME
+}
+
+/// Notices the local ship heading back round the track, and warns its driver
+pub struct WrongWaySign {
+    entity: EntityId,
+    /// Curve position of the ship last frame
+    last_t: Option<f32>,
+    /// Time the ship has been going backwards
+    backwards: f32,
+    /// Time the sign has been up, for flashing it
+    shown_for: Option<f32>,
+    /// Whether the sign was last drawn lit
+    lit: bool,
+}
+
+impl WrongWaySign {
+    const RDR_ID: MeshHandle = MeshHandle::new(pkg_namespace!("WrongWay"));
+
+    pub fn new(io: &mut EngineIo) -> Self {
+        io.send(&UploadMesh {
+            mesh: text_mesh(SIGN_TEXT, LETTER_HEIGHT, SIGN_COLOR),
+            id: Self::RDR_ID,
+        });
+        let entity = io
+            .create_entity()
+            .add_component(Transform::identity())
+            .add_component(Self::render(false))
+            .build();
+
+        Self {
+            entity,
+            last_t: None,
+            backwards: 0.,
+            shown_for: None,
+            lit: false,
+        }
+    }
+
+    fn render(lit: bool) -> Render {
+        HudVisibility::render(lit, Render::new(Self::RDR_ID).primitive(Primitive::Lines))
+    }
+
+    /// Start again from wherever the ship is next seen, e.g. once it's been moved
+    pub fn reset(&mut self) {
+        self.last_t = None;
+        self.backwards = 0.;
+    }
+
+    /// Follow the ship to `pos` over the last `dt` seconds, while `racing`
+    pub fn update(&mut self, path: &Curve, pos: Vec3, dt: f32, racing: bool) {
+        let mut warn = false;
+        if racing {
+            let t = match self.last_t {
+                Some(last_t) => path.nearest_t_from(pos, last_t),
+                None => path.nearest_t(pos),
+            };
+            if let Some(last_t) = self.last_t.replace(t) {
+                let step = path_step(path.ctrlps.len() as f32, last_t, t);
+                warn = wrong_way(step, dt, &mut self.backwards);
+            }
+        } else {
+            self.reset();
+        }
+        self.shown_for = warn.then(|| self.shown_for.map_or(0., |shown| shown + dt));
+    }
+
+    /// Put the sign up ahead of the ship at `tf`, facing `camera`, if it's going the wrong
+    /// way. Nothing is drawn unless `visible`.
+    pub fn draw(&mut self, io: &mut EngineIo, tf: Transform, camera: Transform, visible: bool) {
+        let flash_on = self
+            .shown_for
+            .map_or(false, |shown| (shown / FLASH_TIME) as u32 % 2 == 0);
+        let lit = visible && flash_on;
+        if lit != self.lit {
+            io.add_component(self.entity, Self::render(lit));
+            self.lit = lit;
+        }
+        if !lit {
+            return;
+        }
+
+        // Centered in front of the ship, turned the same way as the camera so it faces it
+        let width = text_width(SIGN_TEXT, LETTER_HEIGHT);
+        let pos = tf.pos
+            + tf.orient * Vec3::new(SIGN_AHEAD, SIGN_HEIGHT, 0.)
+            + camera.orient * Vec3::new(-width / 2., 0., 0.);
+        let sign_tf = Transform::new()
+            .with_position(pos)
+            .with_rotation(camera.orient);
+        io.add_component(self.entity, sign_tf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::ring;
+
+    const N: f32 = 16.;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn steps_have_the_direction_of_travel() {
+        assert!(close(path_step(N, 3.2, 3.5), 0.3));
+        assert!(close(path_step(N, 3.5, 3.2), -0.3));
+        assert_eq!(path_step(N, 7., 7.), 0.);
+    }
+
+    #[test]
+    fn passing_the_first_control_point_is_a_small_step() {
+        assert!(close(path_step(N, N - 0.1, 0.1), 0.2));
+        assert!(close(path_step(N, 0.1, N - 0.1), -0.2));
+        // However the positions are wrapped
+        assert!(close(path_step(N, -0.1, 0.1), 0.2));
+        assert!(close(path_step(N, 0.1, N + 15.9), -0.2));
+    }
+
+    #[test]
+    fn the_warning_needs_a_while_going_backwards() {
+        let mut backwards = 0.;
+        for _ in 0..19 {
+            assert!(!wrong_way(-0.1, 0.1, &mut backwards));
+        }
+        assert!(wrong_way(-0.1, 0.2, &mut backwards));
+
+        // Stopping keeps it up, going forwards takes it down and starts over
+        assert!(wrong_way(0., 0.1, &mut backwards));
+        assert!(!wrong_way(0.01, 0.1, &mut backwards));
+        assert_eq!(backwards, 0.);
+        assert!(!wrong_way(-0.1, WRONG_WAY_TIME * 0.9, &mut backwards));
+    }
+
+    #[test]
+    fn reversing_round_the_first_control_point_keeps_warning() {
+        // As the sign follows the ship: backwards from the second control point, past the
+        // first and on round
+        let path = ring(N as usize, 100.);
+        // A power of two, so the time adds up exactly
+        let dt = 1. / 64.;
+        let (mut last_t, mut backwards) = (path.nearest_t(path.lerp(2.).pos), 0.);
+        let mut t = 2.;
+        for frame in 0..600 {
+            t -= 0.01;
+            let now = path.nearest_t_from(path.lerp(t.rem_euclid(N)).pos, last_t);
+            let step = path_step(N, last_t, now);
+            assert!(
+                step < 0.,
+                "frame {}: {} from {} to {}",
+                frame,
+                step,
+                last_t,
+                now
+            );
+            let warn = wrong_way(step, dt, &mut backwards);
+            assert_eq!(
+                warn,
+                frame as f32 * dt > WRONG_WAY_TIME + dt,
+                "frame {}",
+                frame
+            );
+            last_t = now;
+        }
+        assert!(t < 0., "didn't pass the first control point");
+    }
+}